@@ -0,0 +1,82 @@
+/**
+ * Flaca C API.
+ *
+ * Losslessly compress JPEG and PNG images in memory. Link against
+ * libflaca_capi (shared or static).
+ *
+ * Each optimize function takes the source image and its length, and two
+ * output parameters. On FLACA_OK, *out points to a newly-allocated copy of
+ * the smaller image — release it with flaca_free — and *out_len holds its
+ * length. On anything else, *out is NULL and *out_len is zero.
+ *
+ * Buffers from flaca_alloc must likewise be released with flaca_free; never
+ * mix them with the system malloc/free.
+ *
+ * flaca_optimize_files instead works through a list of JPEG and/or PNG file
+ * paths, overwriting any that shrink. The callbacks in the (optional)
+ * FlacaProgress are fired for each file, and the totals in the (optional)
+ * FlacaStats are updated before each on_finish/on_error call. It is not
+ * available in WASM builds.
+ *
+ * The functions are safe to call from multiple threads at once.
+ */
+
+#ifndef FLACA_H
+#define FLACA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/** The image was optimized. */
+#define FLACA_OK 0
+
+/** The image could not be improved; nothing was allocated. */
+#define FLACA_NO_SAVINGS 1
+
+/** A pointer was NULL or the source was empty. */
+#define FLACA_INVALID_ARGS -1
+
+/** The source was not a valid image of the requested kind. */
+#define FLACA_INVALID_IMAGE -2
+
+/** The output buffer could not be allocated. */
+#define FLACA_NO_MEMORY -3
+
+/** The image kind is not supported by this build (e.g. JPEG under WASM). */
+#define FLACA_UNSUPPORTED -4
+
+/** The file could not be read or written. */
+#define FLACA_IO_ERROR -5
+
+typedef struct FlacaProgress {
+	void* user_data;
+	/** Called before each file is processed. */
+	void (*on_start)(void* user_data, const char* path);
+	/** Called with the before/after sizes (equal if no savings were found). */
+	void (*on_finish)(void* user_data, const char* path, uint64_t before, uint64_t after);
+	/** Called with a FLACA_* status if the file could not be processed. */
+	void (*on_error)(void* user_data, const char* path, int status);
+} FlacaProgress;
+
+typedef struct FlacaStats {
+	uint64_t done;
+	uint64_t failed;
+	uint64_t before;
+	uint64_t after;
+} FlacaStats;
+
+int flaca_optimize_png(const uint8_t* src, size_t src_len, uint8_t** out, size_t* out_len);
+int flaca_optimize_jpeg(const uint8_t* src, size_t src_len, uint8_t** out, size_t* out_len);
+int flaca_optimize_files(const char* const* paths, size_t count, const FlacaProgress* progress, FlacaStats* stats);
+uint8_t* flaca_alloc(size_t len);
+void flaca_free(uint8_t* ptr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,663 @@
+/*!
+# Flaca C API.
+
+This library exposes flaca's lossless JPEG and PNG compression through a
+small C ABI so image services written in other languages can optimize
+uploads in-process instead of spawning the `flaca` binary for each one.
+
+See `include/flaca.h` for the declarations.
+
+```c
+uint8_t* out = NULL;
+size_t out_len = 0;
+if (flaca_optimize_png(src, src_len, &out, &out_len) == FLACA_OK) {
+    // Do something with out…
+    flaca_free(out);
+}
+```
+
+Whole batches of files can be optimized in place with `flaca_optimize_files`,
+which reports on each one — and the running totals — through the callbacks in
+a `FlacaProgress`, so embedders can drive their own progress UIs and metrics.
+
+The encoders match the CLI's defaults: PNGs are run through `Oxipng` and
+then `Zopflipng`; JPEGs through `MozJPEG`. All optional metadata is stripped.
+Every call is independent, so the functions are safe to use from multiple
+threads at once.
+
+## WASM
+
+The library can also be built for `wasm32-unknown-unknown`, for use in edge
+workers and browser-based build tools. Hosts can use `flaca_alloc` to
+reserve space for the source image in the module's memory.
+
+`MozJPEG` requires a libc, so JPEG support is unavailable in WASM builds;
+`flaca_optimize_jpeg` always returns `FLACA_UNSUPPORTED` there. There's no
+filesystem either, so `flaca_optimize_files` is left out entirely.
+*/
+
+#![deny(
+	clippy::allow_attributes_without_reason,
+	clippy::correctness,
+	unreachable_pub,
+	unsafe_code,
+)]
+
+#![warn(
+	clippy::complexity,
+	clippy::nursery,
+	clippy::pedantic,
+	clippy::perf,
+	clippy::style,
+
+	clippy::allow_attributes,
+	clippy::clone_on_ref_ptr,
+	clippy::create_dir,
+	clippy::filetype_is_file,
+	clippy::format_push_string,
+	clippy::get_unwrap,
+	clippy::impl_trait_in_params,
+	clippy::lossy_float_literal,
+	clippy::missing_assert_message,
+	clippy::missing_docs_in_private_items,
+	clippy::needless_raw_strings,
+	clippy::panic_in_result_fn,
+	clippy::pub_without_shorthand,
+	clippy::rest_pat_in_fully_bound_structs,
+	clippy::semicolon_inside_block,
+	clippy::str_to_string,
+	clippy::todo,
+	clippy::undocumented_unsafe_blocks,
+	clippy::unneeded_field_pattern,
+	clippy::unseparated_literal_suffix,
+	clippy::unwrap_in_result,
+
+	macro_use_extern_crate,
+	missing_copy_implementations,
+	missing_docs,
+	non_ascii_idents,
+	trivial_casts,
+	trivial_numeric_casts,
+	unused_crate_dependencies,
+	unused_extern_crates,
+	unused_import_braces,
+)]
+
+use std::{
+	alloc::Layout,
+	ffi::c_int,
+	panic::AssertUnwindSafe,
+	ptr::NonNull,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+	ffi::{
+		c_char,
+		c_void,
+		CStr,
+	},
+	path::Path,
+};
+
+
+
+/// # Status: Optimized.
+///
+/// The image was losslessly compressed; the output pointer holds the new
+/// version.
+pub const FLACA_OK: c_int = 0;
+
+/// # Status: No Savings.
+///
+/// The image could not be improved; nothing was allocated.
+pub const FLACA_NO_SAVINGS: c_int = 1;
+
+/// # Status: Invalid Arguments.
+///
+/// One or more of the pointers was null, or the source was empty.
+pub const FLACA_INVALID_ARGS: c_int = -1;
+
+/// # Status: Invalid Image.
+///
+/// The source was not a valid image of the requested kind, or the encoders
+/// choked on it.
+pub const FLACA_INVALID_IMAGE: c_int = -2;
+
+/// # Status: Out of Memory.
+///
+/// The output buffer could not be allocated.
+pub const FLACA_NO_MEMORY: c_int = -3;
+
+/// # Status: Unsupported.
+///
+/// The image kind is not supported by this build.
+pub const FLACA_UNSUPPORTED: c_int = -4;
+
+/// # Status: I/O Error.
+///
+/// The file could not be read or written.
+pub const FLACA_IO_ERROR: c_int = -5;
+
+/// # PNG Signature.
+const SIG_PNG: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+#[cfg(not(target_arch = "wasm32"))]
+/// # JPEG Signature.
+const SIG_JPEG: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+
+
+/// # Encoder.
+///
+/// Encoders return the smaller image, if any, or a status code on error.
+type Encoder = fn(&[u8]) -> Result<Option<Vec<u8>>, c_int>;
+
+
+
+#[cfg(not(target_arch = "wasm32"))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+/// # Progress Callbacks.
+///
+/// These are called by `flaca_optimize_files` as it works through the batch,
+/// each receiving `user_data` as its first argument. Any or all may be null.
+pub struct FlacaProgress {
+	/// # User Data.
+	pub user_data: *mut c_void,
+
+	/// # On Start.
+	///
+	/// Called with the path before each file is processed.
+	pub on_start: Option<unsafe extern "C" fn(*mut c_void, *const c_char)>,
+
+	/// # On Finish.
+	///
+	/// Called with the path and the before and after sizes once a file has
+	/// been processed. (If no savings were found, the sizes are equal.)
+	pub on_finish: Option<unsafe extern "C" fn(*mut c_void, *const c_char, u64, u64)>,
+
+	/// # On Error.
+	///
+	/// Called with the path and a `FLACA_*` status code in lieu of `on_finish`
+	/// when a file could not be processed.
+	pub on_error: Option<unsafe extern "C" fn(*mut c_void, *const c_char, c_int)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+/// # Batch Totals.
+///
+/// The running totals for a `flaca_optimize_files` batch.
+pub struct FlacaStats {
+	/// # Files Processed (or Failed).
+	pub done: u64,
+
+	/// # Files Failed.
+	pub failed: u64,
+
+	/// # Total Bytes Before.
+	pub before: u64,
+
+	/// # Total Bytes After.
+	pub after: u64,
+}
+
+
+
+#[expect(unsafe_code, reason = "For FFI.")]
+#[no_mangle]
+/// # Optimize PNG.
+///
+/// Losslessly compress the `src_len`-byte PNG at `src`. If the result is
+/// smaller, `*out` is pointed to a newly-allocated copy of it — which must
+/// be released with `flaca_free` — `*out_len` is set to its length, and
+/// `FLACA_OK` is returned.
+///
+/// Otherwise `*out` is set to null, `*out_len` to zero, and one of the
+/// other `FLACA_*` status codes is returned.
+///
+/// ## Safety
+///
+/// `src` must point to at least `src_len` readable bytes, and `out` and
+/// `out_len` must be valid for writes.
+pub unsafe extern "C" fn flaca_optimize_png(
+	src: *const u8,
+	src_len: usize,
+	out: *mut *mut u8,
+	out_len: *mut usize,
+) -> c_int {
+	// Safety: the caller is responsible for the pointers.
+	unsafe { optimize(src, src_len, out, out_len, png) }
+}
+
+#[expect(unsafe_code, reason = "For FFI.")]
+#[no_mangle]
+/// # Optimize JPEG.
+///
+/// Same as `flaca_optimize_png`, but for JPEGs.
+///
+/// ## Safety
+///
+/// `src` must point to at least `src_len` readable bytes, and `out` and
+/// `out_len` must be valid for writes.
+pub unsafe extern "C" fn flaca_optimize_jpeg(
+	src: *const u8,
+	src_len: usize,
+	out: *mut *mut u8,
+	out_len: *mut usize,
+) -> c_int {
+	// Safety: the caller is responsible for the pointers.
+	unsafe { optimize(src, src_len, out, out_len, jpeg) }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[expect(unsafe_code, reason = "For FFI.")]
+#[no_mangle]
+/// # Optimize Files.
+///
+/// Losslessly compress each of the `count` JPEG and/or PNG files in `paths`,
+/// one after another, overwriting any that shrink.
+///
+/// The callbacks in `progress` — if not null — are fired along the way, and
+/// the totals in `stats` — if not null — are updated before each `on_finish`
+/// or `on_error` call, so can be read from inside them.
+///
+/// This returns `FLACA_INVALID_ARGS` if `paths` is null, otherwise
+/// `FLACA_OK`; individual failures are reported through the callbacks and
+/// counted in `stats`.
+///
+/// ## Safety
+///
+/// `paths` must point to `count` pointers, each either null or a valid
+/// NUL-terminated (UTF-8) path. `progress` and `stats`, if not null, must be
+/// valid for reads and writes respectively.
+pub unsafe extern "C" fn flaca_optimize_files(
+	paths: *const *const c_char,
+	count: usize,
+	progress: *const FlacaProgress,
+	stats: *mut FlacaStats,
+) -> c_int {
+	if paths.is_null() { return FLACA_INVALID_ARGS; }
+
+	// Safety: the caller is responsible for the pointers.
+	let (paths, progress, mut stats) = unsafe {
+		(std::slice::from_raw_parts(paths, count), progress.as_ref(), stats.as_mut())
+	};
+
+	let mut totals = FlacaStats::default();
+	for &path in paths {
+		if let Some(cb) = progress.and_then(|p| p.on_start) {
+			// Safety: the caller is responsible for the callback.
+			unsafe { cb(progress.map_or(std::ptr::null_mut(), |p| p.user_data), path); }
+		}
+
+		// Safety: the caller is responsible for the path pointer.
+		let res = unsafe { optimize_file(path) };
+
+		totals.done += 1;
+		match res {
+			Ok((before, after)) => {
+				totals.before += before;
+				totals.after += after;
+			},
+			Err(_) => { totals.failed += 1; },
+		}
+		if let Some(stats) = stats.as_deref_mut() { *stats = totals; }
+
+		let Some(progress) = progress else { continue; };
+		// Safety: the caller is responsible for the callbacks.
+		unsafe {
+			match res {
+				Ok((before, after)) => if let Some(cb) = progress.on_finish {
+					cb(progress.user_data, path, before, after);
+				},
+				Err(e) => if let Some(cb) = progress.on_error {
+					cb(progress.user_data, path, e);
+				},
+			}
+		}
+	}
+
+	FLACA_OK
+}
+
+#[expect(unsafe_code, reason = "For FFI.")]
+#[no_mangle]
+/// # Allocate.
+///
+/// Reserve `len` bytes, returning a pointer to them, or null if `len` is
+/// zero or the allocation fails. The buffer must be released with
+/// `flaca_free`.
+///
+/// This is mainly for WASM hosts, which need somewhere in the module's memory
+/// to put the source image.
+///
+/// ## Safety
+///
+/// This is always safe to call.
+pub unsafe extern "C" fn flaca_alloc(len: usize) -> *mut u8 {
+	allocate(len).map_or(std::ptr::null_mut(), NonNull::as_ptr)
+}
+
+#[expect(unsafe_code, reason = "For FFI.")]
+#[no_mangle]
+/// # Free.
+///
+/// Release a buffer returned by `flaca_alloc` or one of the
+/// `flaca_optimize_*` functions. Null pointers are ignored.
+///
+/// ## Safety
+///
+/// `ptr` must be null or a pointer previously returned by this library that
+/// hasn't already been freed.
+pub unsafe extern "C" fn flaca_free(ptr: *mut u8) {
+	let Some(ptr) = NonNull::new(ptr) else { return; };
+
+	// Safety: the length is stored just before the pointer we handed out.
+	unsafe {
+		let real = ptr.sub(size_of::<usize>());
+		let len = real.cast::<usize>().read();
+		if let Some(layout) = layout_for(len) {
+			std::alloc::dealloc(real.as_ptr(), layout);
+		}
+	}
+}
+
+
+
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Optimize (Generic).
+///
+/// Validate the arguments, run the encoder, and hand back a copy of the
+/// result, if any.
+///
+/// ## Safety
+///
+/// See `flaca_optimize_png`.
+unsafe fn optimize(
+	src: *const u8,
+	src_len: usize,
+	out: *mut *mut u8,
+	out_len: *mut usize,
+	enc: Encoder,
+) -> c_int {
+	if out.is_null() || out_len.is_null() { return FLACA_INVALID_ARGS; }
+
+	// Safety: the caller is responsible for the pointers.
+	unsafe {
+		*out = std::ptr::null_mut();
+		*out_len = 0;
+	}
+	if src.is_null() || src_len == 0 { return FLACA_INVALID_ARGS; }
+
+	// Safety: the caller is responsible for the pointers.
+	let raw = unsafe { std::slice::from_raw_parts(src, src_len) };
+	let new = match encode(raw, enc) {
+		Ok(Some(new)) => new,
+		Ok(None) => return FLACA_NO_SAVINGS,
+		Err(e) => return e,
+	};
+
+	let Some(buf) = allocate(new.len()) else { return FLACA_NO_MEMORY; };
+
+	// Safety: the buffer is big enough for the copy.
+	unsafe {
+		std::ptr::copy_nonoverlapping(new.as_ptr(), buf.as_ptr(), new.len());
+		*out = buf.as_ptr();
+		*out_len = new.len();
+	}
+
+	FLACA_OK
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Optimize File.
+///
+/// Read the file at `path`, crunch it with the appropriate encoder, and
+/// write it back if it shrank, returning the before and after sizes.
+///
+/// ## Errors
+///
+/// A `FLACA_*` status code is returned if the path is invalid, the file
+/// can't be read or written, or it isn't a supported image.
+///
+/// ## Safety
+///
+/// `path` must be null or a valid NUL-terminated string.
+unsafe fn optimize_file(path: *const c_char) -> Result<(u64, u64), c_int> {
+	if path.is_null() { return Err(FLACA_INVALID_ARGS); }
+
+	// Safety: the caller is responsible for the pointer.
+	let path = unsafe { CStr::from_ptr(path) }.to_str()
+		.map(Path::new)
+		.map_err(|_| FLACA_INVALID_ARGS)?;
+
+	let raw = std::fs::read(path).map_err(|_| FLACA_IO_ERROR)?;
+	let enc: Encoder =
+		if is_png(&raw) { png }
+		else if raw.starts_with(&SIG_JPEG) { jpeg }
+		else { return Err(FLACA_INVALID_IMAGE); };
+
+	let before = raw.len() as u64;
+	let Some(new) = encode(&raw, enc)? else { return Ok((before, before)); };
+	write_atomic::write_file(path, &new).map_err(|_| FLACA_IO_ERROR)?;
+	Ok((before, new.len() as u64))
+}
+
+/// # Encode.
+///
+/// Run the encoder, catching any panics so they don't cross the FFI
+/// boundary.
+///
+/// ## Errors
+///
+/// The encoder's error — or `FLACA_INVALID_IMAGE` if it panicked — is
+/// passed through.
+fn encode(raw: &[u8], enc: Encoder) -> Result<Option<Vec<u8>>, c_int> {
+	std::panic::catch_unwind(AssertUnwindSafe(|| enc(raw)))
+		.unwrap_or(Err(FLACA_INVALID_IMAGE))
+}
+
+#[expect(unsafe_code, reason = "For alloc.")]
+/// # Allocate.
+///
+/// Allocate `len` bytes, with an extra `usize` up front — invisible to the
+/// caller — holding the length so `flaca_free` can reconstruct the layout.
+/// (This is the same trick flapfli uses for lodepng.)
+fn allocate(len: usize) -> Option<NonNull<u8>> {
+	if len == 0 { return None; }
+	let layout = layout_for(len)?;
+
+	// Safety: the layout has a non-zero size and is aligned to usize.
+	unsafe {
+		let real = NonNull::new(std::alloc::alloc(layout))?;
+		real.cast::<usize>().write(len);
+		Some(real.add(size_of::<usize>()))
+	}
+}
+
+/// # Layout.
+///
+/// Return the layout for a `len`-byte buffer plus its length prefix.
+fn layout_for(len: usize) -> Option<Layout> {
+	Layout::from_size_align(len.checked_add(size_of::<usize>())?, align_of::<usize>()).ok()
+}
+
+/// # Encode PNG.
+///
+/// ## Errors
+///
+/// An error is returned if the source is not a PNG.
+fn png(raw: &[u8]) -> Result<Option<Vec<u8>>, c_int> {
+	if ! is_png(raw) { return Err(FLACA_INVALID_IMAGE); }
+
+	let mut best = raw.to_vec();
+	// These match the CLI's defaults.
+	let opts = flapfli::oxipng_options(flapfli::OXIPNG_LEVEL_MAX, true);
+	if let Ok(new) = oxipng::optimize_from_memory(&best, &opts) {
+		if new.len() < best.len() && is_png(&new) { best = new; }
+	}
+	if let Some(new) = flapfli::optimize(&best) {
+		if new.len() < best.len() && is_png(&new) { best = new.to_vec(); }
+	}
+
+	Ok((best.len() < raw.len()).then_some(best))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+/// # Encode JPEG.
+///
+/// ## Errors
+///
+/// An error is returned if the source is not a JPEG.
+fn jpeg(raw: &[u8]) -> Result<Option<Vec<u8>>, c_int> {
+	if raw.len() < 4 || raw[..3] != SIG_JPEG { return Err(FLACA_INVALID_IMAGE); }
+	Ok(
+		flapfli::jpegtran::optimize(raw, &())
+			.filter(|new| new.len() < raw.len() && new.starts_with(&SIG_JPEG))
+			.map(|new| new.to_vec())
+	)
+}
+
+#[cfg(target_arch = "wasm32")]
+/// # Encode JPEG.
+///
+/// ## Errors
+///
+/// JPEGs aren't supported in WASM builds.
+const fn jpeg(_raw: &[u8]) -> Result<Option<Vec<u8>>, c_int> { Err(FLACA_UNSUPPORTED) }
+
+/// # Is PNG?
+fn is_png(raw: &[u8]) -> bool { 8 < raw.len() && raw[..8] == SIG_PNG }
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[expect(unsafe_code, reason = "For FFI.")]
+	#[test]
+	fn t_optimize() {
+		/// # Optimizer Signature.
+		type Enc = unsafe extern "C" fn(*const u8, usize, *mut *mut u8, *mut usize) -> c_int;
+
+		let all: [(&str, Enc); 2] = [
+			("../skel/assets/png/01.png", flaca_optimize_png),
+			("../skel/assets/jpg/01.jpg", flaca_optimize_jpeg),
+		];
+		for (path, enc) in all {
+			let raw = std::fs::read(path).expect("Missing image.");
+			let mut out = std::ptr::null_mut();
+			let mut out_len = 0;
+
+			// Safety: the pointers are all valid.
+			let res = unsafe { enc(raw.as_ptr(), raw.len(), &raw mut out, &raw mut out_len) };
+			assert_eq!(res, FLACA_OK, "Failed to optimize {path}.");
+			assert!(! out.is_null(), "Missing output for {path}.");
+			assert!(0 < out_len && out_len < raw.len(), "Wrong length for {path}.");
+
+			// Safety: the buffer came from us.
+			unsafe { flaca_free(out); }
+		}
+	}
+
+	#[expect(unsafe_code, reason = "For FFI.")]
+	#[test]
+	fn t_invalid() {
+		let raw = std::fs::read("../skel/assets/png/01.png").expect("Missing image.");
+		let mut out = std::ptr::null_mut();
+		let mut out_len = 0;
+
+		// Safety: the pointers are all valid (or deliberately null).
+		unsafe {
+			assert_eq!(
+				flaca_optimize_jpeg(raw.as_ptr(), raw.len(), &raw mut out, &raw mut out_len),
+				FLACA_INVALID_IMAGE,
+			);
+			assert_eq!(
+				flaca_optimize_png(std::ptr::null(), 10, &raw mut out, &raw mut out_len),
+				FLACA_INVALID_ARGS,
+			);
+			assert_eq!(
+				flaca_optimize_png(raw.as_ptr(), raw.len(), std::ptr::null_mut(), &raw mut out_len),
+				FLACA_INVALID_ARGS,
+			);
+			flaca_free(std::ptr::null_mut());
+			assert!(flaca_alloc(0).is_null(), "Empty allocations should be null.");
+
+			let buf = flaca_alloc(16);
+			assert!(! buf.is_null(), "Allocation failed.");
+			buf.write_bytes(0xFF, 16);
+			flaca_free(buf);
+		}
+		assert!(out.is_null(), "Output should be null.");
+		assert_eq!(out_len, 0);
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	#[expect(unsafe_code, reason = "For FFI.")]
+	#[test]
+	fn t_optimize_files() {
+		use std::ffi::CString;
+
+		/// # Record Finish.
+		unsafe extern "C" fn finish(data: *mut c_void, _path: *const c_char, before: u64, after: u64) {
+			// Safety: the user data is a Vec<c_int>.
+			unsafe { (*data.cast::<Vec<c_int>>()).push(c_int::from(after < before)); }
+		}
+
+		/// # Record Error.
+		unsafe extern "C" fn error(data: *mut c_void, _path: *const c_char, e: c_int) {
+			// Safety: the user data is a Vec<c_int>.
+			unsafe { (*data.cast::<Vec<c_int>>()).push(e); }
+		}
+
+		let dir = std::env::temp_dir().join(format!("flaca-capi-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).expect("Unable to create directory.");
+
+		let mut files = Vec::new();
+		for (src, name) in [
+			("../skel/assets/png/01.png", "01.png"),
+			("../skel/assets/jpg/01.jpg", "01.jpg"),
+			("../skel/assets/executable.sh", "bogus.png"),
+		] {
+			let dst = dir.join(name);
+			std::fs::copy(src, &dst).expect("Unable to copy image.");
+			files.push(dst);
+		}
+		files.push(dir.join("missing.png"));
+
+		let paths: Vec<CString> = files.iter()
+			.map(|p| CString::new(p.to_string_lossy().into_owned()).expect("Invalid path."))
+			.collect();
+		let ptrs: Vec<*const c_char> = paths.iter().map(|p| p.as_ptr()).collect();
+
+		let mut seen: Vec<c_int> = Vec::new();
+		let progress = FlacaProgress {
+			user_data: (&raw mut seen).cast(),
+			on_start: None,
+			on_finish: Some(finish),
+			on_error: Some(error),
+		};
+		let mut stats = FlacaStats::default();
+
+		// Safety: the pointers are all valid.
+		let res = unsafe {
+			flaca_optimize_files(ptrs.as_ptr(), ptrs.len(), &raw const progress, &raw mut stats)
+		};
+		let _res = std::fs::remove_dir_all(&dir);
+
+		assert_eq!(res, FLACA_OK);
+		assert_eq!(seen, [1, 1, FLACA_INVALID_IMAGE, FLACA_IO_ERROR]);
+		assert_eq!(stats.done, 4);
+		assert_eq!(stats.failed, 2);
+		assert!(stats.after < stats.before, "Nothing was saved.");
+
+		// Safety: null paths are rejected.
+		let res = unsafe { flaca_optimize_files(std::ptr::null(), 1, std::ptr::null(), std::ptr::null_mut()) };
+		assert_eq!(res, FLACA_INVALID_ARGS);
+	}
+}
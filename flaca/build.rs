@@ -31,18 +31,99 @@ pub fn main() {
 /// # Build CLI Arguments.
 fn build_cli() {
 	let mut builder = KeyWordsBuilder::default();
+	builder.push_command("ab");
+	builder.push_command("serve");
 	builder.push_keys([
+		"--advise",
+		"--allow-imperfect",
+		"--archives",
+		"--auto-orient",
+		"--clean-temp",
+		"--daemon",
+		"--default",
+		"--dry-run",
+		"--fast",
+		"--fast-thumbnails",
+		"--fix-extensions",
+		"--follow-symlinks",
+		"--group-variants",
 		"-h", "--help",
+		"--jpeg-grayscale-auto",
+		"--keep-alpha-data",
+		"--keep-colortype",
+		"--mark",
+		"--max",
+		"--memory",
+		"--ndjson",
+		"--nice",
+		"--no-follow-symlinks",
+		"--no-fix",
+		"--no-gif",
+		"--no-ico",
 		"--no-jpg", "--no-jpeg",
+		"--no-oxipng",
 		"--no-png",
+		"--no-recurse",
+		"--no-zopfli",
+		"-0", "--null",
+		"--png-exhaustive",
+		"--preserve-perms",
 		"-p", "--progress",
+		"--primary-only",
+		"--respect-gitignore",
+		"--skip-marked",
+		"--skip-thumbnails",
+		"--skip-variants",
+		"--sniff",
+		"--stdin",
+		"--summarize-by-dir",
+		"--trace-pipeline",
+		"--verify",
 		"-V", "--version",
 	]);
 	builder.push_keys_with_values([
-		"-j",
+		"--confirm-cmd",
+		"--errors",
+		"-j", "--threads",
+		"--jpeg-crop",
+		"--jpeg-keep",
+		"--keep-candidates",
+		"--keep-chunks",
+		"--kind",
 		"-l", "--list",
+		"--listen",
+		"--lock",
+		"--max-depth",
+		"--max-filesize",
+		"--max-memory",
 		"--max-resolution",
+		"--max-size",
+		"--memory-budget",
+		"--metrics-file",
+		"--min-savings",
+		"--min-size",
+		"--mmap-threshold",
+		"--name-template",
+		"--newer-than",
+		"--older-than",
+		"--on-change",
+		"-o", "--out-dir",
+		"--oxipng-level",
+		"--preserve-links",
+		"--reflink",
+		"--report",
+		"--resume",
+		"--settings-a",
+		"--settings-b",
+		"--socket",
+		"--target-size",
+		"--tmpdir",
+		"--top",
 		"-z",
+		"--zopfli-block-size",
+		"--zopfli-cost-model",
+		"--zopfli-max-splits",
+		"--zopfli-store-threshold",
 	]);
 	builder.save(out_path("argyle.rs"));
 }
@@ -53,6 +134,21 @@ fn build_cli() {
 fn build_exts() {
 	let out = format!(
 		r"
+/// # Extension: CUR.
+const E_CUR: Extension = {};
+
+/// # Extension: GIF.
+const E_GIF: Extension = {};
+
+/// # Extension: ICO.
+const E_ICO: Extension = {};
+
+/// # Extension: JFIF.
+const E_JFIF: Extension = {};
+
+/// # Extension: JPE.
+const E_JPE: Extension = {};
+
 /// # Extension: JPEG.
 const E_JPEG: Extension = {};
 
@@ -61,10 +157,23 @@ const E_JPG: Extension = {};
 
 /// # Extension: PNG.
 const E_PNG: Extension = {};
+
+/// # Extension: TAR.
+const E_TAR: Extension = {};
+
+/// # Extension: ZIP.
+const E_ZIP: Extension = {};
 ",
+		Extension::codegen(b"cur"),
+		Extension::codegen(b"gif"),
+		Extension::codegen(b"ico"),
+		Extension::codegen(b"jfif"),
+		Extension::codegen(b"jpe"),
 		Extension::codegen(b"jpeg"),
 		Extension::codegen(b"jpg"),
 		Extension::codegen(b"png"),
+		Extension::codegen(b"tar"),
+		Extension::codegen(b"zip"),
 	);
 
 	write(&out_path("flaca-extensions.rs"), out.as_bytes());
@@ -0,0 +1,461 @@
+/*!
+# Flaca: A/B Comparison
+
+The `ab` subcommand runs two different sets of settings over copies of the
+same images — each in its own child process, since most settings can only be
+set once — and prints a side-by-side comparison of the results.
+
+Settings files use a flat, TOML-style `key = value` syntax, where the keys
+are the long names of the flags and options that affect encoding, e.g.
+
+```toml
+# Flags are true or false.
+auto-orient = true
+
+# Options are strings (or numbers, or lists of strings).
+jpeg-keep = ["exif", "icc"]
+zopfli-iterations = 30
+```
+*/
+
+use crate::{
+	FlacaError,
+	output::common_root,
+};
+use dactyl::{
+	NiceElapsed,
+	NiceU64,
+};
+use fyi_msg::Msg;
+use std::{
+	collections::BTreeMap,
+	num::NonZeroUsize,
+	os::unix::ffi::OsStrExt,
+	path::{
+		Path,
+		PathBuf,
+	},
+	process::{
+		Command,
+		Stdio,
+	},
+	time::{
+		Duration,
+		Instant,
+	},
+};
+
+
+
+/// # Allowed Flags.
+const FLAGS: [&str; 13] = [
+	"auto-orient",
+	"fast",
+	"fast-thumbnails",
+	"max",
+	"nice",
+	"no-gif",
+	"no-ico",
+	"no-jpeg",
+	"no-png",
+	"primary-only",
+	"skip-thumbnails",
+	"skip-variants",
+	"verify",
+];
+
+/// # Allowed Options.
+const OPTIONS: [&str; 11] = [
+	"jpeg-keep",
+	"keep-chunks",
+	"max-filesize",
+	"max-resolution",
+	"min-savings",
+	"oxipng-level",
+	"zopfli-block-size",
+	"zopfli-cost-model",
+	"zopfli-iterations",
+	"zopfli-max-splits",
+	"zopfli-store-threshold",
+];
+
+
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+/// # Settings.
+///
+/// This holds the CLI arguments corresponding to one side of the comparison.
+pub(crate) struct Settings(Vec<String>);
+
+impl Settings {
+	/// # Load.
+	///
+	/// Read and parse the settings file at `path`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be read, is malformed, or
+	/// contains unsupported keys.
+	pub(crate) fn load(path: &str) -> Result<Self, FlacaError> {
+		let raw = std::fs::read_to_string(path.trim()).map_err(|_| FlacaError::AbSettings)?;
+		Self::parse(&raw).ok_or(FlacaError::AbSettings)
+	}
+
+	/// # Parse.
+	fn parse(raw: &str) -> Option<Self> {
+		let mut out = Vec::new();
+		for line in raw.lines() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') { continue; }
+
+			let (key, value) = line.split_once('=')?;
+			let key = key.trim();
+			let value = parse_value(value.trim())?;
+
+			if FLAGS.contains(&key) {
+				match value.as_str() {
+					"true" => { out.push(format!("--{key}")); },
+					"false" => {},
+					_ => return None,
+				}
+			}
+			else if OPTIONS.contains(&key) {
+				out.push(
+					if key == "zopfli-iterations" { "-z".to_owned() }
+					else { format!("--{key}") }
+				);
+				out.push(value);
+			}
+			else { return None; }
+		}
+
+		Some(Self(out))
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # One Side.
+///
+/// The results from one set of settings.
+struct Side {
+	/// # Total Time.
+	elapsed: Duration,
+
+	/// # Results by Path.
+	rows: BTreeMap<String, Row>,
+}
+
+impl Side {
+	/// # Run.
+	///
+	/// Run flaca with `settings` on the images in the `list` file, saving the
+	/// copies and report to `dir` under `name`.
+	fn run(dir: &Path, name: &str, threads: NonZeroUsize, settings: &Settings)
+	-> Result<Self, FlacaError> {
+		let exe = std::env::current_exe().map_err(|_| FlacaError::AbRun)?;
+		let report = dir.join(format!("{name}.csv"));
+
+		let now = Instant::now();
+		let mut cmd = Command::new(exe);
+
+		// Environment overrides would skew the comparison.
+		for var in crate::env::var_names(crate::KEYWORDS) { cmd.env_remove(var); }
+
+		let status = cmd
+			.arg("-0")
+			.arg("-l")
+			.arg(dir.join("list"))
+			.arg("-j")
+			.arg(threads.to_string())
+			.arg("-o")
+			.arg(dir.join(name))
+			.arg("--report")
+			.arg(&report)
+			.args(&settings.0)
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.status()
+			.map_err(|_| FlacaError::AbRun)?;
+		let elapsed = now.elapsed();
+
+		// Anything but a hard error is fine; individual failures show up in
+		// the report.
+		if ! matches!(status.code(), Some(0 | 2 | 3)) { return Err(FlacaError::AbRun); }
+
+		let raw = std::fs::read_to_string(&report).map_err(|_| FlacaError::AbRun)?;
+		let rows = raw.lines().skip(1).filter_map(Row::parse).collect();
+		Ok(Self { elapsed, rows })
+	}
+
+	/// # Total Size After.
+	///
+	/// Failed images count as unchanged.
+	fn after(&self, paths: &[(String, u64)]) -> u64 {
+		paths.iter()
+			.map(|(p, before)| self.rows.get(p).and_then(|r| r.after).unwrap_or(*before))
+			.sum()
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # Report Row.
+struct Row {
+	/// # Size Before.
+	before: u64,
+
+	/// # Size After (If Successful).
+	after: Option<u64>,
+
+	/// # Processing Time (Milliseconds).
+	ms: u64,
+}
+
+impl Row {
+	/// # Parse.
+	///
+	/// Parse a line from a `--report` CSV, returning the path and row.
+	fn parse(line: &str) -> Option<(String, Self)> {
+		use dactyl::traits::BytesToUnsigned;
+
+		// The path is the only field that might contain commas, so work
+		// backwards, skipping over the encoder columns.
+		let mut parts = line.rsplitn(12, ',').skip(6);
+		let ms = u64::btou(parts.next()?.as_bytes())?;
+		let status = parts.next()?;
+		let after = u64::btou(parts.next()?.as_bytes())?;
+		let before = u64::btou(parts.next()?.as_bytes())?;
+		let _kind = parts.next()?;
+		let path = parts.next()?;
+		let path = path.strip_prefix('"')
+			.and_then(|p| p.strip_suffix('"'))
+			.map_or_else(|| path.to_owned(), |p| p.replace("\"\"", "\""));
+
+		let after = matches!(status, "optimized" | "unchanged").then_some(after);
+		Some((path, Self { before, after, ms }))
+	}
+}
+
+
+
+/// # Run!
+///
+/// Run both sets of settings over copies of the `paths`, then print a
+/// side-by-side comparison of the results. The copies are cleaned up
+/// afterward; the originals are never touched.
+///
+/// ## Errors
+///
+/// An error is returned if either run fails.
+pub(crate) fn run(paths: &[PathBuf], threads: NonZeroUsize, a: &Settings, b: &Settings)
+-> Result<(), FlacaError> {
+	let dir = crate::temp::scratch_dir().join(format!("flaca-ab-{}", utc2k::unixtime()));
+	std::fs::create_dir_all(&dir).map_err(|_| FlacaError::AbRun)?;
+
+	// Run each side, then clean up regardless of the outcome.
+	let res = write_list(&dir, paths)
+		.and_then(|()| Side::run(&dir, "a", threads, a))
+		.and_then(|a| Side::run(&dir, "b", threads, b).map(|b| (a, b)));
+	let _res = std::fs::remove_dir_all(&dir);
+	let (a, b) = res?;
+
+	print_table(paths, &a, &b);
+	Ok(())
+}
+
+/// # Write Path List.
+///
+/// Save the paths to a NUL-separated list for the child processes.
+fn write_list(dir: &Path, paths: &[PathBuf]) -> Result<(), FlacaError> {
+	let mut list = Vec::new();
+	for p in paths {
+		list.extend_from_slice(p.as_os_str().as_bytes());
+		list.push(0);
+	}
+	std::fs::write(dir.join("list"), list).map_err(|_| FlacaError::AbRun)
+}
+
+#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+/// # Print Table.
+fn print_table(paths: &[PathBuf], a: &Side, b: &Side) {
+	use std::fmt::Write;
+
+	/// # Size/Status Cell.
+	fn size(row: Option<&Row>) -> String {
+		match row {
+			Some(Row { after: Some(n), .. }) => NiceU64::from(*n).as_str().to_owned(),
+			Some(_) => "error".to_owned(),
+			None => "—".to_owned(),
+		}
+	}
+
+	/// # Time Cell.
+	fn ms(row: Option<&Row>) -> String {
+		row.map_or_else(|| "—".to_owned(), |r| NiceU64::from(r.ms).as_str().to_owned())
+	}
+
+	// Display the paths relative to their common root.
+	let root = common_root(paths).unwrap_or_default();
+	let mut cells = vec![[
+		"IMAGE".to_owned(),
+		"BEFORE".to_owned(),
+		"A".to_owned(),
+		"B".to_owned(),
+		"A (ms)".to_owned(),
+		"B (ms)".to_owned(),
+	]];
+	let mut befores = Vec::with_capacity(paths.len());
+	for p in paths {
+		let key = p.to_string_lossy().into_owned();
+		let (ra, rb) = (a.rows.get(&key), b.rows.get(&key));
+		let before = ra.or(rb).map_or(0, |r| r.before);
+		cells.push([
+			p.strip_prefix(&root).unwrap_or(p).to_string_lossy().into_owned(),
+			NiceU64::from(before).as_str().to_owned(),
+			size(ra),
+			size(rb),
+			ms(ra),
+			ms(rb),
+		]);
+		befores.push((key, before));
+	}
+
+	// Totals.
+	let before: u64 = befores.iter().map(|(_, n)| n).sum();
+	let (after_a, after_b) = (a.after(&befores), b.after(&befores));
+	cells.push([
+		"TOTAL".to_owned(),
+		NiceU64::from(before).as_str().to_owned(),
+		NiceU64::from(after_a).as_str().to_owned(),
+		NiceU64::from(after_b).as_str().to_owned(),
+		NiceU64::from(a.elapsed.as_millis() as u64).as_str().to_owned(),
+		NiceU64::from(b.elapsed.as_millis() as u64).as_str().to_owned(),
+	]);
+
+	// Work out the column widths.
+	let mut widths = [0_usize; 6];
+	for row in &cells {
+		for (w, c) in widths.iter_mut().zip(row) { *w = (*w).max(c.chars().count()); }
+	}
+
+	// Print it!
+	let mut out = String::new();
+	for row in &cells {
+		let _res = write!(out, "{:<w$}", row[0], w = widths[0]);
+		for (c, w) in row.iter().zip(widths).skip(1) {
+			let _res = write!(out, "  {c:>w$}");
+		}
+		out.push('\n');
+	}
+	print!("{out}");
+
+	// And summarize.
+	for (name, side, after) in [("A", a, after_a), ("B", b, after_b)] {
+		Msg::info(format!(
+			"{name}: {} bytes saved in {}.",
+			NiceU64::from(before.saturating_sub(after)),
+			NiceElapsed::from(side.elapsed),
+		)).eprint();
+	}
+}
+
+
+
+/// # Parse Value.
+///
+/// Parse a TOML-style value — a quoted string, a list of quoted strings, or a
+/// bare word or number — ignoring any trailing comment. Lists are joined with
+/// commas.
+fn parse_value(raw: &str) -> Option<String> {
+	/// # Parse Quoted String.
+	///
+	/// Return the string and whatever follows it.
+	fn quoted(raw: &str) -> Option<(&str, &str)> {
+		let q = raw.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+		raw[1..].split_once(q)
+	}
+
+	/// # Nothing Left (But a Comment)?
+	fn done(rest: &str) -> bool {
+		let rest = rest.trim_start();
+		rest.is_empty() || rest.starts_with('#')
+	}
+
+	// A list.
+	if let Some(mut rest) = raw.strip_prefix('[') {
+		let mut out = Vec::new();
+		loop {
+			rest = rest.trim_start();
+			if let Some(r) = rest.strip_prefix(']') {
+				return done(r).then(|| out.join(","));
+			}
+			let (v, r) = quoted(rest)?;
+			out.push(v);
+			rest = r.trim_start();
+			rest = rest.strip_prefix(',').unwrap_or(rest);
+		}
+	}
+
+	// A string.
+	if let Some((v, rest)) = quoted(raw) {
+		return done(rest).then(|| v.to_owned());
+	}
+
+	// Something else.
+	let v = raw.split_once('#').map_or(raw, |(v, _)| v).trim();
+	if v.is_empty() || v.contains(char::is_whitespace) { None }
+	else { Some(v.to_owned()) }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_settings_parse() {
+		let raw = r#"
+# Comments are fine.
+auto-orient = true
+verify = false
+jpeg-keep = ["exif", "icc"]
+min-savings = "5%" # So are trailing comments.
+zopfli-iterations = 30
+"#;
+		assert_eq!(
+			Settings::parse(raw),
+			Some(Settings(vec![
+				"--auto-orient".to_owned(),
+				"--jpeg-keep".to_owned(),
+				"exif,icc".to_owned(),
+				"--min-savings".to_owned(),
+				"5%".to_owned(),
+				"-z".to_owned(),
+				"30".to_owned(),
+			])),
+		);
+
+		// Unsupported keys and bad values are no good.
+		assert_eq!(Settings::parse("out-dir = \"/tmp\""), None);
+		assert_eq!(Settings::parse("verify = 1"), None);
+		assert_eq!(Settings::parse("zopfli-iterations"), None);
+	}
+
+	#[test]
+	fn t_row_parse() {
+		let (path, row) = Row::parse("\"/a,\"\"b\"\".png\",png,100,90,optimized,5,oxipng,,,,10,0")
+			.expect("Row failed to parse.");
+		assert_eq!(path, "/a,\"b\".png");
+		assert_eq!((row.before, row.after, row.ms), (100, Some(90), 5));
+
+		let (path, row) = Row::parse("/c.jpg,jpeg,0,0,read error,1,,,,,,")
+			.expect("Row failed to parse.");
+		assert_eq!(path, "/c.jpg");
+		assert_eq!(row.after, None);
+	}
+}
@@ -0,0 +1,194 @@
+/*!
+# Flaca: Advisor
+*/
+
+use crate::{
+	EncodingError,
+	ImageKind,
+	Observer,
+	Warning,
+};
+use dactyl::traits::NiceInflection;
+use fyi_msg::Msg;
+use std::{
+	path::Path,
+	sync::atomic::{
+		AtomicU64,
+		Ordering::Relaxed,
+	},
+};
+
+
+
+#[derive(Debug, Default)]
+/// # Advisor.
+///
+/// This observer tallies up the warnings and skips that might be worth
+/// re-running with different settings, and suggests said settings at the
+/// end.
+pub(crate) struct Advisor {
+	/// # Images Processed (or Not).
+	total: AtomicU64,
+
+	/// # JPEG ICC Profiles Stripped.
+	icc_jpeg: AtomicU64,
+
+	/// # PNG ICC Profiles Stripped.
+	icc_png: AtomicU64,
+
+	/// # EXIF Orientations Stripped.
+	orientation: AtomicU64,
+
+	/// # Mismatched Extensions.
+	extension: AtomicU64,
+
+	/// # Hard Links Broken.
+	links: AtomicU64,
+
+	/// # Savings Below Minimum.
+	min_savings: AtomicU64,
+
+	/// # Skipped: File Size.
+	file_size: AtomicU64,
+
+	/// # Skipped: Resolution.
+	resolution: AtomicU64,
+
+	/// # Skipped: Memory.
+	memory: AtomicU64,
+}
+
+impl Observer for Advisor {
+	fn on_warning(&self, _src: &Path, warning: Warning) {
+		let counter = match warning {
+			Warning::Extension => &self.extension,
+			Warning::LinkBroken => &self.links,
+			Warning::MinSavings => &self.min_savings,
+			Warning::StrippedIcc(ImageKind::JPEG) => &self.icc_jpeg,
+			Warning::StrippedIcc(_) => &self.icc_png,
+			Warning::StrippedOrientation => &self.orientation,
+			Warning::Panicked(_) | Warning::Renamed => return,
+		};
+		counter.fetch_add(1, Relaxed);
+	}
+
+	fn on_finish(&self, _src: &Path, _before: u64, _after: u64) {
+		self.total.fetch_add(1, Relaxed);
+	}
+
+	fn on_error(&self, _src: &Path, err: EncodingError) {
+		self.total.fetch_add(1, Relaxed);
+		match err {
+			EncodingError::FileSize => { self.file_size.fetch_add(1, Relaxed); },
+			EncodingError::Resolution => { self.resolution.fetch_add(1, Relaxed); },
+			EncodingError::Memory => { self.memory.fetch_add(1, Relaxed); },
+			_ => {},
+		}
+	}
+}
+
+impl Advisor {
+	/// # Summarize.
+	///
+	/// Print a suggestion for each issue encountered, if any.
+	pub(crate) fn summarize(self) {
+		let total = self.total.into_inner();
+		for (count, problem, (one, many), advice) in [
+			(
+				self.orientation.into_inner(),
+				"Stripped the EXIF Orientation from",
+				("JPEG", "JPEGs"),
+				"they may display sideways now, so consider --auto-orient",
+			),
+			(
+				self.icc_jpeg.into_inner(),
+				"Stripped the ICC color profile from",
+				("JPEG", "JPEGs"),
+				"consider --jpeg-keep icc if the colors look off",
+			),
+			(
+				self.icc_png.into_inner(),
+				"Stripped the ICC color profile from",
+				("PNG", "PNGs"),
+				"consider --keep-chunks icc if the colors look off",
+			),
+			(
+				self.extension.into_inner(),
+				"Found mismatched file extensions on",
+				("image", "images"),
+				"consider --fix-extensions",
+			),
+			(
+				self.links.into_inner(),
+				"Broke the hard links of",
+				("image", "images"),
+				"consider --preserve-links rewrite (or skip)",
+			),
+			(
+				self.min_savings.into_inner(),
+				"Savings fell short of the minimum for",
+				("image", "images"),
+				"consider lowering --min-savings",
+			),
+			(
+				self.file_size.into_inner(),
+				"Too big to process:",
+				("image", "images"),
+				"consider raising --max-filesize",
+			),
+			(
+				self.resolution.into_inner(),
+				"Too many pixels to process:",
+				("image", "images"),
+				"consider raising --max-resolution",
+			),
+			(
+				self.memory.into_inner(),
+				"Too much memory needed to process:",
+				("image", "images"),
+				"consider raising --max-memory",
+			),
+		] {
+			if count != 0 {
+				Msg::review(format!(
+					"{problem} {} {}; {advice}.",
+					count.nice_inflect(one, many),
+					percent(count, total),
+				)).eprint();
+			}
+		}
+	}
+}
+
+
+
+/// # Percent.
+///
+/// Format `count` as a (rounded-down) percentage of `total`.
+fn percent(count: u64, total: u64) -> String {
+	let pct = count.saturating_mul(100).checked_div(total).unwrap_or(100).min(100);
+	format!("\x1b[2m({pct}%)\x1b[0m")
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_percent() {
+		for (count, total, expected) in [
+			(0, 10, "0%"),
+			(1, 3, "33%"),
+			(37, 100, "37%"),
+			(5, 5, "100%"),
+			(1, 0, "100%"),
+		] {
+			assert!(
+				percent(count, total).contains(expected),
+				"Wrong percent for {count}/{total}.",
+			);
+		}
+	}
+}
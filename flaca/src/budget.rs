@@ -0,0 +1,149 @@
+/*!
+# Flaca: Memory Budget
+*/
+
+use std::sync::{
+	atomic::{
+		AtomicU64,
+		Ordering::Relaxed,
+	},
+	Condvar,
+	Mutex,
+};
+
+
+
+/// # Global Budget.
+///
+/// This is shared by all of the workers, and set by `--memory-budget`.
+pub(crate) static BUDGET: MemoryBudget = MemoryBudget::new();
+
+
+
+#[derive(Debug)]
+/// # Memory Budget.
+///
+/// This is a semaphore of sorts, capping the total (estimated) memory claimed
+/// by the images being crunched at any one time. Workers reserve their share
+/// before reading, waiting for headroom if need be, and give it back when
+/// they're done.
+///
+/// Images bigger than the entire budget are allowed through on their own,
+/// once everything else has finished, rather than never at all.
+pub(crate) struct MemoryBudget {
+	/// # Limit (Bytes).
+	///
+	/// Zero means unlimited.
+	limit: AtomicU64,
+
+	/// # In Use (Bytes).
+	used: Mutex<u64>,
+
+	/// # Wakeup.
+	freed: Condvar,
+}
+
+impl MemoryBudget {
+	/// # New (Unlimited).
+	pub(crate) const fn new() -> Self {
+		Self {
+			limit: AtomicU64::new(0),
+			used: Mutex::new(0),
+			freed: Condvar::new(),
+		}
+	}
+
+	/// # Enabled?
+	///
+	/// Returns `true` if there's a limit.
+	pub(crate) fn enabled(&self) -> bool { self.limit.load(Relaxed) != 0 }
+
+	/// # Set Limit.
+	pub(crate) fn set_limit(&self, bytes: u64) { self.limit.store(bytes, Relaxed); }
+
+	/// # Reserve.
+	///
+	/// Claim `bytes` from the budget, blocking until there's room, and
+	/// return a guard that gives it back on drop. If there's no limit,
+	/// `None` is returned immediately.
+	pub(crate) fn reserve(&self, bytes: u64) -> Option<Reservation<'_>> {
+		let limit = self.limit.load(Relaxed);
+		if limit == 0 { return None; }
+
+		// Oversized requests take the whole thing.
+		let bytes = bytes.min(limit);
+		let mut used = self.used.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+		while limit - bytes < *used {
+			used = self.freed.wait(used).unwrap_or_else(std::sync::PoisonError::into_inner);
+		}
+		*used += bytes;
+		drop(used);
+
+		Some(Reservation { budget: self, bytes })
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # Reservation.
+///
+/// The claimed share of a `MemoryBudget`, released on drop.
+pub(crate) struct Reservation<'a> {
+	/// # Budget.
+	budget: &'a MemoryBudget,
+
+	/// # Amount.
+	bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+	fn drop(&mut self) {
+		let mut used = self.budget.used.lock()
+			.unwrap_or_else(std::sync::PoisonError::into_inner);
+		*used = used.saturating_sub(self.bytes);
+		drop(used);
+		self.budget.freed.notify_all();
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{
+		sync::atomic::AtomicBool,
+		time::Duration,
+	};
+
+	#[test]
+	fn t_budget() {
+		// No limit, no reservation.
+		let budget = MemoryBudget::new();
+		assert!(budget.reserve(1_000).is_none());
+
+		budget.set_limit(100);
+		let a = budget.reserve(60).expect("Missing reservation.");
+		let b = budget.reserve(40).expect("Missing reservation.");
+
+		// Anything more has to wait until there's room.
+		let done = AtomicBool::new(false);
+		std::thread::scope(|s| {
+			s.spawn(|| {
+				let c = budget.reserve(500).expect("Missing reservation.");
+				assert_eq!(c.bytes, 100, "Oversized reservations should be capped.");
+				done.store(true, Relaxed);
+			});
+
+			std::thread::sleep(Duration::from_millis(50));
+			assert!(! done.load(Relaxed), "The reservation didn't wait.");
+			drop(a);
+			std::thread::sleep(Duration::from_millis(50));
+			assert!(! done.load(Relaxed), "The reservation didn't wait.");
+			drop(b);
+		});
+		assert!(done.load(Relaxed));
+		assert_eq!(*budget.used.lock().expect("Poisoned."), 0);
+	}
+}
@@ -0,0 +1,100 @@
+/*!
+# Flaca: Daemon Base64
+
+Inline image data is passed to and from the daemon as standard (padded)
+base64.
+*/
+
+/// # Alphabet.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+
+
+/// # Encode.
+pub(super) fn encode(src: &[u8]) -> String {
+	let mut out = String::with_capacity(src.len().div_ceil(3) * 4);
+	for chunk in src.chunks(3) {
+		let b = [
+			chunk[0],
+			chunk.get(1).copied().unwrap_or(0),
+			chunk.get(2).copied().unwrap_or(0),
+		];
+		let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+
+		out.push(char::from(ALPHABET[(n >> 18) as usize & 63]));
+		out.push(char::from(ALPHABET[(n >> 12) as usize & 63]));
+		if 1 < chunk.len() { out.push(char::from(ALPHABET[(n >> 6) as usize & 63])); }
+		else { out.push('='); }
+		if 2 < chunk.len() { out.push(char::from(ALPHABET[n as usize & 63])); }
+		else { out.push('='); }
+	}
+	out
+}
+
+/// # Decode.
+///
+/// Decode standard base64, with or without trailing padding. `None` is
+/// returned if the source contains anything else.
+pub(super) fn decode(src: &str) -> Option<Vec<u8>> {
+	let src = src.trim_end_matches('=').as_bytes();
+	if src.len() % 4 == 1 { return None; }
+
+	let mut out = Vec::with_capacity(src.len() * 3 / 4);
+	for chunk in src.chunks(4) {
+		let mut n = 0_u32;
+		for (i, b) in chunk.iter().enumerate() {
+			n |= u32::from(sextet(*b)?) << (18 - 6 * i);
+		}
+
+		let [_, a, b, c] = n.to_be_bytes();
+		out.push(a);
+		if 2 < chunk.len() { out.push(b); }
+		if 3 < chunk.len() { out.push(c); }
+	}
+	Some(out)
+}
+
+/// # Decode Character.
+const fn sextet(b: u8) -> Option<u8> {
+	match b {
+		b'A'..=b'Z' => Some(b - b'A'),
+		b'a'..=b'z' => Some(b - b'a' + 26),
+		b'0'..=b'9' => Some(b - b'0' + 52),
+		b'+' => Some(62),
+		b'/' => Some(63),
+		_ => None,
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_base64() {
+		for (raw, enc) in [
+			("", ""),
+			("f", "Zg=="),
+			("fo", "Zm8="),
+			("foo", "Zm9v"),
+			("foob", "Zm9vYg=="),
+			("fooba", "Zm9vYmE="),
+			("foobar", "Zm9vYmFy"),
+		] {
+			assert_eq!(encode(raw.as_bytes()), enc);
+			assert_eq!(decode(enc).as_deref(), Some(raw.as_bytes()));
+			assert_eq!(decode(enc.trim_end_matches('=')).as_deref(), Some(raw.as_bytes()));
+		}
+
+		// Binary should survive too.
+		let raw: Vec<u8> = (0..=255).collect();
+		assert_eq!(decode(&encode(&raw)), Some(raw));
+
+		// Bad stuff.
+		assert!(decode("Zm9v!").is_none());
+		assert!(decode("Z").is_none());
+		assert!(decode("Zm 9v").is_none());
+	}
+}
@@ -0,0 +1,416 @@
+/*!
+# Flaca: HTTP Mode
+
+This is a deliberately tiny HTTP/1.1 server exposing a single endpoint —
+`POST /optimize` — that crunches the image in the request body and returns
+the result, smaller or not, along with headers describing the savings.
+
+Requests are handed off to the same fixed worker pool used by daemon mode,
+so no matter how many clients connect at once, only so many images are
+crunched at a time. Requests beyond that are turned away (with a `503`)
+before their bodies are read. Each connection serves a single request.
+*/
+
+use crate::{
+	CancellationToken,
+	EncodingError,
+	ExitStatus,
+	FlacaError,
+	ImageKind,
+};
+use crossbeam_channel::Sender;
+use fyi_msg::Msg;
+use std::{
+	io::{
+		BufRead,
+		BufReader,
+		Read,
+		Write,
+	},
+	net::{
+		TcpListener,
+		TcpStream,
+	},
+	num::NonZeroUsize,
+	time::Duration,
+};
+use super::{
+	Job,
+	Outcome,
+	Slot,
+	Source,
+};
+
+
+
+/// # Maximum Header Size.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// # Maximum Body Size.
+const MAX_BODY: usize = 256 * 1024 * 1024;
+
+/// # Connection Timeout.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// # Body Chunk Size.
+///
+/// Bodies are read in pieces this size so memory is only committed as the
+/// data actually arrives.
+const CHUNK: usize = 64 * 1024;
+
+
+
+/// # Serve HTTP.
+///
+/// Listen on `addr` until cancelled, crunching request images with up to
+/// `threads` workers.
+///
+/// ## Errors
+///
+/// An error is returned if the address is invalid or cannot be bound.
+pub(crate) fn serve(
+	addr: &str,
+	threads: NonZeroUsize,
+	kinds: ImageKind,
+	killed: &CancellationToken,
+) -> Result<ExitStatus, FlacaError> {
+	let listener = TcpListener::bind(addr.trim()).map_err(|_| FlacaError::Listen)?;
+	listener.set_nonblocking(true).map_err(|_| FlacaError::Listen)?;
+	let local = listener.local_addr().map_err(|_| FlacaError::Listen)?;
+	Msg::info(format!(
+		"Listening on http://{local}/optimize with {} worker thread{}…",
+		threads,
+		if threads.get() == 1 { "" } else { "s" },
+	)).eprint();
+
+	super::listen(
+		|| listener.accept().map(|(s, _)| s),
+		threads,
+		kinds,
+		killed,
+		handle,
+	);
+
+	Ok(ExitStatus::Success)
+}
+
+/// # Handle Connection.
+///
+/// Read a single request, crunch its image, and write the response.
+fn handle(stream: TcpStream, jobs: &Sender<Job<()>>) {
+	// Accepted streams inherit the listener's non-blocking state.
+	if
+		stream.set_nonblocking(false).is_err() ||
+		stream.set_read_timeout(Some(TIMEOUT)).is_err() ||
+		stream.set_write_timeout(Some(TIMEOUT)).is_err()
+	{
+		return;
+	}
+	let Ok(mut writer) = stream.try_clone() else { return; };
+
+	// Only read the body if there's a worker free to crunch it.
+	let max = jobs.capacity().unwrap_or(1);
+	let mut reader = BufReader::new(stream);
+	let res = read_head(&mut reader)
+		.and_then(|head| {
+			let _slot = Slot::claim(max).ok_or_else(|| Response::error(503, "The server is busy."))?;
+			read_body(&mut reader, &mut writer, head).map(|raw| crunch(raw, jobs))
+		})
+		.unwrap_or_else(|res| res);
+
+	// Errors at this point aren't actionable.
+	let _res = res.write(&mut writer);
+}
+
+/// # Crunch.
+///
+/// Send the body to the worker pool and build a response from the outcome.
+fn crunch(raw: Vec<u8>, jobs: &Sender<Job<()>>) -> Response {
+	let (tx, rx) = crossbeam_channel::bounded(1);
+	if jobs.send(Job { tag: (), src: Source::Data(raw), tx }).is_err() {
+		return Response::error(503, "The server is shutting down.");
+	}
+
+	match rx.recv() {
+		Ok(((), Outcome::Data(raw, res))) => match res {
+			Ok(new) => Response::image(raw, new),
+			// Images deliberately left alone are sent back as-are.
+			Err(e) if ! e.is_failure() => Response::image(raw, None),
+			Err(EncodingError::Format) => Response::error(415, "The body is not a supported image."),
+			Err(_) => Response::error(500, "The image could not be processed."),
+		},
+		_ => Response::error(500, "The image could not be processed."),
+	}
+}
+
+/// # Content Type.
+fn content_type(src: &[u8]) -> &'static str {
+	match ImageKind::from_bytes(src) {
+		Some(ImageKind::GIF) => "image/gif",
+		Some(ImageKind::ICO) => "image/x-icon",
+		Some(ImageKind::JPEG) => "image/jpeg",
+		Some(ImageKind::PNG) => "image/png",
+		_ => "application/octet-stream",
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Request Head.
+///
+/// The bits of the request head needed to read the body.
+struct Head {
+	/// # Content Length.
+	len: usize,
+
+	/// # Expect 100-Continue?
+	expect: bool,
+}
+
+/// # Read Request Head.
+///
+/// Parse the request head and make sure it is a `POST /optimize` with a
+/// reasonable `Content-Length`, returning the details needed to read the
+/// body, or the error response to send instead.
+fn read_head<R: BufRead>(reader: &mut R) -> Result<Head, Response> {
+	let mut head_len = 0;
+	let mut len = None;
+	let mut chunked = false;
+	let mut expect = false;
+	let mut first = true;
+	loop {
+		// Never read more than the header allowance, even mid-line.
+		let mut line = Vec::new();
+		let room = (MAX_HEAD - head_len) as u64;
+		match reader.take(room).read_until(b'\n', &mut line) {
+			Ok(0) if room == 0 => return Err(Response::error(431, "The request headers are too large.")),
+			Ok(0) | Err(_) => return Err(Response::error(400, "Incomplete request.")),
+			Ok(_) => {},
+		}
+		head_len += line.len();
+		if ! line.ends_with(b"\n") {
+			return Err(
+				if head_len == MAX_HEAD { Response::error(431, "The request headers are too large.") }
+				else { Response::error(400, "Incomplete request.") }
+			);
+		}
+
+		let line = std::str::from_utf8(line.trim_ascii())
+			.map_err(|_| Response::error(400, "Malformed request."))?;
+
+		// The request line.
+		if first {
+			first = false;
+			let mut parts = line.split_ascii_whitespace();
+			let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+			else { return Err(Response::error(400, "Malformed request.")); };
+			if ! version.starts_with("HTTP/1.") {
+				return Err(Response::error(505, "Only HTTP/1.x is supported."));
+			}
+
+			let path = target.split_once('?').map_or(target, |(p, _)| p);
+			if path != "/optimize" { return Err(Response::error(404, "Not found.")); }
+			if method != "POST" { return Err(Response::error(405, "Images must be POSTed.")); }
+		}
+		// The end of the headers.
+		else if line.is_empty() { break; }
+		// A header.
+		else if let Some((k, v)) = line.split_once(':') {
+			let v = v.trim();
+			if k.eq_ignore_ascii_case("content-length") {
+				len.replace(v.parse::<usize>().map_err(|_| Response::error(400, "Invalid Content-Length."))?);
+			}
+			else if k.eq_ignore_ascii_case("transfer-encoding") { chunked = true; }
+			else if k.eq_ignore_ascii_case("expect") {
+				expect = v.eq_ignore_ascii_case("100-continue");
+			}
+		}
+		else { return Err(Response::error(400, "Malformed request.")); }
+	}
+
+	let Some(len) = len.filter(|_| ! chunked) else {
+		return Err(Response::error(411, "A Content-Length is required."));
+	};
+	if len == 0 { return Err(Response::error(400, "The body is empty.")); }
+	if MAX_BODY < len { return Err(Response::error(413, "The body is too large.")); }
+
+	Ok(Head { len, expect })
+}
+
+/// # Read Request Body.
+///
+/// Read the body described by `head`, or return the error response to send
+/// instead.
+fn read_body<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, head: Head)
+-> Result<Vec<u8>, Response> {
+	// Let the client know it's okay to keep going, if it asked.
+	if head.expect {
+		writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+			.and_then(|()| writer.flush())
+			.map_err(|_| Response::error(500, "Unable to continue."))?;
+	}
+
+	let mut body = Vec::with_capacity(usize::min(head.len, CHUNK));
+	match reader.take(head.len as u64).read_to_end(&mut body) {
+		Ok(n) if n == head.len => Ok(body),
+		_ => Err(Response::error(400, "Incomplete body.")),
+	}
+}
+
+
+
+/// # Response.
+struct Response {
+	/// # Status Code.
+	status: u16,
+
+	/// # Content Type.
+	content_type: &'static str,
+
+	/// # Extra Headers.
+	headers: Vec<(&'static str, String)>,
+
+	/// # Body.
+	body: Vec<u8>,
+}
+
+impl Response {
+	/// # Image.
+	///
+	/// Return the optimized image — or the original, if there isn't one —
+	/// along with headers describing the savings.
+	fn image(raw: Vec<u8>, new: Option<Vec<u8>>) -> Self {
+		let before = raw.len();
+		let body = new.unwrap_or(raw);
+		let after = body.len();
+		Self {
+			status: 200,
+			content_type: content_type(&body),
+			headers: vec![
+				("X-Flaca-Before", before.to_string()),
+				("X-Flaca-After", after.to_string()),
+				("X-Flaca-Savings", (before - after).to_string()),
+			],
+			body,
+		}
+	}
+
+	/// # Error.
+	fn error(status: u16, msg: &str) -> Self {
+		let mut headers = Vec::new();
+		if status == 405 { headers.push(("Allow", "POST".to_owned())); }
+
+		let mut body = msg.as_bytes().to_vec();
+		body.push(b'\n');
+		Self {
+			status,
+			content_type: "text/plain; charset=utf-8",
+			headers,
+			body,
+		}
+	}
+
+	/// # Reason Phrase.
+	const fn reason(&self) -> &'static str {
+		match self.status {
+			200 => "OK",
+			400 => "Bad Request",
+			404 => "Not Found",
+			405 => "Method Not Allowed",
+			411 => "Length Required",
+			413 => "Content Too Large",
+			415 => "Unsupported Media Type",
+			431 => "Request Header Fields Too Large",
+			503 => "Service Unavailable",
+			505 => "HTTP Version Not Supported",
+			_ => "Internal Server Error",
+		}
+	}
+
+	/// # Write Response.
+	fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+		let mut head = format!(
+			"HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+			self.status,
+			self.reason(),
+			self.content_type,
+			self.body.len(),
+		);
+		for (k, v) in &self.headers {
+			head.push_str(k);
+			head.push_str(": ");
+			head.push_str(v);
+			head.push_str("\r\n");
+		}
+		head.push_str("\r\n");
+
+		writer.write_all(head.as_bytes())?;
+		writer.write_all(&self.body)?;
+		writer.flush()
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// # Read Head and Body.
+	fn read_request(src: &mut &[u8], sink: &mut Vec<u8>) -> Result<Vec<u8>, Response> {
+		read_head(src).and_then(|head| read_body(src, sink, head))
+	}
+
+	#[test]
+	fn t_read_request() {
+		let mut sink = Vec::new();
+
+		let mut src: &[u8] = b"POST /optimize?x=1 HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc";
+		assert_eq!(read_request(&mut src, &mut sink).ok(), Some(b"abc".to_vec()));
+		assert!(sink.is_empty());
+
+		// Continue, if asked.
+		let mut src: &[u8] = b"POST /optimize HTTP/1.1\r\ncontent-length: 3\r\nExpect: 100-continue\r\n\r\nabc";
+		assert_eq!(read_request(&mut src, &mut sink).ok(), Some(b"abc".to_vec()));
+		assert_eq!(sink, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+		// Bad stuff.
+		for (raw, status) in [
+			(&b"GET /optimize HTTP/1.1\r\n\r\n"[..], 405),
+			(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc", 404),
+			(b"POST /optimize HTTP/1.1\r\n\r\n", 411),
+			(b"POST /optimize HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n", 411),
+			(b"POST /optimize HTTP/1.1\r\nContent-Length: 0\r\n\r\n", 400),
+			(b"POST /optimize HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc", 400),
+			(b"POST /optimize HTTP/1.1\r\nContent-Length: 999999999999\r\n\r\n", 413),
+			(b"POST /optimize HTTP/2\r\n\r\n", 505),
+			(b"POST /optimize", 400),
+		] {
+			let mut src = raw;
+			let res = read_request(&mut src, &mut sink);
+			assert_eq!(
+				res.err().map(|r| r.status),
+				Some(status),
+				"Wrong status for {:?}.", String::from_utf8_lossy(raw),
+			);
+		}
+
+		// Oversized heads are cut off, even without a newline in sight.
+		let mut raw = b"POST /optimize HTTP/1.1\r\nX-Junk: ".to_vec();
+		raw.resize(MAX_HEAD * 2, b'a');
+		let mut src = raw.as_slice();
+		assert_eq!(read_head(&mut src).err().map(|r| r.status), Some(431));
+		assert_eq!(src.len(), MAX_HEAD, "Too much of the head was read.");
+	}
+
+	#[test]
+	fn t_response() {
+		let mut out = Vec::new();
+		Response::error(405, "Nope.").write(&mut out).expect("Write failed.");
+		assert_eq!(
+			String::from_utf8_lossy(&out),
+			"HTTP/1.1 405 Method Not Allowed\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 6\r\nConnection: close\r\nAllow: POST\r\n\r\nNope.\n",
+		);
+	}
+}
@@ -0,0 +1,313 @@
+/*!
+# Flaca: Daemon JSON
+
+Daemon jobs are flat JSON objects, so rather than pull in a whole
+serialization framework, this implements just enough of the spec to read
+them — strings, numbers, booleans, and nulls; no nesting — and escape the
+strings written back out.
+*/
+
+use std::fmt::Write;
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # JSON Value.
+pub(super) enum Value {
+	/// # Null.
+	Null,
+
+	/// # Boolean.
+	Bool(bool),
+
+	/// # Number (Raw).
+	///
+	/// Numbers are kept as-written since they're only ever echoed back.
+	Number(String),
+
+	/// # String (Unescaped).
+	String(String),
+}
+
+impl Value {
+	/// # As Str.
+	///
+	/// Return the string value, if any.
+	pub(super) fn as_str(&self) -> Option<&str> {
+		if let Self::String(s) = self { Some(s) }
+		else { None }
+	}
+
+	/// # Write JSON.
+	///
+	/// Append the value to `out` as (escaped) JSON.
+	pub(super) fn push_json(&self, out: &mut String) {
+		match self {
+			Self::Null => { out.push_str("null"); },
+			Self::Bool(true) => { out.push_str("true"); },
+			Self::Bool(false) => { out.push_str("false"); },
+			Self::Number(s) => { out.push_str(s); },
+			Self::String(s) => { push_str(out, s); },
+		}
+	}
+}
+
+
+
+/// # Parse Object.
+///
+/// Parse a single, flat JSON object into its key/value pairs, or return
+/// `None` if the source is malformed or contains nested arrays/objects.
+pub(super) fn parse_object(src: &str) -> Option<Vec<(String, Value)>> {
+	let mut parser = Parser { src: src.as_bytes(), pos: 0 };
+	let mut out = Vec::new();
+
+	parser.expect(b'{')?;
+	if ! parser.eat(b'}') {
+		loop {
+			let key = parser.string()?;
+			parser.expect(b':')?;
+			let value = parser.value()?;
+			out.push((key, value));
+
+			if parser.eat(b'}') { break; }
+			parser.expect(b',')?;
+		}
+	}
+
+	// There shouldn't be anything else.
+	parser.skip_ws();
+	if parser.pos == parser.src.len() { Some(out) }
+	else { None }
+}
+
+/// # Push (Escaped) String.
+///
+/// Append `src` to `out` as a quoted JSON string.
+pub(crate) fn push_str(out: &mut String, src: &str) {
+	out.push('"');
+	for c in src.chars() {
+		match c {
+			'"' => { out.push_str("\\\""); },
+			'\\' => { out.push_str("\\\\"); },
+			'\n' => { out.push_str("\\n"); },
+			'\r' => { out.push_str("\\r"); },
+			'\t' => { out.push_str("\\t"); },
+			c if c.is_control() => {
+				let _res = write!(out, "\\u{:04x}", u32::from(c));
+			},
+			c => { out.push(c); },
+		}
+	}
+	out.push('"');
+}
+
+
+
+/// # Parser.
+struct Parser<'a> {
+	/// # Source.
+	src: &'a [u8],
+
+	/// # Position.
+	pos: usize,
+}
+
+impl Parser<'_> {
+	/// # Skip Whitespace.
+	fn skip_ws(&mut self) {
+		while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.src.get(self.pos) {
+			self.pos += 1;
+		}
+	}
+
+	/// # Eat Byte.
+	///
+	/// Consume the next non-whitespace byte if it is `b`.
+	fn eat(&mut self, b: u8) -> bool {
+		self.skip_ws();
+		if self.src.get(self.pos) == Some(&b) {
+			self.pos += 1;
+			true
+		}
+		else { false }
+	}
+
+	/// # Expect Byte.
+	fn expect(&mut self, b: u8) -> Option<()> {
+		if self.eat(b) { Some(()) }
+		else { None }
+	}
+
+	/// # Eat Literal.
+	fn literal(&mut self, lit: &[u8]) -> Option<()> {
+		let end = self.pos + lit.len();
+		if self.src.get(self.pos..end) == Some(lit) {
+			self.pos = end;
+			Some(())
+		}
+		else { None }
+	}
+
+	/// # Value.
+	fn value(&mut self) -> Option<Value> {
+		self.skip_ws();
+		match self.src.get(self.pos)? {
+			b'"' => self.string().map(Value::String),
+			b'n' => self.literal(b"null").map(|()| Value::Null),
+			b't' => self.literal(b"true").map(|()| Value::Bool(true)),
+			b'f' => self.literal(b"false").map(|()| Value::Bool(false)),
+			b'-' | b'0'..=b'9' => self.number().map(Value::Number),
+			_ => None,
+		}
+	}
+
+	/// # Number.
+	///
+	/// Numbers are validated, but returned as-written.
+	fn number(&mut self) -> Option<String> {
+		let start = self.pos;
+		if self.src.get(self.pos) == Some(&b'-') { self.pos += 1; }
+
+		// Integer part.
+		match self.src.get(self.pos)? {
+			b'0' => { self.pos += 1; },
+			b'1'..=b'9' => { self.digits(); },
+			_ => return None,
+		}
+
+		// Fraction.
+		if self.src.get(self.pos) == Some(&b'.') {
+			self.pos += 1;
+			if ! self.digits() { return None; }
+		}
+
+		// Exponent.
+		if let Some(b'e' | b'E') = self.src.get(self.pos) {
+			self.pos += 1;
+			if let Some(b'+' | b'-') = self.src.get(self.pos) { self.pos += 1; }
+			if ! self.digits() { return None; }
+		}
+
+		std::str::from_utf8(&self.src[start..self.pos]).ok().map(String::from)
+	}
+
+	/// # Digits.
+	///
+	/// Consume a run of ASCII digits, returning `true` if there were any.
+	fn digits(&mut self) -> bool {
+		let start = self.pos;
+		while self.src.get(self.pos).is_some_and(u8::is_ascii_digit) { self.pos += 1; }
+		start != self.pos
+	}
+
+	/// # String.
+	fn string(&mut self) -> Option<String> {
+		self.expect(b'"')?;
+		let mut out = Vec::new();
+		loop {
+			let b = *self.src.get(self.pos)?;
+			self.pos += 1;
+			match b {
+				b'"' => break,
+				b'\\' => {
+					let b = *self.src.get(self.pos)?;
+					self.pos += 1;
+					match b {
+						b'"' | b'\\' | b'/' => { out.push(b); },
+						b'b' => { out.push(0x08); },
+						b'f' => { out.push(0x0c); },
+						b'n' => { out.push(b'\n'); },
+						b'r' => { out.push(b'\r'); },
+						b't' => { out.push(b'\t'); },
+						b'u' => {
+							let c = self.unicode()?;
+							let mut buf = [0_u8; 4];
+							out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+						},
+						_ => return None,
+					}
+				},
+				0..=0x1f => return None,
+				b => { out.push(b); },
+			}
+		}
+
+		String::from_utf8(out).ok()
+	}
+
+	/// # Unicode Escape.
+	///
+	/// Parse the hex following a `\u`, along with the second half of a
+	/// surrogate pair, if applicable.
+	fn unicode(&mut self) -> Option<char> {
+		let hi = self.hex4()?;
+		if (0xD800..0xDC00).contains(&hi) {
+			self.literal(b"\\u")?;
+			let lo = self.hex4()?;
+			if ! (0xDC00..0xE000).contains(&lo) { return None; }
+			char::from_u32(0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00))
+		}
+		else { char::from_u32(hi) }
+	}
+
+	/// # Four Hex Digits.
+	fn hex4(&mut self) -> Option<u32> {
+		let end = self.pos + 4;
+		let raw = std::str::from_utf8(self.src.get(self.pos..end)?).ok()?;
+		if ! raw.bytes().all(|b| b.is_ascii_hexdigit()) { return None; }
+		self.pos = end;
+		u32::from_str_radix(raw, 16).ok()
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_parse_object() {
+		assert_eq!(parse_object("{}"), Some(Vec::new()));
+		assert_eq!(
+			parse_object(r#" { "id": 5, "path" : "/tmp/a\"b.png", "x": null, "y": false } "#),
+			Some(vec![
+				("id".to_owned(), Value::Number("5".to_owned())),
+				("path".to_owned(), Value::String("/tmp/a\"b.png".to_owned())),
+				("x".to_owned(), Value::Null),
+				("y".to_owned(), Value::Bool(false)),
+			]),
+		);
+		assert_eq!(
+			parse_object(r#"{"a":"é😀\n","b":-1.5e3}"#),
+			Some(vec![
+				("a".to_owned(), Value::String("é😀\n".to_owned())),
+				("b".to_owned(), Value::Number("-1.5e3".to_owned())),
+			]),
+		);
+
+		// Bad stuff.
+		for bad in [
+			"", "[]", "{", r#"{"a"}"#, r#"{"a":1,}"#, r#"{"a":[1]}"#,
+			r#"{"a":{}}"#, r#"{"a":01}"#, r#"{"a":"\x"}"#, r#"{"a":"\ud83d"}"#,
+			r#"{"a":1} x"#, "{\"a\":\"\n\"}",
+		] {
+			assert!(parse_object(bad).is_none(), "Parsed: {bad:?}");
+		}
+	}
+
+	#[test]
+	fn t_push_str() {
+		let mut out = String::new();
+		push_str(&mut out, "a\"b\\c\nd\u{1}é");
+		assert_eq!(out, r#""a\"b\\c\nd\u0001é""#);
+
+		// It should survive the round trip.
+		let src = format!("{{\"k\":{out}}}");
+		assert_eq!(
+			parse_object(&src),
+			Some(vec![("k".to_owned(), Value::String("a\"b\\c\nd\u{1}é".to_owned()))]),
+		);
+	}
+}
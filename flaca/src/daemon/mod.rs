@@ -0,0 +1,637 @@
+/*!
+# Flaca: Daemon Mode
+
+This listens on a Unix socket for newline-delimited JSON jobs, crunching them
+on a fixed pool of worker threads — so the per-thread encoder state stays
+warm between jobs — and streaming the results back over the same connection
+as they finish.
+
+Each job is a flat JSON object with an optional `id` — echoed back verbatim
+so clients can match up the replies, which arrive in completion order — and
+either a `path` to crunch in place, or base64-encoded `data` to crunch in
+memory:
+
+```text
+{"id": 1, "path": "/var/www/image.jpg"}
+{"id": "two", "data": "iVBORw0KGgo…"}
+```
+
+Each reply is a flat JSON object too, with a `status` of `ok`, `skipped`, or
+`error`, and either the `before`/`after` sizes or an `error` message. Inline
+replies include the optimized `data` only if it is actually smaller.
+
+Short jobs — paths, tiny images — are always welcome, but only as many
+connections as there are workers can be reading a longer line at any given
+time; jobs beyond that are turned away as busy, keeping the total memory in
+check no matter how many clients connect.
+*/
+
+mod base64;
+#[cfg(feature = "serve")]
+mod http;
+pub(crate) mod json;
+
+#[cfg(feature = "serve")]
+pub(crate) use http::serve;
+
+use crate::{
+	CancellationToken,
+	EncodingError,
+	ExitStatus,
+	FlacaError,
+	ImageKind,
+	MAX_FILESIZE,
+};
+use crossbeam_channel::{
+	Receiver,
+	Sender,
+};
+use fyi_msg::Msg;
+use json::Value;
+use std::{
+	fs::Permissions,
+	io::{
+		BufRead,
+		BufReader,
+		ErrorKind,
+		Read,
+		Write,
+	},
+	num::NonZeroUsize,
+	os::unix::{
+		fs::PermissionsExt,
+		net::{
+			UnixListener,
+			UnixStream,
+		},
+	},
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::atomic::{
+		AtomicUsize,
+		Ordering::{
+			AcqRel,
+			Acquire,
+			Relaxed,
+		},
+	},
+	thread,
+	time::Duration,
+};
+
+
+
+/// # Poll Interval.
+///
+/// How often idle accepts and reads wake up to check for cancellation.
+const POLL: Duration = Duration::from_millis(200);
+
+/// # Default Maximum Image Size.
+///
+/// Inline images are capped at this size unless `--max-filesize` says
+/// otherwise.
+const MAX_DATA: usize = 256 * 1024 * 1024;
+
+/// # JSON Overhead.
+///
+/// Job lines are allowed this much on top of the (base64) image data for
+/// the ID, keys, and whitespace.
+const JSON_OVERHEAD: usize = 64 * 1024;
+
+/// # Maximum Short Line.
+///
+/// Job lines up to this size — plenty for a path — can be read without
+/// claiming a [`Slot`].
+const MAX_SHORT: usize = 64 * 1024;
+
+/// # Maximum Connections.
+///
+/// Each connection gets its own thread; past this many, new ones are left
+/// waiting in the backlog until some of the others hang up.
+const MAX_CONNECTIONS: usize = 256;
+
+/// # Jobs In Flight.
+///
+/// The number of long job lines (or HTTP requests) currently being read or
+/// crunched.
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+
+
+/// # Job.
+///
+/// The tag is passed back with the outcome so the caller can tell which job
+/// it belongs to.
+struct Job<T> {
+	/// # Tag.
+	tag: T,
+
+	/// # Source.
+	src: Source,
+
+	/// # Reply Channel.
+	tx: Sender<(T, Outcome)>,
+}
+
+impl<T> Job<T> {
+	/// # Crunch!
+	///
+	/// Process the job and send the outcome back to the caller.
+	fn crunch(self, kinds: ImageKind) {
+		let out = match self.src {
+			Source::Path(path) => {
+				let res = crate::image::encode(&path, kinds, &());
+				Outcome::Path(path, res)
+			},
+			Source::Data(raw) => {
+				let res = crate::image::crunch(&raw, kinds);
+				Outcome::Data(raw, res)
+			},
+		};
+
+		// The client may have hung up; that's fine.
+		let _res = self.tx.send((self.tag, out));
+	}
+}
+
+
+
+/// # In-Flight Slot.
+///
+/// A claim on one of the worker slots, released when dropped.
+struct Slot;
+
+impl Drop for Slot {
+	fn drop(&mut self) { IN_FLIGHT.fetch_sub(1, AcqRel); }
+}
+
+impl Slot {
+	/// # Claim.
+	///
+	/// Claim a slot, unless `max` are already in flight.
+	fn claim(max: usize) -> Option<Self> {
+		IN_FLIGHT.fetch_update(AcqRel, Acquire, |n| (n < max).then_some(n + 1))
+			.ok()
+			.map(|_| Self)
+	}
+}
+
+
+
+/// # Job Source.
+enum Source {
+	/// # File Path.
+	Path(PathBuf),
+
+	/// # Inline Data.
+	Data(Vec<u8>),
+}
+
+
+
+/// # Job Outcome.
+enum Outcome {
+	/// # File Path.
+	///
+	/// This holds the path and the before/after sizes.
+	Path(PathBuf, Result<(u64, u64), EncodingError>),
+
+	/// # Inline Data.
+	///
+	/// This holds the original data and the optimized version, if smaller.
+	Data(Vec<u8>, Result<Option<Vec<u8>>, EncodingError>),
+
+	/// # Invalid Job.
+	Invalid(&'static str),
+}
+
+
+
+/// # Reply Builder.
+struct Reply(String);
+
+impl Reply {
+	/// # From Outcome.
+	///
+	/// Build the (JSON) reply for a finished job.
+	fn build(id: Option<&Value>, outcome: &Outcome) -> String {
+		let mut out = Self::new(id);
+		match outcome {
+			Outcome::Path(path, res) => {
+				out.path(path);
+				match res {
+					Ok((before, after)) => { out.ok(*before, *after, None); },
+					Err(e) => { out.encoding_error(*e); },
+				}
+			},
+			Outcome::Data(raw, Ok(new)) => {
+				let before = raw.len() as u64;
+				let after = new.as_ref().map_or(before, |n| n.len() as u64);
+				out.ok(before, after, new.as_deref());
+			},
+			Outcome::Data(_, Err(e)) => { out.encoding_error(*e); },
+			Outcome::Invalid(msg) => { out.error(msg); },
+		}
+		out.finish()
+	}
+
+	/// # New.
+	fn new(id: Option<&Value>) -> Self {
+		let mut out = String::from("{");
+		if let Some(id) = id {
+			out.push_str("\"id\":");
+			id.push_json(&mut out);
+			out.push(',');
+		}
+		Self(out)
+	}
+
+	/// # Path.
+	fn path(&mut self, path: &Path) {
+		self.0.push_str("\"path\":");
+		json::push_str(&mut self.0, &path.to_string_lossy());
+		self.0.push(',');
+	}
+
+	/// # Success.
+	fn ok(&mut self, before: u64, after: u64, data: Option<&[u8]>) {
+		self.0.push_str("\"status\":\"ok\",\"before\":");
+		self.0.push_str(&before.to_string());
+		self.0.push_str(",\"after\":");
+		self.0.push_str(&after.to_string());
+		if let Some(data) = data {
+			self.0.push_str(",\"data\":\"");
+			self.0.push_str(&base64::encode(data));
+			self.0.push('"');
+		}
+	}
+
+	/// # Encoding Error.
+	///
+	/// Images deliberately left alone are "skipped" rather than "error".
+	fn encoding_error(&mut self, err: EncodingError) {
+		if err.is_failure() { self.error(err.as_str()); }
+		else {
+			self.0.push_str("\"status\":\"skipped\",\"error\":");
+			json::push_str(&mut self.0, if err.as_str().is_empty() { "skipped" } else { err.as_str() });
+		}
+	}
+
+	/// # Error.
+	fn error(&mut self, msg: &str) {
+		self.0.push_str("\"status\":\"error\",\"error\":");
+		json::push_str(&mut self.0, msg);
+	}
+
+	/// # Finish.
+	///
+	/// Close the object and return it as a line.
+	fn finish(mut self) -> String {
+		self.0.push_str("}\n");
+		self.0
+	}
+}
+
+
+
+/// # Run Daemon.
+///
+/// Listen on `socket` until cancelled, crunching jobs with up to `threads`
+/// workers. The socket file is removed on the way out.
+///
+/// ## Errors
+///
+/// An error is returned if the socket cannot be bound, e.g. because another
+/// instance is already listening there.
+pub(crate) fn run(
+	socket: &Path,
+	threads: NonZeroUsize,
+	kinds: ImageKind,
+	killed: &CancellationToken,
+) -> Result<ExitStatus, FlacaError> {
+	let listener = bind(socket)?;
+	Msg::info(format!(
+		"Listening on {} with {} worker thread{}…",
+		socket.display(),
+		threads,
+		if threads.get() == 1 { "" } else { "s" },
+	)).eprint();
+
+	listen(
+		|| listener.accept().map(|(s, _)| s),
+		threads,
+		kinds,
+		killed,
+		|stream, jobs| session(stream, jobs, killed),
+	);
+
+	let _res = std::fs::remove_file(socket);
+	Ok(ExitStatus::Success)
+}
+
+/// # Accept Loop.
+///
+/// Spin up the worker pool, then hand each accepted connection — up to
+/// `MAX_CONNECTIONS` at a time — off to its own thread, until cancelled. This
+/// returns once the connections and workers have all finished up.
+fn listen<S, T, A, H>(
+	mut accept: A,
+	threads: NonZeroUsize,
+	kinds: ImageKind,
+	killed: &CancellationToken,
+	handle: H,
+)
+where
+	S: Send,
+	T: Send,
+	A: FnMut() -> std::io::Result<S>,
+	H: Fn(S, &Sender<Job<T>>) + Sync,
+{
+	let (tx, rx) = crossbeam_channel::bounded::<Job<T>>(threads.get());
+	let open = AtomicUsize::new(0);
+	thread::scope(|s| {
+		for _ in 0..threads.get() { s.spawn(|| work(&rx, kinds)); }
+
+		while ! killed.is_cancelled() {
+			// Hold off while we're full up.
+			if MAX_CONNECTIONS <= open.load(Acquire) {
+				thread::sleep(POLL);
+				continue;
+			}
+
+			match accept() {
+				Ok(stream) => {
+					open.fetch_add(1, AcqRel);
+					let tx = tx.clone();
+					let (handle, open) = (&handle, &open);
+					s.spawn(move || {
+						handle(stream, &tx);
+						open.fetch_sub(1, AcqRel);
+					});
+				},
+				// Nothing to accept, or something like EMFILE; either way,
+				// give it a moment.
+				Err(_) => { thread::sleep(POLL); },
+			}
+		}
+
+		// Hang up so the workers know to stop once the queue is drained.
+		drop(tx);
+	});
+}
+
+/// # Bind Socket.
+///
+/// Bind a non-blocking listener to `socket`, replacing the file if it was
+/// left behind by an instance that is no longer running.
+///
+/// Jobs run with the daemon's privileges, so the socket is restricted to its
+/// owner. To keep anybody from sneaking in before that takes effect, it is
+/// bound to a temporary path first, and only moved into place afterward.
+fn bind(socket: &Path) -> Result<UnixListener, FlacaError> {
+	// If somebody answers, it isn't stale!
+	if UnixStream::connect(socket).is_ok() { return Err(FlacaError::DaemonSocket); }
+
+	let mut tmp = socket.as_os_str().to_owned();
+	tmp.push(format!(".{}.tmp", std::process::id()));
+	let tmp = PathBuf::from(tmp);
+	let _res = std::fs::remove_file(&tmp);
+
+	let listener = UnixListener::bind(&tmp).map_err(|_| FlacaError::DaemonSocket)?;
+	if
+		std::fs::set_permissions(&tmp, Permissions::from_mode(0o600))
+			.and_then(|()| std::fs::rename(&tmp, socket))
+			.is_err()
+	{
+		let _res = std::fs::remove_file(&tmp);
+		return Err(FlacaError::DaemonSocket);
+	}
+
+	listener.set_nonblocking(true).map_err(|_| FlacaError::DaemonSocket)?;
+	Ok(listener)
+}
+
+/// # Worker.
+///
+/// Crunch jobs until the queue closes, sending the outcomes back to their
+/// connections.
+fn work<T>(rx: &Receiver<Job<T>>, kinds: ImageKind) {
+	while let Ok(job) = rx.recv() { job.crunch(kinds); }
+}
+
+/// # Connection Session.
+///
+/// Read jobs from the stream until the client hangs up or the daemon is
+/// cancelled, queueing them for the workers, while a second thread writes
+/// the replies back as they come in.
+fn session(stream: UnixStream, jobs: &Sender<Job<Option<Value>>>, killed: &CancellationToken) {
+	// Accepted streams inherit the listener's non-blocking state; reads
+	// should block, but not forever.
+	if
+		stream.set_nonblocking(false).is_err() ||
+		stream.set_read_timeout(Some(POLL)).is_err()
+	{
+		return;
+	}
+	let Ok(writer) = stream.try_clone() else { return; };
+
+	let (tx, rx) = crossbeam_channel::unbounded::<(Option<Value>, Outcome)>();
+	thread::scope(|s| {
+		s.spawn(move || reply(writer, &rx));
+
+		let max = max_line();
+		let slots = jobs.capacity().unwrap_or(1);
+		let mut slot = None;
+		let mut reader = BufReader::new(stream);
+		let mut line = Vec::new();
+		let mut overflow = false;
+		while ! killed.is_cancelled() {
+			// Partial reads are kept between timeouts, so only read up to
+			// whatever room is left. Long lines need a slot.
+			let cap = if slot.is_some() { max } else { usize::min(max, MAX_SHORT) };
+			let room = (cap - line.len()) as u64;
+			match (&mut reader).take(room).read_until(b'\n', &mut line) {
+				Ok(0) => break,
+				// A complete line, or the last one.
+				Ok(_) if line.ends_with(b"\n") || line.len() < cap => {
+					// The tail end of an overlong line isn't worth parsing.
+					if overflow { overflow = false; }
+					else { queue(&line, jobs, &tx); }
+					line.clear();
+					slot = None;
+				},
+				// Out of room.
+				Ok(_) => {
+					// Long lines are fine if there's a slot free.
+					if ! overflow && cap < max {
+						slot = Slot::claim(slots);
+						if slot.is_some() { continue; }
+					}
+
+					// Otherwise complain (once) and drop it on the floor.
+					if ! overflow {
+						overflow = true;
+						let msg = if cap < max { "busy" } else { "job too large" };
+						let _res = tx.send((None, Outcome::Invalid(msg)));
+					}
+					line.clear();
+					slot = None;
+				},
+				Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {},
+				Err(_) => break,
+			}
+		}
+
+		// The writer will finish once the workers are done with our jobs.
+		drop(tx);
+	});
+}
+
+/// # Maximum Line Length.
+///
+/// Return the longest job line worth reading: enough for the largest
+/// acceptable image — base64-encoded — plus some JSON overhead.
+fn max_line() -> usize {
+	let max = match MAX_FILESIZE.load(Relaxed) {
+		0 => MAX_DATA,
+		n => usize::try_from(n).unwrap_or(usize::MAX),
+	};
+	max.div_ceil(3).saturating_mul(4).saturating_add(JSON_OVERHEAD)
+}
+
+/// # Queue Job.
+///
+/// Parse a line and hand it off to the workers, or reply straight away if
+/// it is invalid.
+fn queue(
+	line: &[u8],
+	jobs: &Sender<Job<Option<Value>>>,
+	tx: &Sender<(Option<Value>, Outcome)>,
+) {
+	let line = line.trim_ascii();
+	if line.is_empty() { return; }
+
+	match parse(line) {
+		(tag, Ok(src)) => {
+			if let Err(e) = jobs.send(Job { tag, src, tx: tx.clone() }) {
+				let _res = tx.send((e.0.tag, Outcome::Invalid("shutting down")));
+			}
+		},
+		(tag, Err(msg)) => { let _res = tx.send((tag, Outcome::Invalid(msg))); },
+	}
+}
+
+/// # Parse Job.
+///
+/// Parse a job line into its ID — if any — and source or error message.
+fn parse(line: &[u8]) -> (Option<Value>, Result<Source, &'static str>) {
+	let Some(pairs) = std::str::from_utf8(line).ok().and_then(json::parse_object) else {
+		return (None, Err("invalid JSON"));
+	};
+
+	let mut id = None;
+	let mut path = None;
+	let mut data = None;
+	for (k, v) in pairs {
+		match k.as_str() {
+			"id" => { id.replace(v); },
+			"path" => { path.replace(v); },
+			"data" => { data.replace(v); },
+			_ => {},
+		}
+	}
+
+	let src = match (path, data) {
+		(Some(path), None) => path.as_str()
+			.filter(|p| ! p.is_empty())
+			.map(|p| Source::Path(PathBuf::from(p))),
+		(None, Some(data)) => data.as_str()
+			.and_then(base64::decode)
+			.filter(|d| ! d.is_empty())
+			.map(Source::Data),
+		_ => None,
+	};
+
+	(id, src.ok_or("expected a path or (base64) data"))
+}
+
+/// # Write Replies.
+///
+/// Write each reply to the stream as it arrives, until the senders hang up
+/// or the client does.
+fn reply(mut writer: UnixStream, rx: &Receiver<(Option<Value>, Outcome)>) {
+	for (id, outcome) in rx {
+		let line = Reply::build(id.as_ref(), &outcome);
+		if writer.write_all(line.as_bytes()).and_then(|()| writer.flush()).is_err() {
+			break;
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_parse() {
+		let (id, Ok(Source::Path(p))) = parse(br#"{"id":1,"path":"/tmp/a.png"}"#) else {
+			panic!("Path job failed to parse.");
+		};
+		assert_eq!(id, Some(Value::Number("1".to_owned())));
+		assert_eq!(p, Path::new("/tmp/a.png"));
+
+		let (id, Ok(Source::Data(d))) = parse(br#"{"data":"Zm9v"}"#) else {
+			panic!("Data job failed to parse.");
+		};
+		assert!(id.is_none());
+		assert_eq!(d, b"foo");
+
+		// The ID should be kept for bad jobs if possible.
+		assert!(matches!(
+			parse(br#"{"id":"x","path":"/a","data":"Zm9v"}"#),
+			(Some(Value::String(_)), Err(_)),
+		));
+		assert!(matches!(parse(br#"{"id":2,"data":"!!"}"#), (Some(_), Err(_))));
+		assert!(matches!(parse(br#"{"id":2,"path":5}"#), (Some(_), Err(_))));
+		assert!(matches!(parse(b"nope"), (None, Err(_))));
+	}
+
+	#[test]
+	fn t_slot() {
+		let a = Slot::claim(2).expect("Slot unavailable.");
+		let b = Slot::claim(2).expect("Slot unavailable.");
+		assert!(Slot::claim(2).is_none(), "Slots exceeded capacity.");
+		drop(a);
+		assert!(Slot::claim(2).is_some(), "Slot not released.");
+		drop(b);
+		assert_eq!(IN_FLIGHT.load(Acquire), 0);
+	}
+
+	#[test]
+	fn t_reply() {
+		let id = Value::String("a\"b".to_owned());
+		let mut out = Reply::new(Some(&id));
+		out.path(Path::new("/tmp/x.png"));
+		out.ok(10, 5, None);
+		assert_eq!(
+			out.finish(),
+			"{\"id\":\"a\\\"b\",\"path\":\"/tmp/x.png\",\"status\":\"ok\",\"before\":10,\"after\":5}\n",
+		);
+
+		let mut out = Reply::new(None);
+		out.ok(3, 2, Some(b"hi"));
+		assert_eq!(out.finish(), "{\"status\":\"ok\",\"before\":3,\"after\":2,\"data\":\"aGk=\"}\n");
+
+		let mut out = Reply::new(None);
+		out.encoding_error(EncodingError::Skipped);
+		assert_eq!(out.finish(), "{\"status\":\"skipped\",\"error\":\"skipped\"}\n");
+
+		let mut out = Reply::new(None);
+		out.encoding_error(EncodingError::Format);
+		assert_eq!(out.finish(), "{\"status\":\"error\",\"error\":\"invalid format\"}\n");
+	}
+}
@@ -0,0 +1,160 @@
+/*!
+# Flaca: Directory Summary
+*/
+
+use crate::Observer;
+use dactyl::traits::NiceInflection;
+use fyi_msg::{
+	BeforeAfter,
+	Msg,
+};
+use std::{
+	collections::BTreeMap,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+#[derive(Debug)]
+/// # Directory Summary.
+///
+/// This observer tallies the before and after sizes for each of the input
+/// directories — or, if there was only one, each of its immediate
+/// subdirectories — so multi-site servers can see where the savings came
+/// from.
+pub(crate) struct DirSummary {
+	/// # Roots (Canonical, Deepest First).
+	roots: Vec<PathBuf>,
+
+	/// # Totals by Directory.
+	totals: Mutex<BTreeMap<PathBuf, Totals>>,
+}
+
+impl Observer for DirSummary {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		let key = self.key(src);
+		if let Ok(mut map) = self.totals.lock() {
+			let totals = map.entry(key).or_default();
+			totals.images += 1;
+			totals.before += before;
+			totals.after += after;
+		}
+	}
+}
+
+impl DirSummary {
+	/// # New.
+	///
+	/// Files are grouped by their parent directories, and duplicates are
+	/// ignored.
+	pub(crate) fn new(roots: &[PathBuf]) -> Self {
+		let mut roots: Vec<PathBuf> = roots.iter()
+			.filter_map(|p| {
+				let p = std::fs::canonicalize(p).ok()?;
+				if p.is_dir() { Some(p) }
+				else { p.parent().map(Path::to_path_buf) }
+			})
+			.collect();
+		roots.sort_unstable();
+		roots.dedup();
+
+		// Check the deepest directories first.
+		roots.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+		Self {
+			roots,
+			totals: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	/// # Key.
+	///
+	/// Return the directory `src` should be grouped under.
+	fn key(&self, src: &Path) -> PathBuf {
+		let Some(root) = self.roots.iter().find(|r| src.starts_with(r)) else {
+			return src.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+		};
+
+		// With only one root, go one level deeper (if the image isn't
+		// directly inside it).
+		if self.roots.len() == 1 {
+			if let Ok(rel) = src.strip_prefix(root) {
+				let mut parts = rel.components();
+				if let (Some(first), Some(_)) = (parts.next(), parts.next()) {
+					return root.join(first);
+				}
+			}
+		}
+
+		root.clone()
+	}
+
+	/// # Summarize.
+	///
+	/// Print the savings for each directory, in order.
+	pub(crate) fn summarize(self) {
+		let Ok(map) = self.totals.into_inner() else { return; };
+		for (path, totals) in map {
+			Msg::crunched(format!(
+				"{} \x1b[2m({})\x1b[0m",
+				path.display(),
+				totals.images.nice_inflect("image", "images"),
+			))
+				.with_bytes_saved(BeforeAfter::from((totals.before, totals.after)))
+				.eprint();
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Default)]
+/// # Directory Totals.
+struct Totals {
+	/// # Number of Images.
+	images: u64,
+
+	/// # Total Size Before.
+	before: u64,
+
+	/// # Total Size After.
+	after: u64,
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_key() {
+		let one = DirSummary {
+			roots: vec![PathBuf::from("/srv/www")],
+			totals: Mutex::default(),
+		};
+		for (src, expected) in [
+			("/srv/www/site-a/img/logo.png", "/srv/www/site-a"),
+			("/srv/www/site-b/hero.jpg", "/srv/www/site-b"),
+			("/srv/www/favicon.png", "/srv/www"),
+			("/elsewhere/image.png", "/elsewhere"),
+		] {
+			assert_eq!(one.key(Path::new(src)), Path::new(expected), "Wrong key for {src}.");
+		}
+
+		let two = DirSummary {
+			roots: vec![PathBuf::from("/srv/www/site-a/img"), PathBuf::from("/srv/www")],
+			totals: Mutex::default(),
+		};
+		for (src, expected) in [
+			("/srv/www/site-a/img/logo.png", "/srv/www/site-a/img"),
+			("/srv/www/site-b/hero.jpg", "/srv/www"),
+		] {
+			assert_eq!(two.key(Path::new(src)), Path::new(expected), "Wrong key for {src}.");
+		}
+	}
+}
@@ -0,0 +1,395 @@
+/*!
+# Flaca: Engine
+
+This runs batches of images across the worker threads, reporting on each
+through an `Observer`, and stops handing out new work once its
+`CancellationToken` is cancelled.
+
+The hooks keep the CLI's extras — progress, reports, signals, etc. — out of
+the batch runner, but they're internal to the binary, not a public API.
+Embedders should use the `flaca-capi` library instead.
+*/
+
+use crate::{
+	EncodingError,
+	ImageKind,
+	Warning,
+};
+use crossbeam_channel::Receiver;
+use std::{
+	num::NonZeroUsize,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::{
+		Arc,
+		atomic::{
+			AtomicBool,
+			Ordering::{
+				Acquire,
+				Relaxed,
+				SeqCst,
+			},
+		},
+	},
+	thread,
+};
+
+
+
+#[derive(Debug, Clone, Default)]
+/// # Cancellation Token.
+///
+/// This is a cheaply-cloneable flag used to request an early stop. Once
+/// cancelled, the batch runner will stop handing out new work, but any
+/// in-progress images will be allowed to finish.
+pub(crate) struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+	/// # Cancel.
+	///
+	/// Flip the switch, returning `true` if this was the first call to do so.
+	pub(crate) fn cancel(&self) -> bool {
+		self.0.compare_exchange(false, true, SeqCst, Relaxed).is_ok()
+	}
+
+	#[inline]
+	/// # Is Cancelled?
+	pub(crate) fn is_cancelled(&self) -> bool { self.0.load(Acquire) }
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Encoding Stage.
+///
+/// This identifies the encoder that just finished its pass.
+pub(crate) enum Stage {
+	/// # GIF Cleanup.
+	Gif,
+
+	/// # ICO/CUR Members.
+	Ico,
+
+	/// # `MozJPEG`.
+	Mozjpeg,
+
+	/// # `Oxipng`.
+	Oxipng,
+
+	/// # `Zopflipng`.
+	Zopflipng,
+}
+
+impl Stage {
+	/// # All Stages.
+	pub(crate) const ALL: [Self; 5] = [
+		Self::Gif,
+		Self::Ico,
+		Self::Mozjpeg,
+		Self::Oxipng,
+		Self::Zopflipng,
+	];
+
+	#[must_use]
+	/// # As Str.
+	pub(crate) const fn as_str(self) -> &'static str {
+		match self {
+			Self::Gif => "GIF",
+			Self::Ico => "ICO",
+			Self::Mozjpeg => "MozJPEG",
+			Self::Oxipng => "Oxipng",
+			Self::Zopflipng => "Zopflipng",
+		}
+	}
+}
+
+/// # Observer.
+///
+/// This trait provides hooks into the lifecycle of each image processed by
+/// the batch runner. All methods are no-ops by default.
+///
+/// Note: images are processed in parallel, so implementations must be
+/// thread-safe.
+pub(crate) trait Observer: Sync {
+	/// # On Start.
+	///
+	/// This is called when a worker picks up a new image.
+	fn on_start(&self, _src: &Path) {}
+
+	/// # On Stage Complete.
+	///
+	/// This is called after each encoder pass with the (possibly unchanged)
+	/// size of the image.
+	fn on_stage_complete(&self, _src: &Path, _stage: Stage, _size: u64) {}
+
+	/// # On Progress.
+	///
+	/// This is called during the zopfli pass — which can take a while for
+	/// huge images — with the number of (million-byte) blocks done so far,
+	/// and the total: once at the start, and again after each block.
+	fn on_progress(&self, _src: &Path, _done: usize, _total: usize) {}
+
+	/// # On Identify.
+	///
+	/// This is called once an image's kind has been determined (and the
+	/// encoders have run), before `Observer::on_warning`. Archives are not
+	/// identified.
+	fn on_identify(&self, _src: &Path, _kind: ImageKind) {}
+
+	/// # On Warning.
+	///
+	/// This is called for each non-fatal issue encountered along the way,
+	/// before `Observer::on_finish`.
+	fn on_warning(&self, _src: &Path, _warning: Warning) {}
+
+	/// # On Finish.
+	///
+	/// This is called when an image has been successfully processed, with its
+	/// before and after sizes. (If no savings were achieved, the two will be
+	/// equal.)
+	fn on_finish(&self, _src: &Path, _before: u64, _after: u64) {}
+
+	/// # On Error.
+	///
+	/// This is called in lieu of `Observer::on_finish` when an image could
+	/// not be processed.
+	fn on_error(&self, _src: &Path, _err: EncodingError) {}
+}
+
+/// # Quiet Observer.
+///
+/// This observer doesn't observe anything.
+impl Observer for () {}
+
+impl<O: Observer> Observer for &O {
+	fn on_start(&self, src: &Path) { (*self).on_start(src); }
+	fn on_stage_complete(&self, src: &Path, stage: Stage, size: u64) {
+		(*self).on_stage_complete(src, stage, size);
+	}
+	fn on_progress(&self, src: &Path, done: usize, total: usize) {
+		(*self).on_progress(src, done, total);
+	}
+	fn on_identify(&self, src: &Path, kind: ImageKind) { (*self).on_identify(src, kind); }
+	fn on_warning(&self, src: &Path, warning: Warning) {
+		(*self).on_warning(src, warning);
+	}
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		(*self).on_finish(src, before, after);
+	}
+	fn on_error(&self, src: &Path, err: EncodingError) { (*self).on_error(src, err); }
+}
+
+/// # Optional Observer.
+impl<O: Observer> Observer for Option<O> {
+	fn on_start(&self, src: &Path) {
+		if let Some(o) = self { o.on_start(src); }
+	}
+	fn on_stage_complete(&self, src: &Path, stage: Stage, size: u64) {
+		if let Some(o) = self { o.on_stage_complete(src, stage, size); }
+	}
+	fn on_progress(&self, src: &Path, done: usize, total: usize) {
+		if let Some(o) = self { o.on_progress(src, done, total); }
+	}
+	fn on_identify(&self, src: &Path, kind: ImageKind) {
+		if let Some(o) = self { o.on_identify(src, kind); }
+	}
+	fn on_warning(&self, src: &Path, warning: Warning) {
+		if let Some(o) = self { o.on_warning(src, warning); }
+	}
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		if let Some(o) = self { o.on_finish(src, before, after); }
+	}
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		if let Some(o) = self { o.on_error(src, err); }
+	}
+}
+
+#[derive(Default)]
+/// # Observer List.
+///
+/// This forwards each call to all of its members, in the order they were
+/// added.
+pub(crate) struct Observers<'a>(Vec<&'a dyn Observer>);
+
+impl Observer for Observers<'_> {
+	fn on_start(&self, src: &Path) {
+		for o in &self.0 { o.on_start(src); }
+	}
+	fn on_stage_complete(&self, src: &Path, stage: Stage, size: u64) {
+		for o in &self.0 { o.on_stage_complete(src, stage, size); }
+	}
+	fn on_progress(&self, src: &Path, done: usize, total: usize) {
+		for o in &self.0 { o.on_progress(src, done, total); }
+	}
+	fn on_identify(&self, src: &Path, kind: ImageKind) {
+		for o in &self.0 { o.on_identify(src, kind); }
+	}
+	fn on_warning(&self, src: &Path, warning: Warning) {
+		for o in &self.0 { o.on_warning(src, warning); }
+	}
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		for o in &self.0 { o.on_finish(src, before, after); }
+	}
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		for o in &self.0 { o.on_error(src, err); }
+	}
+}
+
+impl<'a> Observers<'a> {
+	/// # Push.
+	///
+	/// Add an observer to the end of the list.
+	pub(crate) fn push(&mut self, o: &'a dyn Observer) { self.0.push(o); }
+
+	/// # Push (If Some).
+	///
+	/// Add an optional observer to the end of the list, if it exists.
+	pub(crate) fn push_some<O: Observer>(&mut self, o: Option<&'a O>) {
+		if let Some(o) = o { self.0.push(o); }
+	}
+}
+
+
+
+/// # Run!
+///
+/// Losslessly compress `paths` in parallel using up to `threads` workers,
+/// notifying `observer` along the way.
+///
+/// The paths are handed out largest first, so the slowest images get
+/// started early rather than leaving one core grinding away on its own at
+/// the end of the run.
+///
+/// Cancellation is checked between images; any paths not handed off to a
+/// worker as a result are returned so the caller can let the user know.
+pub(crate) fn run<'a, O: Observer>(
+	paths: &'a [PathBuf],
+	threads: NonZeroUsize,
+	kinds: ImageKind,
+	observer: &O,
+	token: &CancellationToken,
+) -> Vec<&'a Path> {
+	let mut undone: Vec<&Path> = Vec::new(); // Skipped because of CTRL+C or tx fail.
+	let (tx, rx) = crossbeam_channel::bounded::<&Path>(threads.get());
+	thread::scope(#[inline(always)] |s| {
+		// Set up the worker threads.
+		let mut workers = Vec::with_capacity(threads.get());
+		for _ in 0..threads.get() {
+			workers.push(s.spawn(#[inline(always)] || crunch(&rx, kinds, observer)));
+		}
+
+		// Queue up all the image paths!
+		let mut already_dead = false;
+		for path in largest_first(paths) {
+			// Early abort in progress; mark as skipped instead of giving it
+			// to a worker.
+			if token.is_cancelled() {
+				// Skip this path for sure.
+				undone.push(path);
+
+				// But also skip anything still in the queue.
+				if ! already_dead {
+					already_dead = true;
+					undone.extend(rx.try_iter());
+				}
+			}
+			// Add the path to the queue; this shouldn't fail, but if it does
+			// add it to our list so we can let the user know at the end.
+			else if tx.send(path).is_err() { undone.push(path); }
+		}
+
+		// Disconnect and wait for the threads to finish!
+		drop(tx);
+		for worker in workers { let _res = worker.join(); }
+	});
+
+	undone
+}
+
+/// # Largest First.
+///
+/// Return the paths ordered by file size, descending. Ties — and unreadable
+/// files, which count as empty — keep their original relative order.
+fn largest_first(paths: &[PathBuf]) -> Vec<&Path> {
+	let mut out: Vec<(u64, &Path)> = paths.iter()
+		.map(|p| (std::fs::metadata(p).map_or(0, |m| m.len()), p.as_path()))
+		.collect();
+	out.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+	out.into_iter().map(|(_, p)| p).collect()
+}
+
+#[inline(never)]
+/// # Worker Callback.
+///
+/// This is the worker callback. It listens for "new" image paths and crunches
+/// them — keeping the observer in the loop — then quits when the work has
+/// dried up.
+fn crunch<O: Observer>(rx: &Receiver::<&Path>, kinds: ImageKind, observer: &O) {
+	while let Ok(p) = rx.recv() {
+		observer.on_start(p);
+		match crate::image::encode(p, kinds, observer) {
+			Ok((b, a)) => { observer.on_finish(p, b, a); },
+			Err(e) => { observer.on_error(p, e); },
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_largest_first() {
+		let dir = std::env::temp_dir().join(format!("flaca-largest-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).expect("Unable to create directory.");
+
+		let paths: Vec<PathBuf> = [("a.png", 10), ("b.png", 30), ("c.png", 20), ("d.png", 30)]
+			.into_iter()
+			.map(|(name, len)| {
+				let path = dir.join(name);
+				std::fs::write(&path, vec![0_u8; len]).expect("Unable to write file.");
+				path
+			})
+			.chain(std::iter::once(dir.join("missing.png")))
+			.collect();
+
+		assert_eq!(
+			largest_first(&paths),
+			[
+				dir.join("b.png"),
+				dir.join("d.png"),
+				dir.join("c.png"),
+				dir.join("a.png"),
+				dir.join("missing.png"),
+			],
+		);
+
+		let _res = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn t_observers() {
+		/// # Counter.
+		struct Counter(std::sync::atomic::AtomicU64);
+		impl Observer for Counter {
+			fn on_finish(&self, _src: &Path, before: u64, _after: u64) {
+				self.0.fetch_add(before, Relaxed);
+			}
+		}
+
+		let (a, b) = (Counter(0.into()), Counter(0.into()));
+		let mut list = Observers::default();
+		list.push(&a);
+		list.push_some(Some(&b));
+		list.push_some(None::<&Counter>);
+		list.on_finish(Path::new("a.png"), 5, 3);
+		list.on_start(Path::new("b.png"));
+
+		assert_eq!(a.0.load(Relaxed), 5, "First observer missed the call.");
+		assert_eq!(b.0.load(Relaxed), 5, "Second observer missed the call.");
+	}
+}
@@ -0,0 +1,234 @@
+/*!
+# Flaca: Environment Overrides
+
+Every long-form flag and option can also be set via a `FLACA_*` environment
+variable — the long name, upper-cased, with dashes swapped for underscores —
+e.g. `FLACA_THREADS=4` or `FLACA_NO_PNG=1`. (The zopfli iteration count,
+which only has a short form, is `FLACA_ZOPFLI_ITERATIONS`.)
+
+Arguments passed on the command line take precedence; variables are only
+applied for the flags and options not already present. Defaults come last.
+
+Flags accept `1`, `true`, `yes`, or `on` to enable, and `0`, `false`, `no`,
+`off`, or an empty value to leave them be.
+*/
+
+use argyle::{
+	Argue,
+	Argument,
+	KeyWord,
+};
+use crate::FlacaError;
+use std::{
+	collections::{
+		BTreeMap,
+		BTreeSet,
+	},
+	ffi::{
+		OsStr,
+		OsString,
+	},
+};
+
+
+
+/// # Short/Long Aliases.
+///
+/// These are mapped to the same variable, and count as the same key when
+/// checking the command line.
+const ALIASES: [(&str, &str); 9] = [
+	("-0", "--null"),
+	("-h", "--help"),
+	("-j", "--threads"),
+	("-l", "--list"),
+	("-o", "--out-dir"),
+	("-p", "--progress"),
+	("-V", "--version"),
+	("-z", "--zopfli-iterations"),
+	("--no-jpg", "--no-jpeg"),
+];
+
+/// # Backup Key.
+///
+/// This is matched by hand (rather than by argyle), so has to be handled
+/// separately here too.
+const BACKUP: &str = "--backup";
+
+/// # Ignored Keys.
+const IGNORE: [&str; 2] = ["--help", "--version"];
+
+
+
+/// # Arguments.
+///
+/// Return the command-line arguments with any environment overrides — for
+/// flags and options not already present — prepended.
+///
+/// ## Errors
+///
+/// An error is returned if a flag variable has an unrecognized value.
+pub(crate) fn args<I>(keywords: &[KeyWord], cli: I)
+-> Result<Vec<OsString>, FlacaError>
+where I: IntoIterator<Item=OsString> {
+	let cli: Vec<OsString> = cli.into_iter().collect();
+
+	// Note which keys were passed directly.
+	let mut seen = BTreeSet::new();
+	for arg in Argue::from(cli.iter().cloned()).with_keywords(keywords.iter().copied()) {
+		match arg {
+			Argument::Key(k) | Argument::KeyWithValue(k, _) => { seen.insert(canonical(k)); },
+			Argument::Other(s) if s == BACKUP || s.starts_with("--backup=") => {
+				seen.insert(BACKUP);
+			},
+			_ => {},
+		}
+	}
+
+	let mut out = Vec::new();
+	for (long, key) in overrides(keywords) {
+		if seen.contains(long) { continue; }
+		let Some(value) = std::env::var_os(var_name(long)) else { continue; };
+		match key {
+			KeyWord::Key(k) => if flag(&value)? { out.push(OsString::from(k)); },
+			KeyWord::KeyWithValue(k) => {
+				out.push(OsString::from(k));
+				out.push(value);
+			},
+			KeyWord::Command(_) => {},
+		}
+	}
+
+	// Backups can be a flag or an option.
+	if ! seen.contains(BACKUP) {
+		if let Some(value) = std::env::var_os(var_name(BACKUP)) {
+			match flag(&value) {
+				// Attach the (empty) value so the paths that follow aren't
+				// mistaken for one.
+				Ok(true) => { out.push(OsString::from("--backup=")); },
+				Ok(false) => {},
+				Err(_) => {
+					let mut arg = OsString::from("--backup=");
+					arg.push(value);
+					out.push(arg);
+				},
+			}
+		}
+	}
+
+	out.extend(cli);
+	Ok(out)
+}
+
+/// # Variable Names.
+///
+/// Return the names of all of the environment variables that can be used
+/// to override settings.
+pub(crate) fn var_names(keywords: &[KeyWord]) -> Vec<String> {
+	overrides(keywords).into_keys()
+		.chain(std::iter::once(BACKUP))
+		.map(var_name)
+		.collect()
+}
+
+
+
+/// # Canonical Key.
+///
+/// Return the long form of the key, if it has one.
+fn canonical(key: &'static str) -> &'static str {
+	ALIASES.iter().find_map(|&(s, l)| (s == key).then_some(l)).unwrap_or(key)
+}
+
+/// # Parse Flag.
+///
+/// ## Errors
+///
+/// An error is returned if the value is not a recognized boolean.
+fn flag(raw: &OsStr) -> Result<bool, FlacaError> {
+	let raw = raw.to_str().ok_or(FlacaError::EnvFlag)?.trim();
+	if
+		raw.is_empty() ||
+		["0", "false", "no", "off"].iter().any(|v| raw.eq_ignore_ascii_case(v))
+	{
+		Ok(false)
+	}
+	else if ["1", "true", "yes", "on"].iter().any(|v| raw.eq_ignore_ascii_case(v)) {
+		Ok(true)
+	}
+	else { Err(FlacaError::EnvFlag) }
+}
+
+/// # Overridable Keys.
+///
+/// Return the (canonical) long key and keyword for each flag and option that
+/// can be set via the environment.
+fn overrides(keywords: &[KeyWord]) -> BTreeMap<&'static str, KeyWord> {
+	let mut out = BTreeMap::new();
+	for key in keywords {
+		if matches!(key, KeyWord::Command(_)) { continue; }
+		let long = canonical(key.as_str());
+		if long.starts_with("--") && ! IGNORE.contains(&long) {
+			out.entry(long).or_insert(*key);
+		}
+	}
+	out
+}
+
+/// # Variable Name.
+///
+/// Convert a long key like `--no-png` into the corresponding variable name,
+/// `FLACA_NO_PNG`.
+fn var_name(long: &str) -> String {
+	let mut out = String::with_capacity(long.len() + 4);
+	out.push_str("FLACA_");
+	for c in long.trim_start_matches('-').chars() {
+		out.push(if c == '-' { '_' } else { c.to_ascii_uppercase() });
+	}
+	out
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_var_name() {
+		assert_eq!(var_name("--threads"), "FLACA_THREADS");
+		assert_eq!(var_name("--no-png"), "FLACA_NO_PNG");
+		assert_eq!(var_name(canonical("-z")), "FLACA_ZOPFLI_ITERATIONS");
+		assert_eq!(var_name(canonical("--no-jpg")), "FLACA_NO_JPEG");
+	}
+
+	#[test]
+	fn t_flag() {
+		for v in ["1", "true", "YES", " on "] {
+			assert!(matches!(flag(OsStr::new(v)), Ok(true)), "Wrong answer for {v:?}.");
+		}
+		for v in ["", "0", "False", "no", "OFF"] {
+			assert!(matches!(flag(OsStr::new(v)), Ok(false)), "Wrong answer for {v:?}.");
+		}
+		assert!(flag(OsStr::new("maybe")).is_err());
+	}
+
+	#[test]
+	fn t_overrides() {
+		let keywords = [
+			KeyWord::Key("-h"),
+			KeyWord::Key("--help"),
+			KeyWord::Key("--no-jpeg"),
+			KeyWord::Key("--no-jpg"),
+			KeyWord::KeyWithValue("-j"),
+			KeyWord::KeyWithValue("--threads"),
+			KeyWord::KeyWithValue("-z"),
+			KeyWord::Command("ab"),
+		];
+		let found = overrides(&keywords);
+		assert_eq!(
+			found.keys().copied().collect::<Vec<_>>(),
+			["--no-jpeg", "--threads", "--zopfli-iterations"],
+		);
+		assert!(matches!(found.get("--zopfli-iterations"), Some(KeyWord::KeyWithValue("-z"))));
+	}
+}
@@ -0,0 +1,93 @@
+/*!
+# Flaca: Error Log
+*/
+
+use crate::{
+	EncodingError,
+	FlacaError,
+	Observer,
+};
+use std::{
+	fs::File,
+	io::Write,
+	os::unix::ffi::OsStrExt,
+	path::Path,
+	sync::Mutex,
+};
+
+
+
+#[derive(Debug)]
+/// # Error Log.
+///
+/// This observer writes a line to the log file for each image that failed
+/// or was skipped — everything but kind-filtered images — consisting of the
+/// path and reason, separated by a tab.
+///
+/// The file is truncated at the start of the run, so `cut -f1` yields a list
+/// of just the problem images, suitable for retrying with `-l`/`--list`.
+pub(crate) struct ErrorLog(Mutex<File>);
+
+impl Observer for ErrorLog {
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		if matches!(err, EncodingError::Skipped) { return; }
+
+		let raw = src.as_os_str().as_bytes();
+
+		// Line breaks and tabs would corrupt the file.
+		if raw.contains(&b'\n') || raw.contains(&b'\t') { return; }
+
+		let mut line = Vec::with_capacity(raw.len() + 24);
+		line.extend_from_slice(raw);
+		line.push(b'\t');
+		line.extend_from_slice(err.as_str().as_bytes());
+		line.push(b'\n');
+
+		if let Ok(mut file) = self.0.lock() {
+			let _res = file.write_all(&line);
+		}
+	}
+}
+
+impl ErrorLog {
+	/// # New.
+	///
+	/// Create (or truncate) the log file.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be opened.
+	pub(crate) fn new(path: &str) -> Result<Self, FlacaError> {
+		let path = path.trim();
+		if path.is_empty() { return Err(FlacaError::ErrorLog); }
+
+		File::create(path)
+			.map(|file| Self(Mutex::new(file)))
+			.map_err(|_| FlacaError::ErrorLog)
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_error_log() {
+		let path = std::env::temp_dir().join(format!("flaca-errors-{}.tsv", std::process::id()));
+		let path_str = path.to_str().expect("Temp path is not UTF-8.");
+
+		let log = ErrorLog::new(path_str).expect("Unable to open log.");
+		log.on_error(Path::new("/tmp/a.png"), EncodingError::Format);
+		log.on_error(Path::new("/tmp/b.png"), EncodingError::Skipped);
+		log.on_error(Path::new("/tmp/c.jpg"), EncodingError::Read);
+		log.on_error(Path::new("/tmp/d\n.jpg"), EncodingError::Read);
+		drop(log);
+
+		let raw = std::fs::read_to_string(&path).expect("Unable to read log.");
+		assert_eq!(raw, "/tmp/a.png\tinvalid format\n/tmp/c.jpg\tread error\n");
+
+		let _res = std::fs::remove_file(&path);
+	}
+}
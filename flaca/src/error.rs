@@ -31,44 +31,389 @@ const HELP: &str = concat!(r"
 
 USAGE:
     flaca [FLAGS] [OPTIONS] <PATH(S)>...
+    flaca --stdin [--kind <KIND>] [OPTIONS] < IN > OUT
 
 FLAGS:
+        --advise      Print suggestions at the end for settings that might be
+                      worth changing, e.g. if EXIF Orientations or ICC
+                      profiles were stripped, or images were skipped for
+                      being too big.
+        --allow-imperfect
+                      Let --jpeg-crop regions that don't start on an iMCU
+                      boundary, or that run past the edge of the image, be
+                      adjusted to fit rather than failing.
+        --archives    Open ZIP and TAR archives and crunch the images inside
+                      them, rewriting each archive only if it shrinks. (Only
+                      stored — uncompressed — ZIP members can be optimized.)
+        --auto-orient
+                      Losslessly rotate/flip JPEGs to match their EXIF
+                      Orientation before stripping the metadata. (Images
+                      whose dimensions don't allow a perfect transformation
+                      are left as-are.)
+        --backup[=<SUFFIX|DIR>]
+                      Copy each original to a sibling file with this suffix
+                      — or if it contains a slash, into this (mirrored)
+                      directory — before overwriting it. Existing backups
+                      are never replaced. [default: .bak]
+        --clean-temp  Remove the temporary files left behind by an earlier,
+                      interrupted run. (Without this, they're just reported.)
+                      Only files at least an hour old are considered.
+        --daemon      Run in the background, crunching the newline-delimited
+                      JSON jobs — {"path":…} or {"data":"<BASE64>"} — sent
+                      to the --socket, and streaming the results back as
+                      they finish. Stop with CTRL+C/SIGINT.
+        --default     Use the default compression profile. [default]
+        --dry-run     Crunch the images and report the savings, but leave the
+                      originals alone and save nothing (except with
+                      --keep-candidates).
+        --fast        Use a faster compression profile that skips the zopfli
+                      pass and tries fewer oxipng filters.
+        --fast-thumbnails
+                      Compress thumbnails — images in thumbs/ directories
+                      beneath the given paths or with WordPress-style -WxH
+                      suffixes, and their @2x copies — using the faster
+                      --fast profile.
+        --fix-extensions
+                      Rename images whose file extensions don't match their
+                      contents, e.g. a PNG named .jpg. (Without this, they're
+                      just flagged.) Only applies to images saved in place.
+        --follow-symlinks
+                      Follow symlinks passed directly as paths. Links found
+                      while crawling are resolved too, but only images
+                      within the requested paths are processed. [default]
+        --group-variants
+                      Group WordPress-style -WxH variants with their
+                      originals, and print the savings for each family at
+                      the end.
     -h, --help        Print help information and exit.
+        --jpeg-grayscale-auto
+                      Re-encode JPEGs whose chroma planes are completely
+                      empty — scanned documents, mostly — as true
+                      grayscale. Nothing visible changes, but the savings
+                      can be significant.
+        --keep-alpha-data
+                      Preserve the colors hiding under fully transparent PNG
+                      pixels instead of zeroing them out for better
+                      compression. (--verify will compare them too.)
+        --keep-colortype
+                      Re-encode PNGs with their original color types, bit
+                      depths, and palettes, limiting optimization to the
+                      filtering and compression stages. (16-bit PNGs skip the
+                      zopfli pass.)
+        --mark        Tag each image saved in place — or confirmed to have no
+                      savings — with a user.flaca extended attribute
+                      recording the flaca version and a hash of its contents.
+        --max         Use a slower compression profile that runs 500 zopfli
+                      iterations per PNG (unless -z is set).
+        --memory      Sample the resident memory usage after each encoder pass
+                      and print the peak for each at the end.
+        --ndjson      Print a JSON object to STDOUT for each image as soon as
+                      it finishes — its path, status, and before/after sizes
+                      or error — so other tools can follow along in real
+                      time.
+        --nice        Run with the lowest CPU (and IO, on Linux) priority so
+                      as not to compete with more important processes.
+        --no-follow-symlinks
+                      Skip symlinks passed directly as paths, and never
+                      process images outside the requested paths.
+        --no-fix      Reject PNGs with minor damage — bad checksums, mostly —
+                      rather than repairing them.
+        --no-gif      Skip GIF images.
+        --no-ico      Skip ICO/CUR images.
         --no-jpeg     Skip JPEG images.
+        --no-oxipng   Skip the oxipng pass for PNG and ICO/CUR images, leaving
+                      them to zopfli alone.
         --no-png      Skip PNG images.
-    -p, --progress    Show pretty progress while minifying.
+        --no-recurse  Only process the immediate contents of directories
+                      passed as <PATH(S)>, leaving their subdirectories
+                      alone. (This is shorthand for --max-depth 1, and takes
+                      precedence over it.)
+        --no-zopfli   Skip the (slow) zopfli pass for PNG and ICO/CUR images,
+                      leaving them to oxipng alone.
+    -0, --null        Entries in -l/--list files are separated by NUL bytes
+                      rather than line breaks, e.g. from find -print0.
+        --png-exhaustive
+                      Try re-encoding PNGs as every viable color type and bit
+                      depth — palette, grey, grey-alpha, RGB, and RGBA, at 1,
+                      2, 4, and/or 8 bits — keeping whichever compresses
+                      best. This is very slow, but can help with favicons and
+                      sprites.
+        --preserve-perms
+                      Give copies saved via -o/--out-dir and/or
+                      --name-template the same owner, group, and mode as
+                      their originals. (In-place rewrites always keep them.)
+    -p, --progress    Show pretty progress while minifying, and a summary of
+                      the results — overall and by image kind — at the end.
+        --primary-only
+                      Only process one image per WordPress-style -WxH
+                      family: the original, if present, otherwise the
+                      largest variant. (This takes precedence over
+                      --skip-variants.)
+        --respect-gitignore
+                      Skip files and directories excluded by any .gitignore
+                      or .flacaignore files found in the directories being
+                      crawled.
+        --skip-marked Skip images tagged by --mark whose version and contents
+                      still match, without decoding them.
+        --skip-thumbnails
+                      Skip thumbnails entirely. (This takes precedence over
+                      --fast-thumbnails.)
+        --skip-variants
+                      Skip WordPress-style -WxH variants whose originals are
+                      also being processed, as they'll presumably be
+                      regenerated anyway.
+        --sniff       Include files without extensions — e.g. the hashed names
+                      some CMSes use — relying on their headers to tell which
+                      are images. (Anything else is quietly skipped.)
+        --stdin       Read a single image from STDIN and write the optimized —
+                      or unchanged — version to STDOUT instead of processing
+                      files.
+        --summarize-by-dir
+                      Print the savings for each of the input directories —
+                      or, if there's only one, each of its immediate
+                      subdirectories — at the end.
+        --trace-pipeline
+                      Run a single image through the encoders without saving
+                      anything, and print each stage's input/output sizes,
+                      timing, and parameters, along with the reasons any
+                      stages were skipped.
+        --verify      Decode each re-encoded image and compare it against the
+                      original before saving it, then read it back afterward
+                      to make sure it was written correctly. (Slow, but
+                      paranoid.)
     -V, --version     Print version information and exit.
 
 OPTIONS:
-    -j <NUM>          Limit parallelization to this many threads (instead of
+        --confirm-cmd <CMD>
+                      Run this shell command before rewriting each image in
+                      place, with {} replaced by its path and {before} and
+                      {after} by its sizes, keeping the original unless it
+                      exits successfully.
+        --errors <FILE>
+                      Write the path and reason for each image that failed or
+                      was skipped to this file, one tab-separated pair per
+                      line. (Use cut -f1 to build a -l/--list for a retry.)
+    -j, --threads <NUM>
+                      Limit parallelization to this many threads (instead of
                       giving each logical core its own image to work on). If
                       negative, the value will be subtracted from the total
                       number of logical cores.
+        --jpeg-crop <WxH+X+Y>
+                      Losslessly crop JPEGs to this region, e.g. to remove
+                      the letterboxing from a set of same-sized banners.
+                      The top-left corner must fall on an iMCU boundary — a
+                      multiple of 8 or 16 pixels, depending on the chroma
+                      subsampling — unless --allow-imperfect is set.
+        --jpeg-keep <LIST>
+                      Preserve these (comma-separated) JPEG metadata markers
+                      instead of stripping them: exif, icc, and/or xmp. Kept
+                      EXIF data still has its embedded thumbnail removed
+                      unless thumbnail is specified too.
+        --keep-candidates <DIR>
+                      During a --dry-run, save the would-be optimized images
+                      to this directory — mirroring the source tree
+                      structure — for review, e.g. in an image diff tool.
+        --keep-chunks <LIST>
+                      Preserve these (comma-separated) PNG metadata chunks
+                      instead of stripping them: chrm, cicp, exif, gama, icc,
+                      phys, srgb, text, and/or time. Transparency is image
+                      data, not metadata, so is always preserved.
+        --listen <ADDR>
+                      The IP:PORT address to listen on in serve mode.
+                      [default: 127.0.0.1:8080]
     -l, --list <FILE> Read (absolute) image and/or directory paths from this
                       text file — or STDIN if "-" — one entry per line, instead
                       of or in addition to (actually trailing) <PATH(S)>.
+        --kind <KIND> Require the --stdin image to be this kind: gif, ico, jpeg,
+                      or png.
+        --lock <FILE> Hold an exclusive lock on this file for the duration of
+                      the run, exiting with an error if another run already
+                      holds it, so overlapping (e.g. cron) invocations don't
+                      crunch the same trees at once.
+        --max-depth <NUM>
+                      Only descend this many levels into directories passed
+                      as <PATH(S)>, e.g. 1 for their immediate contents,
+                      without enumerating anything deeper.
+        --max-filesize <NUM>
+                      Skip images larger than <NUM> bytes. The number may be
+                      suffixed with k, m, or g for thousands, millions, or
+                      billions, respectively.
+        --max-memory <MB>
+                      Skip images whose decoded pixels — estimated from the
+                      dimensions and channels in their headers — would take
+                      up more than this many megabytes of memory.
         --max-resolution <NUM>
                       Skip images containing more than <NUM> total pixels to
                       avoid potential OOM errors during decompression.
                       [default: ~4.29 billion]
+        --max-size <NUM>
+                      Leave files larger than <NUM> bytes out of the run
+                      entirely, before any decoding. (The number may be
+                      suffixed like --max-filesize.)
+        --min-size <NUM>
+                      Leave files smaller than <NUM> bytes out of the run
+                      entirely, e.g. tracking pixels.
+        --name-template <TPL>
+                      Save the images under this file name instead, e.g.
+                      "{stem}.opt.{ext}". Supported tokens are {stem}, {ext},
+                      {hash}, {width}, and {height}. If no -o/--out-dir is
+                      set, the copies are saved alongside the originals.
+        --memory-budget <MB>
+                      Cap the (estimated) memory claimed by all of the images
+                      being crunched at once to this many megabytes; big
+                      images wait for headroom instead of piling on. (Any
+                      too big for the whole budget are crunched on their
+                      own.)
+        --metrics-file <FILE>
+                      Write the run's totals — images processed by outcome,
+                      bytes before, after, and saved, and durations — to this
+                      file in the Prometheus text format, e.g. for
+                      node_exporter's textfile collector.
+        --mmap-threshold <NUM>
+                      Memory-map images of at least <NUM> bytes instead of
+                      reading them, sparing a full-size copy of each. (The
+                      number may be suffixed like --max-filesize.)
+        --min-savings <BYTES|PERCENT>
+                      Only rewrite images if the savings amount to at least
+                      this many bytes or, if suffixed with a %, this
+                      percentage of the original size.
+        --newer-than <WHEN>
+                      Only process images modified after this point, either
+                      a duration ago — e.g. 90s, 15m, 36h, 1d, 2w — or a
+                      YYYY-MM-DD [HH:MM:SS] date (UTC).
+        --older-than <WHEN>
+                      Only process images modified before this point, in the
+                      same format as --newer-than.
+        --on-change <CMD>
+                      Run this shell command for each image rewritten in
+                      place (i.e. made smaller), with {} replaced by its path
+                      and {before} and {after} by its sizes, e.g. to purge it
+                      from a CDN. Leave the tokens unquoted; if there aren't
+                      any, the path is appended.
+    -o, --out-dir <DIR>
+                      Save the compressed images to this directory — mirroring
+                      the source tree structure — instead of overwriting the
+                      originals in place.
+        --oxipng-level <NUM>
+                      Set the oxipng effort level, from 0 (a quick cleanup
+                      pass) to 6 (brute force). Lower levels try fewer row
+                      filters with a faster deflater. [default: 6]
+        --preserve-links <MODE>
+                      How to handle images with multiple hard links: break
+                      them, replacing the file like any other; skip them; or
+                      rewrite them in place — non-atomically — so every link
+                      sees the new version. [default: break]
+        --reflink <auto|never>
+                      Copy backups and unchanged --out-dir/--name-template
+                      copies file-to-file — letting copy-on-write filesystems
+                      like btrfs and XFS share their extents — rather than
+                      writing them from memory. With never, every copy is
+                      written out in full. [default: auto]
+        --report <FILE>
+                      Append a CSV row to this file for each image processed,
+                      recording its path, kind, before and after sizes,
+                      status, processing time (in milliseconds), the encoder
+                      that produced the final bytes, and how much each
+                      encoder saved.
+        --resume <FILE>
+                      Record the path of each image processed to this file so
+                      an interrupted run can pick up where it left off. Pass
+                      the same file again to skip the images already done; it
+                      is removed once a run completes.
+        --socket <PATH>
+                      The Unix socket to listen on in --daemon mode.
+        --target-size <BYTES|PERCENT>
+                      List the images still bigger than this many bytes — or,
+                      if suffixed with a %, this percentage of their original
+                      size — once the (lossless) compression is done, exiting
+                      with code 4 if there are any.
+        --tmpdir <DIR>
+                      Save scratch files — A/B copies, lists of missed images,
+                      heap profiles — here instead of the system temporary
+                      directory. (Images are always saved via temporary files
+                      alongside them, regardless.)
+        --top <NUM>   Print the NUM images with the biggest savings — by bytes
+                      and by percentage — at the end.
     -z <NUM>          Run NUM lz77 backward/forward iterations during zopfli
                       PNG encoding passes. More iterations yield better
                       compression (up to a point), but require *significantly*
                       longer processing times. In practice, values beyond 500
                       are unlikely to save more than a few bytes, and could
-                      take *days* to complete! Haha. [default: 20 or 60,
-                      depending on the file size]
+                      take *days* to complete! Haha. Pass three comma-separated
+                      values — SMALL,MEDIUM,LARGE — to scale the count by
+                      image size instead: under 200 KB, up to 1 MB, and
+                      beyond. [default: 60,20,5]
+        --zopfli-block-size <NUM>
+                      The number of (filtered) image bytes zopfli splits and
+                      compresses at a time, between 64k and 8m. Larger blocks
+                      can help big images, at the cost of roughly 40 bytes of
+                      memory per byte, per thread; smaller ones do the
+                      opposite. [default: 1m]
+        --zopfli-cost-model <v1|v2>
+                      The zopfli tree cost model to use. v2 also weighs the
+                      actual costs of the 16/17/18 repeat codes — extra bits
+                      included — which can shave a few bytes from some
+                      images, icons in particular. [default: v1]
+        --zopfli-max-splits <NUM>
+                      Split each zopfli block into at most NUM+1 DEFLATE
+                      blocks, between 1 and 63. Higher limits can help very
+                      mixed images — screenshots with photos, for example —
+                      but take longer. [default: 14]
+        --zopfli-store-threshold <PERCENT>
+                      Store zopfli blocks uncompressed unless compression
+                      would make them at least this much smaller. (When
+                      progress is enabled, the number of stored blocks is
+                      included in the summary.) [default: 0]
 ARGS:
     <PATH(S)>...      One or more image and/or directory paths to losslessly
                       compress.
 
+A/B MODE:
+    flaca ab --settings-a <FILE> --settings-b <FILE> <PATH(S)>...
+
+    Run both sets of settings over (copies of) the images and print a
+    side-by-side comparison of the resulting sizes and times. The originals
+    are left untouched.
+
+    Settings files are TOML-style lists of key = value pairs, where the keys
+    are the long names of the flags and options above, e.g.
+    zopfli-iterations = 30 or auto-orient = true. Only settings that affect
+    the encoding are supported.
+
+HTTP MODE:
+    flaca serve [--listen <ADDR>] [OPTIONS]
+
+    Run a minimal HTTP server that optimizes the images POSTed to /optimize,
+    responding with the new — or unchanged — image, and X-Flaca-Before,
+    X-Flaca-After, and X-Flaca-Savings headers. (This requires flaca be
+    built with the serve feature.)
+
 EARLY EXIT:
     Press "#, "\x1b[38;5;208mCTRL\x1b[0m+\x1b[38;5;208mC\x1b[0m once to quit as soon as the already-in-progress operations
     have finished (ignoring any pending images still in the queue).
 
-    Press \x1b[38;5;208mCTRL\x1b[0m+\x1b[38;5;208mC\x1b[0m a second time if you need to exit IMMEDIATELY, but note that
-    doing so may leave artifacts (temporary files) behind, and in rare cases,
-    lead to image corruption.
+    Press \x1b[38;5;208mCTRL\x1b[0m+\x1b[38;5;208mC\x1b[0m a second time if you need to exit IMMEDIATELY. Any images still
+    being worked on are abandoned, their originals left untouched.
+
+    SIGTERM is treated the same way.
+
+SIGNALS:
+    SIGHUP   Re-read the -l/--list files once the current batch has finished,
+             and crunch any new images found. (This is unsupported when using
+             -o/--out-dir or --backup.)
+    SIGUSR1  Print a snapshot of the progress so far.
+
+ENVIRONMENT:
+    Every flag and option can also be set with a FLACA_* environment variable
+    named after the long form, e.g. FLACA_THREADS=4 or FLACA_NO_PNG=1. (For
+    -z, use FLACA_ZOPFLI_ITERATIONS.) Flags take 1/0, true/false, yes/no, or
+    on/off. Anything passed on the command line takes precedence.
+
+EXIT STATUS:
+    0  Success; bytes were saved.
+    1  Hard error; the run could not be completed.
+    2  Success, but nothing could be improved.
+    3  Some images could not be processed (invalid, unreadable, etc.).
 
 OPTIMIZERS USED:
     MozJPEG   <https://github.com/mozilla/mozjpeg>
@@ -81,12 +426,36 @@ OPTIMIZERS USED:
 #[derive(Debug, Copy, Clone)]
 /// # Encoding Errors.
 pub(super) enum EncodingError {
+	/// # Imperfect or Out-of-Bounds Crop.
+	Crop,
+
+	/// # Damaged File.
+	Damaged,
+
+	/// # Declined by --confirm-cmd.
+	Declined,
+
 	/// # Empty File.
 	Empty,
 
+	/// # File Size.
+	FileSize,
+
 	/// # Wrong/Unknown Format.
 	Format,
 
+	/// # Hard-Linked (and Skipped).
+	HardLinked,
+
+	/// # Locked by Another Process.
+	Locked,
+
+	/// # Decode Memory.
+	Memory,
+
+	/// # Modified Mid-Flight.
+	Modified,
+
 	/// # Read Error.
 	Read,
 
@@ -99,6 +468,9 @@ pub(super) enum EncodingError {
 	/// # Vanished.
 	Vanished,
 
+	/// # Verification Failed.
+	Verify,
+
 	/// # Write Error.
 	Write,
 }
@@ -108,15 +480,37 @@ impl EncodingError {
 	/// # As Str.
 	pub(super) const fn as_str(self) -> &'static str {
 		match self {
+			Self::Crop => "can't crop losslessly",
+			Self::Damaged => "damaged",
+			Self::Declined => "declined",
 			Self::Empty => "empty file",
+			Self::FileSize => "file too big",
 			Self::Format => "invalid format",
+			Self::HardLinked => "hard-linked",
+			Self::Locked => "locked",
+			Self::Memory => "too much memory",
+			Self::Modified => "modified during optimization",
 			Self::Read => "read error",
 			Self::Resolution => "too big",
 			Self::Skipped => "",
 			Self::Vanished => "vanished!",
+			Self::Verify => "verification failed",
 			Self::Write => "write error",
 		}
 	}
+
+	#[must_use]
+	/// # Is Failure?
+	///
+	/// Returns `true` if the error means the image could not be processed,
+	/// rather than was deliberately left alone.
+	pub(super) const fn is_failure(self) -> bool {
+		matches!(
+			self,
+			Self::Crop | Self::Damaged | Self::Format | Self::Read | Self::Vanished |
+			Self::Verify | Self::Write
+		)
+	}
 }
 
 
@@ -124,27 +518,184 @@ impl EncodingError {
 #[derive(Debug, Copy, Clone)]
 /// # General/Deal-Breaking Errors.
 pub(super) enum FlacaError {
+	/// # A/B Run Failed.
+	AbRun,
+
+	/// # A/B Settings.
+	AbSettings,
+
+	/// # Backup.
+	Backup,
+
+	/// # Backup (Detached Value).
+	BackupValue,
+
+	/// # Confirmation Hook.
+	ConfirmCmd,
+
+	/// # Daemon Options.
+	Daemon,
+
+	/// # Daemon Socket.
+	DaemonSocket,
+
+	/// # Environment Flag.
+	EnvFlag,
+
+	/// # Error Log.
+	ErrorLog,
+
+	/// # JPEG Crop.
+	JpegCrop,
+
+	/// # JPEG Keep.
+	JpegKeep,
+
+	/// # Keep Candidates.
+	KeepCandidates,
+
+	/// # Keep Chunks.
+	KeepChunks,
+
+	/// # Duplicate Keep Chunks.
+	KeepChunks2,
+
 	/// # Killed Early.
 	Killed,
 
+	/// # Pipe Image Kind.
+	Kind,
+
+	/// # Listen Address.
+	Listen,
+
 	/// # List File.
 	ListFile,
 
+	/// # Locked.
+	Locked,
+
+	/// # Lock File.
+	LockFile,
+
+	/// # Newer Than.
+	NewerThan,
+
 	/// # No Images.
 	NoImages,
 
+	/// # No PNG Encoders.
+	NoPngEncoders,
+
+	/// # Max Depth.
+	MaxDepth,
+
+	/// # Max File Size.
+	MaxFileSize,
+
+	/// # Max Memory.
+	MaxMemory,
+
 	/// # Max Resolution.
 	MaxResolution,
 
+	/// # Maximum Size.
+	MaxSize,
+
+	/// # Memory Budget.
+	MemoryBudget,
+
+	/// # Metrics File.
+	Metrics,
+
+	/// # Mmap Threshold.
+	MmapThreshold,
+
+	/// # Minimum Size.
+	MinSize,
+
+	/// # Minimum Savings.
+	MinSavings,
+
+	/// # Name Template.
+	NameTemplate,
+
+	/// # Older Than.
+	OlderThan,
+
+	/// # On-Change Hook.
+	OnChange,
+
+	/// # Output Directory.
+	OutDir,
+
+	/// # Oxipng Level.
+	OxipngLevel,
+
+	/// # PNG Exhaustive.
+	PngExhaustive,
+
+	/// # Hard Link Handling.
+	PreserveLinks,
+
 	/// # Progress Passthrough.
 	Progress(ProglessError),
 
+	/// # Reflink Mode.
+	Reflink,
+
+	/// # Report File.
+	Report,
+
+	/// # Resume File.
+	Resume,
+
+	#[cfg(not(feature = "serve"))]
+	/// # Serve Unsupported.
+	Serve,
+
+	/// # Pipe Input.
+	Stdin,
+
+	/// # Pipe Output.
+	Stdout,
+
+	/// # Target Size.
+	TargetSize,
+
+	/// # Invalid Thread Count.
+	Threads,
+
+	/// # Scratch Directory.
+	TmpDir,
+
+	/// # Leaderboard Size.
+	Top,
+
+	/// # Trace Pipeline.
+	TracePipeline,
+
 	/// # Invalid Zopfli Iterations.
 	ZopfliIterations,
 
 	/// # Duplicate Zopfli Iterations.
 	ZopfliIterations2,
 
+	/// # Invalid/Duplicate Zopfli Block Size.
+	ZopfliBlockSize,
+
+	/// # Invalid Zopfli Cost Model.
+	ZopfliCostModel,
+
+	/// # Duplicate Zopfli Cost Model.
+	ZopfliCostModel2,
+
+	/// # Invalid/Duplicate Zopfli Max Splits.
+	ZopfliMaxSplits,
+
+	/// # Invalid/Duplicate Zopfli Store Threshold.
+	ZopfliStoreThreshold,
+
 	/// # Print Help (Not an Error).
 	PrintHelp,
 
@@ -176,13 +727,66 @@ impl FlacaError {
 	/// # As Str.
 	pub(super) const fn as_str(self) -> &'static str {
 		match self {
+			Self::AbRun => "The A/B comparison could not be completed.",
+			Self::AbSettings => "A/B mode requires valid --settings-a and --settings-b files.",
+			Self::Backup => "Invalid --backup suffix or directory.",
+			Self::BackupValue => "A bare --backup cannot be followed by a path; use --backup=<SUFFIX|DIR>, or list the paths first.",
+			Self::ConfirmCmd => "The --confirm-cmd cannot be empty.",
+			Self::Daemon => "The --daemon flag and --socket option require one another.",
+			Self::DaemonSocket => "Unable to listen on the --socket path; is another daemon already using it?",
+			Self::EnvFlag => "Invalid FLACA_* flag value; expected 1/0, true/false, yes/no, or on/off.",
+			Self::ErrorLog => "Invalid --errors file.",
+			Self::JpegCrop => "Invalid --jpeg-crop region; expected WxH+X+Y. (It cannot be combined with --auto-orient.)",
+			Self::JpegKeep => "Invalid --jpeg-keep value(s); expected one or more of: exif, icc, thumbnail, xmp.",
+			Self::KeepCandidates => "Invalid --keep-candidates directory, or missing --dry-run.",
+			Self::KeepChunks => "Invalid --keep-chunks value(s); expected one or more of: chrm, cicp, exif, gama, icc, phys, srgb, text, time.",
+			Self::KeepChunks2 => "The --keep-chunks option can only be set once.",
 			Self::Killed => "The process was aborted early.",
+			Self::Kind => "The --kind option must be gif, ico, jpeg, or png, and requires --stdin.",
+			Self::Listen => "The --listen option must be an available IP:PORT address, and requires the serve subcommand.",
 			Self::ListFile => "Invalid -l/--list text file.",
+			Self::Locked => "Another flaca run is holding the --lock file.",
+			Self::LockFile => "Invalid --lock file.",
+			Self::NewerThan => "Invalid --newer-than value; expected a duration like 36h or 2w, or a YYYY-MM-DD date.",
 			Self::NoImages => "No images were found.",
+			Self::NoPngEncoders => "The --no-oxipng and --no-zopfli flags cannot be combined; use --no-png to skip PNGs instead.",
+			Self::MaxDepth => "The --max-depth must be a non-zero integer.",
+			Self::MaxFileSize => "File size limits must be a non-zero number of bytes.",
+			Self::MaxMemory => "The --max-memory limit must be a non-zero number of megabytes.",
 			Self::MaxResolution => "Pixel limits must be between 1..=4_294_967_295.",
+			Self::MaxSize => "Invalid --max-size value; expected a non-zero number of bytes.",
+			Self::MemoryBudget => "The --memory-budget must be a non-zero number of megabytes.",
+			Self::Metrics => "Unable to write the --metrics-file.",
+			Self::MmapThreshold => "Invalid --mmap-threshold value; expected a non-zero number of bytes.",
+			Self::MinSize => "Invalid --min-size value; expected a non-zero number of bytes.",
+			Self::MinSavings => "Minimum savings must be a number of bytes or a percentage between 1%..=99%.",
+			Self::NameTemplate => "Invalid --name-template; only {stem}, {ext}, {hash}, {width}, and {height} are supported, and path separators are not allowed.",
+			Self::OlderThan => "Invalid --older-than value; expected a duration like 36h or 2w, or a YYYY-MM-DD date.",
+			Self::OnChange => "The --on-change command cannot be empty.",
+			Self::OutDir => "Invalid -o/--out-dir directory.",
+			Self::OxipngLevel => "The oxipng level must be between 0..=6.",
+			Self::PngExhaustive => "The --png-exhaustive flag cannot be combined with --keep-colortype.",
+			Self::PreserveLinks => "The --preserve-links mode must be break, skip, or rewrite.",
 			Self::Progress(e) => e.as_str(),
-			Self::ZopfliIterations => "The number of (zopfli) lz77 iterations must be between 1..=2_147_483_647.",
+			Self::Reflink => "The --reflink mode must be auto or never.",
+			Self::Report => "Invalid --report file.",
+			Self::Resume => "Invalid --resume state file.",
+			#[cfg(not(feature = "serve"))]
+			Self::Serve => "This build of flaca does not support the serve subcommand; recompile it with the serve feature enabled.",
+			Self::Stdin => "Unable to read a supported image from STDIN.",
+			Self::Stdout => "Unable to write the image to STDOUT.",
+			Self::TargetSize => "The --target-size must be a non-zero number of bytes or a percentage between 1%..=99%.",
+			Self::Threads => "The number of threads must be a non-zero integer, optionally negative (to subtract from the number of logical cores).",
+			Self::TmpDir => "Invalid --tmpdir directory.",
+			Self::Top => "The --top count must be a non-zero integer.",
+			Self::TracePipeline => "The --trace-pipeline flag requires exactly one image.",
+			Self::ZopfliIterations => "The number of (zopfli) lz77 iterations must be one or three (comma-separated) values between 1..=2_147_483_647.",
 			Self::ZopfliIterations2 => "The -z option can only be set once.",
+			Self::ZopfliBlockSize => "The (zopfli) block size must be between 64k..=8m, and can only be set once.",
+			Self::ZopfliCostModel => "The (zopfli) cost model must be v1 or v2.",
+			Self::ZopfliCostModel2 => "The --zopfli-cost-model option can only be set once.",
+			Self::ZopfliMaxSplits => "The (zopfli) maximum split count must be between 1..=63, and can only be set once.",
+			Self::ZopfliStoreThreshold => "The (zopfli) store threshold must be a percentage between 0%..=99%, and can only be set once.",
 			Self::PrintHelp => HELP,
 			Self::PrintVersion => concat!("Flaca v", env!("CARGO_PKG_VERSION")),
 		}
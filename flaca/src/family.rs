@@ -0,0 +1,213 @@
+/*!
+# Flaca: Image Families
+*/
+
+use crate::{
+	Observer,
+	rules::original_stem,
+};
+use dactyl::traits::NiceInflection;
+use fyi_msg::{
+	BeforeAfter,
+	Msg,
+};
+use std::{
+	collections::{
+		BTreeMap,
+		HashMap,
+		HashSet,
+	},
+	ffi::OsStr,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+#[derive(Debug, Default)]
+/// # Image Families.
+///
+/// CMSes like `WordPress` generate a bunch of resized variants for each
+/// uploaded image, named after the original with a `-WxH` suffix. This
+/// observer groups the results by original so savings can be reported per
+/// source image rather than per file.
+pub(crate) struct Families(Mutex<BTreeMap<PathBuf, Family>>);
+
+impl Observer for Families {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		let (key, variant) = family_key(src);
+		if let Ok(mut map) = self.0.lock() {
+			let family = map.entry(key).or_default();
+			family.before += before;
+			family.after += after;
+			if variant { family.variants += 1; }
+		}
+	}
+}
+
+impl Families {
+	/// # Summarize.
+	///
+	/// Print the savings for each family, largest first.
+	pub(crate) fn summarize(self) {
+		let Ok(map) = self.0.into_inner() else { return; };
+		let mut all: Vec<(PathBuf, Family)> = map.into_iter().collect();
+		all.sort_by(|a, b| b.1.saved().cmp(&a.1.saved()).then_with(|| a.0.cmp(&b.0)));
+
+		for (path, family) in all {
+			let msg =
+				if family.variants == 0 { path.to_string_lossy().into_owned() }
+				else {
+					format!(
+						"{} \x1b[2m(+{})\x1b[0m",
+						path.display(),
+						family.variants.nice_inflect("variant", "variants"),
+					)
+				};
+
+			Msg::crunched(msg)
+				.with_bytes_saved(BeforeAfter::from((family.before, family.after)))
+				.eprint();
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Default)]
+/// # Image Family.
+struct Family {
+	/// # Total Size Before.
+	before: u64,
+
+	/// # Total Size After.
+	after: u64,
+
+	/// # Number of Variants.
+	variants: u32,
+}
+
+impl Family {
+	/// # Bytes Saved.
+	const fn saved(&self) -> u64 { self.before.saturating_sub(self.after) }
+}
+
+
+
+/// # Skip Variants.
+///
+/// Remove any `-WxH` variants from `paths` whose original is also present,
+/// since the CMS will presumably regenerate them from the latter anyway.
+pub(crate) fn skip_variants(paths: &mut Vec<PathBuf>) {
+	let all: HashSet<PathBuf> = paths.iter().cloned().collect();
+	paths.retain(|p| {
+		let (key, variant) = family_key(p);
+		! variant || ! all.contains(&key)
+	});
+}
+
+/// # Primary Only.
+///
+/// Reduce `paths` to a single image per family: the original, if present,
+/// otherwise the largest (by file size) of its variants.
+pub(crate) fn primary_only(paths: &mut Vec<PathBuf>) {
+	let mut best: HashMap<PathBuf, (bool, u64, usize)> = HashMap::with_capacity(paths.len());
+	for (idx, p) in paths.iter().enumerate() {
+		let (key, variant) = family_key(p);
+		let size = std::fs::metadata(p).map_or(0, |m| m.len());
+
+		// Originals trump variants; after that, bigger is better.
+		let rank = (! variant, size, idx);
+		best.entry(key)
+			.and_modify(|old| if (old.0, old.1) < (rank.0, rank.1) { *old = rank; })
+			.or_insert(rank);
+	}
+
+	let keep: HashSet<usize> = best.into_values().map(|(_, _, idx)| idx).collect();
+	let mut idx = 0;
+	paths.retain(|_| {
+		let out = keep.contains(&idx);
+		idx += 1;
+		out
+	});
+}
+
+/// # Family Key.
+///
+/// Return the path of the original image `src` was (presumably) derived
+/// from — or `src` itself if it isn't a variant — along with a bool
+/// indicating which.
+fn family_key(src: &Path) -> (PathBuf, bool) {
+	let Some(stem) = src.file_stem().and_then(OsStr::to_str) else {
+		return (src.to_path_buf(), false);
+	};
+	let Some(base) = original_stem(stem) else {
+		return (src.to_path_buf(), false);
+	};
+
+	let name = src.extension()
+		.and_then(OsStr::to_str)
+		.map_or_else(|| base.to_owned(), |ext| format!("{base}.{ext}"));
+	(src.with_file_name(name), true)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_primary_only() {
+		// File sizes matter, so we need real files.
+		let tmp = std::env::temp_dir().join("flaca-t-primary-only");
+		let _res = std::fs::remove_dir_all(&tmp);
+		std::fs::create_dir_all(&tmp).expect("Unable to create temp dir.");
+
+		let mut paths = Vec::new();
+		for (name, size) in [
+			// The original wins.
+			("foo.png", 10),
+			("foo-150x150.png", 20),
+
+			// No original; the largest variant wins.
+			("bar-1x1.png", 10),
+			("bar-2x2.png", 20),
+
+			// All by itself.
+			("baz-10x10.png", 10),
+		] {
+			let p = tmp.join(name);
+			std::fs::write(&p, vec![0_u8; size]).expect("Write failed.");
+			paths.push(p);
+		}
+
+		let expected = vec![paths[0].clone(), paths[3].clone(), paths[4].clone()];
+		primary_only(&mut paths);
+		let _res = std::fs::remove_dir_all(&tmp);
+		assert_eq!(paths, expected);
+	}
+
+	#[test]
+	fn t_skip_variants() {
+		let mut paths = vec![
+			PathBuf::from("/foo/image.jpg"),
+			PathBuf::from("/foo/image-150x150.jpg"),
+			PathBuf::from("/foo/image-300x200.jpg"),
+			PathBuf::from("/foo/orphan-150x150.png"),
+			PathBuf::from("/foo/other.png"),
+		];
+		skip_variants(&mut paths);
+		assert_eq!(
+			paths,
+			[
+				PathBuf::from("/foo/image.jpg"),
+				PathBuf::from("/foo/orphan-150x150.png"),
+				PathBuf::from("/foo/other.png"),
+			],
+		);
+	}
+}
@@ -0,0 +1,277 @@
+/*!
+# Flaca: File I/O (and Fault Injection)
+
+All image reads and writes pass through here so that, when the
+`fault-injection` crate feature is enabled, failures can be simulated via the
+`FLACA_FAULTS` environment variable, a comma-separated list of:
+
+| Rule | Effect |
+| ---- | ------ |
+| `fail-read=N` | Every Nth read fails. |
+| `fail-write=N` | Every Nth write fails. |
+| `enospc=N` | Every Nth write fails with ENOSPC (no space left on device). |
+| `slow-read=MS` | Every read is delayed by MS milliseconds. |
+
+For example, `FLACA_FAULTS=fail-write=3,slow-read=50`.
+
+Without the feature, these are just thin wrappers around the usual calls.
+*/
+
+use crate::{
+	mmap::{
+		Input,
+		Mmap,
+		MMAP_THRESHOLD,
+	},
+	output::{
+		HardLinks,
+		REFLINK,
+	},
+};
+use std::{
+	fs::{
+		File,
+		OpenOptions,
+	},
+	io::{
+		Result,
+		Write,
+	},
+	os::unix::fs::MetadataExt,
+	path::Path,
+	sync::atomic::Ordering::Relaxed,
+};
+
+
+
+#[cfg(not(feature = "fault-injection"))]
+#[expect(clippy::inline_always, reason = "For performance.")]
+#[inline(always)]
+/// # Read File.
+pub(crate) fn read(src: &Path) -> Result<Vec<u8>> { std::fs::read(src) }
+
+#[cfg(not(feature = "fault-injection"))]
+#[expect(clippy::inline_always, reason = "For performance.")]
+#[inline(always)]
+/// # Write File (Atomically).
+pub(crate) fn write(dst: &Path, raw: &[u8]) -> Result<()> {
+	write_atomic::write_file(dst, raw)
+}
+
+
+
+#[cfg(feature = "fault-injection")]
+/// # Read File.
+pub(crate) fn read(src: &Path) -> Result<Vec<u8>> {
+	if let Some(faults) = inject::Faults::get() { faults.read()?; }
+	std::fs::read(src)
+}
+
+#[cfg(feature = "fault-injection")]
+/// # Write File (Atomically).
+pub(crate) fn write(dst: &Path, raw: &[u8]) -> Result<()> {
+	if let Some(faults) = inject::Faults::get() { faults.write()?; }
+	write_atomic::write_file(dst, raw)
+}
+
+
+
+/// # Read File (or Map It).
+///
+/// Same as `read`, except files at least `MMAP_THRESHOLD` bytes — if set —
+/// are memory-mapped instead.
+///
+/// Hard-linked files are always read when `--preserve-links rewrite` is set,
+/// since the rewrite would change (and shrink) the very inode being mapped.
+pub(crate) fn read_input(src: &Path) -> Result<Input> {
+	let threshold = MMAP_THRESHOLD.load(Relaxed);
+	if threshold != 0 {
+		let file = File::open(src)?;
+		let meta = file.metadata()?;
+		let len = meta.len();
+		let rewrite = 1 < meta.nlink() && HardLinks::get() == HardLinks::Rewrite;
+		if threshold <= len && ! rewrite {
+			#[cfg(feature = "fault-injection")]
+			if let Some(faults) = inject::Faults::get() { faults.read()?; }
+			return Mmap::new(&file, len).map(Input::Mapped);
+		}
+	}
+
+	read(src).map(Input::Owned)
+}
+
+
+
+/// # Copy File (Atomically).
+///
+/// Save a duplicate of the file at `src` — whose contents are `raw` — to
+/// `dst`.
+///
+/// This copies file-to-file, using `copy_file_range` under the hood where
+/// supported, letting filesystems like btrfs and XFS share the extents rather
+/// than duplicating them. With `--reflink never`, `raw` is instead written
+/// out like any other file, so the copy always gets extents of its own.
+pub(crate) fn copy(src: &Path, dst: &Path, raw: &[u8]) -> Result<()> {
+	if ! REFLINK.load(Relaxed) { return write(dst, raw); }
+
+	#[cfg(feature = "fault-injection")]
+	if let Some(faults) = inject::Faults::get() { faults.write()?; }
+
+	write_atomic::copy_file(src, dst)
+}
+
+/// # Overwrite File (In Place).
+///
+/// Write `raw` over the existing contents of `dst`, keeping its inode — and
+/// any hard links to it — intact. Unlike `write`, this is _not_ atomic.
+pub(crate) fn overwrite(dst: &Path, raw: &[u8]) -> Result<()> {
+	#[cfg(feature = "fault-injection")]
+	if let Some(faults) = inject::Faults::get() { faults.write()?; }
+
+	let mut file = OpenOptions::new().write(true).open(dst)?;
+	file.write_all(raw)?;
+	file.set_len(raw.len() as u64)?;
+	file.sync_all()
+}
+
+
+
+#[cfg(feature = "fault-injection")]
+/// # Fault Injection.
+mod inject {
+	use std::{
+		io::{
+			Error,
+			Result,
+		},
+		num::NonZeroU64,
+		sync::{
+			atomic::{
+				AtomicU64,
+				Ordering::Relaxed,
+			},
+			OnceLock,
+		},
+		time::Duration,
+	};
+
+	/// # Environment Variable.
+	const ENV: &str = "FLACA_FAULTS";
+
+	/// # Parsed Faults.
+	static FAULTS: OnceLock<Option<Faults>> = OnceLock::new();
+
+	#[derive(Debug, Default)]
+	/// # Fault Rules.
+	pub(super) struct Faults {
+		/// # Fail Every Nth Read.
+		fail_read: Option<NonZeroU64>,
+
+		/// # Fail Every Nth Write.
+		fail_write: Option<NonZeroU64>,
+
+		/// # ENOSPC Every Nth Write.
+		enospc: Option<NonZeroU64>,
+
+		/// # Read Delay.
+		slow_read: Option<Duration>,
+
+		/// # Read Count.
+		reads: AtomicU64,
+
+		/// # Write Count.
+		writes: AtomicU64,
+	}
+
+	impl Faults {
+		/// # Get.
+		///
+		/// Return the rules, parsing them from the environment on first use.
+		pub(super) fn get() -> Option<&'static Self> {
+			FAULTS.get_or_init(||
+				std::env::var(ENV).ok().and_then(|raw| Self::parse(&raw))
+			).as_ref()
+		}
+
+		/// # Parse.
+		///
+		/// Invalid rules are ignored; `None` is returned if there aren't any
+		/// valid ones.
+		fn parse(raw: &str) -> Option<Self> {
+			let mut out = Self::default();
+			let mut any = false;
+			for rule in raw.split(',') {
+				let Some((k, v)) = rule.split_once('=') else { continue; };
+				let Ok(v) = v.trim().parse::<u64>() else { continue; };
+				match k.trim() {
+					"enospc" => { out.enospc = NonZeroU64::new(v); },
+					"fail-read" => { out.fail_read = NonZeroU64::new(v); },
+					"fail-write" => { out.fail_write = NonZeroU64::new(v); },
+					"slow-read" => { out.slow_read = Some(Duration::from_millis(v)); },
+					_ => continue,
+				}
+				any = true;
+			}
+
+			if any { Some(out) }
+			else { None }
+		}
+
+		/// # Read.
+		///
+		/// Sleep and/or return an error, if that's what the rules call for.
+		pub(super) fn read(&self) -> Result<()> {
+			if let Some(delay) = self.slow_read { std::thread::sleep(delay); }
+			let n = self.reads.fetch_add(1, Relaxed) + 1;
+			if self.fail_read.is_some_and(|every| n % every == 0) {
+				Err(Error::other("injected read failure"))
+			}
+			else { Ok(()) }
+		}
+
+		/// # Write.
+		///
+		/// Return an error, if that's what the rules call for.
+		pub(super) fn write(&self) -> Result<()> {
+			let n = self.writes.fetch_add(1, Relaxed) + 1;
+			if self.enospc.is_some_and(|every| n % every == 0) {
+				Err(Error::from_raw_os_error(libc::ENOSPC))
+			}
+			else if self.fail_write.is_some_and(|every| n % every == 0) {
+				Err(Error::other("injected write failure"))
+			}
+			else { Ok(()) }
+		}
+	}
+
+
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn t_parse() {
+			assert!(Faults::parse("").is_none());
+			assert!(Faults::parse("bogus=1,fail-write").is_none());
+
+			let faults = Faults::parse("fail-write=2, enospc=3,slow-read=5")
+				.expect("Faults failed to parse.");
+			assert_eq!(faults.fail_write, NonZeroU64::new(2));
+			assert_eq!(faults.enospc, NonZeroU64::new(3));
+			assert_eq!(faults.slow_read, Some(Duration::from_millis(5)));
+			assert!(faults.fail_read.is_none());
+
+			// Writes: 1 ok, 2 fail, 3 ENOSPC, 4 fail, 5 ok, 6 ENOSPC.
+			assert!(faults.write().is_ok());
+			assert!(faults.write().is_err());
+			assert_eq!(
+				faults.write().map_err(|e| e.raw_os_error()),
+				Err(Some(libc::ENOSPC)),
+			);
+			assert!(faults.write().is_err());
+			assert!(faults.write().is_ok());
+			assert!(faults.write().is_err());
+		}
+	}
+}
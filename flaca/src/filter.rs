@@ -0,0 +1,332 @@
+/*!
+# Flaca: Candidate Filters
+
+These are applied during traversal — alongside the extension matching — so
+unwanted files never make it into the queue.
+
+Symlinks are handled here too. The crawler canonicalizes everything it finds,
+so links are always resolved, but anything they lead to outside the requested
+paths is dropped afterward, keeping runs from wandering into other trees (or
+read-only mounts).
+*/
+
+use crate::FlacaError;
+use dactyl::traits::BytesToUnsigned;
+use std::{
+	num::NonZeroUsize,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::{
+		atomic::{
+			AtomicBool,
+			Ordering::Relaxed,
+		},
+		OnceLock,
+	},
+	time::UNIX_EPOCH,
+};
+use utc2k::{
+	DAY_IN_SECONDS,
+	HOUR_IN_SECONDS,
+	MINUTE_IN_SECONDS,
+	Utc2k,
+	WEEK_IN_SECONDS,
+};
+
+
+
+/// # Active Filters.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static FILTERS: OnceLock<Filters> = OnceLock::new();
+
+/// # Sniff Extension-less Files?
+///
+/// When set, files without extensions are included in the walk too, leaving
+/// the magic-byte checks to sort out which are actually images.
+pub(crate) static SNIFF: AtomicBool = AtomicBool::new(false);
+
+
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+/// # Candidate Filters.
+///
+/// This holds the metadata-based restrictions, if any, imposed on the files
+/// found during traversal.
+pub(crate) struct Filters {
+	/// # Modified After (Unixtime).
+	newer_than: Option<u32>,
+
+	/// # Modified Before (Unixtime).
+	older_than: Option<u32>,
+
+	/// # Minimum File Size.
+	min_size: Option<u64>,
+
+	/// # Maximum File Size.
+	max_size: Option<u64>,
+}
+
+impl Filters {
+	/// # Initialize.
+	///
+	/// Save the filters for later reference, if any were set.
+	pub(crate) fn init(self) {
+		if self != Self::default() { let _res = FILTERS.set(self); }
+	}
+
+	/// # Set Newer Than.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is not a valid duration or date.
+	pub(crate) fn set_newer_than(&mut self, raw: &str) -> Result<(), FlacaError> {
+		let time = parse_time(raw.trim(), utc2k::unixtime()).ok_or(FlacaError::NewerThan)?;
+		self.newer_than = Some(time);
+		Ok(())
+	}
+
+	/// # Set Older Than.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is not a valid duration or date.
+	pub(crate) fn set_older_than(&mut self, raw: &str) -> Result<(), FlacaError> {
+		let time = parse_time(raw.trim(), utc2k::unixtime()).ok_or(FlacaError::OlderThan)?;
+		self.older_than = Some(time);
+		Ok(())
+	}
+
+	/// # Set Minimum Size.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is not a valid size.
+	pub(crate) fn set_min_size(&mut self, raw: &str) -> Result<(), FlacaError> {
+		let size = crate::parse_limit(raw.trim().as_bytes()).ok_or(FlacaError::MinSize)?;
+		self.min_size = Some(size);
+		Ok(())
+	}
+
+	/// # Set Maximum Size.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is not a valid size.
+	pub(crate) fn set_max_size(&mut self, raw: &str) -> Result<(), FlacaError> {
+		let size = crate::parse_limit(raw.trim().as_bytes()).ok_or(FlacaError::MaxSize)?;
+		self.max_size = Some(size);
+		Ok(())
+	}
+
+	/// # Matches?
+	///
+	/// Returns `true` if the file at `path` satisfies the filters.
+	fn matches(self, path: &Path) -> bool {
+		let Ok(meta) = std::fs::metadata(path) else { return false; };
+
+		let size = meta.len();
+		if self.min_size.is_some_and(|s| size < s) { return false; }
+		if self.max_size.is_some_and(|s| s < size) { return false; }
+
+		if self.newer_than.is_some() || self.older_than.is_some() {
+			let Some(mtime) = meta.modified().ok()
+				.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+				.map(|d| u32::try_from(d.as_secs()).unwrap_or(u32::MAX))
+			else { return false; };
+
+			if self.newer_than.is_some_and(|t| mtime <= t) { return false; }
+			if self.older_than.is_some_and(|t| t <= mtime) { return false; }
+		}
+
+		true
+	}
+}
+
+
+
+/// # Check Path.
+///
+/// Returns `true` if the file at `path` satisfies the active filters (or
+/// there aren't any).
+pub(crate) fn check(path: &Path) -> bool {
+	FILTERS.get().is_none_or(|f| f.matches(path))
+}
+
+/// # Sniffable?
+///
+/// Returns `true` if `--sniff` is enabled and `path` has no extension.
+pub(crate) fn sniffable(path: &Path) -> bool {
+	SNIFF.load(Relaxed) && path.extension().is_none()
+}
+
+/// # Confine to Roots.
+///
+/// Remove any paths lying outside the (canonicalized) `roots` — i.e. those
+/// reached via symlinks leading elsewhere — returning the number removed.
+pub(crate) fn confine<P, I>(roots: I, paths: &mut Vec<PathBuf>) -> usize
+where P: AsRef<Path>, I: IntoIterator<Item=P> {
+	let roots: Vec<PathBuf> = roots.into_iter()
+		.filter_map(|p| std::fs::canonicalize(p).ok())
+		.collect();
+
+	let before = paths.len();
+	paths.retain(|p| roots.iter().any(|r| p.starts_with(r)));
+	before - paths.len()
+}
+
+/// # Expand to Depth.
+///
+/// Replace any directories among `paths` with the entries found within them,
+/// recursively, up to `depth` levels down, so the crawler never has to go
+/// any further. (A depth of one yields only the immediate children.)
+///
+/// Files are passed through as-are; deeper directories are dropped.
+pub(crate) fn expand_to_depth<P, I>(paths: I, depth: NonZeroUsize) -> Vec<PathBuf>
+where P: AsRef<Path>, I: IntoIterator<Item=P> {
+	let mut out = Vec::new();
+	let mut stack: Vec<(PathBuf, usize)> = paths.into_iter()
+		.map(|p| (p.as_ref().to_path_buf(), 0))
+		.collect();
+
+	while let Some((path, level)) = stack.pop() {
+		if ! path.is_dir() { out.push(path); }
+		else if level < depth.get() {
+			if let Ok(entries) = std::fs::read_dir(&path) {
+				stack.extend(entries.filter_map(|e| e.ok().map(|e| (e.path(), level + 1))));
+			}
+		}
+	}
+
+	out
+}
+
+/// # Is Symlink?
+pub(crate) fn is_symlink(path: &Path) -> bool {
+	std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// # Parse Time.
+///
+/// Parse a relative duration — a number followed by `s`, `m`, `h`, `d`, or
+/// `w`, e.g. `36h` — or an absolute `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS`
+/// date (UTC), returning the corresponding unixtime.
+fn parse_time(raw: &str, now: u32) -> Option<u32> {
+	if raw.len() == 10 || raw.len() == 19 {
+		return Utc2k::try_from(raw).ok().map(u32::from);
+	}
+
+	let (num, unit) = raw.split_at_checked(raw.len().checked_sub(1)?)?;
+	let unit = match unit {
+		"s" => 1,
+		"m" => MINUTE_IN_SECONDS,
+		"h" => HOUR_IN_SECONDS,
+		"d" => DAY_IN_SECONDS,
+		"w" => WEEK_IN_SECONDS,
+		_ => return None,
+	};
+	let num = u32::btou(num.as_bytes())?;
+	Some(now.saturating_sub(num.saturating_mul(unit)))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_matches() {
+		let path = std::env::temp_dir().join(format!("flaca-filter-{}.png", std::process::id()));
+		std::fs::write(&path, [0_u8; 100]).expect("Unable to write file.");
+
+		let now = utc2k::unixtime();
+		for (filters, expected) in [
+			(Filters::default(), true),
+			(Filters { min_size: Some(50), ..Filters::default() }, true),
+			(Filters { min_size: Some(200), ..Filters::default() }, false),
+			(Filters { max_size: Some(100), ..Filters::default() }, true),
+			(Filters { max_size: Some(99), ..Filters::default() }, false),
+			(Filters { newer_than: Some(now - 3600), ..Filters::default() }, true),
+			(Filters { older_than: Some(now - 3600), ..Filters::default() }, false),
+		] {
+			assert_eq!(filters.matches(&path), expected, "Wrong answer for {filters:?}.");
+		}
+
+		let _res = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn t_confine() {
+		let dir = std::env::temp_dir().join(format!("flaca-confine-{}", std::process::id()));
+		let inside = dir.join("inside");
+		let outside = dir.join("outside");
+		std::fs::create_dir_all(&inside).expect("Unable to create directory.");
+		std::fs::create_dir_all(&outside).expect("Unable to create directory.");
+		std::fs::write(inside.join("a.png"), b"a").expect("Unable to write file.");
+		std::fs::write(outside.join("b.png"), b"b").expect("Unable to write file.");
+		std::os::unix::fs::symlink(&outside, inside.join("link")).expect("Unable to symlink.");
+
+		assert!(is_symlink(&inside.join("link")));
+		assert!(! is_symlink(&inside));
+
+		let inside = std::fs::canonicalize(&inside).expect("Unable to canonicalize.");
+		let outside = std::fs::canonicalize(&outside).expect("Unable to canonicalize.");
+		let mut paths = vec![inside.join("a.png"), outside.join("b.png")];
+		assert_eq!(confine([&inside], &mut paths), 1);
+		assert_eq!(paths, [inside.join("a.png")]);
+
+		let _res = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn t_expand_to_depth() {
+		let dir = std::env::temp_dir().join(format!("flaca-depth-{}", std::process::id()));
+		let deep = dir.join("one/two");
+		std::fs::create_dir_all(&deep).expect("Unable to create directory.");
+		for p in [dir.join("a.png"), dir.join("one/b.png"), deep.join("c.png")] {
+			std::fs::write(p, b"a").expect("Unable to write file.");
+		}
+
+		for (depth, expected) in [
+			(1, vec![dir.join("a.png")]),
+			(2, vec![dir.join("a.png"), dir.join("one/b.png")]),
+			(3, vec![dir.join("a.png"), dir.join("one/b.png"), deep.join("c.png")]),
+		] {
+			let depth = NonZeroUsize::new(depth).expect("Depth is zero.");
+			let mut found = expand_to_depth([&dir], depth);
+			found.sort();
+			assert_eq!(found, expected, "Wrong paths at depth {depth}.");
+		}
+
+		// Files are passed through regardless.
+		let file = deep.join("c.png");
+		assert_eq!(expand_to_depth([&file], NonZeroUsize::MIN), [file]);
+
+		let _res = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn t_parse_time() {
+		const NOW: u32 = 1_700_000_000;
+		for (raw, expected) in [
+			("90s", Some(NOW - 90)),
+			("15m", Some(NOW - 900)),
+			("36h", Some(NOW - 36 * HOUR_IN_SECONDS)),
+			("1d", Some(NOW - DAY_IN_SECONDS)),
+			("2w", Some(NOW - 2 * WEEK_IN_SECONDS)),
+			("99999999w", Some(0)),
+			("2023-11-14", Some(1_699_920_000)),
+			("2023-11-14 22:13:20", Some(NOW)),
+			("1y", None),
+			("d", None),
+			("", None),
+			("yesterday", None),
+		] {
+			assert_eq!(parse_time(raw, NOW), expected, "Wrong answer for {raw:?}.");
+		}
+	}
+}
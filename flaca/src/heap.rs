@@ -0,0 +1,229 @@
+/*!
+# Flaca: Heap Profiling
+
+When the `profiling` crate feature is enabled, the global allocator is
+wrapped with a set of counters, and a snapshot of them is appended to a
+tab-separated profile after every N images so allocation behavior can be
+tracked over the course of a real-world run.
+
+The following environment variables can be used to tweak the behavior:
+
+| Variable | Effect |
+| -------- | ------ |
+| `FLACA_HEAP_EVERY=N` | Take a snapshot every N images. [default: 100] |
+| `FLACA_HEAP_PROFILE=PATH` | Save the profile here instead of the temporary directory. |
+
+Each row records the number of images processed so far, the total number of
+allocations and bytes allocated, the bytes currently in use, and the peak
+bytes in use since the previous row.
+
+Note: only allocations made through Rust — including flapfli's lodepng
+buffers — are counted. `MozJPEG` uses the system allocator directly.
+
+Without the feature, this does nothing.
+*/
+
+
+
+#[cfg(not(feature = "profiling"))]
+#[expect(clippy::inline_always, reason = "For performance.")]
+#[inline(always)]
+/// # Profiler.
+///
+/// Profiling is disabled, so there's nothing to observe.
+pub(crate) const fn profiler() -> Option<()> { None }
+
+#[cfg(feature = "profiling")]
+pub(crate) use inner::profiler;
+
+
+
+#[cfg(feature = "profiling")]
+/// # Heap Profiling.
+mod inner {
+	use crate::Observer;
+	use std::{
+		alloc::{
+			GlobalAlloc,
+			Layout,
+			System,
+		},
+		fs::File,
+		io::Write,
+		num::NonZeroU64,
+		path::{
+			Path,
+			PathBuf,
+		},
+		sync::{
+			atomic::{
+				AtomicU64,
+				Ordering::Relaxed,
+			},
+			Mutex,
+		},
+	};
+
+	/// # Environment Variable: Interval.
+	const ENV_EVERY: &str = "FLACA_HEAP_EVERY";
+
+	/// # Environment Variable: Output Path.
+	const ENV_PROFILE: &str = "FLACA_HEAP_PROFILE";
+
+	/// # Default Interval.
+	const DEFAULT_EVERY: NonZeroU64 = NonZeroU64::new(100).unwrap();
+
+	#[global_allocator]
+	/// # Tracking Allocator.
+	static GLOBAL: Tracking = Tracking;
+
+	/// # Total Allocations.
+	static ALLOCS: AtomicU64 = AtomicU64::new(0);
+
+	/// # Total Bytes Allocated.
+	static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+	/// # Bytes In Use.
+	static LIVE: AtomicU64 = AtomicU64::new(0);
+
+	/// # Peak Bytes In Use.
+	static PEAK: AtomicU64 = AtomicU64::new(0);
+
+
+
+	/// # Tracking Allocator.
+	///
+	/// This passes everything through to the system allocator, keeping count
+	/// along the way.
+	struct Tracking;
+
+	#[expect(unsafe_code, reason = "For alloc.")]
+	// Safety: the actual (de)allocation is left to the system allocator.
+	unsafe impl GlobalAlloc for Tracking {
+		unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+			let ptr = System.alloc(layout);
+			if ! ptr.is_null() { grow(layout.size(), true); }
+			ptr
+		}
+
+		unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+			let ptr = System.alloc_zeroed(layout);
+			if ! ptr.is_null() { grow(layout.size(), true); }
+			ptr
+		}
+
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+			System.dealloc(ptr, layout);
+			LIVE.fetch_sub(layout.size() as u64, Relaxed);
+		}
+
+		unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+			let new = System.realloc(ptr, layout, new_size);
+			if ! new.is_null() {
+				let old_size = layout.size();
+				if old_size < new_size { grow(new_size - old_size, false); }
+				else { LIVE.fetch_sub((old_size - new_size) as u64, Relaxed); }
+			}
+			new
+		}
+	}
+
+	#[inline]
+	/// # Grow.
+	///
+	/// Record the addition of `size` bytes, and if `new`, a new allocation.
+	fn grow(size: usize, new: bool) {
+		let size = size as u64;
+		if new { ALLOCS.fetch_add(1, Relaxed); }
+		ALLOCATED.fetch_add(size, Relaxed);
+		let live = LIVE.fetch_add(size, Relaxed) + size;
+		PEAK.fetch_max(live, Relaxed);
+	}
+
+
+
+	/// # Profiler.
+	///
+	/// Return a new profiler, or `None` if the profile file can't be created.
+	pub(crate) fn profiler() -> Option<Profiler> {
+		let every = std::env::var(ENV_EVERY).ok()
+			.and_then(|v| v.trim().parse::<NonZeroU64>().ok())
+			.unwrap_or(DEFAULT_EVERY);
+
+		let path = std::env::var_os(ENV_PROFILE).map_or_else(
+			|| crate::temp::scratch_dir().join(format!("flaca-heap-{}.tsv", utc2k::unixtime())),
+			PathBuf::from,
+		);
+
+		let mut file = File::create(&path).ok()?;
+		file.write_all(b"images\tallocations\tallocated\tlive\tpeak\n").ok()?;
+
+		Some(Profiler {
+			every,
+			done: AtomicU64::new(0),
+			file: Mutex::new(file),
+			path,
+		})
+	}
+
+
+
+	/// # Heap Profiler.
+	///
+	/// This observer counts finished images, appending a snapshot of the
+	/// allocation counters to the profile every so often.
+	pub(crate) struct Profiler {
+		/// # Snapshot Interval.
+		every: NonZeroU64,
+
+		/// # Images Done.
+		done: AtomicU64,
+
+		/// # Profile File.
+		file: Mutex<File>,
+
+		/// # Profile Path.
+		path: PathBuf,
+	}
+
+	impl Observer for Profiler {
+		fn on_finish(&self, _src: &Path, _before: u64, _after: u64) { self.tick(); }
+		fn on_error(&self, _src: &Path, _err: crate::EncodingError) { self.tick(); }
+	}
+
+	impl Drop for Profiler {
+		fn drop(&mut self) {
+			// Record the tail end, unless that's already happened.
+			let done = self.done.load(Relaxed);
+			if done % self.every != 0 { self.snapshot(done); }
+
+			fyi_msg::Msg::notice(format!(
+				"The heap profile has been saved to \x1b[95;1m{}\x1b[0m.",
+				self.path.display(),
+			)).eprint();
+		}
+	}
+
+	impl Profiler {
+		/// # Tick.
+		fn tick(&self) {
+			let done = self.done.fetch_add(1, Relaxed) + 1;
+			if done % self.every == 0 { self.snapshot(done); }
+		}
+
+		/// # Snapshot.
+		///
+		/// Append the current counters to the profile and reset the peak.
+		fn snapshot(&self, done: u64) {
+			let Ok(mut file) = self.file.lock() else { return; };
+			let live = LIVE.load(Relaxed);
+			let _res = writeln!(
+				file,
+				"{done}\t{}\t{}\t{live}\t{}",
+				ALLOCS.load(Relaxed),
+				ALLOCATED.load(Relaxed),
+				PEAK.swap(live, Relaxed),
+			);
+		}
+	}
+}
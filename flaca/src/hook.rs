@@ -0,0 +1,192 @@
+/*!
+# Flaca: Hooks
+
+Hook commands are run through `sh -c`, with each `{}` replaced by the image
+path, and `{before}` and `{after}` by its original and optimized sizes. These
+are passed as positional arguments rather than pasted into the script, so odd
+file names can't break (or break out of) the command; just leave the tokens
+unquoted. If there are no tokens at all, the path is tacked onto the end.
+
+There are two of them:
+* `--confirm-cmd` runs before an image is rewritten in place, keeping the
+  original unless it exits successfully;
+* `--on-change` runs after, e.g. to purge the image from a CDN.
+*/
+
+use crate::{
+	FlacaError,
+	Observer,
+	output,
+};
+use dactyl::traits::NiceInflection;
+use fyi_msg::Msg;
+use std::{
+	path::Path,
+	process::{
+		Command,
+		Stdio,
+	},
+	sync::{
+		atomic::{
+			AtomicU64,
+			Ordering::Relaxed,
+		},
+		OnceLock,
+	},
+};
+
+
+
+/// # Confirmation Hook.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static CONFIRM: OnceLock<Hook> = OnceLock::new();
+
+
+
+#[derive(Debug)]
+/// # On Change.
+///
+/// This observer runs the `--on-change` command for each image actually
+/// rewritten — i.e. saved in place, smaller — so CDN and cache purges can
+/// happen as the run goes, rather than by diffing mtimes afterward.
+///
+/// Commands are run on the worker threads, one at a time per thread, so slow
+/// hooks slow the run rather than piling up.
+pub(crate) struct OnChange {
+	/// # Hook.
+	hook: Hook,
+
+	/// # Failures.
+	failed: AtomicU64,
+}
+
+impl Observer for OnChange {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		if after < before && ! output::enabled() && ! self.hook.run(src, before, after) {
+			self.failed.fetch_add(1, Relaxed);
+		}
+	}
+}
+
+impl OnChange {
+	/// # New.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the command is empty.
+	pub(crate) fn new(cmd: &str) -> Result<Self, FlacaError> {
+		let hook = Hook::new(cmd).ok_or(FlacaError::OnChange)?;
+		Ok(Self { hook, failed: AtomicU64::new(0) })
+	}
+
+	/// # Summarize.
+	///
+	/// Mention any failures.
+	pub(crate) fn summarize(self) {
+		let failed = self.failed.into_inner();
+		if failed != 0 {
+			Msg::warning(format!(
+				"The --on-change command failed for {}.",
+				failed.nice_inflect("image", "images"),
+			)).eprint();
+		}
+	}
+}
+
+
+
+/// # Set Confirmation Hook.
+///
+/// ## Errors
+///
+/// An error is returned if the command is empty or has already been set.
+pub(crate) fn set_confirm(cmd: &str) -> Result<(), FlacaError> {
+	let hook = Hook::new(cmd).ok_or(FlacaError::ConfirmCmd)?;
+	CONFIRM.set(hook).map_err(|_| FlacaError::ConfirmCmd)
+}
+
+/// # Confirmed?
+///
+/// Run the `--confirm-cmd`, if any, returning `true` if it approves the
+/// rewrite of `path` from `before` to `after` bytes (or there isn't one).
+pub(crate) fn confirmed(path: &Path, before: u64, after: u64) -> bool {
+	CONFIRM.get().is_none_or(|h| h.run(path, before, after))
+}
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # Hook Command.
+struct Hook(String);
+
+impl Hook {
+	/// # New.
+	///
+	/// Convert the user's command into a script referencing the path and
+	/// sizes as `$1`, `$2`, and `$3`, or return `None` if it is empty.
+	fn new(cmd: &str) -> Option<Self> {
+		let cmd = cmd.trim();
+		if cmd.is_empty() { return None; }
+
+		let script = cmd.replace("{}", "\"$1\"")
+			.replace("{before}", "\"$2\"")
+			.replace("{after}", "\"$3\"");
+		if script == cmd { Some(Self(format!("{cmd} \"$1\""))) }
+		else { Some(Self(script)) }
+	}
+
+	/// # Run.
+	///
+	/// Run the command for `path` — `before` and `after` bytes — returning
+	/// `true` if it exits successfully.
+	///
+	/// The command's output is sent to STDERR so it can't get mixed up with
+	/// anything flaca prints to STDOUT.
+	fn run(&self, path: &Path, before: u64, after: u64) -> bool {
+		Command::new("sh")
+			.arg("-c")
+			.arg(&self.0)
+			.arg("sh")
+			.arg(path)
+			.arg(before.to_string())
+			.arg(after.to_string())
+			.stdin(Stdio::null())
+			.stdout(std::io::stderr())
+			.status()
+			.is_ok_and(|s| s.success())
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_hook() {
+		assert!(Hook::new(" ").is_none());
+		assert_eq!(Hook::new("purge {} --now"), Some(Hook("purge \"$1\" --now".to_owned())));
+		assert_eq!(Hook::new("purge"), Some(Hook("purge \"$1\"".to_owned())));
+		assert_eq!(
+			Hook::new("check {before} {after}"),
+			Some(Hook("check \"$2\" \"$3\"".to_owned())),
+		);
+
+		// The path should come through intact, however weird.
+		let path = std::env::temp_dir().join(format!("flaca-hook-{}; $(false) '\".png", std::process::id()));
+		let _res = std::fs::remove_file(&path);
+		assert!(Hook::new("touch").expect("Missing hook.").run(&path, 10, 5));
+		assert!(path.is_file(), "The hook didn't touch the file.");
+		let _res = std::fs::remove_file(&path);
+
+		assert!(! Hook::new("false {}").expect("Missing hook.").run(&path, 10, 5));
+
+		// Sizes too.
+		let hook = Hook::new("test {before} -gt {after}").expect("Missing hook.");
+		assert!(hook.run(&path, 10, 5));
+		assert!(! hook.run(&path, 5, 10));
+	}
+}
@@ -0,0 +1,333 @@
+/*!
+# Flaca: Ignore Files
+
+With `--respect-gitignore`, directories are walked by hand rather than by the
+crawler, reading the `.gitignore` and `.flacaignore` files found along the
+way and pruning whatever they exclude before descending, so running flaca at
+a project root won't wander into `target/`, `node_modules/`, and the like.
+
+The usual gitignore syntax is supported: comments, `!` negation, trailing `/`
+for directories, leading (or inner) `/` for anchoring, and the `*`, `?`,
+`[...]`, and `**` wildcards. Rules in deeper files take precedence over those
+above them, and `.flacaignore` over `.gitignore` in the same directory.
+
+Only ignore files within the requested directories are considered, and paths
+passed explicitly are never ignored.
+*/
+
+use std::{
+	collections::HashSet,
+	num::NonZeroUsize,
+	os::unix::ffi::OsStrExt,
+	path::{
+		Path,
+		PathBuf,
+	},
+	rc::Rc,
+	sync::atomic::AtomicBool,
+};
+
+
+
+/// # Respect Ignore Files?
+pub(crate) static RESPECT_IGNORE: AtomicBool = AtomicBool::new(false);
+
+/// # Ignore File Names.
+///
+/// In order of precedence, lowest first.
+const FILES: [&str; 2] = [".gitignore", ".flacaignore"];
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # Ignore Rule.
+///
+/// A single (parsed) line from an ignore file.
+struct Rule {
+	/// # Base Directory.
+	///
+	/// The directory holding the ignore file.
+	base: Rc<Path>,
+
+	/// # Pattern.
+	pattern: Box<[u8]>,
+
+	/// # Negated?
+	negate: bool,
+
+	/// # Directories Only?
+	dir_only: bool,
+
+	/// # Anchored?
+	///
+	/// Anchored patterns are matched against the path relative to the base;
+	/// the rest against the file name alone.
+	anchored: bool,
+}
+
+impl Rule {
+	/// # Parse.
+	///
+	/// Parse a line from an ignore file, returning `None` for blanks and
+	/// comments.
+	fn parse(base: &Rc<Path>, line: &[u8]) -> Option<Self> {
+		// Trailing whitespace is ignored unless escaped.
+		let mut line = line.strip_suffix(b"\r").unwrap_or(line);
+		while let [rest @ .., b' ' | b'\t'] = line {
+			if rest.last() == Some(&b'\\') { break; }
+			line = rest;
+		}
+		if line.is_empty() || line[0] == b'#' { return None; }
+
+		let (negate, line) = line.strip_prefix(b"!").map_or((false, line), |l| (true, l));
+		let (dir_only, line) = line.strip_suffix(b"/").map_or((false, line), |l| (true, l));
+		let anchored = line.contains(&b'/');
+		let line = line.strip_prefix(b"/").unwrap_or(line);
+		if line.is_empty() { return None; }
+
+		Some(Self {
+			base: Rc::clone(base),
+			pattern: line.into(),
+			negate,
+			dir_only,
+			anchored,
+		})
+	}
+
+	/// # Matches?
+	fn matches(&self, path: &Path, is_dir: bool) -> bool {
+		if self.dir_only && ! is_dir { return false; }
+		if self.anchored {
+			path.strip_prefix(&self.base)
+				.is_ok_and(|rel| glob(&self.pattern, rel.as_os_str().as_bytes()))
+		}
+		else {
+			path.file_name().is_some_and(|n| glob(&self.pattern, n.as_bytes()))
+		}
+	}
+}
+
+
+
+/// # Expand.
+///
+/// Replace any directories among `paths` with the files found within them,
+/// recursively (up to `depth` levels down, if specified), skipping anything
+/// excluded by an ignore file.
+///
+/// Files are passed through as-are.
+pub(crate) fn expand<P, I>(paths: I, depth: Option<NonZeroUsize>) -> Vec<PathBuf>
+where P: AsRef<Path>, I: IntoIterator<Item=P> {
+	let depth = depth.map_or(usize::MAX, NonZeroUsize::get);
+	let mut out = Vec::new();
+	let mut seen = HashSet::new();
+	let mut stack: Vec<(PathBuf, usize, Rc<Vec<Rule>>)> = paths.into_iter()
+		.map(|p| (p.as_ref().to_path_buf(), 0, Rc::default()))
+		.collect();
+
+	while let Some((path, level, rules)) = stack.pop() {
+		if ! path.is_dir() {
+			out.push(path);
+			continue;
+		}
+
+		// Guard against symlink loops.
+		if level >= depth || ! std::fs::canonicalize(&path).is_ok_and(|p| seen.insert(p)) {
+			continue;
+		}
+		let Ok(entries) = std::fs::read_dir(&path) else { continue; };
+		let rules = load(&path, rules);
+
+		for e in entries.filter_map(Result::ok) {
+			let entry = e.path();
+			if e.file_name() == ".git" { continue; }
+			let is_dir = entry.is_dir();
+			if ! ignored(&rules, &entry, is_dir) {
+				stack.push((entry, level + 1, Rc::clone(&rules)));
+			}
+		}
+	}
+
+	out
+}
+
+/// # Ignored?
+///
+/// The last matching rule wins.
+fn ignored(rules: &[Rule], path: &Path, is_dir: bool) -> bool {
+	rules.iter().rev()
+		.find(|r| r.matches(path, is_dir))
+		.is_some_and(|r| ! r.negate)
+}
+
+/// # Load Rules.
+///
+/// Add the rules from any ignore files in `dir` to those inherited from its
+/// parents.
+fn load(dir: &Path, parent: Rc<Vec<Rule>>) -> Rc<Vec<Rule>> {
+	let mut rules: Option<Vec<Rule>> = None;
+	let base: Rc<Path> = Rc::from(dir);
+	for name in FILES {
+		let Ok(raw) = std::fs::read(dir.join(name)) else { continue; };
+		rules.get_or_insert_with(|| parent.as_ref().clone())
+			.extend(raw.split(|&b| b == b'\n').filter_map(|l| Rule::parse(&base, l)));
+	}
+
+	rules.map_or(parent, Rc::new)
+}
+
+/// # Glob Match.
+///
+/// Match `text` against a gitignore-style `pattern`. Single wildcards never
+/// cross a `/`; `**` segments match any number of directories.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+	match pattern {
+		[] => text.is_empty(),
+
+		// Leading or inner double-stars match zero or more directories.
+		[b'*', b'*', b'/', rest @ ..] => {
+			glob(rest, text) ||
+			text.iter().enumerate().any(|(i, &b)| b == b'/' && glob(rest, &text[i + 1..]))
+		},
+
+		// Trailing double-stars match everything.
+		[b'*', b'*'] => true,
+
+		[b'*', rest @ ..] => {
+			let end = text.iter().position(|&b| b == b'/').unwrap_or(text.len());
+			(0..=end).any(|i| glob(rest, &text[i..]))
+		},
+
+		[b'?', rest @ ..] => match text {
+			[t, text @ ..] if *t != b'/' => glob(rest, text),
+			_ => false,
+		},
+
+		[b'[', rest @ ..] => match (class(rest), text) {
+			(Some((matched, rest)), [t, text @ ..]) => matched(*t) && glob(rest, text),
+			// Unterminated classes are literal.
+			(None, [b'[', text @ ..]) => glob(rest, text),
+			_ => false,
+		},
+
+		[b'\\', p, rest @ ..] | [p, rest @ ..] => match text {
+			[t, text @ ..] if t == p => glob(rest, text),
+			_ => false,
+		},
+	}
+}
+
+/// # Character Class.
+///
+/// Parse a `[...]` class — minus the opening bracket — returning a matcher
+/// and the remainder of the pattern, or `None` if it is unterminated.
+fn class(pattern: &[u8]) -> Option<(impl Fn(u8) -> bool + '_, &[u8])> {
+	let (negate, body) = match pattern {
+		[b'!' | b'^', rest @ ..] => (true, rest),
+		_ => (false, pattern),
+	};
+
+	// A leading bracket is literal.
+	let end = body.iter().skip(1).position(|&b| b == b']')? + 1;
+	let (set, rest) = (&body[..end], &body[end + 1..]);
+
+	let matched = move |c: u8| {
+		if c == b'/' { return false; }
+		let mut i = 0;
+		let mut found = false;
+		while i < set.len() {
+			if i + 2 < set.len() && set[i + 1] == b'-' {
+				found |= set[i] <= c && c <= set[i + 2];
+				i += 3;
+			}
+			else {
+				found |= set[i] == c;
+				i += 1;
+			}
+		}
+		found != negate
+	};
+
+	Some((matched, rest))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_glob() {
+		for (pattern, text, expected) in [
+			("*.png", "foo.png", true),
+			("*.png", "foo.jpg", false),
+			("*.png", "dir/foo.png", false),
+			("foo?.png", "foo1.png", true),
+			("foo?.png", "foo.png", false),
+			("img[0-9].png", "img5.png", true),
+			("img[!0-9].png", "img5.png", false),
+			("img[!0-9].png", "imgx.png", true),
+			("img[.png", "img[.png", true),
+			("**/foo", "foo", true),
+			("**/foo", "a/b/foo", true),
+			("a/**/b", "a/b", true),
+			("a/**/b", "a/x/y/b", true),
+			("a/**", "a/x/y", true),
+			("a/**", "b/x", false),
+			("\\*.png", "*.png", true),
+			("\\*.png", "a.png", false),
+		] {
+			assert_eq!(glob(pattern.as_bytes(), text.as_bytes()), expected, "{pattern} vs {text}");
+		}
+	}
+
+	#[test]
+	fn t_rule() {
+		let base: Rc<Path> = Rc::from(Path::new("/root"));
+		assert!(Rule::parse(&base, b"").is_none());
+		assert!(Rule::parse(&base, b"  ").is_none());
+		assert!(Rule::parse(&base, b"# Comment").is_none());
+
+		let rule = Rule::parse(&base, b"!/build/ ").expect("Missing rule.");
+		assert!(rule.negate && rule.dir_only && rule.anchored);
+		assert_eq!(&*rule.pattern, b"build");
+		assert!(rule.matches(Path::new("/root/build"), true));
+		assert!(! rule.matches(Path::new("/root/build"), false));
+		assert!(! rule.matches(Path::new("/root/src/build"), true));
+
+		let rule = Rule::parse(&base, b"*.gif").expect("Missing rule.");
+		assert!(! rule.anchored);
+		assert!(rule.matches(Path::new("/root/a/b/c.gif"), false));
+	}
+
+	#[test]
+	fn t_expand() {
+		let dir = std::env::temp_dir().join(format!("flaca-ignore-{}", std::process::id()));
+		let _res = std::fs::remove_dir_all(&dir);
+		for p in ["a.png", "b.gif", "keep.gif", "build/c.png", "sub/d.png", "sub/e.png", ".git/f.png"] {
+			let p = dir.join(p);
+			std::fs::create_dir_all(p.parent().expect("Missing parent.")).expect("Unable to create directory.");
+			std::fs::write(p, b"a").expect("Unable to write file.");
+		}
+		std::fs::write(dir.join(".gitignore"), b"build/\n*.gif\n").expect("Unable to write file.");
+		std::fs::write(dir.join(".flacaignore"), b"!keep.gif\n").expect("Unable to write file.");
+		std::fs::write(dir.join("sub/.gitignore"), b"/d.png\n").expect("Unable to write file.");
+
+		let mut found = expand([&dir], None);
+		found.retain(|p| p.extension().is_some());
+		found.sort();
+		assert_eq!(found, [dir.join("a.png"), dir.join("keep.gif"), dir.join("sub/e.png")]);
+
+		// Depth limits still apply.
+		let mut found = expand([&dir], NonZeroUsize::new(1));
+		found.retain(|p| p.extension().is_some_and(|e| e == "png"));
+		assert_eq!(found, [dir.join("a.png")]);
+
+		// Explicit files are never ignored.
+		let file = dir.join("b.gif");
+		assert_eq!(expand([&file], None), [file]);
+
+		let _res = std::fs::remove_dir_all(&dir);
+	}
+}
@@ -0,0 +1,463 @@
+/*!
+# Flaca: Archives
+
+With `--archives`, ZIP and TAR bundles are opened up and the images inside
+are crunched in memory just like their loose counterparts. The archive is
+only rewritten if the total shrinks.
+
+Member names, timestamps, permissions, comments, etc., are carried over
+verbatim; only the image data — and the sizes and checksums describing it —
+changes.
+
+Limitations:
+* ZIP members are only touched if stored (rather than deflated) and not
+  encrypted; the rest are copied as-are;
+* ZIP64 and multi-disk archives are left alone entirely;
+* TAR archives must be uncompressed, and are left alone if they use PAX size
+  overrides.
+*/
+
+use crate::{
+	E_TAR,
+	E_ZIP,
+};
+use dowser::Extension;
+use std::{
+	path::Path,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::Relaxed,
+	},
+};
+
+
+
+/// # Process Archives?
+pub(crate) static ARCHIVES: AtomicBool = AtomicBool::new(false);
+
+/// # ZIP: Local File Header Signature.
+const ZIP_LOCAL: [u8; 4] = *b"PK\x03\x04";
+
+/// # ZIP: Central Directory Header Signature.
+const ZIP_CENTRAL: [u8; 4] = *b"PK\x01\x02";
+
+/// # ZIP: End of Central Directory Signature.
+const ZIP_END: [u8; 4] = *b"PK\x05\x06";
+
+/// # ZIP: Local File Header Length.
+const ZIP_LOCAL_LEN: usize = 30;
+
+/// # ZIP: Central Directory Header Length.
+const ZIP_CENTRAL_LEN: usize = 46;
+
+/// # ZIP: End of Central Directory Length.
+const ZIP_END_LEN: usize = 22;
+
+/// # ZIP: Data Descriptor Flag.
+const ZIP_DESCRIPTOR: u16 = 0b1000;
+
+/// # ZIP: Encrypted Flag.
+const ZIP_ENCRYPTED: u16 = 0b0001;
+
+/// # TAR: Block Size.
+const TAR_BLOCK: usize = 512;
+
+
+
+#[inline]
+/// # Enabled?
+pub(super) fn enabled() -> bool { ARCHIVES.load(Relaxed) }
+
+/// # Is Archive?
+pub(super) fn is_archive(src: &[u8]) -> bool { is_zip(src) || is_tar(src) }
+
+/// # Is Archive Path?
+///
+/// Returns `true` if `src` has a ZIP or TAR extension.
+pub(super) fn is_archive_path(src: &Path) -> bool {
+	Extension::try_from3(src).is_some_and(|e| e == E_TAR || e == E_ZIP)
+}
+
+/// # Optimize Archive.
+///
+/// Pass each member through `crunch` — which should return a smaller
+/// version, if possible — and rebuild the archive. `None` is returned if the
+/// archive is unsupported or malformed, or no savings were possible.
+pub(super) fn optimize<F>(src: &[u8], crunch: F) -> Option<Vec<u8>>
+where F: FnMut(&[u8]) -> Option<Vec<u8>> {
+	let out =
+		if is_zip(src) { zip(src, crunch)? }
+		else if is_tar(src) { tar(src, crunch)? }
+		else { return None; };
+
+	if out.len() < src.len() { Some(out) }
+	else { None }
+}
+
+
+
+/// # Is ZIP?
+fn is_zip(src: &[u8]) -> bool {
+	src.starts_with(&ZIP_LOCAL) && zip_end(src).is_some()
+}
+
+/// # Find ZIP End of Central Directory.
+///
+/// This is the last thing in the file, save for an optional comment.
+fn zip_end(src: &[u8]) -> Option<usize> {
+	let last = src.len().checked_sub(ZIP_END_LEN)?;
+	let first = last.saturating_sub(usize::from(u16::MAX));
+	(first..=last).rev().find(|&pos|
+		src[pos..pos + 4] == ZIP_END &&
+		pos + ZIP_END_LEN + usize::from(read_u16(src, pos + 20).unwrap_or(0)) == src.len()
+	)
+}
+
+/// # Rebuild ZIP.
+fn zip<F>(src: &[u8], mut crunch: F) -> Option<Vec<u8>>
+where F: FnMut(&[u8]) -> Option<Vec<u8>> {
+	/// # Entry.
+	struct Entry<'a> {
+		/// # Central Header (and Name, Extra, Comment).
+		central: &'a [u8],
+
+		/// # Local Header (and Name, Extra).
+		local: &'a [u8],
+
+		/// # Local Header Offset.
+		offset: usize,
+
+		/// # Data.
+		data: &'a [u8],
+
+		/// # Crunched Data.
+		new: Option<Vec<u8>>,
+	}
+
+	let end = zip_end(src)?;
+
+	// Single-disk, non-ZIP64 archives only.
+	let count = read_u16(src, end + 10)?;
+	let cd_offset = read_u32(src, end + 16)?;
+	if
+		read_u16(src, end + 4)? != 0 ||
+		read_u16(src, end + 6)? != 0 ||
+		count == u16::MAX ||
+		cd_offset == u32::MAX
+	{
+		return None;
+	}
+
+	// Parse the central directory, and find the corresponding local data.
+	let mut entries = Vec::with_capacity(usize::from(count));
+	let mut pos = usize::try_from(cd_offset).ok()?;
+	for _ in 0..count {
+		if src.get(pos..pos + 4)? != ZIP_CENTRAL { return None; }
+		let flags = read_u16(src, pos + 8)?;
+		let method = read_u16(src, pos + 10)?;
+		let size = read_u32(src, pos + 20)?;
+		if size == u32::MAX || read_u32(src, pos + 24)? == u32::MAX { return None; }
+		let len = ZIP_CENTRAL_LEN +
+			usize::from(read_u16(src, pos + 28)?) +
+			usize::from(read_u16(src, pos + 30)?) +
+			usize::from(read_u16(src, pos + 32)?);
+		let central = src.get(pos..pos + len)?;
+
+		let offset = usize::try_from(read_u32(src, pos + 42)?).ok()?;
+		if src.get(offset..offset + 4)? != ZIP_LOCAL { return None; }
+		let local_len = ZIP_LOCAL_LEN +
+			usize::from(read_u16(src, offset + 26)?) +
+			usize::from(read_u16(src, offset + 28)?);
+		let local = src.get(offset..offset + local_len)?;
+		let data = src.get(offset + local_len..offset + local_len + usize::try_from(size).ok()?)?;
+
+		let new =
+			if method == 0 && 0 == flags & ZIP_ENCRYPTED { crunch(data).filter(|n| n.len() < data.len()) }
+			else { None };
+
+		entries.push(Entry { central, local, offset, data, new });
+		pos += len;
+	}
+
+	// Nothing to do?
+	if entries.iter().all(|e| e.new.is_none()) { return None; }
+
+	// Write the local data in its original order, keeping anything before
+	// the first entry (e.g. a self-extractor stub).
+	let mut order: Vec<usize> = (0..entries.len()).collect();
+	order.sort_unstable_by_key(|&idx| entries[idx].offset);
+	let mut out = Vec::with_capacity(src.len());
+	out.extend_from_slice(src.get(..order.first().map_or(0, |&idx| entries[idx].offset))?);
+
+	let mut offsets = vec![0_u32; entries.len()];
+	for idx in order {
+		let e = &entries[idx];
+		let data = e.new.as_deref().unwrap_or(e.data);
+		let crc = if e.new.is_some() { crc32fast::hash(data) } else { read_u32(e.central, 16)? };
+		let size = u32::try_from(data.len()).ok()?;
+		let full = if e.new.is_some() { size } else { read_u32(e.central, 24)? };
+
+		// The sizes are known now, so data descriptors aren't needed.
+		offsets[idx] = u32::try_from(out.len()).ok()?;
+		let start = out.len();
+		out.extend_from_slice(e.local);
+		write_u16(&mut out, start + 6, read_u16(e.local, 6)? & ! ZIP_DESCRIPTOR);
+		write_u32(&mut out, start + 14, crc);
+		write_u32(&mut out, start + 18, size);
+		write_u32(&mut out, start + 22, full);
+		out.extend_from_slice(data);
+	}
+
+	// Now the central directory, in its original order.
+	let cd_start = out.len();
+	for (e, offset) in entries.iter().zip(offsets) {
+		let data = e.new.as_deref().unwrap_or(e.data);
+		let start = out.len();
+		out.extend_from_slice(e.central);
+		write_u16(&mut out, start + 8, read_u16(e.central, 8)? & ! ZIP_DESCRIPTOR);
+		if e.new.is_some() {
+			let size = u32::try_from(data.len()).ok()?;
+			write_u32(&mut out, start + 16, crc32fast::hash(data));
+			write_u32(&mut out, start + 20, size);
+			write_u32(&mut out, start + 24, size);
+		}
+		write_u32(&mut out, start + 42, offset);
+	}
+
+	// And finally the end record (and comment).
+	let cd_len = u32::try_from(out.len() - cd_start).ok()?;
+	let cd_start = u32::try_from(cd_start).ok()?;
+	let start = out.len();
+	out.extend_from_slice(&src[end..]);
+	write_u32(&mut out, start + 12, cd_len);
+	write_u32(&mut out, start + 16, cd_start);
+
+	Some(out)
+}
+
+
+
+/// # Is TAR?
+///
+/// TAR has no magic to speak of, but each header has a checksum.
+fn is_tar(src: &[u8]) -> bool {
+	src.get(..TAR_BLOCK).is_some_and(|h| h.iter().any(|&b| b != 0) && tar_checksum_ok(h))
+}
+
+/// # Rebuild TAR.
+fn tar<F>(src: &[u8], mut crunch: F) -> Option<Vec<u8>>
+where F: FnMut(&[u8]) -> Option<Vec<u8>> {
+	let mut out = Vec::with_capacity(src.len());
+	let mut changed = false;
+	let mut pos = 0;
+	loop {
+		// The end is marked by empty blocks; copy those (and anything
+		// after) verbatim.
+		let Some(header) = src.get(pos..pos + TAR_BLOCK) else {
+			if let Some(rest) = src.get(pos..) { out.extend_from_slice(rest); }
+			break;
+		};
+		if header.iter().all(|&b| b == 0) {
+			out.extend_from_slice(&src[pos..]);
+			break;
+		}
+		if ! tar_checksum_ok(header) { return None; }
+
+		let size = read_octal(&header[124..136])?;
+		let data = src.get(pos + TAR_BLOCK..pos + TAR_BLOCK + size)?;
+		let next = pos + TAR_BLOCK + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+		let new = match header[156] {
+			// PAX headers might override the size of the next entry, which
+			// we'd then get wrong.
+			b'x' | b'g' => {
+				if data.windows(6).any(|w| w == b" size=") { return None; }
+				None
+			},
+			// Regular files.
+			0 | b'0' | b'7' => crunch(data).filter(|n| n.len() < data.len()),
+			_ => None,
+		};
+
+		if let Some(new) = new {
+			let start = out.len();
+			out.extend_from_slice(header);
+			out[start + 124..start + 136].copy_from_slice(format!("{:011o}\0", new.len()).as_bytes());
+			out[start + 148..start + 156].copy_from_slice(b"        ");
+			let sum: u32 = out[start..].iter().copied().map(u32::from).sum();
+			out[start + 148..start + 156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+			out.extend_from_slice(&new);
+			out.resize(out.len() + new.len().next_multiple_of(TAR_BLOCK) - new.len(), 0);
+			changed = true;
+		}
+		else { out.extend_from_slice(src.get(pos..next.min(src.len()))?); }
+
+		pos = next;
+	}
+
+	if changed { Some(out) }
+	else { None }
+}
+
+/// # TAR Checksum Okay?
+///
+/// The checksum is the sum of the header bytes, with the checksum field
+/// itself counted as spaces.
+fn tar_checksum_ok(header: &[u8]) -> bool {
+	let Some(expected) = read_octal(&header[148..156]) else { return false; };
+	let sum: usize = header.iter().enumerate()
+		.map(|(idx, &b)| if (148..156).contains(&idx) { usize::from(b' ') } else { usize::from(b) })
+		.sum();
+	sum == expected
+}
+
+/// # Read Octal.
+///
+/// TAR numbers are octal strings, padded with spaces and/or NULs.
+fn read_octal(raw: &[u8]) -> Option<usize> {
+	let raw = raw.trim_ascii_start();
+	let end = raw.iter().position(|&b| b == 0 || b == b' ').unwrap_or(raw.len());
+	let raw = &raw[..end];
+	if raw.is_empty() { return Some(0); }
+	raw.iter().try_fold(0_usize, |acc, &b|
+		if b.is_ascii_digit() && b < b'8' { acc.checked_mul(8)?.checked_add(usize::from(b - b'0')) }
+		else { None }
+	)
+}
+
+
+
+/// # Read u16 (LE).
+fn read_u16(src: &[u8], idx: usize) -> Option<u16> {
+	let &[a, b] = src.get(idx..idx + 2)? else { return None; };
+	Some(u16::from_le_bytes([a, b]))
+}
+
+/// # Read u32 (LE).
+fn read_u32(src: &[u8], idx: usize) -> Option<u32> {
+	let &[a, b, c, d] = src.get(idx..idx + 4)? else { return None; };
+	Some(u32::from_le_bytes([a, b, c, d]))
+}
+
+/// # Write u16 (LE).
+fn write_u16(out: &mut [u8], idx: usize, num: u16) {
+	out[idx..idx + 2].copy_from_slice(&num.to_le_bytes());
+}
+
+/// # Write u32 (LE).
+fn write_u32(out: &mut [u8], idx: usize, num: u32) {
+	out[idx..idx + 4].copy_from_slice(&num.to_le_bytes());
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// # Fake Crunch.
+	///
+	/// Halve anything starting with "IMG".
+	fn crunch(src: &[u8]) -> Option<Vec<u8>> {
+		if src.starts_with(b"IMG") { Some(src[..src.len() / 2].to_vec()) }
+		else { None }
+	}
+
+	/// # Build a (Stored) ZIP.
+	fn zip_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut out = Vec::new();
+		let mut central = Vec::new();
+		for (name, data) in files {
+			let offset = u32::try_from(out.len()).unwrap_or_default();
+			let size = u32::try_from(data.len()).unwrap_or_default();
+			let name_len = u16::try_from(name.len()).unwrap_or_default();
+
+			out.extend_from_slice(&ZIP_LOCAL);
+			out.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+			out.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+			out.extend_from_slice(&size.to_le_bytes());
+			out.extend_from_slice(&size.to_le_bytes());
+			out.extend_from_slice(&name_len.to_le_bytes());
+			out.extend_from_slice(&[0, 0]);
+			out.extend_from_slice(name.as_bytes());
+			out.extend_from_slice(data);
+
+			central.extend_from_slice(&ZIP_CENTRAL);
+			central.extend_from_slice(&[20, 3, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+			central.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+			central.extend_from_slice(&size.to_le_bytes());
+			central.extend_from_slice(&size.to_le_bytes());
+			central.extend_from_slice(&name_len.to_le_bytes());
+			central.extend_from_slice(&[0; 12]);
+			central.extend_from_slice(&offset.to_le_bytes());
+			central.extend_from_slice(name.as_bytes());
+		}
+
+		let count = u16::try_from(files.len()).unwrap_or_default();
+		let cd_offset = u32::try_from(out.len()).unwrap_or_default();
+		let cd_len = u32::try_from(central.len()).unwrap_or_default();
+		out.extend_from_slice(&central);
+		out.extend_from_slice(&ZIP_END);
+		out.extend_from_slice(&[0, 0, 0, 0]);
+		out.extend_from_slice(&count.to_le_bytes());
+		out.extend_from_slice(&count.to_le_bytes());
+		out.extend_from_slice(&cd_len.to_le_bytes());
+		out.extend_from_slice(&cd_offset.to_le_bytes());
+		out.extend_from_slice(&[0, 0]);
+		out
+	}
+
+	/// # Build a TAR.
+	fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut out = Vec::new();
+		for (name, data) in files {
+			let mut header = [0_u8; TAR_BLOCK];
+			header[..name.len()].copy_from_slice(name.as_bytes());
+			header[100..108].copy_from_slice(b"0000644\0");
+			header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+			header[156] = b'0';
+			header[257..263].copy_from_slice(b"ustar\0");
+			header[148..156].copy_from_slice(b"        ");
+			let sum: u32 = header.iter().copied().map(u32::from).sum();
+			header[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+
+			out.extend_from_slice(&header);
+			out.extend_from_slice(data);
+			out.resize(out.len().next_multiple_of(TAR_BLOCK), 0);
+		}
+		out.resize(out.len() + TAR_BLOCK * 2, 0);
+		out
+	}
+
+	#[test]
+	fn t_zip() {
+		let img: &[u8] = &[b"IMG".as_slice(), &[7; 1000]].concat();
+		let raw = zip_of(&[("readme.txt", b"Hello World"), ("a/logo.png", img)]);
+		assert!(is_archive(&raw), "ZIP not detected.");
+
+		let Some(new) = optimize(&raw, crunch) else { panic!("ZIP not optimized."); };
+		assert!(new.len() < raw.len(), "ZIP didn't shrink.");
+
+		// It should match a freshly-built archive with the smaller image.
+		let expected = zip_of(&[("readme.txt", b"Hello World"), ("a/logo.png", &img[..501])]);
+		assert_eq!(new, expected, "ZIP rebuilt incorrectly.");
+
+		// Nothing to do.
+		assert!(optimize(&raw, |_| None).is_none(), "Unchanged ZIP rewritten.");
+	}
+
+	#[test]
+	fn t_tar() {
+		let img: &[u8] = &[b"IMG".as_slice(), &[7; 1500]].concat();
+		let raw = tar_of(&[("readme.txt", b"Hello World"), ("a/logo.png", img)]);
+		assert!(is_archive(&raw), "TAR not detected.");
+
+		let Some(new) = optimize(&raw, crunch) else { panic!("TAR not optimized."); };
+		let expected = tar_of(&[("readme.txt", b"Hello World"), ("a/logo.png", &img[..751])]);
+		assert_eq!(new, expected, "TAR rebuilt incorrectly.");
+
+		// Garbage isn't an archive.
+		assert!(! is_archive(&[1; 2048]), "Garbage detected as archive.");
+		assert!(! is_archive(&[0; 2048]), "Empty blocks detected as archive.");
+	}
+}
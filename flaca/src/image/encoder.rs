@@ -0,0 +1,154 @@
+/*!
+# Flaca: Encoders
+
+Each encoding pass — `MozJPEG`, `Oxipng`, etc. — is an `Encoder`. The
+optimizer runs the ones matching the image's kind, in order, keeping track
+of the sizes along the way.
+
+Pipelines are just (static) lists of encoders, so passes can be added,
+removed, or reordered without touching the dispatch logic.
+*/
+
+use crate::{
+	Profile,
+	Stage,
+};
+use std::{
+	fmt,
+	sync::atomic::Ordering::Relaxed,
+};
+use super::{
+	encode_gif,
+	encode_ico,
+	encode_mozjpeg,
+	encode_oxipng,
+	encode_zopflipng,
+	ImageKind,
+	OXIPNG,
+	ZOPFLI,
+};
+
+
+
+/// # Built-In Pipeline.
+///
+/// This is the default set of encoders, in the order they're run.
+pub(crate) const BUILTIN: &[&dyn Encoder] = &[
+	&GifEncoder,
+	&IcoEncoder,
+	&MozjpegEncoder,
+	&OxipngEncoder,
+	&ZopflipngEncoder,
+];
+
+
+
+/// # Encoder.
+///
+/// An encoding pass for a single kind of image.
+///
+/// Passes must be lossless, and should leave `raw` untouched unless they
+/// manage to make it smaller. Panics are caught by the optimizer, which will
+/// abandon the image (keeping the original).
+pub(crate) trait Encoder: fmt::Debug + Sync {
+	/// # Stage.
+	///
+	/// The stage to report the pass as.
+	fn stage(&self) -> Stage;
+
+	/// # Image Kind.
+	///
+	/// The kind of image this pass applies to.
+	fn kind(&self) -> ImageKind;
+
+	/// # Skip?
+	///
+	/// Return the reason this pass shouldn't be run on the (original) image,
+	/// if any.
+	fn skip(&self, _src: &[u8], _profile: Profile) -> Option<&'static str> { None }
+
+	/// # Encode.
+	///
+	/// Re-encode the image in place.
+	fn encode(&self, raw: &mut Vec<u8>, profile: Profile);
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # GIF Cleanup.
+pub(crate) struct GifEncoder;
+
+impl Encoder for GifEncoder {
+	fn stage(&self) -> Stage { Stage::Gif }
+	fn kind(&self) -> ImageKind { ImageKind::GIF }
+	fn encode(&self, raw: &mut Vec<u8>, _profile: Profile) { encode_gif(raw); }
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # ICO/CUR Members.
+pub(crate) struct IcoEncoder;
+
+impl Encoder for IcoEncoder {
+	fn stage(&self) -> Stage { Stage::Ico }
+	fn kind(&self) -> ImageKind { ImageKind::ICO }
+	fn encode(&self, raw: &mut Vec<u8>, profile: Profile) { encode_ico(raw, profile); }
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # `MozJPEG`.
+pub(crate) struct MozjpegEncoder;
+
+impl Encoder for MozjpegEncoder {
+	fn stage(&self) -> Stage { Stage::Mozjpeg }
+	fn kind(&self) -> ImageKind { ImageKind::JPEG }
+	fn encode(&self, raw: &mut Vec<u8>, _profile: Profile) {
+		encode_mozjpeg(raw);
+
+		// Encoding checks this explicitly, but debug asserts are nothing if
+		// not redundant!
+		debug_assert!(ImageKind::is_jpeg(raw), "BUG: raw was unexpectedly corrupted");
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # `Oxipng`.
+pub(crate) struct OxipngEncoder;
+
+impl Encoder for OxipngEncoder {
+	fn stage(&self) -> Stage { Stage::Oxipng }
+	fn kind(&self) -> ImageKind { ImageKind::PNG }
+	fn skip(&self, src: &[u8], _profile: Profile) -> Option<&'static str> {
+		// The encoder would strip the animation, so leave these be.
+		if ImageKind::is_apng(src) { Some("animated (APNG)") }
+		else if OXIPNG.load(Relaxed) { None }
+		else { Some("disabled (--no-oxipng)") }
+	}
+	fn encode(&self, raw: &mut Vec<u8>, profile: Profile) { encode_oxipng(raw, profile); }
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # `Zopflipng`.
+pub(crate) struct ZopflipngEncoder;
+
+impl Encoder for ZopflipngEncoder {
+	fn stage(&self) -> Stage { Stage::Zopflipng }
+	fn kind(&self) -> ImageKind { ImageKind::PNG }
+	fn skip(&self, src: &[u8], profile: Profile) -> Option<&'static str> {
+		// The encoder would strip the animation, so leave these be.
+		if ImageKind::is_apng(src) { Some("animated (APNG)") }
+		else if ! ZOPFLI.load(Relaxed) { Some("disabled (--no-zopfli)") }
+		// Zopfli is slow; skip it if we're in a hurry.
+		else if profile.zopfli() { None }
+		else { Some("fast profile") }
+	}
+	fn encode(&self, raw: &mut Vec<u8>, _profile: Profile) { encode_zopflipng(raw); }
+}
@@ -0,0 +1,267 @@
+/*!
+# Flaca: EXIF
+
+This module contains just enough TIFF/EXIF parsing to find and reset the
+Orientation, and to strip the embedded (IFD1) thumbnail without disturbing
+any of the other metadata.
+*/
+
+
+
+/// # EXIF Signature.
+pub(super) const SIG_EXIF: &[u8] = b"Exif\0\0";
+
+/// # EXIF Tag: Orientation.
+const TAG_ORIENTATION: u16 = 0x0112;
+
+/// # EXIF Tags: Sub-IFD Pointers (EXIF, GPS, Interoperability).
+const TAG_SUB_IFDS: [u16; 3] = [0x8769, 0x8825, 0xA005];
+
+/// # Maximum Sub-IFD Depth.
+///
+/// Real-world files only nest a couple levels deep; anything beyond this is
+/// probably a loop.
+const MAX_DEPTH: u8 = 4;
+
+
+
+/// # Orientation.
+///
+/// Parse the Orientation tag from the first IFD of an EXIF (APP1) marker,
+/// returning its value and the byte position of said value within `data`.
+pub(super) fn orientation(data: &[u8]) -> Option<(u16, usize)> {
+	let tiff = Tiff::new(data)?;
+
+	// Loop through the IFD0 entries.
+	let ifd = tiff.offset_at(4)?;
+	let count = usize::from(tiff.u16_at(ifd)?);
+	for i in 0..count {
+		let pos = ifd + 2 + i * 12;
+		if tiff.u16_at(pos)? == TAG_ORIENTATION {
+			// The value should be a SHORT.
+			if tiff.u16_at(pos + 2)? != 3 { return None; }
+			let value = tiff.u16_at(pos + 8)?;
+			return Some((value, SIG_EXIF.len() + pos + 8));
+		}
+	}
+
+	None
+}
+
+/// # Reset Orientation.
+///
+/// Set the Orientation (if any) back to normal, returning `true` if the data
+/// was changed.
+pub(super) fn reset_orientation(data: &mut [u8]) -> bool {
+	if let Some((value, pos)) = orientation(data) {
+		if value != 1 {
+			let normal =
+				if data[SIG_EXIF.len()] == b'M' { 1_u16.to_be_bytes() }
+				else { 1_u16.to_le_bytes() };
+			data[pos..pos + 2].copy_from_slice(&normal);
+			return true;
+		}
+	}
+
+	false
+}
+
+/// # Strip Thumbnail.
+///
+/// Unlink IFD1 — the thumbnail directory — from IFD0 and truncate everything
+/// that is no longer referenced, returning `true` if the data was changed.
+///
+/// Cameras almost always stick the thumbnail at the very end, but if
+/// anything still in use lives after it, or the structure can't be fully
+/// parsed, the data is left alone.
+pub(super) fn strip_thumbnail(data: &mut Vec<u8>) -> bool {
+	let Some((link, end)) = Tiff::new(data).and_then(|tiff| {
+		let ifd0 = tiff.offset_at(4)?;
+		let link = ifd0 + 2 + usize::from(tiff.u16_at(ifd0)?) * 12;
+
+		// Nothing to do if there's no IFD1.
+		if tiff.u32_at(link)? == 0 { return None; }
+
+		// Everything still needed ends here, rounded up to a word boundary.
+		let end = tiff.extent(ifd0, 0)?.max(8);
+		Some((link, end + (end & 1)))
+	})
+	else { return false; };
+
+	// Only bother if there's something to cut.
+	let (link, end) = (SIG_EXIF.len() + link, SIG_EXIF.len() + end);
+	if end < data.len() {
+		data[link..link + 4].fill(0);
+		data.truncate(end);
+		true
+	}
+	else { false }
+}
+
+
+
+/// # TIFF Reader.
+///
+/// This is a thin, bounds-checked wrapper around the TIFF data following the
+/// EXIF signature.
+struct Tiff<'a> {
+	/// # Data.
+	data: &'a [u8],
+
+	/// # Big Endian?
+	be: bool,
+}
+
+impl<'a> Tiff<'a> {
+	/// # New.
+	///
+	/// Strip the EXIF signature and sort out the byte order.
+	fn new(data: &'a [u8]) -> Option<Self> {
+		let data = data.strip_prefix(SIG_EXIF)?;
+		let be = match data.get(..4)? {
+			b"MM\0*" => true,
+			b"II*\0" => false,
+			_ => return None,
+		};
+		Some(Self { data, be })
+	}
+
+	/// # U16 At.
+	fn u16_at(&self, pos: usize) -> Option<u16> {
+		let bytes = self.data.get(pos..pos + 2)?.try_into().ok()?;
+		Some(if self.be { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+	}
+
+	/// # U32 At.
+	fn u32_at(&self, pos: usize) -> Option<u32> {
+		let bytes = self.data.get(pos..pos + 4)?.try_into().ok()?;
+		Some(if self.be { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+	}
+
+	/// # Offset At.
+	fn offset_at(&self, pos: usize) -> Option<usize> {
+		usize::try_from(self.u32_at(pos)?).ok()
+	}
+
+	/// # Extent.
+	///
+	/// Return the end position of the IFD at `ifd` — its entries, out-of-line
+	/// values, and any sub-IFDs — or `None` if anything is out of bounds or
+	/// otherwise unparseable.
+	///
+	/// Note: the next-IFD link is _not_ followed.
+	fn extent(&self, ifd: usize, depth: u8) -> Option<usize> {
+		if MAX_DEPTH < depth { return None; }
+
+		let count = usize::from(self.u16_at(ifd)?);
+		let mut end = ifd + 2 + count * 12 + 4;
+		for i in 0..count {
+			let pos = ifd + 2 + i * 12;
+			let tag = self.u16_at(pos)?;
+			let size = type_size(self.u16_at(pos + 2)?)?
+				.checked_mul(self.offset_at(pos + 4)?)?;
+
+			// Values bigger than four bytes live elsewhere.
+			if 4 < size { end = end.max(self.offset_at(pos + 8)?.checked_add(size)?); }
+
+			// Sub-IFDs need to be walked too.
+			if TAG_SUB_IFDS.contains(&tag) {
+				end = end.max(self.extent(self.offset_at(pos + 8)?, depth + 1)?);
+			}
+		}
+
+		if end <= self.data.len() { Some(end) }
+		else { None }
+	}
+}
+
+
+
+/// # Type Size.
+///
+/// Return the size in bytes of a single value of the given TIFF field type.
+const fn type_size(kind: u16) -> Option<usize> {
+	match kind {
+		1 | 2 | 6 | 7 => Some(1),   // BYTE, ASCII, SBYTE, UNDEFINED.
+		3 | 8 => Some(2),           // SHORT, SSHORT.
+		4 | 9 | 11 | 13 => Some(4), // LONG, SLONG, FLOAT, IFD.
+		5 | 10 | 12 => Some(8),     // RATIONAL, SRATIONAL, DOUBLE.
+		_ => None,
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_orientation() {
+		// Big endian, one entry.
+		let mut data = SIG_EXIF.to_vec();
+		data.extend_from_slice(b"MM\0*\0\0\0\x08\0\x01");
+		data.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0]);
+		assert_eq!(orientation(&data), Some((6, 24)));
+
+		// Reset it.
+		assert!(reset_orientation(&mut data), "Orientation not reset.");
+		assert_eq!(orientation(&data), Some((1, 24)));
+		assert!(! reset_orientation(&mut data), "Orientation already normal.");
+
+		// Little endian, two entries.
+		let mut data = SIG_EXIF.to_vec();
+		data.extend_from_slice(b"II*\0\x08\0\0\0\x02\0");
+		data.extend_from_slice(&[0x0F, 0x01, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+		data.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 8, 0, 0, 0]);
+		assert_eq!(orientation(&data), Some((8, 36)));
+
+		// Truncated.
+		data.truncate(40);
+		assert_eq!(orientation(&data), Some((8, 36)));
+		data.truncate(30);
+		assert_eq!(orientation(&data), None);
+
+		// Not EXIF.
+		assert_eq!(orientation(b"http://ns.adobe.com/xap/1.0/\0"), None);
+	}
+
+	#[test]
+	fn t_strip_thumbnail() {
+		// IFD0 with an Orientation, linked to IFD1 at 26.
+		let mut data = SIG_EXIF.to_vec();
+		data.extend_from_slice(b"II*\0\x08\0\0\0\x01\0");
+		data.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+		data.extend_from_slice(&[26, 0, 0, 0]);
+
+		// IFD1 with a four-byte thumbnail at 56.
+		data.extend_from_slice(&[2, 0]);
+		data.extend_from_slice(&[0x01, 0x02, 4, 0, 1, 0, 0, 0, 56, 0, 0, 0]);
+		data.extend_from_slice(&[0x02, 0x02, 4, 0, 1, 0, 0, 0, 4, 0, 0, 0]);
+		data.extend_from_slice(&[0, 0, 0, 0]);
+		data.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xD9]);
+		assert_eq!(data.len(), SIG_EXIF.len() + 60);
+
+		// The thumbnail should be gone, but the Orientation should remain.
+		assert!(strip_thumbnail(&mut data), "Thumbnail not stripped.");
+		assert_eq!(data.len(), SIG_EXIF.len() + 26);
+		assert_eq!(&data[SIG_EXIF.len() + 22..], &[0, 0, 0, 0]);
+		assert_eq!(orientation(&data), Some((6, SIG_EXIF.len() + 18)));
+
+		// Doing it again should do nothing.
+		assert!(! strip_thumbnail(&mut data), "Nothing to strip.");
+
+		// If IFD0 data lives past the thumbnail, it can't be cut.
+		let mut data = SIG_EXIF.to_vec();
+		data.extend_from_slice(b"II*\0\x08\0\0\0\x01\0");
+		data.extend_from_slice(&[0x0E, 0x01, 2, 0, 6, 0, 0, 0, 44, 0, 0, 0]);
+		data.extend_from_slice(&[26, 0, 0, 0]);
+		data.extend_from_slice(&[1, 0]);
+		data.extend_from_slice(&[0x01, 0x02, 4, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+		data.extend_from_slice(&[0, 0, 0, 0]);
+		data.extend_from_slice(b"hello\0");
+		let before = data.clone();
+		assert!(! strip_thumbnail(&mut data), "Data should be unchanged.");
+		assert_eq!(data, before);
+	}
+}
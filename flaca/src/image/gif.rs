@@ -0,0 +1,563 @@
+/*!
+# Flaca: GIF
+
+GIFs are optimized losslessly at the block level, comparable to running:
+```bash
+gifsicle --no-comments --no-extensions
+```
+
+Specifically:
+* Comment extensions are removed;
+* Application extensions other than the looping ones are removed;
+* Image data is recompressed — if that helps — and repacked into full-sized
+  (255-byte) sub-blocks;
+* Anything after the trailer is removed.
+
+Recompression decodes each frame's LZW data back to color indices and
+re-encodes them greedily, starting a fresh dictionary whenever the old one
+fills up. Plenty of encoders cut corners here, resetting early or never
+reusing codes at all, so this can be worth a surprising amount.
+
+Everything else — the color indices themselves, palettes, timing,
+transparency, etc. — is copied over verbatim.
+*/
+
+use std::{
+	cmp::Ordering,
+	collections::HashMap,
+};
+
+
+
+/// # Extension Introducer.
+const EXTENSION: u8 = 0x21;
+
+/// # Image Separator.
+const IMAGE: u8 = 0x2C;
+
+/// # Trailer.
+const TRAILER: u8 = 0x3B;
+
+/// # Application Extension Label.
+const LABEL_APPLICATION: u8 = 0xFF;
+
+/// # Comment Extension Label.
+const LABEL_COMMENT: u8 = 0xFE;
+
+/// # Maximum Sub-Block Size.
+const MAX_BLOCK: usize = 255;
+
+/// # Maximum LZW Code Size (Bits).
+const MAX_CODE_SIZE: u8 = 12;
+
+/// # Maximum LZW Dictionary Size.
+const MAX_CODES: u16 = 1 << MAX_CODE_SIZE;
+
+
+
+/// # Optimize GIF.
+///
+/// Return a leaner copy of the GIF, or `None` if it is malformed or no
+/// savings were possible.
+pub(super) fn optimize(src: &[u8]) -> Option<Vec<u8>> {
+	// Header (6) + logical screen descriptor (7) + global color table.
+	let header_len = 13 + color_table_len(*src.get(10)?);
+	let mut out = Vec::with_capacity(src.len());
+	out.extend_from_slice(src.get(..header_len)?);
+
+	let mut raw = src.get(header_len..)?;
+	loop {
+		let [kind, rest @ ..] = raw else { return None; };
+		match *kind {
+			EXTENSION => {
+				let [label, rest @ ..] = rest else { return None; };
+				let (blocks, data) = sub_blocks(rest)?;
+				if keep_extension(*label, blocks) {
+					out.push(EXTENSION);
+					out.push(*label);
+					out.extend_from_slice(blocks);
+				}
+				raw = data;
+			},
+			IMAGE => {
+				// Descriptor (9) + local color table + LZW minimum code size.
+				let len = 9 + color_table_len(*rest.get(8)?) + 1;
+				let desc = rest.get(..len)?;
+				out.push(IMAGE);
+				out.extend_from_slice(desc);
+
+				let (blocks, data) = sub_blocks(&rest[len..])?;
+				let mut lzw = Vec::with_capacity(blocks.len());
+				unchunk(blocks, &mut lzw);
+				if let Some(new) = recompress(desc, &lzw) {
+					if new.len() < lzw.len() { lzw = new; }
+				}
+				repack(&lzw, &mut out);
+				raw = data;
+			},
+			TRAILER => {
+				out.push(TRAILER);
+				break;
+			},
+			_ => return None,
+		}
+	}
+
+	if out.len() < src.len() { Some(out) }
+	else { None }
+}
+
+/// # Image Data.
+///
+/// Return everything that affects how the GIF is rendered — the screen
+/// descriptor, color tables, graphic control and plain text extensions, and
+/// the decoded color indices for each frame — or `None` if it is malformed.
+///
+/// Frames that can't be decoded cleanly are never recompressed, so for those
+/// the (unchunked) LZW data is used instead.
+pub(super) fn image_data(src: &[u8]) -> Option<Vec<u8>> {
+	let header_len = 13 + color_table_len(*src.get(10)?);
+	let mut out = Vec::with_capacity(src.len());
+	out.extend_from_slice(src.get(6..header_len)?);
+
+	let mut raw = src.get(header_len..)?;
+	loop {
+		let [kind, rest @ ..] = raw else { return None; };
+		match *kind {
+			EXTENSION => {
+				let [label, rest @ ..] = rest else { return None; };
+				let (blocks, data) = sub_blocks(rest)?;
+				if ! matches!(*label, LABEL_APPLICATION | LABEL_COMMENT) {
+					out.push(*label);
+					unchunk(blocks, &mut out);
+				}
+				raw = data;
+			},
+			IMAGE => {
+				let len = 9 + color_table_len(*rest.get(8)?) + 1;
+				let desc = rest.get(..len)?;
+				out.push(IMAGE);
+				out.extend_from_slice(desc);
+
+				let (blocks, data) = sub_blocks(&rest[len..])?;
+				let mut lzw = Vec::with_capacity(blocks.len());
+				unchunk(blocks, &mut lzw);
+				if let Some(pixels) = pixels(desc, &lzw) {
+					out.push(1);
+					out.extend_from_slice(&pixels);
+				}
+				else {
+					out.push(0);
+					out.extend_from_slice(&lzw);
+				}
+				raw = data;
+			},
+			TRAILER => return Some(out),
+			_ => return None,
+		}
+	}
+}
+
+/// # Color Table Length.
+///
+/// Return the size in bytes of the color table described by the packed
+/// field of a screen or image descriptor, if any.
+const fn color_table_len(packed: u8) -> usize {
+	if 0 == packed & 0b1000_0000 { 0 }
+	else { 3 << ((packed & 0b0111) + 1) }
+}
+
+/// # Keep Extension?
+///
+/// Comments and third-party application data are metadata, but the
+/// `NETSCAPE2.0`/`ANIMEXTS1.0` application extensions control looping, and
+/// everything else (graphic control, plain text) affects the image itself.
+fn keep_extension(label: u8, blocks: &[u8]) -> bool {
+	match label {
+		LABEL_COMMENT => false,
+		LABEL_APPLICATION => matches!(
+			blocks.get(..12),
+			Some(b"\x0BNETSCAPE2.0" | b"\x0BANIMEXTS1.0"),
+		),
+		_ => true,
+	}
+}
+
+/// # Split Sub-Blocks.
+///
+/// Return the sub-blocks at the start of `raw` — including the terminator —
+/// along with whatever follows them.
+fn sub_blocks(raw: &[u8]) -> Option<(&[u8], &[u8])> {
+	let mut len = 0;
+	loop {
+		let size = usize::from(*raw.get(len)?);
+		len += 1 + size;
+		if size == 0 { break; }
+	}
+
+	if len <= raw.len() { Some(raw.split_at(len)) }
+	else { None }
+}
+
+#[expect(clippy::cast_possible_truncation, reason = "Chunks are at most 255.")]
+/// # Repack Sub-Blocks.
+///
+/// Chunk the (unchunked) `data` into full-sized sub-blocks, writing the
+/// result (and terminator) to `out`.
+fn repack(data: &[u8], out: &mut Vec<u8>) {
+	for chunk in data.chunks(MAX_BLOCK) {
+		out.push(chunk.len() as u8);
+		out.extend_from_slice(chunk);
+	}
+	out.push(0);
+}
+
+/// # Unchunk Sub-Blocks.
+///
+/// Append the data from `blocks` to `out`, minus the size prefixes and
+/// terminator.
+fn unchunk(mut blocks: &[u8], out: &mut Vec<u8>) {
+	while let [size, rest @ ..] = blocks {
+		let size = usize::from(*size);
+		if size == 0 { break; }
+		out.extend_from_slice(&rest[..size]);
+		blocks = &rest[size..];
+	}
+}
+
+/// # Frame Pixels.
+///
+/// Decode the frame with the image descriptor `desc` — as returned by the
+/// main loops, ending with the LZW minimum code size — and (unchunked) `lzw`
+/// data, returning its color indices.
+///
+/// This returns `None` if the data is invalid or works out to the wrong
+/// number of pixels.
+fn pixels(desc: &[u8], lzw: &[u8]) -> Option<Vec<u8>> {
+	let [_, _, _, _, w1, w2, h1, h2, ..] = *desc else { return None; };
+	let len = usize::from(u16::from_le_bytes([w1, w2])) *
+		usize::from(u16::from_le_bytes([h1, h2]));
+	decompress(*desc.last()?, lzw, len)
+}
+
+/// # Recompress Frame.
+///
+/// Decode and re-encode the frame's LZW data. See `pixels` for the
+/// arguments.
+fn recompress(desc: &[u8], lzw: &[u8]) -> Option<Vec<u8>> {
+	let pixels = pixels(desc, lzw)?;
+	Some(compress(*desc.last()?, &pixels))
+}
+
+
+
+/// # LZW Decompress.
+///
+/// Decode `len` color indices from the `lzw` data using the minimum code size
+/// `min`. Trailing data after the end-of-information code is ignored, but
+/// everything else — unknown codes, a missing end, the wrong number of
+/// pixels — is an error.
+fn decompress(min: u8, lzw: &[u8], len: usize) -> Option<Vec<u8>> {
+	if ! (2..=8).contains(&min) { return None; }
+	let clear = 1_u16 << min;
+	let eoi = clear + 1;
+
+	// The dictionary is stored as prefix codes plus final bytes, along with
+	// the first byte and length of each entry for quicker lookups.
+	let mut prefix = vec![0_u16; usize::from(MAX_CODES)];
+	let mut last = vec![0_u8; usize::from(MAX_CODES)];
+	let mut first = vec![0_u8; usize::from(MAX_CODES)];
+	let mut lens = vec![0_usize; usize::from(MAX_CODES)];
+	for (idx, b) in (0..=u8::MAX).take(usize::from(clear)).enumerate() {
+		last[idx] = b;
+		first[idx] = b;
+		lens[idx] = 1;
+	}
+
+	let mut out = Vec::with_capacity(len);
+	let mut reader = BitReader::new(lzw);
+	let mut size = min + 1;
+	let mut next = eoi + 1;
+	let mut prev: Option<u16> = None;
+	loop {
+		let code = reader.read(size)?;
+		if code == clear {
+			size = min + 1;
+			next = eoi + 1;
+			prev = None;
+			continue;
+		}
+		if code == eoi { break; }
+
+		// Add a new entry — the previous string plus the first byte of this
+		// one — unless this is the first code or the dictionary is full.
+		if let Some(p) = prev {
+			if next < MAX_CODES {
+				let idx = usize::from(next);
+				prefix[idx] = p;
+				let p = usize::from(p);
+				first[idx] = first[p];
+				last[idx] = match code.cmp(&next) {
+					Ordering::Less => first[usize::from(code)],
+					Ordering::Equal => first[p],
+					Ordering::Greater => return None,
+				};
+				lens[idx] = lens[p] + 1;
+				next += 1;
+				if next == 1 << size && size < MAX_CODE_SIZE { size += 1; }
+			}
+			else if next <= code { return None; }
+		}
+		else if clear <= code { return None; }
+
+		// Write out the string, back to front.
+		let idx = usize::from(code);
+		let start = out.len();
+		if len < start + lens[idx] { return None; }
+		out.resize(start + lens[idx], 0);
+		let mut c = idx;
+		for b in out[start..].iter_mut().rev() {
+			*b = last[c];
+			c = usize::from(prefix[c]);
+		}
+
+		prev = Some(code);
+	}
+
+	if out.len() == len { Some(out) }
+	else { None }
+}
+
+/// # LZW Compress.
+///
+/// Encode the color indices in `pixels` using the minimum code size `min`,
+/// starting with a clear code and ending with an end-of-information code.
+/// The indices must all fit within `min` bits.
+fn compress(min: u8, pixels: &[u8]) -> Vec<u8> {
+	let clear = 1_u16 << min;
+	let eoi = clear + 1;
+
+	let mut dict: HashMap<(u16, u8), u16> = HashMap::new();
+	let mut writer = BitWriter::default();
+	let mut size = min + 1;
+	let mut next = eoi + 1;
+	writer.write(clear, size);
+
+	let [head, rest @ ..] = pixels else {
+		writer.write(eoi, size);
+		return writer.finish();
+	};
+	let mut cur = u16::from(*head);
+	for &px in rest {
+		if let Some(&code) = dict.get(&(cur, px)) {
+			cur = code;
+			continue;
+		}
+
+		writer.write(cur, size);
+		dict.insert((cur, px), next);
+		next += 1;
+		if next - 1 == 1 << size && size < MAX_CODE_SIZE { size += 1; }
+
+		// Start over once the dictionary fills up.
+		if next == MAX_CODES {
+			writer.write(clear, size);
+			dict.clear();
+			size = min + 1;
+			next = eoi + 1;
+		}
+
+		cur = u16::from(px);
+	}
+
+	// The decoder adds an entry for the final code too (unless it's the first
+	// since a clear), which may bump the size for the end code.
+	writer.write(cur, size);
+	if eoi + 1 < next && next == 1 << size && size < MAX_CODE_SIZE { size += 1; }
+	writer.write(eoi, size);
+	writer.finish()
+}
+
+
+
+/// # LZW Bit Reader.
+///
+/// This reads variable-width codes from a byte slice, least significant bit
+/// first.
+struct BitReader<'a> {
+	/// # Data.
+	data: &'a [u8],
+
+	/// # Buffer.
+	buf: u32,
+
+	/// # Bits in Buffer.
+	bits: u8,
+}
+
+impl<'a> BitReader<'a> {
+	/// # New.
+	const fn new(data: &'a [u8]) -> Self {
+		Self { data, buf: 0, bits: 0 }
+	}
+
+	#[expect(clippy::cast_possible_truncation, reason = "Codes are at most 12 bits.")]
+	/// # Read Code.
+	///
+	/// Return the next `size`-bit code, or `None` if the data runs out.
+	fn read(&mut self, size: u8) -> Option<u16> {
+		while self.bits < size {
+			let [next, rest @ ..] = self.data else { return None; };
+			self.buf |= u32::from(*next) << self.bits;
+			self.bits += 8;
+			self.data = rest;
+		}
+
+		let code = (self.buf & ((1 << size) - 1)) as u16;
+		self.buf >>= size;
+		self.bits -= size;
+		Some(code)
+	}
+}
+
+#[derive(Default)]
+/// # LZW Bit Writer.
+///
+/// This packs variable-width codes into bytes, least significant bit first.
+struct BitWriter {
+	/// # Output.
+	out: Vec<u8>,
+
+	/// # Buffer.
+	buf: u32,
+
+	/// # Bits in Buffer.
+	bits: u8,
+}
+
+impl BitWriter {
+	#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+	/// # Write Code.
+	fn write(&mut self, code: u16, size: u8) {
+		self.buf |= u32::from(code) << self.bits;
+		self.bits += size;
+		while 8 <= self.bits {
+			self.out.push(self.buf as u8);
+			self.buf >>= 8;
+			self.bits -= 8;
+		}
+	}
+
+	#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+	/// # Finish.
+	///
+	/// Flush any leftover bits and return the bytes.
+	fn finish(mut self) -> Vec<u8> {
+		if self.bits != 0 { self.out.push(self.buf as u8); }
+		self.out
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_optimize() {
+		for file in ["../skel/assets/gif/01.gif", "../skel/assets/gif/02.gif"] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			let Some(new) = optimize(&raw) else { panic!("Unable to optimize {file}."); };
+			assert!(new.len() < raw.len(), "No savings for {file}.");
+
+			// The comments should be gone.
+			assert!(! new.windows(2).any(|w| w == [EXTENSION, LABEL_COMMENT]));
+
+			// But looping (if any) should be preserved.
+			assert_eq!(
+				raw.windows(11).any(|w| w == b"NETSCAPE2.0"),
+				new.windows(11).any(|w| w == b"NETSCAPE2.0"),
+				"Looping mismatch for {file}.",
+			);
+
+			// The image data should be untouched.
+			assert!(image_data(&raw).is_some(), "Unable to parse {file}.");
+			assert_eq!(image_data(&raw), image_data(&new), "Image mismatch for {file}.");
+
+			// And the output should be stable.
+			assert!(optimize(&new).is_none(), "Unstable output for {file}.");
+		}
+	}
+
+	#[test]
+	fn t_lzw() {
+		// Decoding the (foreign-encoded) samples should work out exactly.
+		for file in ["../skel/assets/gif/01.gif", "../skel/assets/gif/02.gif"] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			let header_len = 13 + color_table_len(raw[10]);
+			let mut rest = &raw[header_len..];
+			let mut frames = 0;
+			while let [kind, tail @ ..] = rest {
+				match *kind {
+					EXTENSION => { rest = sub_blocks(&tail[1..]).expect("Bad extension.").1; },
+					IMAGE => {
+						let len = 9 + color_table_len(tail[8]) + 1;
+						let (blocks, data) = sub_blocks(&tail[len..]).expect("Bad frame.");
+						let mut lzw = Vec::new();
+						unchunk(blocks, &mut lzw);
+
+						let desc = &tail[..len];
+						let Some(px) = pixels(desc, &lzw) else { panic!("Unable to decode {file}."); };
+						let new = compress(desc[len - 1], &px);
+						assert_eq!(pixels(desc, &new), Some(px), "Round trip failed for {file}.");
+
+						frames += 1;
+						rest = data;
+					},
+					_ => break,
+				}
+			}
+			assert_ne!(frames, 0, "No frames found in {file}.");
+		}
+
+		// Plus some synthetic ones, big enough to fill the dictionary (many
+		// times over).
+		let mut seed = 0x2545_f491_u32;
+		for min in 2..=8_u8 {
+			for len in [0, 1, 2, 3, 100, 5000, 200_000] {
+				let pixels: Vec<u8> = (0..len).map(|i| {
+					seed ^= seed << 13;
+					seed ^= seed >> 17;
+					seed ^= seed << 5;
+					// Mix up noise and runs.
+					if i % 1000 < 500 { seed.to_le_bytes()[0] & (u8::MAX >> (8 - min)) }
+					else { 1 }
+				}).collect();
+				let lzw = compress(min, &pixels);
+				assert_eq!(
+					decompress(min, &lzw, pixels.len()),
+					Some(pixels),
+					"Round trip failed for {min}/{len}.",
+				);
+			}
+		}
+
+		// Wrong lengths or codes shouldn't fly.
+		let lzw = compress(2, &[0, 1, 2, 3]);
+		assert!(decompress(2, &lzw, 3).is_none());
+		assert!(decompress(2, &lzw, 5).is_none());
+		assert!(decompress(2, &[0xFF, 0xFF], 1).is_none());
+	}
+
+	#[test]
+	fn t_repack() {
+		let raw = [1_u8; 300];
+		let mut out = Vec::new();
+		repack(&raw, &mut out);
+		assert_eq!(out.len(), 1 + 255 + 1 + 45 + 1);
+		assert_eq!(out[0], 255);
+		assert_eq!(out[256], 45);
+		assert_eq!(out.last(), Some(&0));
+	}
+}
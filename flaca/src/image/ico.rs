@@ -0,0 +1,394 @@
+/*!
+# Flaca: ICO/CUR
+
+Icons and cursors are containers: a small directory followed by one image
+per size, each either a PNG or a headerless BMP (a "DIB" with an extra 1-bit
+transparency mask tacked on).
+
+PNG members are recompressed using the regular PNG pipeline, and BMP members
+are converted to PNGs if — and only if — that makes them smaller. The
+directory entries, member order, cursor hotspots, etc., are left as-are.
+
+Note: PNG members require Windows Vista or later. That ship has long since
+sailed for the 256x256 sizes, but it is worth keeping in mind for the rest.
+*/
+
+use oxipng::{
+	BitDepth,
+	ColorType,
+	Options,
+	RawImage,
+};
+use super::ImageKind;
+
+
+
+/// # Header Length.
+const HEADER: usize = 6;
+
+/// # Directory Entry Length.
+const ENTRY: usize = 16;
+
+/// # Minimum DIB Header Length.
+const DIB_HEADER: usize = 40;
+
+
+
+/// # Optimize ICO/CUR.
+///
+/// Run each member through `png` — converting BMPs to PNGs first — keeping
+/// whichever version is smaller, then rebuild the container. `None` is
+/// returned if the file is malformed or no savings were possible.
+pub(super) fn optimize<F>(src: &[u8], mut png: F) -> Option<Vec<u8>>
+where F: FnMut(&mut Vec<u8>) {
+	let entries = entries(src)?;
+
+	let mut members = Vec::with_capacity(entries.len());
+	for e in &entries {
+		let data = e.data(src);
+		let new =
+			// The encoders would strip the animation, so leave these be.
+			if ImageKind::is_apng(data) { None }
+			else if ImageKind::is_png(data) {
+				let mut new = data.to_vec();
+				png(&mut new);
+				Some(new)
+			}
+			else {
+				dib_to_png(data).map(|mut new| {
+					png(&mut new);
+					new
+				})
+			};
+
+		members.push(new.filter(|new| new.len() < data.len()));
+	}
+
+	// Nothing to do?
+	if members.iter().all(Option::is_none) { return None; }
+
+	// Rebuild the directory, then the members.
+	let mut out = Vec::with_capacity(src.len());
+	out.extend_from_slice(&src[..HEADER]);
+	let mut offset = HEADER + ENTRY * entries.len();
+	for (e, new) in entries.iter().zip(&members) {
+		let size = new.as_ref().map_or(e.size, Vec::len);
+		out.extend_from_slice(&src[e.pos..e.pos + 8]);
+		out.extend_from_slice(&u32::try_from(size).ok()?.to_le_bytes());
+		out.extend_from_slice(&u32::try_from(offset).ok()?.to_le_bytes());
+		offset += size;
+	}
+	for (e, new) in entries.iter().zip(&members) {
+		out.extend_from_slice(new.as_deref().unwrap_or_else(|| e.data(src)));
+	}
+
+	if out.len() < src.len() { Some(out) }
+	else { None }
+}
+
+/// # Same Image?
+///
+/// Return `true` if `old` and `new` have the same directory and each of
+/// their members decode to the same pixels.
+pub(super) fn same_image(old: &[u8], new: &[u8]) -> bool {
+	let (Some(a), Some(b)) = (entries(old), entries(new)) else { return false; };
+	a.len() == b.len() &&
+	a.iter().zip(&b).all(|(ea, eb)| {
+		let (ma, mb) = (ea.data(old), eb.data(new));
+		old[ea.pos..ea.pos + 8] == new[eb.pos..eb.pos + 8] &&
+		(
+			ma == mb ||
+			(
+				ImageKind::is_png(mb) &&
+				if ImageKind::is_png(ma) { flapfli::same_pixels(ma, mb) }
+				else { dib_to_png(ma).is_some_and(|ma| flapfli::same_pixels(&ma, mb)) }
+			)
+		)
+	})
+}
+
+/// # Largest Member Dimensions.
+///
+/// Return the width and height of the biggest member, according to the
+/// directory. (Zero means 256, or larger for PNGs.)
+pub(super) fn dimensions(src: &[u8]) -> Option<(u32, u32)> {
+	entries(src)?.into_iter()
+		.map(|e| (e.width, e.height))
+		.max_by_key(|(w, h)| w * h)
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # Directory Entry.
+pub(super) struct Entry {
+	/// # Entry Position.
+	pos: usize,
+
+	/// # Member Offset.
+	offset: usize,
+
+	/// # Member Size.
+	size: usize,
+
+	/// # Width.
+	width: u32,
+
+	/// # Height.
+	height: u32,
+}
+
+impl Entry {
+	/// # Member Data.
+	///
+	/// Note: bounds are checked during parsing, so this can't fail.
+	fn data(self, src: &[u8]) -> &[u8] { &src[self.offset..self.offset + self.size] }
+}
+
+/// # Parse Directory.
+///
+/// Return the directory entries, or `None` if the header is wrong, the
+/// directory is empty, or any of the members are out of bounds or overlap.
+pub(super) fn entries(src: &[u8]) -> Option<Vec<Entry>> {
+	// Reserved, type (1 for icons, 2 for cursors), count.
+	let &[0, 0, 1 | 2, 0, a, b] = src.get(..HEADER)? else { return None; };
+	let count = usize::from(u16::from_le_bytes([a, b]));
+	if count == 0 { return None; }
+
+	let start = HEADER + ENTRY * count;
+	let mut out = Vec::with_capacity(count);
+	for pos in (HEADER..start).step_by(ENTRY) {
+		let raw = src.get(pos..pos + ENTRY)?;
+		let size = usize::try_from(u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]])).ok()?;
+		let offset = usize::try_from(u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]])).ok()?;
+		if size == 0 || offset < start || src.len() < offset.checked_add(size)? {
+			return None;
+		}
+
+		out.push(Entry {
+			pos,
+			offset,
+			size,
+			width: if raw[0] == 0 { 256 } else { u32::from(raw[0]) },
+			height: if raw[1] == 0 { 256 } else { u32::from(raw[1]) },
+		});
+	}
+
+	// Shared or overlapping members can't be rebuilt faithfully.
+	let mut ranges: Vec<(usize, usize)> = out.iter().map(|e| (e.offset, e.offset + e.size)).collect();
+	ranges.sort_unstable();
+	if ranges.windows(2).any(|w| w[1].0 < w[0].1) { return None; }
+
+	Some(out)
+}
+
+/// # DIB to PNG.
+///
+/// Decode the BMP member and re-encode it as a (quick and dirty) PNG, ready
+/// for the real encoders.
+fn dib_to_png(src: &[u8]) -> Option<Vec<u8>> {
+	let (width, height, rgba) = dib_to_rgba(src)?;
+	RawImage::new(width, height, ColorType::RGBA, BitDepth::Eight, rgba).ok()?
+		.create_optimized_png(&Options::from_preset(0))
+		.ok()
+}
+
+/// # DIB to RGBA.
+///
+/// Decode an uncompressed 1-, 4-, 8-, 24-, or 32-bit BMP member to 8-bit
+/// RGBA, returning the width, height, and pixels.
+///
+/// Unless a 32-bit image has its own (non-blank) alpha channel, the
+/// transparency comes from the trailing AND mask.
+///
+/// Masked pixels with a non-black colour invert whatever is behind them,
+/// which PNG has no way to express, so such images are left alone.
+fn dib_to_rgba(src: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+	/// # Read u32.
+	fn read_u32(src: &[u8], idx: usize) -> Option<u32> {
+		let &[a, b, c, d] = src.get(idx..idx + 4)? else { return None; };
+		Some(u32::from_le_bytes([a, b, c, d]))
+	}
+
+	// Parse the header. The height covers both the XOR and AND bitmaps.
+	let header = usize::try_from(read_u32(src, 0)?).ok()?;
+	let width = i32::from_le_bytes(read_u32(src, 4)?.to_le_bytes());
+	let height2 = i32::from_le_bytes(read_u32(src, 8)?.to_le_bytes());
+	let bpp = u16::from_le_bytes([*src.get(14)?, *src.get(15)?]);
+	let compression = read_u32(src, 16)?;
+	let colors = usize::try_from(read_u32(src, 32)?).ok()?;
+	if header < DIB_HEADER || compression != 0 || width <= 0 || height2 <= 0 || height2 % 2 != 0 {
+		return None;
+	}
+	let w = usize::try_from(width).ok()?;
+	let h = usize::try_from(height2 / 2).ok()?;
+
+	// Find the palette, if any.
+	let palette_len = match bpp {
+		1 | 4 | 8 => {
+			let max = 1_usize << bpp;
+			if colors == 0 { max } else { colors.min(max) }
+		},
+		24 | 32 => 0,
+		_ => return None,
+	};
+	let palette = src.get(header..header + palette_len * 4)?;
+
+	// Rows are padded to four bytes.
+	let xor_stride = (w * usize::from(bpp)).div_ceil(32) * 4;
+	let and_stride = w.div_ceil(32) * 4;
+	let xor_start = header + palette.len();
+	let and_start = xor_start + xor_stride * h;
+	let xor = src.get(xor_start..and_start)?;
+	let and = src.get(and_start..and_start + and_stride * h);
+
+	// Rows are stored bottom-up.
+	let mut out = Vec::with_capacity(w * h * 4);
+	for row in xor.chunks_exact(xor_stride).rev() {
+		for x in 0..w {
+			let px = match bpp {
+				32 => [row[x * 4 + 2], row[x * 4 + 1], row[x * 4], row[x * 4 + 3]],
+				24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], u8::MAX],
+				_ => {
+					let bits = usize::from(bpp);
+					let bit = x * bits;
+					let idx = usize::from((row[bit / 8] >> (8 - bits - bit % 8)) & (u8::MAX >> (8 - bits)));
+					let c = palette.get(idx * 4..idx * 4 + 3)?;
+					[c[2], c[1], c[0], u8::MAX]
+				},
+			};
+			out.extend_from_slice(&px);
+		}
+	}
+
+	// Apply the mask, if needed.
+	if bpp != 32 || out.chunks_exact(4).all(|px| px[3] == 0) {
+		for (mask, px) in and?.chunks_exact(and_stride).rev().zip(out.chunks_exact_mut(w * 4)) {
+			for (x, px) in px.chunks_exact_mut(4).enumerate() {
+				px[3] =
+					if 0 == (mask[x / 8] >> (7 - x % 8)) & 1 { u8::MAX }
+					else if px[..3] == [0, 0, 0] { 0 }
+					else { return None; };
+			}
+		}
+	}
+
+	Some((u32::try_from(w).ok()?, u32::try_from(h).ok()?, out))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// # Build a 32-bit BMP Member.
+	///
+	/// The pixels are opaque red, except for a transparent top-left corner.
+	fn bmp(size: u8) -> Vec<u8> {
+		let s = usize::from(size);
+		let mut out = Vec::new();
+		out.extend_from_slice(&40_u32.to_le_bytes());
+		out.extend_from_slice(&u32::from(size).to_le_bytes());
+		out.extend_from_slice(&(u32::from(size) * 2).to_le_bytes());
+		out.extend_from_slice(&1_u16.to_le_bytes());
+		out.extend_from_slice(&32_u16.to_le_bytes());
+		out.extend_from_slice(&[0; 24]);
+		for y in 0..s {
+			for x in 0..s {
+				if x == 0 && y == s - 1 { out.extend_from_slice(&[0, 0, 0, 0]); }
+				else { out.extend_from_slice(&[0, 0, 255, 255]); }
+			}
+		}
+		out.resize(out.len() + s.div_ceil(32) * 4 * s, 0);
+		out
+	}
+
+	/// # Build an ICO.
+	fn ico(members: &[&[u8]]) -> Vec<u8> {
+		let count = u16::try_from(members.len()).unwrap_or_default();
+		let mut out = vec![0, 0, 1, 0];
+		out.extend_from_slice(&count.to_le_bytes());
+		let mut offset = HEADER + ENTRY * members.len();
+		for m in members {
+			out.extend_from_slice(&[0, 0, 0, 0, 1, 0, 32, 0]);
+			out.extend_from_slice(&u32::try_from(m.len()).unwrap_or_default().to_le_bytes());
+			out.extend_from_slice(&u32::try_from(offset).unwrap_or_default().to_le_bytes());
+			offset += m.len();
+		}
+		for m in members { out.extend_from_slice(m); }
+		out
+	}
+
+	#[test]
+	fn t_dib_to_rgba() {
+		let Some((w, h, rgba)) = dib_to_rgba(&bmp(4)) else {
+			panic!("Unable to decode BMP.");
+		};
+		assert_eq!((w, h), (4, 4), "Wrong dimensions.");
+		assert_eq!(rgba.len(), 4 * 4 * 4, "Wrong pixel count.");
+		assert_eq!(rgba[..4], [0, 0, 0, 0], "Top-left should be transparent.");
+		assert!(
+			rgba[4..].chunks_exact(4).all(|px| px == [255, 0, 0, 255]),
+			"The rest should be red.",
+		);
+
+		// Blank the alpha channel so the mask is used instead.
+		let mut raw = bmp(4);
+		for px in raw[40..40 + 4 * 4 * 4].chunks_exact_mut(4) { px[3] = 0; }
+		let mask = raw.len() - 4 * 4;
+		raw[mask + 3 * 4] = 0b1000_0000; // Top-left.
+		assert_eq!(dib_to_rgba(&raw).map(|(_, _, rgba)| rgba), Some(rgba), "Mask not applied.");
+
+		// Masking a coloured pixel inverts the screen; PNG can't do that.
+		raw[mask] = 0b1000_0000; // Bottom-left.
+		assert!(dib_to_rgba(&raw).is_none(), "Inverted pixel accepted.");
+	}
+
+	#[test]
+	fn t_entries() {
+		let Ok(png) = std::fs::read("../skel/assets/png/small.png") else {
+			panic!("Unable to open small.png.");
+		};
+		let raw = ico(&[&bmp(16), &png]);
+		assert!(ImageKind::is_ico(&raw), "ICO not detected.");
+		assert_eq!(entries(&raw).as_deref().map(<[Entry]>::len), Some(2), "Wrong entry count.");
+
+		// Bad header.
+		let mut bad = raw.clone();
+		bad[2] = 3;
+		assert!(entries(&bad).is_none(), "Bad type accepted.");
+
+		// Truncated.
+		assert!(entries(&raw[..raw.len() - 1]).is_none(), "Truncated member accepted.");
+
+		// Not an icon at all.
+		assert!(! ImageKind::is_ico(&png), "PNG detected as ICO.");
+	}
+
+	#[test]
+	fn t_optimize() {
+		let Ok(png) = std::fs::read("../skel/assets/png/small.png") else {
+			panic!("Unable to open small.png.");
+		};
+		let bmp = bmp(32);
+		let raw = ico(&[&bmp, &png]);
+
+		// Leave the PNG member as-is; the BMP alone should shrink.
+		let Some(new) = optimize(&raw, |_| {}) else {
+			panic!("ICO not optimized.");
+		};
+		assert!(new.len() < raw.len(), "ICO got bigger.");
+
+		let Some(e) = entries(&new) else { panic!("Output is not an ICO."); };
+		assert_eq!(e.len(), 2, "Wrong entry count.");
+		assert!(ImageKind::is_png(e[0].data(&new)), "BMP not converted.");
+		assert_eq!(e[1].data(&new), png, "PNG member changed.");
+
+		// And it should look the same.
+		assert!(same_image(&raw, &new), "ICO pixels changed.");
+
+		// A second pass should find nothing to do.
+		assert!(optimize(&new, |_| {}).is_none(), "Unstable output.");
+	}
+}
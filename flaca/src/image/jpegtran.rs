@@ -1,415 +1,592 @@
 /*!
 # Flaca: Jpegtran
 
-This is essentially a port of the `MozJPEG` code relating to:
-```bash
-jpegtran -copy none -progressive -optimize
-```
-
-## Reference:
-
-The reference materials are a bit all over the place, but the main sources
-looked at to bring this all together were:
-* [mozjpeg](https://github.com/mozilla/mozjpeg/blob/master/libjpeg.txt)
-* [mozjpeg-sys](https://github.com/kornelski/mozjpeg-sys/blob/master/examples/reencode.rs)
-* [mozjpeg-rs](https://github.com/immunant/mozjpeg-rs/blob/master/bin/jpegtran.rs)
+The recompression itself — equivalent to `jpegtran -copy none -progressive
+-optimize` — is shared with the C API by way of `flapfli::jpegtran`; this
+module layers flaca's own settings on top.
+
+Optionally, EXIF, ICC, and/or XMP markers can be kept, and/or the EXIF
+Orientation can be applied (losslessly) before it is stripped.
+
+YCbCr images whose chroma planes are completely empty — every coefficient
+zero, i.e. a neutral gray — can also be re-encoded as true grayscale, like
+`jpegtran -grayscale`, since dropping nothing changes nothing.
+
+Images can also be (losslessly) cropped, like `jpegtran -crop WxH+X+Y`. The
+top-left corner has to land on an iMCU boundary for that to work, so unless
+`--allow-imperfect` is set, crops that don't — or that run off the edge of
+the image — are treated as errors rather than quietly adjusted.
 */
 
+use crate::{
+	EncodingError,
+	FlacaError,
+};
+use dactyl::traits::BytesToUnsigned;
+use super::{
+	exif::{
+		self,
+		SIG_EXIF,
+	},
+	ImageKind,
+};
+use flapfli::jpegtran::{
+	EncodedJPEG,
+	JpegSrcInfo,
+	Transcode,
+};
 use mozjpeg_sys::{
-	jcopy_markers_execute,
-	jcopy_markers_setup,
-	JCOPY_OPTION_JCOPYOPT_NONE,
-	JCROP_CODE_JCROP_UNSET,
-	jpeg_common_struct,
+	JCROP_CODE_JCROP_POS,
 	jpeg_compress_struct,
-	jpeg_copy_critical_parameters,
-	jpeg_create_decompress,
-	jpeg_CreateCompress,
 	jpeg_decompress_struct,
-	jpeg_destroy_compress,
-	jpeg_destroy_decompress,
-	jpeg_error_mgr,
-	jpeg_finish_compress,
 	jpeg_finish_decompress,
-	JPEG_LIB_VERSION,
-	jpeg_mem_dest,
-	jpeg_mem_src,
 	jpeg_read_coefficients,
-	jpeg_read_header,
-	jpeg_simple_progression,
-	jpeg_std_error,
+	jpeg_read_scanlines,
+	jpeg_save_markers,
+	jpeg_start_decompress,
 	jpeg_transform_info,
-	jpeg_write_coefficients,
-	jtransform_adjust_parameters,
-	jtransform_execute_transform,
-	jtransform_request_workspace,
-	jvirt_barray_ptr,
+	jpeg_write_marker,
+	J_COLOR_SPACE,
+	JXFORM_CODE,
+	JXFORM_CODE_JXFORM_FLIP_H,
+	JXFORM_CODE_JXFORM_FLIP_V,
 	JXFORM_CODE_JXFORM_NONE,
+	JXFORM_CODE_JXFORM_ROT_180,
+	JXFORM_CODE_JXFORM_ROT_270,
+	JXFORM_CODE_JXFORM_ROT_90,
+	JXFORM_CODE_JXFORM_TRANSPOSE,
+	JXFORM_CODE_JXFORM_TRANSVERSE,
 };
 use std::{
 	ffi::{
 		c_int,
-		c_uchar,
-		c_ulong,
-		c_void,
+		c_uint,
+	},
+	sync::{
+		atomic::{
+			AtomicBool,
+			AtomicU8,
+			Ordering::Relaxed,
+		},
+		OnceLock,
 	},
-	marker::PhantomPinned,
-	ops::Deref,
-	ptr::NonNull,
 };
 
 
 
-#[derive(Debug)]
-/// # Encoded Image.
+/// # Allow Imperfect Crops.
 ///
-/// This holds a buffer pointer and size for an image allocated in C-land. It
-/// exists primarily to enforce cleanup at destruction, but also makes it easy
-/// to view the data as a slice.
-pub(super) struct EncodedJPEG {
-	/// # Buffer.
-	buf: *mut c_uchar,
-
-	/// # Buffer Size.
-	size: c_ulong,
-}
+/// When `true`, crops whose top-left corner doesn't line up with an iMCU
+/// boundary — or whose region extends past the image — are adjusted to fit
+/// instead of failing.
+pub(crate) static ALLOW_IMPERFECT: AtomicBool = AtomicBool::new(false);
 
-impl Deref for EncodedJPEG {
-	type Target = [u8];
+/// # Auto-Orient.
+///
+/// When `true`, images with an EXIF Orientation are losslessly rotated and/or
+/// flipped to match before the metadata is stripped.
+pub(crate) static AUTO_ORIENT: AtomicBool = AtomicBool::new(false);
 
-	#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
-	#[expect(unsafe_code, reason = "For slice from raw.")]
-	fn deref(&self) -> &Self::Target {
-		if self.is_null() { &[] }
-		else {
-			// Safety: the pointer is non-null.
-			unsafe { std::slice::from_raw_parts(self.buf, self.size as usize) }
-		}
-	}
+/// # Crop Region.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static CROP: OnceLock<Crop> = OnceLock::new();
+
+/// # Drop Empty Chroma.
+///
+/// When `true`, JPEGs without any actual color are re-encoded as grayscale.
+pub(crate) static GRAYSCALE_AUTO: AtomicBool = AtomicBool::new(false);
+
+/// # Markers to Keep.
+///
+/// This holds a bitmask of the `KEEP_*` flags below. If zero, all optional
+/// markers are stripped.
+pub(crate) static KEEP_MARKERS: AtomicU8 = AtomicU8::new(0);
+
+/// # Keep EXIF (APP1).
+pub(crate) const KEEP_EXIF: u8 = 0b0001;
+
+/// # Keep ICC Profile (APP2).
+pub(crate) const KEEP_ICC: u8 = 0b0010;
+
+/// # Keep XMP (APP1).
+pub(crate) const KEEP_XMP: u8 = 0b0100;
+
+/// # Keep EXIF Thumbnail.
+///
+/// By default, the embedded thumbnail is stripped from kept EXIF markers.
+pub(crate) const KEEP_THUMBNAIL: u8 = 0b1000;
+
+/// # APP1 Marker.
+const APP1: u8 = 0xE1;
+
+/// # APP2 Marker.
+const APP2: u8 = 0xE2;
+
+/// # ICC Profile Signature.
+const SIG_ICC: &[u8] = b"ICC_PROFILE\0";
+
+/// # XMP Signature.
+const SIG_XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Crop Region.
+///
+/// This is a `WxH+X+Y` region, in pixels.
+pub(crate) struct Crop {
+	/// # Width.
+	pub(super) width: u32,
+
+	/// # Height.
+	pub(super) height: u32,
+
+	/// # Left Offset.
+	pub(super) x: u32,
+
+	/// # Top Offset.
+	pub(super) y: u32,
 }
 
-impl Drop for EncodedJPEG {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	fn drop(&mut self) {
-		if ! self.buf.is_null() {
-			// Safety: the pointer is non-null and was created by C, so if
-			// anybody knows what to do with it, C should!
-			unsafe { libc::free(self.buf.cast::<c_void>()); }
-			self.buf = std::ptr::null_mut(); // Probably unnecessary?
-		}
+impl Crop {
+	/// # Initialize.
+	///
+	/// Parse and save the region for later reference.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the region is invalid or has already been
+	/// set.
+	pub(crate) fn init(raw: &str) -> Result<(), FlacaError> {
+		let crop = Self::parse(raw.trim().as_bytes()).ok_or(FlacaError::JpegCrop)?;
+		CROP.set(crop).map_err(|_| FlacaError::JpegCrop)
 	}
-}
 
-impl EncodedJPEG {
-	/// # New.
-	const fn new() -> Self {
-		Self {
-			buf: std::ptr::null_mut(),
-			size: 0,
-		}
+	/// # Get.
+	///
+	/// Return the crop, if any.
+	pub(crate) fn get() -> Option<Self> { CROP.get().copied() }
+
+	/// # Parse.
+	///
+	/// The offsets are optional, defaulting to the top-left corner.
+	fn parse(raw: &[u8]) -> Option<Self> {
+		let mut parts = raw.split(|&b| b == b'+');
+		let size = parts.next()?;
+		let pos = size.iter().position(|&b| b == b'x')?;
+		let width = u32::btou(&size[..pos]).filter(|&n| n != 0)?;
+		let height = u32::btou(&size[pos + 1..]).filter(|&n| n != 0)?;
+		let (x, y) = match (parts.next(), parts.next(), parts.next()) {
+			(None, None, None) => (0, 0),
+			(Some(x), Some(y), None) => (u32::btou(x)?, u32::btou(y)?),
+			_ => return None,
+		};
+
+		Some(Self { width, height, x, y })
 	}
 
-	/// # Is Null?
+	/// # Region.
 	///
-	/// This is essentially an `is_empty`, returning `true` if the length value
-	/// is zero or the buffer pointer is literally null.
+	/// Return the crop — if any — as it actually applies to the JPEG `src`:
+	/// with the top-left corner moved up and left to the nearest iMCU
+	/// boundary, and the bottom-right pulled in to the edges of the image.
+	/// (This is what `jpegtran` does.)
 	///
-	/// (The name was chosen to help avoid conflicts with dereferenced slice
-	/// methods.)
-	const fn is_null(&self) -> bool { self.size == 0 || self.buf.is_null() }
+	/// ## Errors
+	///
+	/// An error is returned if the image can't be parsed, the crop starts
+	/// outside it, or — unless `--allow-imperfect` — any adjustments were
+	/// needed.
+	pub(super) fn region(src: &[u8]) -> Result<Option<Self>, EncodingError> {
+		let Some(crop) = Self::get() else { return Ok(None); };
+		let (w, h) = ImageKind::jpeg_dimensions(src).ok_or(EncodingError::Format)?;
+		let (mw, mh) = ImageKind::jpeg_imcu(src).ok_or(EncodingError::Format)?;
+		let (w, h) = (w.get(), h.get());
+		if w <= crop.x || h <= crop.y { return Err(EncodingError::Crop); }
+
+		let x = crop.x - crop.x % mw;
+		let y = crop.y - crop.y % mh;
+		let out = Self {
+			width: crop.width.min(w - crop.x) + (crop.x - x),
+			height: crop.height.min(h - crop.y) + (crop.y - y),
+			x,
+			y,
+		};
+
+		if out == crop || ALLOW_IMPERFECT.load(Relaxed) { Ok(Some(out)) }
+		else { Err(EncodingError::Crop) }
+	}
 }
 
 
 
 #[expect(clippy::inline_always, reason = "For performance.")]
-#[expect(unsafe_code, reason = "For FFI.")]
 #[inline(always)]
 /// # Jpegtran (Memory Mode)
 ///
-/// ## Errors
-///
-/// An error is returned on failure, including cases where everything worked
-/// but no compression was possible.
-///
-/// ## Safety
+/// Losslessly recompress `src` with the current settings, returning the
+/// result if it is smaller.
 ///
-/// The data should be valid JPEG data. Weird things could happen if it isn't.
+/// Like everything else in `MozJPEG`, this may panic on error.
 pub(super) fn optimize(src: &[u8]) -> Option<EncodedJPEG> {
-	let mut transformoption = jpeg_transform_info {
-		transform: JXFORM_CODE_JXFORM_NONE,
-		perfect: 0,
-		trim: 0,
-		force_grayscale: 0,
-		crop: 0,
-		slow_hflip: 0,
-		crop_width: 0,
-		crop_width_set: JCROP_CODE_JCROP_UNSET,
-		crop_height: 0,
-		crop_height_set: JCROP_CODE_JCROP_UNSET,
-		crop_xoffset: 0,
-		crop_xoffset_set: JCROP_CODE_JCROP_UNSET,
-		crop_yoffset: 0,
-		crop_yoffset_set: JCROP_CODE_JCROP_UNSET,
-		num_components: 0,
-		workspace_coef_arrays: std::ptr::null_mut::<jvirt_barray_ptr>(),
-		output_width: 0,
-		output_height: 0,
-		x_crop_offset: 0,
-		y_crop_offset: 0,
-		iMCU_sample_width: 0,
-		iMCU_sample_height: 0,
+	let Ok(crop) = Crop::region(src) else { return None; };
+	let settings = Settings {
+		keep: KEEP_MARKERS.load(Relaxed),
+		orient: AUTO_ORIENT.load(Relaxed),
+		crop,
 	};
+	flapfli::jpegtran::optimize(src, &settings)
+}
+
+/// # Metadata Lost?
+///
+/// Return whether or not optimizing `src` with the current settings strips
+/// an ICC profile and/or a (non-normal) EXIF Orientation, respectively.
+///
+/// This is a plain marker walk; no decoding is required.
+pub(super) fn metadata_lost(src: &[u8]) -> (bool, bool) {
+	let Some(mut raw) = src.strip_prefix(&[0xFF, 0xD8]) else { return (false, false); };
+	let (mut icc, mut rotated) = (false, false);
+	loop {
+		match raw {
+			// Fill bytes.
+			[0xFF, 0xFF, ..] => { raw = &raw[1..]; },
+			// The metadata all comes before the scans.
+			[0xFF, 0xDA | 0xD9, ..] => break,
+			[0xFF, marker, a, b, rest @ ..] => {
+				let len = usize::from(u16::from_be_bytes([*a, *b])).saturating_sub(2);
+				let Some(data) = rest.get(..len) else { break; };
+				if *marker == APP1 && data.starts_with(SIG_EXIF) {
+					rotated |= exif::orientation(data).is_some_and(|(o, _)| (2..=8).contains(&o));
+				}
+				else if *marker == APP2 && data.starts_with(SIG_ICC) { icc = true; }
+				raw = &rest[len..];
+			},
+			_ => break,
+		}
+	}
+
+	let keep = KEEP_MARKERS.load(Relaxed);
+	(
+		icc && 0 == keep & KEEP_ICC,
+		rotated && 0 == keep & KEEP_EXIF && ! AUTO_ORIENT.load(Relaxed),
+	)
+}
 
-	// Our original image length.
-	let src_size = src.len() as c_ulong; // We know this fits.
 
-	// Set up the decompression/compression structs.
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Effectively Grayscale?
+///
+/// Returns `true` if `src` is a (three-component) YCbCr JPEG whose chroma
+/// planes are completely empty, i.e. a neutral gray throughout.
+///
+/// Like everything else in `MozJPEG`, this may panic on error.
+fn is_grayscale(src: &[u8]) -> bool {
 	let mut srcinfo = JpegSrcInfo::from(src);
-	let mut dstinfo = JpegDstInfo::from(&mut srcinfo);
+	srcinfo.read_header(&[]);
 
 	// Safety: these are FFI calls…
 	unsafe {
-		// Load the source file.
-		jpeg_mem_src(&mut srcinfo.cinfo, srcinfo.raw.as_ptr(), src_size);
-
-		// Ignore markers.
-		jcopy_markers_setup(&mut srcinfo.cinfo, JCOPY_OPTION_JCOPYOPT_NONE);
-
-		// Read the file header to get to the goods.
-		jpeg_read_header(&mut srcinfo.cinfo, 1);
-
-		// Read a few more properties into the source struct.
-		if jtransform_request_workspace(&mut srcinfo.cinfo, &mut transformoption) == 0 {
-			return None;
+		// The luma has to be full-sized too, or jpegtran won't be able to
+		// drop the rest.
+		if
+			srcinfo.cinfo.num_components != 3 ||
+			srcinfo.cinfo.jpeg_color_space != J_COLOR_SPACE::JCS_YCbCr ||
+			(*srcinfo.cinfo.comp_info).h_samp_factor != srcinfo.cinfo.max_h_samp_factor ||
+			(*srcinfo.cinfo.comp_info).v_samp_factor != srcinfo.cinfo.max_v_samp_factor
+		{
+			return false;
 		}
-	}
 
-	// Read source file as DCT coefficients.
-	// Safety: this is an FFI call…
-	let src_coef_arrays: *mut jvirt_barray_ptr = unsafe {
-		jpeg_read_coefficients(&mut srcinfo.cinfo)
-	};
+		let coefs = jpeg_read_coefficients(&mut srcinfo.cinfo);
+		let Some(access) = (*srcinfo.cinfo.common.mem).access_virt_barray else { return false; };
+		if coefs.is_null() { return false; }
+
+		for idx in 1..3 {
+			let comp = &*srcinfo.cinfo.comp_info.add(idx);
+			let arr = *coefs.add(idx);
+			for row in 0..comp.height_in_blocks {
+				let rows = access(&mut srcinfo.cinfo.common, arr, row, 1, 0);
+				if rows.is_null() || (*rows).is_null() { return false; }
+				let blocks = std::slice::from_raw_parts(*rows, comp.width_in_blocks as usize);
+				if blocks.iter().any(|b| b.iter().any(|&c| c != 0)) { return false; }
+			}
+		}
 
-	// Initialize destination compression parameters from source values.
-	// Safety: this is an FFI call…
-	unsafe { jpeg_copy_critical_parameters(&srcinfo.cinfo, &mut dstinfo.cinfo); }
-
-	// Adjust destination parameters if required by transform options, and sync
-	// the coefficient arrays.
-	// Safety: this is an FFI call…
-	let dst_coef_arrays: *mut jvirt_barray_ptr = unsafe {
-		jtransform_adjust_parameters(
-			&mut srcinfo.cinfo,
-			&mut dstinfo.cinfo,
-			src_coef_arrays,
-			&mut transformoption,
-		)
-	};
+		jpeg_finish_decompress(&mut srcinfo.cinfo);
+		true
+	}
+}
 
-	// Turn on "code optimizing".
-	dstinfo.cinfo.optimize_coding = 1;
+#[expect(clippy::cast_sign_loss, reason = "False positive.")]
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Decode (Pixels).
+///
+/// Fully decode the image, returning its pixels (in its native output color
+/// space) along with the dimensions, components per pixel, and the EXIF
+/// Orientation, if any.
+///
+/// Like everything else in `MozJPEG`, this may panic on error.
+pub(super) fn decode(src: &[u8]) -> Option<DecodedJPEG> {
+	let mut srcinfo = JpegSrcInfo::from(src);
+	srcinfo.read_header(&[APP1]);
 
-	// Compress!
-	let mut out = EncodedJPEG::new();
 	// Safety: these are FFI calls…
 	unsafe {
-		// Enable "progressive".
-		jpeg_simple_progression(&mut dstinfo.cinfo);
+		let orientation = orientation(&srcinfo.cinfo);
+
+		// Smoothing and interpolation can vary with block placement, which
+		// would make comparisons across transforms a lot noisier.
+		srcinfo.cinfo.do_fancy_upsampling = 0;
+		srcinfo.cinfo.do_block_smoothing = 0;
+		if 0 == jpeg_start_decompress(&mut srcinfo.cinfo) { return None; }
+
+		let width = srcinfo.cinfo.output_width;
+		let height = srcinfo.cinfo.output_height;
+		let components = srcinfo.cinfo.output_components as usize;
+		let stride = width as usize * components;
+		let mut pixels = vec![0_u8; stride * height as usize];
+		while srcinfo.cinfo.output_scanline < height {
+			let start = srcinfo.cinfo.output_scanline as usize * stride;
+			let mut row = pixels[start..].as_mut_ptr();
+			if 0 == jpeg_read_scanlines(&mut srcinfo.cinfo, &raw mut row, 1) { return None; }
+		}
+		jpeg_finish_decompress(&mut srcinfo.cinfo);
 
-		// And load the destination file.
-		jpeg_mem_dest(&mut dstinfo.cinfo, &mut out.buf, &mut out.size);
+		Some(DecodedJPEG { width, height, components, orientation, pixels })
+	}
+}
 
-		// Start the compressor. Note: no data is written here.
-		jpeg_write_coefficients(&mut dstinfo.cinfo, dst_coef_arrays);
 
-		// Make sure we aren't copying any markers.
-		jcopy_markers_execute(&mut srcinfo.cinfo, &mut dstinfo.cinfo, JCOPY_OPTION_JCOPYOPT_NONE);
 
-		// Execute and write the transformation, if any.
-		jtransform_execute_transform(
-			&mut srcinfo.cinfo,
-			&mut dstinfo.cinfo,
-			src_coef_arrays,
-			&mut transformoption,
-		);
-	}
+#[derive(Debug)]
+/// # Decoded Image.
+///
+/// This holds the raw pixels and details for a fully-decoded JPEG.
+pub(super) struct DecodedJPEG {
+	/// # Width.
+	pub(super) width: u32,
 
-	// Finish it up, and note whether or not it (probably) worked.
-	let happy = dstinfo.finish();
+	/// # Height.
+	pub(super) height: u32,
 
-	// The decompression will have finished much earlier, but we had to wait
-	// to call this deconstructor until now because of all the shared
-	// references.
-	// Safety: this is an FFI call…
-	unsafe { jpeg_finish_decompress(&mut srcinfo.cinfo); }
+	/// # Components (Per Pixel).
+	pub(super) components: usize,
 
-	// Return it if we got it!
-	if happy  && ! out.is_null() && out.size < src_size { Some(out) }
-	else { None }
+	/// # EXIF Orientation.
+	pub(super) orientation: u16,
+
+	/// # Pixels.
+	pub(super) pixels: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Copy)]
+/// # Transcode Settings.
+///
+/// This holds the marker and transformation settings for a single
+/// `jpegtran` pass.
+struct Settings {
+	/// # Markers to Keep.
+	keep: u8,
 
+	/// # Auto-Orient?
+	orient: bool,
 
-/// # JPEG Source Info.
-///
-/// This struct is used to parse the source image details and related errors.
-/// The abstraction is primarily used to ensure the C-related resources are
-/// correctly broken down on drop.
-struct JpegSrcInfo<'a> {
-	/// # Source Data.
-	raw: &'a [u8],
-
-	/// # Decompressor.
-	cinfo: jpeg_decompress_struct,
-
-	/// # Error Instance.
-	err: Box<jpeg_error_mgr>,
+	/// # Crop Region.
+	crop: Option<Crop>,
 }
 
-impl<'a> From<&'a [u8]> for JpegSrcInfo<'a> {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	fn from(raw: &'a [u8]) -> Self {
-		let mut out = Self {
-			raw,
-			// Safety: the subsequent FFI call expects zeroed memory.
-			cinfo: unsafe { std::mem::zeroed() },
-			err: new_err(),
-		};
+#[expect(unsafe_code, reason = "For FFI.")]
+impl Transcode for Settings {
+	unsafe fn prepare(&self, srcinfo: &mut jpeg_decompress_struct) {
+		if self.orient || 0 != self.keep & (KEEP_EXIF | KEEP_XMP) {
+			jpeg_save_markers(srcinfo, c_int::from(APP1), 0xFFFF);
+		}
+		if 0 != self.keep & KEEP_ICC {
+			jpeg_save_markers(srcinfo, c_int::from(APP2), 0xFFFF);
+		}
+	}
+
+	unsafe fn transform(
+		&self,
+		src: &[u8],
+		srcinfo: &jpeg_decompress_struct,
+		transform: &mut jpeg_transform_info,
+	) {
+		// If the image needs rotating, set up a transformation for it. Only
+		// perfect transforms are allowed; if the dimensions don't play nice,
+		// the image will be left alone entirely.
+		if self.orient {
+			transform.transform = orientation_transform(srcinfo);
+			if transform.transform != JXFORM_CODE_JXFORM_NONE {
+				transform.perfect = 1;
+			}
+		}
 
-		// Safety: and here is that FFI call…
-		unsafe {
-			// Set up the error, then the struct.
-			out.cinfo.common.err = std::ptr::addr_of_mut!(*out.err);
-			jpeg_create_decompress(&mut out.cinfo);
+		// Drop the (empty) chroma, if desired.
+		if GRAYSCALE_AUTO.load(Relaxed) && is_grayscale(src) {
+			transform.force_grayscale = 1;
 		}
 
-		out
+		// Crop it, if desired. The region has already been vetted by the
+		// optimizer.
+		if let Some(crop) = self.crop {
+			transform.crop = 1;
+			transform.crop_width = crop.width;
+			transform.crop_width_set = JCROP_CODE_JCROP_POS;
+			transform.crop_height = crop.height;
+			transform.crop_height_set = JCROP_CODE_JCROP_POS;
+			transform.crop_xoffset = crop.x;
+			transform.crop_xoffset_set = JCROP_CODE_JCROP_POS;
+			transform.crop_yoffset = crop.y;
+			transform.crop_yoffset_set = JCROP_CODE_JCROP_POS;
+		}
 	}
-}
 
-impl Drop for JpegSrcInfo<'_> {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	fn drop(&mut self) {
-		// Safety: mozjpeg handles deallocation itself.
-		unsafe { jpeg_destroy_decompress(&mut self.cinfo); }
+	unsafe fn copy_markers(
+		&self,
+		srcinfo: &jpeg_decompress_struct,
+		dstinfo: &mut jpeg_compress_struct,
+		transformed: bool,
+	) {
+		copy_markers(srcinfo, dstinfo, self.keep, transformed);
 	}
 }
 
 
 
-/// # JPEG Destination Info.
+#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Copy Markers.
 ///
-/// This struct is used to hold the output-related image details, but not the
-/// image itself.
+/// Copy the saved EXIF, ICC, and/or XMP markers from the source to the
+/// destination, ignoring everything else.
 ///
-/// On the surface, this looks almost exactly like the `JpegSrcInfo` wrapper,
-/// but its error is a raw pointer because `mozjpeg` is really weird. Haha.
-struct JpegDstInfo {
-	/// # Compressor.
-	cinfo: jpeg_compress_struct,
-
-	/// # Error Instance.
-	err: NonNull<jpeg_error_mgr>,
-
-	/// # Pinned Data.
-	_pin: PhantomPinned,
-}
-
-impl From<&mut JpegSrcInfo<'_>> for JpegDstInfo {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	fn from(src: &mut JpegSrcInfo<'_>) -> Self {
-		let mut out = Self {
-			// Safety: the subsequent FFI call requires zeroed memory.
-			cinfo: unsafe { std::mem::zeroed() },
-			// Safety: boxes point somewhere!
-			err: unsafe { NonNull::new_unchecked(Box::into_raw(new_err())) },
-			_pin: PhantomPinned,
-		};
-
-		// Safety: these are FFI calls…
-		unsafe {
-			// Set up the error, then the struct.
-			out.cinfo.common.err = out.err.as_ptr();
-			jpeg_CreateCompress(&mut out.cinfo, JPEG_LIB_VERSION, size_of_val(&out.cinfo));
-
-			// Note: depending on the compiler/flags, JPEG compression can
-			// segfault if this isn't explicitly made null. Not sure why it
-			// isn't an always/never behavior…
-			out.cinfo.common.progress = std::ptr::null_mut();
-
-			// Sync the source trace level with the destination.
-			src.err.trace_level = out.err.as_ref().trace_level;
+/// Kept EXIF markers have their embedded thumbnails stripped unless
+/// `KEEP_THUMBNAIL` is also set, and if the image was `rotated`, their
+/// Orientation is reset to normal so viewers don't rotate it a second time.
+///
+/// ## Safety
+///
+/// This must be called after `jpeg_write_coefficients` but before any
+/// image data is written.
+unsafe fn copy_markers(
+	srcinfo: &jpeg_decompress_struct,
+	dstinfo: &mut jpeg_compress_struct,
+	keep: u8,
+	rotated: bool,
+) {
+	if keep == 0 { return; }
+
+	let mut marker = srcinfo.marker_list;
+	while let Some(m) = marker.as_ref() {
+		if ! m.data.is_null() {
+			let data = std::slice::from_raw_parts(m.data, m.data_length as usize);
+			match m.marker {
+				APP1 if 0 != keep & KEEP_EXIF && data.starts_with(SIG_EXIF) => {
+					let mut exif = data.to_vec();
+					let reset = rotated && exif::reset_orientation(&mut exif);
+					let stripped = 0 == keep & KEEP_THUMBNAIL && exif::strip_thumbnail(&mut exif);
+
+					if reset || stripped {
+						jpeg_write_marker(
+							dstinfo,
+							c_int::from(APP1),
+							exif.as_ptr(),
+							exif.len() as c_uint, // Never bigger than the original.
+						);
+					}
+					else {
+						jpeg_write_marker(dstinfo, c_int::from(APP1), m.data, m.data_length);
+					}
+				},
+				APP1 if 0 != keep & KEEP_XMP && data.starts_with(SIG_XMP) => {
+					jpeg_write_marker(dstinfo, c_int::from(APP1), m.data, m.data_length);
+				},
+				APP2 if 0 != keep & KEEP_ICC && data.starts_with(SIG_ICC) => {
+					jpeg_write_marker(dstinfo, c_int::from(APP2), m.data, m.data_length);
+				},
+				_ => {},
+			}
 		}
 
-		out
+		marker = m.next;
 	}
 }
 
-impl Drop for JpegDstInfo {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	fn drop(&mut self) {
-		// Safety: mozjpeg handles deallocation itself.
-		unsafe {
-			jpeg_destroy_compress(&mut self.cinfo);
-			let _ = Box::from_raw(self.err.as_ptr());
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Orientation.
+///
+/// Return the EXIF Orientation from among the saved markers, or `1` (normal)
+/// if there isn't one.
+unsafe fn orientation(srcinfo: &jpeg_decompress_struct) -> u16 {
+	let mut marker = srcinfo.marker_list;
+	while let Some(m) = marker.as_ref() {
+		if m.marker == APP1 && ! m.data.is_null() {
+			let data = std::slice::from_raw_parts(m.data, m.data_length as usize);
+			if let Some((o, _)) = exif::orientation(data) { return o; }
 		}
-	}
-}
 
-impl JpegDstInfo {
-	#[expect(unsafe_code, reason = "For FFI.")]
-	/// # Finish Compression!
-	///
-	/// This finishes writing the new image, consuming the details struct in
-	/// the process.
-	///
-	/// A simple `true`/`false` boolean is returned to indicate (likely)
-	/// success.
-	fn finish(mut self) -> bool {
-		// Safety: mozjpeg handles deallocation itself.
-		unsafe {
-			jpeg_finish_compress(&mut self.cinfo);
-			0 == (*self.cinfo.common.err).msg_code
-		}
+		marker = m.next;
 	}
-}
-
 
+	1
+}
 
-#[expect(clippy::unnecessary_box_returns, reason = "We want a box.")]
 #[expect(unsafe_code, reason = "For FFI.")]
-/// # New Unwinding Error.
+/// # Orientation Transform.
 ///
-/// Mozjpeg is largely designed to panic anytime there's an error instead of
-/// returning helpful status messages or anything like that.
-///
-/// This initializes a new error struct for de/compression use with handlers
-/// set to suppress the messaging and unwind.
-///
-/// Shout out to the [mozjpeg](https://github.com/ImageOptim/mozjpeg-rust/blob/main/src/errormgr.rs)
-/// crate for the inspiration!
-fn new_err() -> Box<jpeg_error_mgr> {
-	// Safety: the FFI call requires zeroed memory to start from.
-	unsafe {
-		let mut err = Box::new(std::mem::zeroed());
-		jpeg_std_error(&mut err);
-		err.error_exit = Some(unwind_error_exit);
-		err.emit_message = Some(silence_message);
-		err
+/// Find the EXIF Orientation (if any) among the saved markers and return the
+/// corresponding `jpegtran` transformation needed to bake it in.
+unsafe fn orientation_transform(srcinfo: &jpeg_decompress_struct) -> JXFORM_CODE {
+	match orientation(srcinfo) {
+		2 => JXFORM_CODE_JXFORM_FLIP_H,
+		3 => JXFORM_CODE_JXFORM_ROT_180,
+		4 => JXFORM_CODE_JXFORM_FLIP_V,
+		5 => JXFORM_CODE_JXFORM_TRANSPOSE,
+		6 => JXFORM_CODE_JXFORM_ROT_90,
+		7 => JXFORM_CODE_JXFORM_TRANSVERSE,
+		8 => JXFORM_CODE_JXFORM_ROT_270,
+		_ => JXFORM_CODE_JXFORM_NONE,
 	}
 }
 
-#[cold]
-/// # Error Message.
-///
-/// This is a noop method; no error message is printed.
-extern "C-unwind" fn silence_message(_cinfo: &mut jpeg_common_struct, _msg_level: c_int) {}
 
-#[cold]
-/// # Error Exit.
-///
-/// Emit an unwinding panic so we can recover somewhat gracefully from mozjpeg
-/// errors.
-extern "C-unwind" fn unwind_error_exit(_cinfo: &mut jpeg_common_struct) {
-	std::panic::resume_unwind(Box::new(()));
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_is_grayscale() {
+		for (file, expected) in [
+			("../skel/assets/gray-ycc.jpg", true),
+			("../skel/assets/jpg/01.jpg", false),
+			("../skel/assets/jpg/07.jpg", false),
+			// Already grayscale.
+			("../skel/assets/jpg/17.jpg", false),
+		] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			assert_eq!(is_grayscale(&raw), expected, "Wrong answer for {file}.");
+		}
+	}
+
+	#[test]
+	fn t_crop_parse() {
+		let crop = |width, height, x, y| Some(Crop { width, height, x, y });
+		for (raw, expected) in [
+			("1920x800+0+144", crop(1920, 800, 0, 144)),
+			("32x32", crop(32, 32, 0, 0)),
+			("32x32+8", None),
+			("32x32+8+8+8", None),
+			("0x32+0+0", None),
+			("32x+0+0", None),
+			("32", None),
+			("", None),
+		] {
+			assert_eq!(Crop::parse(raw.as_bytes()), expected, "Wrong answer for {raw:?}.");
+		}
+	}
 }
@@ -2,27 +2,50 @@
 # Flaca: Image Kind
 */
 
-use crate::FlacaError;
-use std::num::NonZeroU32;
+use crate::{
+	E_CUR,
+	E_GIF,
+	E_ICO,
+	E_JFIF,
+	E_JPE,
+	E_JPEG,
+	E_JPG,
+	E_PNG,
+	FlacaError,
+};
+use dowser::Extension;
+use super::ico;
+use std::{
+	num::NonZeroU32,
+	path::Path,
+};
 
 
 
-#[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-/// # Image Kind.
+/// # Image Kind(s).
 ///
-/// This evaluates the file type from its headers, ensuring we process images
-/// correctly even if they have the wrong extension (or don't process them if
-/// they're bunk).
-pub(crate) enum ImageKind {
-	/// # All.
-	All,
+/// This is a set of image formats, used both to describe what a given file
+/// is — evaluated from its headers, ensuring we process images correctly even
+/// if they have the wrong extension (or don't process them if they're bunk) —
+/// and which formats are enabled.
+pub(crate) struct ImageKind(u8);
+
+impl ImageKind {
+	/// # GIF.
+	pub(crate) const GIF: Self = Self(0b0001);
+
+	/// # JPEG.
+	pub(crate) const JPEG: Self = Self(0b0010);
+
+	/// # PNG.
+	pub(crate) const PNG: Self = Self(0b0100);
 
-	/// # Jpeg.
-	Jpeg,
+	/// # ICO/CUR.
+	pub(crate) const ICO: Self = Self(0b1000);
 
-	/// # Png.
-	Png,
+	/// # All.
+	pub(crate) const ALL: Self = Self(Self::GIF.0 | Self::JPEG.0 | Self::PNG.0 | Self::ICO.0);
 }
 
 impl ImageKind {
@@ -31,29 +54,141 @@ impl ImageKind {
 	/// Subtract `other` from `self`, returning an error if that leaves
 	/// nothing.
 	pub(crate) const fn diff(self, other: Self) -> Result<Self, FlacaError> {
-		match other {
-			Self::Jpeg if matches!(self, Self::All | Self::Png) => Ok(Self::Png),
-			Self::Png if matches!(self, Self::All | Self::Jpeg) => Ok(Self::Jpeg),
-			_ => Err(FlacaError::NoImages),
-		}
+		let out = self.0 & ! other.0;
+		if out == 0 { Err(FlacaError::NoImages) }
+		else { Ok(Self(out)) }
 	}
 
 	#[expect(clippy::inline_always, reason = "For performance.")]
 	#[inline(always)]
-	/// # Supports JPEG?
-	pub(crate) const fn supports_jpeg(self) -> bool {
-		matches!(self, Self::All | Self::Jpeg)
+	/// # Contains?
+	///
+	/// Returns `true` if `self` includes (any of) `other`.
+	pub(crate) const fn contains(self, other: Self) -> bool {
+		0 != self.0 & other.0
+	}
+}
+
+impl ImageKind {
+	/// # From Bytes.
+	///
+	/// Return the kind of image `src` is, according to its headers, if any.
+	pub(crate) fn from_bytes(src: &[u8]) -> Option<Self> {
+		if Self::is_png(src) { Some(Self::PNG) }
+		else if Self::is_jpeg(src) { Some(Self::JPEG) }
+		else if Self::is_gif(src) { Some(Self::GIF) }
+		else if Self::is_ico(src) { Some(Self::ICO) }
+		else { None }
+	}
+
+	/// # From Path.
+	///
+	/// Return the kind of image `src` claims to be, according to its file
+	/// extension, if any.
+	///
+	/// This is the one place extensions are mapped to kinds, so covers all
+	/// the common aliases — `.jpe`, `.jfif`, etc. — in any case.
+	pub(crate) fn from_path(src: &Path) -> Option<Self> {
+		let Some(ext) = Extension::try_from3(src) else {
+			return Extension::try_from4(src)
+				.filter(|e| *e == E_JPEG || *e == E_JFIF)
+				.map(|_| Self::JPEG);
+		};
+		if ext == E_PNG { Some(Self::PNG) }
+		else if ext == E_JPG || ext == E_JPE { Some(Self::JPEG) }
+		else if ext == E_GIF { Some(Self::GIF) }
+		else if ext == E_ICO || ext == E_CUR { Some(Self::ICO) }
+		else { None }
+	}
+
+	/// # Extension.
+	///
+	/// Return the conventional file extension for this kind of image, if
+	/// singular. Icons and cursors share a kind, so the header — `src` — is
+	/// used to tell them apart.
+	pub(crate) fn extension(self, src: &[u8]) -> Option<&'static str> {
+		match self {
+			Self::GIF => Some("gif"),
+			Self::ICO => Some(if src.get(2) == Some(&2) { "cur" } else { "ico" }),
+			Self::JPEG => Some("jpg"),
+			Self::PNG => Some("png"),
+			_ => None,
+		}
+	}
+
+	/// # Is APNG?
+	///
+	/// Animated PNGs are regular PNGs with an `acTL` chunk somewhere before
+	/// the first `IDAT`. (The encoders only understand the latter, so would
+	/// strip the animation.)
+	pub(crate) fn is_apng(src: &[u8]) -> bool { Self::png_has_chunk(src, *b"acTL") }
+
+	/// # Damaged PNG?
+	///
+	/// Returns `true` if any of the PNG's chunks are truncated or fail their
+	/// CRC checks, or there's no `IEND`. (Anything after the `IEND` is
+	/// ignored.)
+	pub(crate) fn png_is_damaged(src: &[u8]) -> bool {
+		if ! Self::is_png(src) { return true; }
+
+		// Each chunk is a four-byte length, four-byte name, the data, and a
+		// four-byte CRC of the name and data.
+		let mut raw = &src[8..];
+		while let [a, b, c, d, rest @ ..] = raw {
+			let Some((body, rest)) = usize::try_from(u32::from_be_bytes([*a, *b, *c, *d]))
+				.ok()
+				.and_then(|len| len.checked_add(4))
+				.and_then(|len| rest.split_at_checked(len))
+			else { return true; };
+			let Some((crc, rest)) = rest.split_at_checked(4) else { return true; };
+			if crc32fast::hash(body).to_be_bytes() != crc { return true; }
+			if body.starts_with(b"IEND") { return false; }
+			raw = rest;
+		}
+
+		true
+	}
+
+	/// # PNG Has Chunk?
+	///
+	/// Returns `true` if `src` is a PNG with a `name` chunk somewhere before
+	/// the first `IDAT`.
+	pub(crate) fn png_has_chunk(src: &[u8], name: [u8; 4]) -> bool {
+		if ! Self::is_png(src) { return false; }
+
+		// Each chunk is a four-byte length, four-byte name, the data, and a
+		// four-byte CRC.
+		let mut raw = &src[8..];
+		while let [a, b, c, d, e, f, g, h, rest @ ..] = raw {
+			let chunk = [*e, *f, *g, *h];
+			if chunk == name { return true; }
+			if chunk == *b"IDAT" { return false; }
+
+			let Ok(len) = usize::try_from(u32::from_be_bytes([*a, *b, *c, *d])) else {
+				return false;
+			};
+			let Some(next) = len.checked_add(4).and_then(|n| rest.get(n..)) else {
+				return false;
+			};
+			raw = next;
+		}
+
+		false
 	}
 
 	#[expect(clippy::inline_always, reason = "For performance.")]
 	#[inline(always)]
-	/// # Supports PNG?
-	pub(crate) const fn supports_png(self) -> bool {
-		matches!(self, Self::All | Self::Png)
+	/// # Is GIF?
+	pub(crate) fn is_gif(src: &[u8]) -> bool {
+		13 < src.len() && (src[..6] == *b"GIF87a" || src[..6] == *b"GIF89a")
 	}
-}
 
-impl ImageKind {
+	/// # Is ICO/CUR?
+	///
+	/// The signature is only four bytes — mostly zeroes — so the directory is
+	/// checked too.
+	pub(crate) fn is_ico(src: &[u8]) -> bool { ico::entries(src).is_some() }
+
 	#[expect(clippy::inline_always, reason = "For performance.")]
 	#[inline(always)]
 	/// # Is JPEG?
@@ -77,10 +212,63 @@ impl ImageKind {
 }
 
 impl ImageKind {
+	/// # Width and Height.
+	///
+	/// Parse the image's (logical screen) width and height from the headers.
+	pub(crate) fn gif_dimensions(raw: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+		if 13 < raw.len() {
+			let width = NonZeroU32::new(u32::from(u16::from_le_bytes([raw[6], raw[7]])))?;
+			let height = NonZeroU32::new(u32::from(u16::from_le_bytes([raw[8], raw[9]])))?;
+			Some((width, height))
+		}
+		else { None }
+	}
+
+	/// # Width and Height.
+	///
+	/// Return the width and height of the largest icon or cursor in the
+	/// directory.
+	pub(crate) fn ico_dimensions(raw: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+		let (w, h) = ico::dimensions(raw)?;
+		Some((NonZeroU32::new(w)?, NonZeroU32::new(h)?))
+	}
+
 	/// # Width and Height.
 	///
 	/// Parse the image's width and height from the headers.
-	pub(crate) fn jpeg_dimensions(mut raw: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+	pub(crate) fn jpeg_dimensions(raw: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+		let raw = Self::jpeg_sof(raw)?;
+		if 4 < raw.len() {
+			// Height before width for whatever reason!
+			let height = NonZeroU32::new(u32::from(u16::from_be_bytes([raw[0], raw[1]])))?;
+			let width = NonZeroU32::new(u32::from(u16::from_be_bytes([raw[2], raw[3]])))?;
+			Some((width, height))
+		}
+		else { None }
+	}
+
+	/// # JPEG iMCU Size.
+	///
+	/// Return the width and height of the JPEG's iMCUs — the smallest units
+	/// it can be losslessly cropped by — as determined by its sampling
+	/// factors. (Single-component images always use plain 8x8 blocks.)
+	pub(crate) fn jpeg_imcu(raw: &[u8]) -> Option<(u32, u32)> {
+		let raw = Self::jpeg_sof(raw)?;
+		let n = usize::from(*raw.get(4)?);
+		if n == 0 { return None; }
+		if n == 1 { return Some((8, 8)); }
+
+		let (h, v) = raw.get(5..5 + n * 3)?
+			.chunks_exact(3)
+			.fold((1, 1), |(h, v), c| (h.max(c[1] >> 4), v.max(c[1] & 0x0F)));
+		Some((u32::from(h) * 8, u32::from(v) * 8))
+	}
+
+	/// # Start of Frame.
+	///
+	/// Find the (top-level) start-of-frame segment and return everything
+	/// following its precision byte, i.e. starting with the height.
+	fn jpeg_sof(mut raw: &[u8]) -> Option<&[u8]> {
 		// We need to find the damn dimensions first!
 		raw = raw.strip_prefix(&[0xFF, 0xD8])?;
 		let mut depth = 0_i32;
@@ -90,20 +278,17 @@ impl ImageKind {
 
 			// Where we go from here depends on the SOFn marker…
 			match sof {
-				// C4, C8, and CC don't count, haha.
-				0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF =>
-					// We found it!
-					if depth == 0 {
-						raw = rest.get(3..)?;
-						break;
-					},
+				// C4, C8, and CC don't count, haha. We found it!
+				0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF if depth == 0 => {
+					return rest.get(3..);
+				},
 				0xD8 => { depth += 1; },
 				0xD9 => {
 					depth -= 1;
 					if depth < 0 { return None; }
 				},
 				_ => {},
-			};
+			}
 
 			// Fast forward through the remainder of the section.
 			if 2 < rest.len() {
@@ -112,14 +297,35 @@ impl ImageKind {
 			}
 			else { return None; }
 		}
+	}
 
-		if 4 < raw.len() {
-			// Height before width for whatever reason!
-			let height = NonZeroU32::new(u32::from(u16::from_be_bytes([raw[0], raw[1]])))?;
-			let width = NonZeroU32::new(u32::from(u16::from_be_bytes([raw[2], raw[3]])))?;
-			Some((width, height))
+	/// # Decoded Bytes Per Pixel.
+	///
+	/// Estimate how many bytes each pixel will take up once decoded, going by
+	/// the channels — and for PNGs, bit depth — declared in the headers.
+	/// Palettes are assumed to expand to RGBA, as do GIFs and icons.
+	pub(crate) fn bytes_per_pixel(self, raw: &[u8]) -> Option<u64> {
+		match self {
+			Self::GIF | Self::ICO => Some(4),
+			// The component count follows the dimensions in the SOF.
+			Self::JPEG => match Self::jpeg_sof(raw)?.get(4)? {
+				0 => None,
+				n => Some(u64::from(*n)),
+			},
+			Self::PNG => {
+				// Make sure the IHDR is where it should be.
+				Self::png_dimensions(raw)?;
+				let channels = match raw[25] {
+					0 => 1,
+					2 => 3,
+					4 => 2,
+					3 | 6 => 4,
+					_ => return None,
+				};
+				Some(if raw[24] == 16 { channels * 2 } else { channels })
+			},
+			_ => None,
 		}
-		else { None }
 	}
 
 	/// # Width and Height.
@@ -141,6 +347,77 @@ impl ImageKind {
 mod tests {
 	use super::*;
 
+	#[test]
+	fn t_is_apng() {
+		/// # Chunk.
+		fn chunk(name: [u8; 4], data: &[u8]) -> Vec<u8> {
+			let mut out = u32::try_from(data.len()).unwrap_or_default().to_be_bytes().to_vec();
+			out.extend_from_slice(&name);
+			out.extend_from_slice(data);
+			out.extend_from_slice(&[0, 0, 0, 0]);
+			out
+		}
+
+		let sig = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+		let ihdr = chunk(*b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+		let actl = chunk(*b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+		let idat = chunk(*b"IDAT", &[1, 2, 3]);
+		let iend = chunk(*b"IEND", &[]);
+
+		// Animated.
+		let apng = [&sig[..], &ihdr, &actl, &idat, &iend].concat();
+		assert!(ImageKind::is_png(&apng), "APNG should still be a PNG.");
+		assert!(ImageKind::is_apng(&apng), "APNG not detected.");
+
+		// Not animated.
+		let png = [&sig[..], &ihdr, &idat, &iend].concat();
+		assert!(! ImageKind::is_apng(&png), "Static PNG detected as animated.");
+
+		// acTL after IDAT doesn't count.
+		let png = [&sig[..], &ihdr, &idat, &actl, &iend].concat();
+		assert!(! ImageKind::is_apng(&png), "Misplaced acTL detected.");
+
+		// The real thing.
+		let Ok(raw) = std::fs::read("../skel/assets/png/01.png") else {
+			panic!("Unable to open 01.png.");
+		};
+		assert!(! ImageKind::is_apng(&raw), "01.png detected as animated.");
+	}
+
+	#[test]
+	fn t_png_is_damaged() {
+		let Ok(raw) = std::fs::read("../skel/assets/png/01.png") else {
+			panic!("Unable to open 01.png.");
+		};
+		assert!(! ImageKind::png_is_damaged(&raw), "01.png is fine.");
+
+		// Trailing junk is fine too.
+		let mut junk = raw.clone();
+		junk.extend_from_slice(b"junk");
+		assert!(! ImageKind::png_is_damaged(&junk), "Trailing data isn't damage.");
+
+		// Flip a bit in the middle somewhere.
+		let mut bad = raw.clone();
+		let mid = bad.len() / 2;
+		bad[mid] ^= 1;
+		assert!(ImageKind::png_is_damaged(&bad), "Bad CRC not detected.");
+
+		// Chop off the end.
+		assert!(ImageKind::png_is_damaged(&raw[..raw.len() - 1]), "Truncation not detected.");
+	}
+
+	#[test]
+	fn t_gif_dimensions() {
+		for file in ["../skel/assets/gif/01.gif", "../skel/assets/gif/02.gif"] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			let Some((w, h)) = ImageKind::gif_dimensions(&raw) else {
+				panic!("Unable to parse dimensions from {file}.");
+			};
+			assert_eq!(w.get(), 40, "Width mismatch {w} / 40 for {file}.");
+			assert_eq!(h.get(), 40, "Height mismatch {h} / 40 for {file}.");
+		}
+	}
+
 	#[test]
 	fn t_jpeg_dimensions() {
 		let raw: &[(&str, u32, u32)] = &[
@@ -190,6 +467,21 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn t_jpeg_imcu() {
+		for (file, expected) in [
+			("../skel/assets/jpg/01.jpg", (8, 8)),   // 4:4:4.
+			("../skel/assets/jpg/06.jpg", (16, 8)),  // 4:2:2.
+			("../skel/assets/jpg/07.jpg", (16, 16)), // 4:2:0.
+			("../skel/assets/jpg/17.jpg", (8, 8)),   // Grayscale.
+			("../skel/assets/jpg/23.jpg", (16, 16)), // CMYK.
+		] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			assert_eq!(ImageKind::jpeg_imcu(&raw), Some(expected), "Wrong iMCU for {file}.");
+		}
+		assert!(ImageKind::jpeg_imcu(b"nope").is_none());
+	}
+
 	#[test]
 	fn t_png_dimensions() {
 		let raw: &[(&str, u32, u32)] = &[
@@ -215,6 +507,76 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn t_bytes_per_pixel() {
+		for (kind, file, expected) in [
+			(ImageKind::GIF, "../skel/assets/gif/01.gif", 4),
+			(ImageKind::JPEG, "../skel/assets/jpg/01.jpg", 3),
+			(ImageKind::JPEG, "../skel/assets/wolf.png", 3),
+			(ImageKind::PNG, "../skel/assets/png/01.png", 3),
+			(ImageKind::PNG, "../skel/assets/png/02.png", 4),
+			(ImageKind::PNG, "../skel/assets/png/04.png", 1),
+			(ImageKind::PNG, "../skel/assets/png/poe.png", 4),
+			(ImageKind::PNG, "../skel/assets/png/small-bwa.png", 2),
+		] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			assert_eq!(kind.bytes_per_pixel(&raw), Some(expected), "Wrong estimate for {file}.");
+		}
+
+		// Sixteen bits doubles it.
+		let Ok(mut raw) = std::fs::read("../skel/assets/png/02.png") else {
+			panic!("Unable to open 02.png.");
+		};
+		raw[24] = 16;
+		assert_eq!(ImageKind::PNG.bytes_per_pixel(&raw), Some(8));
+
+		// Junk is junk.
+		assert!(ImageKind::PNG.bytes_per_pixel(b"nope").is_none());
+		assert!(ImageKind::JPEG.bytes_per_pixel(b"nope").is_none());
+	}
+
+	#[test]
+	fn t_from_path() {
+		for (path, kind) in [
+			("/img/a.png", Some(ImageKind::PNG)),
+			("/img/a.PNG", Some(ImageKind::PNG)),
+			("/img/a.Png", Some(ImageKind::PNG)),
+			("/img/a.jpg", Some(ImageKind::JPEG)),
+			("/img/a.jpeg", Some(ImageKind::JPEG)),
+			("/img/A.JPG", Some(ImageKind::JPEG)),
+			("/img/a.Jpeg", Some(ImageKind::JPEG)),
+			("/img/a.jpe", Some(ImageKind::JPEG)),
+			("/img/a.JFIF", Some(ImageKind::JPEG)),
+			("/img/a.gif", Some(ImageKind::GIF)),
+			("/img/a.ico", Some(ImageKind::ICO)),
+			("/img/a.cur", Some(ImageKind::ICO)),
+			("/img/a.zip", None),
+			("/img/a.webp", None),
+			("/img/.png", None),
+			("/img/a", None),
+		] {
+			assert_eq!(ImageKind::from_path(Path::new(path)), kind, "Wrong kind for {path}.");
+		}
+	}
+
+	#[test]
+	fn t_extension() {
+		assert_eq!(ImageKind::GIF.extension(&[]), Some("gif"));
+		assert_eq!(ImageKind::JPEG.extension(&[]), Some("jpg"));
+		assert_eq!(ImageKind::PNG.extension(&[]), Some("png"));
+		assert_eq!(ImageKind::ICO.extension(&[0, 0, 1, 0, 1, 0]), Some("ico"));
+		assert_eq!(ImageKind::ICO.extension(&[0, 0, 2, 0, 1, 0]), Some("cur"));
+		assert_eq!(ImageKind::ALL.extension(&[]), None);
+
+		// The assets with the wrong extensions should be caught.
+		for (file, ext) in [("../skel/assets/wolf.jpg", "png"), ("../skel/assets/wolf.png", "jpg")] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			let kind = ImageKind::from_bytes(&raw).expect("Unknown kind.");
+			assert_ne!(ImageKind::from_path(Path::new(file)), Some(kind), "Mismatch missed for {file}.");
+			assert_eq!(kind.extension(&raw), Some(ext), "Wrong extension for {file}.");
+		}
+	}
+
 	#[test]
 	#[expect(clippy::cognitive_complexity, reason = "It is what it is.")]
 	fn t_parse() {
@@ -224,17 +586,29 @@ mod tests {
 					panic!("Unable to open {}.", $file);
 				};
 				match $ty {
-					Some(ImageKind::Jpeg) => {
+					Some(ImageKind::GIF) => {
+						assert!(ImageKind::is_gif(&raw));
+						assert!(! ImageKind::is_jpeg(&raw));
+						assert!(! ImageKind::is_png(&raw));
+						assert!(! ImageKind::is_ico(&raw));
+					},
+					Some(ImageKind::JPEG) => {
+						assert!(! ImageKind::is_gif(&raw));
 						assert!(ImageKind::is_jpeg(&raw));
 						assert!(! ImageKind::is_png(&raw));
+						assert!(! ImageKind::is_ico(&raw));
 					},
-					Some(ImageKind::Png) => {
+					Some(ImageKind::PNG) => {
+						assert!(! ImageKind::is_gif(&raw));
 						assert!(! ImageKind::is_jpeg(&raw));
 						assert!(ImageKind::is_png(&raw));
+						assert!(! ImageKind::is_ico(&raw));
 					},
 					_ => {
+						assert!(! ImageKind::is_gif(&raw));
 						assert!(! ImageKind::is_jpeg(&raw));
 						assert!(! ImageKind::is_png(&raw));
+						assert!(! ImageKind::is_ico(&raw));
 					},
 				}
 			)+);
@@ -243,43 +617,45 @@ mod tests {
 		test_kind!(
 			"../skel/assets/empty.jpg" None,
 			"../skel/assets/executable.sh" None,
+			"../skel/assets/gif/01.gif" Some(ImageKind::GIF),
+			"../skel/assets/gif/02.gif" Some(ImageKind::GIF),
 			"../skel/assets/herring.png" None,
-			"../skel/assets/jpg/01.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/02.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/03.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/04.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/05.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/06.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/07.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/08.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/09.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/10.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/11.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/12.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/13.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/14.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/15.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/16.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/17.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/18.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/19.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/20.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/21.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/22.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/23.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/jpg/24.jpg" Some(ImageKind::Jpeg),
-			"../skel/assets/png/01.png" Some(ImageKind::Png),
-			"../skel/assets/png/02.png" Some(ImageKind::Png),
-			"../skel/assets/png/03.png" Some(ImageKind::Png),
-			"../skel/assets/png/04.png" Some(ImageKind::Png),
-			"../skel/assets/png/05.png" Some(ImageKind::Png),
-			"../skel/assets/png/06.png" Some(ImageKind::Png),
-			"../skel/assets/png/poe.png" Some(ImageKind::Png),
-			"../skel/assets/png/small-bw.png" Some(ImageKind::Png),
-			"../skel/assets/png/small-bwa.png" Some(ImageKind::Png),
-			"../skel/assets/png/small.png" Some(ImageKind::Png),
-			"../skel/assets/wolf.jpg" Some(ImageKind::Png),
-			"../skel/assets/wolf.png" Some(ImageKind::Jpeg)
+			"../skel/assets/jpg/01.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/02.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/03.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/04.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/05.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/06.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/07.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/08.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/09.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/10.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/11.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/12.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/13.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/14.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/15.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/16.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/17.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/18.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/19.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/20.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/21.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/22.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/23.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/jpg/24.jpg" Some(ImageKind::JPEG),
+			"../skel/assets/png/01.png" Some(ImageKind::PNG),
+			"../skel/assets/png/02.png" Some(ImageKind::PNG),
+			"../skel/assets/png/03.png" Some(ImageKind::PNG),
+			"../skel/assets/png/04.png" Some(ImageKind::PNG),
+			"../skel/assets/png/05.png" Some(ImageKind::PNG),
+			"../skel/assets/png/06.png" Some(ImageKind::PNG),
+			"../skel/assets/png/poe.png" Some(ImageKind::PNG),
+			"../skel/assets/png/small-bw.png" Some(ImageKind::PNG),
+			"../skel/assets/png/small-bwa.png" Some(ImageKind::PNG),
+			"../skel/assets/png/small.png" Some(ImageKind::PNG),
+			"../skel/assets/wolf.jpg" Some(ImageKind::PNG),
+			"../skel/assets/wolf.png" Some(ImageKind::JPEG)
 		);
 	}
 }
@@ -2,98 +2,570 @@
 # Flaca: Images!
 */
 
+mod exif;
+mod archive;
+mod encoder;
+mod gif;
+mod ico;
 mod jpegtran;
 pub(super) mod kind;
+mod optimizer;
+mod pipe;
+mod trace;
+mod verify;
+
+pub(super) use archive::ARCHIVES;
+pub(super) use jpegtran::{
+	ALLOW_IMPERFECT,
+	AUTO_ORIENT,
+	Crop,
+	GRAYSCALE_AUTO,
+	KEEP_EXIF,
+	KEEP_ICC,
+	KEEP_MARKERS,
+	KEEP_THUMBNAIL,
+	KEEP_XMP,
+};
+pub(super) use optimizer::Warning;
+pub(super) use pipe::{
+	crunch,
+	pipe,
+};
+pub(super) use trace::trace;
+pub(super) use verify::VERIFY;
 
 
 
-use crate::MAX_RESOLUTION;
+use crate::{
+	budget::BUDGET,
+	fault,
+	hook,
+	mark::{
+		self,
+		MARK,
+		SKIP_MARKED,
+	},
+	FlacaError,
+	ImageLock,
+	MAX_FILESIZE,
+	MAX_MEMORY,
+	MAX_RESOLUTION,
+	Observer,
+	output::{
+		self,
+		HardLinks,
+		Sink,
+	},
+	Profile,
+};
 use kind::ImageKind;
+use optimizer::Optimizer;
 use std::{
+	fs::File,
+	io::{
+		ErrorKind,
+		Read,
+	},
+	num::{
+		NonZeroU32,
+		NonZeroU64,
+	},
+	os::unix::fs::MetadataExt,
 	path::Path,
-	sync::atomic::Ordering::Relaxed,
+	sync::{
+		atomic::{
+			AtomicBool,
+			AtomicU8,
+			Ordering::Relaxed,
+		},
+		OnceLock,
+		RwLock,
+	},
+	time::SystemTime,
 };
 use super::EncodingError;
 
 
 
+/// # Estimate Head Size.
+///
+/// The number of bytes read to estimate an image's memory needs; enough to
+/// get past the metadata of all but the most bloated JPEGs.
+const ESTIMATE_HEAD: u64 = 256 * 1024;
+
+/// # Aborted?
+///
+/// Saves hold a read lock for their duration so that `abort` — which takes
+/// the write lock — can wait for them to finish before flipping the switch.
+static ABORTED: RwLock<bool> = RwLock::new(false);
+
+/// # Minimum Savings.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static MIN_SAVINGS: OnceLock<MinSavings> = OnceLock::new();
+
+/// # Oxipng Effort Level.
+///
+/// This works like `oxipng -o`, from `0` (quick) to `6` (brute force).
+pub(crate) static OXIPNG_LEVEL: AtomicU8 = AtomicU8::new(OXIPNG_LEVEL_MAX);
+
+/// # Oxipng Effort Level (Fast).
+///
+/// The lighter level used for images given the fast profile, unless
+/// overridden by `--oxipng-level`.
+pub(crate) static OXIPNG_LEVEL_FAST: AtomicU8 = AtomicU8::new(2);
+
+/// # Maximum Oxipng Effort Level.
+pub(crate) const OXIPNG_LEVEL_MAX: u8 = flapfli::OXIPNG_LEVEL_MAX;
+
+/// # Fix Extensions?
+///
+/// Images whose extensions don't match their contents — a PNG named
+/// `.jpg`, say — are renamed when `--fix-extensions` is set, rather than
+/// just flagged.
+pub(crate) static FIX_EXTENSIONS: AtomicBool = AtomicBool::new(false);
+
+/// # Fix Errors?
+///
+/// PNGs with minor damage — bad CRCs, mostly — are repaired by oxipng
+/// unless `--no-fix`, in which case they're rejected.
+pub(crate) static FIX_ERRORS: AtomicBool = AtomicBool::new(true);
+
+/// # Oxipng Enabled?
+///
+/// This is cleared by `--no-oxipng`, leaving PNGs (and ICO members) to
+/// zopfli alone.
+pub(crate) static OXIPNG: AtomicBool = AtomicBool::new(true);
+
+/// # Zopfli Enabled?
+///
+/// This is cleared by `--no-zopfli`, leaving PNGs (and ICO members) to
+/// oxipng alone.
+pub(crate) static ZOPFLI: AtomicBool = AtomicBool::new(true);
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # File Fingerprint.
+///
+/// The bits of metadata that change when a file is written to or replaced,
+/// used to tell whether an image was modified while it was being crunched.
+struct Fingerprint {
+	/// # Size.
+	len: u64,
+
+	/// # Modification Time.
+	mtime: Option<SystemTime>,
+
+	/// # Inode.
+	ino: u64,
+}
+
+impl Fingerprint {
+	/// # New.
+	fn new(path: &Path) -> Option<Self> {
+		let meta = std::fs::metadata(path).ok()?;
+		Some(Self {
+			len: meta.len(),
+			mtime: meta.modified().ok(),
+			ino: meta.ino(),
+		})
+	}
+
+	/// # Changed?
+	///
+	/// Returns `true` if the file at `path` no longer matches.
+	fn changed(self, path: &Path) -> bool { Self::new(path) != Some(self) }
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Minimum Savings.
+///
+/// Rewriting an image to save a handful of bytes isn't always worth the
+/// churn. This sets the minimum improvement required before anything gets
+/// written.
+pub(crate) enum MinSavings {
+	/// # Absolute Bytes.
+	Bytes(NonZeroU64),
+
+	/// # Percentage of the Original (1-99).
+	Percent(u8),
+}
+
+impl MinSavings {
+	/// # Set.
+	///
+	/// Parse and save the threshold from a raw CLI value, either a number of
+	/// bytes like `1024` or a percentage like `5%`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is invalid or has already been set.
+	pub(crate) fn set(raw: &[u8]) -> Result<(), FlacaError> {
+		let min = Self::parse(raw).ok_or(FlacaError::MinSavings)?;
+		MIN_SAVINGS.set(min).map_err(|_| FlacaError::MinSavings)
+	}
+
+	/// # Parse.
+	fn parse(raw: &[u8]) -> Option<Self> {
+		use dactyl::traits::BytesToUnsigned;
+
+		if let Some(raw) = raw.strip_suffix(b"%") {
+			let pct = u8::btou(raw.trim_ascii())?;
+			if (1..100).contains(&pct) { Some(Self::Percent(pct)) }
+			else { None }
+		}
+		else { NonZeroU64::btou(raw).map(Self::Bytes) }
+	}
+
+	/// # Worth It?
+	///
+	/// Returns `true` if the savings — if any — meet the minimum threshold.
+	fn worth_it(before: u64, after: u64) -> bool {
+		let Some(saved) = before.checked_sub(after).filter(|s| *s != 0) else {
+			return false;
+		};
+		match MIN_SAVINGS.get() {
+			None => true,
+			Some(Self::Bytes(min)) => min.get() <= saved,
+			Some(Self::Percent(min)) =>
+				u128::from(before) * u128::from(*min) <= u128::from(saved) * 100,
+		}
+	}
+}
+
+
+
 #[expect(clippy::inline_always, reason = "For performance.")]
 #[inline(always)]
 /// # Encode Image.
 ///
 /// This will attempt to losslessly re-encode the image, overriding the
-/// original if the compression results in savings.
+/// original if the compression results in savings (that meet the minimum, if
+/// any).
+///
+/// If an output directory and/or name template was specified, the best
+/// version — compressed or not — is written there instead, leaving the
+/// original untouched.
 ///
 /// The before and after sizes are returned, unless there's an error or the
 /// image is invalid. In cases where compression doesn't help, the before and
 /// after sizes will be identical.
-pub(super) fn encode(file: &Path, kinds: ImageKind)
+///
+/// If verification is enabled, the new version is only saved if it decodes
+/// to the same image as the original, and is read back afterward to make
+/// sure it was written correctly. (In-place failures are rolled back.)
+///
+/// With `--mark`, in-place images are tagged once they're done; with
+/// `--skip-marked`, images so tagged are skipped.
+///
+/// Hard-linked images are handled according to `--preserve-links`; if the
+/// links end up broken, the observer is warned.
+///
+/// If there's a `--memory-budget`, this waits for its share of it before
+/// reading the image in.
+///
+/// The image is `flock`ed throughout; if another process already holds a
+/// lock, it is skipped. If it is modified anyway — by a process that doesn't
+/// bother with locks — the write is skipped instead.
+///
+/// If the file extension doesn't match the image's contents, the observer is
+/// warned, and — if `--fix-extensions` is set and the image was being saved
+/// in place — the file is renamed to match.
+///
+/// The observer is notified after each encoding pass.
+pub(super) fn encode<O: Observer>(file: &Path, kinds: ImageKind, observer: &O)
 -> Result<(u64, u64), EncodingError> {
-	// Read the file.
-	let mut raw = std::fs::read(file).map_err(|_|
+	// Skip files that are too big to bother with, if there's a limit. (The
+	// optimizer checks too, but this saves reading them.)
+	let max = MAX_FILESIZE.load(Relaxed);
+	if max != 0 && std::fs::metadata(file).is_ok_and(|m| max < m.len()) {
+		return Err(EncodingError::FileSize);
+	}
+
+	// Lock it for the duration, or leave it to whoever else has it locked.
+	// (If it can't be opened at all, the read will say why.)
+	let _lock = match ImageLock::new(file) {
+		Ok(lock) => Some(lock),
+		Err(e) if e.kind() == ErrorKind::WouldBlock => return Err(EncodingError::Locked),
+		Err(_) => None,
+	};
+
+	// Wait for enough memory to free up, if there's a budget, before
+	// reading it in.
+	let _budget =
+		if BUDGET.enabled() { BUDGET.reserve(memory_estimate(file)) }
+		else { None };
+
+	// Note its vitals, then read it.
+	let stamp = Fingerprint::new(file);
+	let linked = 1 < output::link_count(file);
+	let raw = fault::read_input(file).map_err(|_|
 		if file.is_file() { EncodingError::Read }
 		else { EncodingError::Vanished }
 	)?;
-	let before = raw.len() as u64;
-	if before == 0 { return Err(EncodingError::Empty); }
 
-	// Do PNG stuff?
-	if ImageKind::is_png(&raw) {
-		if ! kinds.supports_png() { return Err(EncodingError::Skipped); }
-		check_resolution(ImageKind::Png, &raw)?;
+	// Skip it if it's already been done, if desired.
+	if SKIP_MARKED.load(Relaxed) && mark::is_marked(file, &raw) {
+		return Err(EncodingError::Skipped);
+	}
 
-		encode_oxipng(&mut raw);
-		encode_zopflipng(&mut raw);
+	// Archives are unpacked and their images crunched one by one.
+	let optimizer = Optimizer::default()
+		.with_kinds(kinds)
+		.with_profile(Profile::for_path(file));
+	if archive::enabled() && archive::is_archive(&raw) {
+		let new = archive::optimize(&raw, |member| {
+			let res = optimizer.optimize_bytes(member).ok()?;
+			if res.warnings.iter().any(|w| matches!(w, Warning::Panicked(_))) { return None; }
+			res.output.filter(|new| ! verify::enabled() || verify::same_image(member, new))
+		});
+		save(output::sink(), file, stamp, &raw, new.as_deref())?;
+		return Ok((raw.len() as u64, new.map_or(raw.len(), |n| n.len()) as u64));
 	}
-	// Do JPEG stuff?
-	else if ImageKind::is_jpeg(&raw) {
-		if ! kinds.supports_jpeg() { return Err(EncodingError::Skipped); }
-		check_resolution(ImageKind::Jpeg, &raw)?;
 
-		// Mozjpeg usually panics on error, so we have to do a weird little
-		// dance to keep it from killing the whole thread.
-		if let Ok(r) = std::panic::catch_unwind(move || {
-			encode_mozjpeg(&mut raw);
-			raw
-		}) { raw = r; }
-		// Abort without changing anything; raw might be tainted.
-		else { return Ok((before, before)); }
+	// Sniffed (extension-less) files are only images if they say so.
+	if file.extension().is_none() && ImageKind::from_bytes(&raw).is_none() {
+		return Err(EncodingError::Skipped);
+	}
 
-		// Encoding checks this explicitly, but debug asserts are nothing if
-		// not redundant!
-		debug_assert!(ImageKind::is_jpeg(&raw), "BUG: raw was unexpectedly corrupted");
+	// Crunch it!
+	let res = flapfli::with_progress(
+		|done, total| observer.on_progress(file, done, total),
+		|| optimizer.optimize_observed(&raw, |stage, size| observer.on_stage_complete(file, stage, size)),
+	)?;
+	observer.on_identify(file, res.kind);
+
+	for w in &res.warnings { observer.on_warning(file, *w); }
+
+	// Abort without changing anything if an encoder blew up.
+	if res.warnings.iter().any(|w| matches!(w, Warning::Panicked(_))) {
+		return Ok((res.before, res.before));
+	}
+
+	// Save it!
+	save(output::sink(), file, stamp, &raw, res.output.as_deref())?;
+
+	// Tag it as done, if desired. (This happens before any renaming, but
+	// the tag sticks with the file.)
+	if MARK.load(Relaxed) && ! output::enabled() {
+		mark::mark(file, res.output.as_deref().unwrap_or(&raw));
 	}
-	// Something else entirely?
-	else { return Err(EncodingError::Format); }
 
-	// Save it if better.
-	let after = raw.len() as u64;
-	if after < before {
-		write_atomic::write_file(file, &raw)
-			.map(|()| (before, after))
-			.map_err(|_| EncodingError::Write)
+	// Mention any hard links broken along the way.
+	if
+		linked &&
+		res.output.is_some() &&
+		! output::enabled() &&
+		HardLinks::get() == HardLinks::Break
+	{
+		observer.on_warning(file, Warning::LinkBroken);
 	}
-	else { Ok((before, before)) }
+
+	// Flag (or fix) mismatched extensions.
+	if ImageKind::from_path(file).is_some_and(|k| k != res.kind) {
+		let fixed =
+			FIX_EXTENSIONS.load(Relaxed) &&
+			! output::enabled() &&
+			fix_extension(file, res.kind, &raw);
+		observer.on_warning(file, if fixed { Warning::Renamed } else { Warning::Extension });
+	}
+
+	Ok((res.before, res.after))
+}
+
+/// # Fix Extension.
+///
+/// Rename `file` to use the conventional extension for `kind`, returning
+/// `true` if successful. Existing files are never overwritten.
+fn fix_extension(file: &Path, kind: ImageKind, raw: &[u8]) -> bool {
+	let Some(ext) = kind.extension(raw) else { return false; };
+	let dst = file.with_extension(ext);
+	! dst.exists() && std::fs::rename(file, dst).is_ok()
+}
+
+/// # Save Image.
+///
+/// Hand the image off to the sink, making sure along the way that the `new`
+/// version — if any — is really the same image as the `original`, and was
+/// written correctly. (In-place failures are rolled back.)
+///
+/// In-place rewrites are subject to the `--confirm-cmd`, if any.
+///
+/// If the source no longer matches its `stamp` — i.e. someone else wrote to
+/// it while it was being crunched — nothing is saved, lest their changes be
+/// lost.
+fn save<S: Sink + ?Sized>(
+	sink: &S,
+	file: &Path,
+	stamp: Option<Fingerprint>,
+	original: &[u8],
+	new: Option<&[u8]>,
+) -> Result<(), EncodingError> {
+	// Make sure the new version is really the same image.
+	let check = verify::enabled();
+	if let Some(new) = new {
+		if check && ! verify::same_image(original, new) {
+			return Err(EncodingError::Verify);
+		}
+	}
+
+	// Leave everything be once aborted.
+	if ! ABORTED.read().is_ok_and(|a| ! *a) { return Err(EncodingError::Skipped); }
+
+	// Figure out where it's going, if anywhere.
+	let data = new.unwrap_or(original);
+	let Some(dst) = sink.destination(file, data, new.is_some()) else { return Ok(()); };
+
+	// Leave hard-linked originals be, if desired.
+	if dst == file && HardLinks::get() == HardLinks::Skip && 1 < output::link_count(file) {
+		return Err(EncodingError::HardLinked);
+	}
+
+	// Get the go-ahead, if required. The hook could take a while, so this
+	// happens before the lock is taken, lest it hold up an abort.
+	if dst == file && new.is_some() && ! hook::confirmed(file, original.len() as u64, data.len() as u64) {
+		return Err(EncodingError::Declined);
+	}
+
+	// Check again, holding the lock this time until the write is done.
+	let aborted = ABORTED.read().ok().filter(|a| ! **a).ok_or(EncodingError::Skipped)?;
+
+	// Make sure the original hasn't changed out from under us.
+	if stamp.is_none_or(|s| s.changed(file)) { return Err(EncodingError::Modified); }
+
+	// Back up the original before overwriting it, if desired.
+	if dst == file { output::backup(file, original).map_err(|_| EncodingError::Write)?; }
+	if new.is_some() { sink.write(file, &dst, data) }
+	else { sink.write_unchanged(file, &dst, data) }
+		.map_err(|_| EncodingError::Write)?;
+
+	// Make sure it was written correctly too, restoring the original if
+	// it wasn't.
+	if check && fault::read(&dst).ok().as_deref() != Some(data) {
+		if dst == file { let _res = sink.write(file, &dst, original); }
+		return Err(EncodingError::Verify);
+	}
+
+	drop(aborted);
+	Ok(())
+}
+
+/// # Candidate Path?
+///
+/// Returns `true` if `path` has an image extension — or, with `--archives`,
+/// an archive one — worth queueing up.
+pub(crate) fn is_candidate(path: &Path) -> bool {
+	ImageKind::from_path(path).is_some() ||
+	(archive::enabled() && archive::is_archive_path(path))
+}
+
+/// # Abort.
+///
+/// Wait for any in-progress saves to finish, then prevent any more from
+/// starting, leaving the remaining originals untouched.
+pub(crate) fn abort() {
+	if let Ok(mut aborted) = ABORTED.write() { *aborted = true; }
 }
 
 #[inline(never)]
 /// # Check Resolution.
+///
+/// Make sure the image's pixel count — and estimated decoded size — are
+/// within the limits, if any.
 fn check_resolution(kind: ImageKind, src: &[u8]) -> Result<(), EncodingError> {
 	// Get the width and height.
-	let (w, h) = match kind {
-		ImageKind::Jpeg => ImageKind::jpeg_dimensions(src),
-		ImageKind::Png => ImageKind::png_dimensions(src),
-		ImageKind::All => None,
-	}
-		.ok_or(EncodingError::Format)?;
+	let (w, h) = dimensions(kind, src).ok_or(EncodingError::Format)?;
 
 	// Make sure the resolution fits u32.
 	let res = w.checked_mul(h).ok_or(EncodingError::Resolution)?;
 
-	// And finally check the limit.
+	// Check the limit.
 	let max = MAX_RESOLUTION.load(Relaxed);
-	if max == 0 || res.get() <= max { Ok(()) }
-	else { Err(EncodingError::Resolution) }
+	if max != 0 && max < res.get() { return Err(EncodingError::Resolution); }
+
+	// And finally, the memory needed to hold the decoded pixels.
+	let max = MAX_MEMORY.load(Relaxed);
+	if max != 0 {
+		let bpp = kind.bytes_per_pixel(src).ok_or(EncodingError::Format)?;
+		if max < u64::from(res.get()).saturating_mul(bpp) {
+			return Err(EncodingError::Memory);
+		}
+	}
+
+	Ok(())
+}
+
+/// # Dimensions.
+fn dimensions(kind: ImageKind, src: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+	match kind {
+		ImageKind::GIF => ImageKind::gif_dimensions(src),
+		ImageKind::ICO => ImageKind::ico_dimensions(src),
+		ImageKind::JPEG => ImageKind::jpeg_dimensions(src),
+		ImageKind::PNG => ImageKind::png_dimensions(src),
+		_ => None,
+	}
+}
+
+/// # Memory Estimate.
+///
+/// Guess how much memory crunching the image at `file` will take: the
+/// decoded pixels — if they can be worked out from the headers — plus the
+/// original, working, and output copies of the file itself.
+///
+/// Only the first `ESTIMATE_HEAD` bytes are actually read, so this can be
+/// called before committing to the whole thing.
+fn memory_estimate(file: &Path) -> u64 {
+	let mut head = Vec::new();
+	let len = File::open(file)
+		.and_then(|f| {
+			let len = f.metadata()?.len();
+			f.take(ESTIMATE_HEAD).read_to_end(&mut head)?;
+			Ok(len)
+		})
+		.unwrap_or(0);
+
+	let pixels = ImageKind::from_bytes(&head).and_then(|kind| {
+		let (w, h) = dimensions(kind, &head)?;
+		let bpp = kind.bytes_per_pixel(&head)?;
+		Some(u64::from(w.get()) * u64::from(h.get()) * bpp)
+	});
+	pixels.unwrap_or(0).saturating_add(len.saturating_mul(3))
+}
+
+#[inline(never)]
+/// # Compress GIF.
+///
+/// Strip comments and other non-essential extensions, and recompress and
+/// repack the image data more efficiently. See the `gif` module for details.
+fn encode_gif(raw: &mut Vec<u8>) {
+	if let Some(mut new) = gif::optimize(raw) {
+		if new.len() < raw.len() && ImageKind::is_gif(&new) {
+			std::mem::swap(raw, &mut new);
+		}
+	}
+}
+
+#[inline(never)]
+/// # Compress ICO/CUR.
+///
+/// Recompress the PNG members — with zopfli too, if `zopfli` — and convert
+/// the BMP members to PNGs if that makes them smaller. See the `ico` module
+/// for details.
+fn encode_ico(raw: &mut Vec<u8>, profile: Profile) {
+	let zopfli = profile.zopfli() && ZOPFLI.load(Relaxed);
+	let png = |member: &mut Vec<u8>| {
+		if OXIPNG.load(Relaxed) { encode_oxipng(member, profile); }
+		if zopfli { encode_zopflipng(member); }
+	};
+	if let Some(mut new) = ico::optimize(raw, png) {
+		if new.len() < raw.len() && ImageKind::is_ico(&new) {
+			std::mem::swap(raw, &mut new);
+		}
+	}
 }
 
 #[inline(never)]
@@ -104,6 +576,9 @@ fn check_resolution(kind: ImageKind, src: &[u8]) -> Result<(), EncodingError> {
 /// ```bash
 /// jpegtran -copy none -optimize -progressive
 /// ```
+///
+/// (If any markers are to be kept, `-copy none` becomes `-copy icc`, etc.,
+/// and if auto-orienting, `-rotate`/`-flip` and `-perfect` are thrown in too.)
 fn encode_mozjpeg(raw: &mut Vec<u8>) {
 	if let Some(new) = jpegtran::optimize(raw) {
 		let slice: &[u8] = &new;
@@ -117,53 +592,28 @@ fn encode_mozjpeg(raw: &mut Vec<u8>) {
 #[inline(never)]
 /// # Compress w/ `Oxipng`
 ///
-/// The result is comparable to calling:
+/// At the default (maximum) level, the result is comparable to calling:
 ///
 /// ```bash
-/// oxipng -o 3 -s -a -i 0 --fix
+/// oxipng -o 6 -s -a -i 0 --fix
 /// ```
-fn encode_oxipng(raw: &mut Vec<u8>) {
-	use oxipng::{
-		Deflaters,
-		IndexSet,
-		Interlacing,
-		Options,
-		RowFilter,
-		StripChunks,
-	};
-
+///
+/// If any chunks are to be kept, `-s` is swapped for `--keep`. Images given
+/// the fast profile get a lighter pass.
+fn encode_oxipng(raw: &mut Vec<u8>, profile: Profile) {
 	thread_local!(
-		static OXI: Options = Options {
-			fix_errors: true,
-			force: false,
-			filter: IndexSet::from([
-				RowFilter::None,
-				RowFilter::Average,
-				RowFilter::BigEnt,
-				RowFilter::Bigrams,
-				RowFilter::Brute,
-				RowFilter::Entropy,
-				RowFilter::MinSum,
-				RowFilter::Paeth,
-				RowFilter::Sub,
-				RowFilter::Up,
-			]),
-			interlace: Some(Interlacing::None),
-			optimize_alpha: true,
-			bit_depth_reduction: true,
-			color_type_reduction: true,
-			palette_reduction: true,
-			grayscale_reduction: true,
-			idat_recoding: true,
-			scale_16: false,
-			strip: StripChunks::All,
-			deflate: Deflaters::Libdeflater { compression: 12 },
-			fast_evaluation: false,
-			timeout: None,
-		}
+		static OXI: oxipng::Options = flapfli::oxipng_options(
+			Profile::Default.oxipng_level(),
+			FIX_ERRORS.load(Relaxed),
+		);
+		static OXI_FAST: oxipng::Options = flapfli::oxipng_options(
+			Profile::Fast.oxipng_level(),
+			FIX_ERRORS.load(Relaxed),
+		);
 	);
 
-	if let Ok(mut new) = OXI.with(|opts| oxipng::optimize_from_memory(raw, opts)) {
+	let oxi = if matches!(profile, Profile::Fast) { &OXI_FAST } else { &OXI };
+	if let Ok(mut new) = oxi.with(|opts| oxipng::optimize_from_memory(raw, opts)) {
 		if new.len() < raw.len() && ImageKind::is_png(&new) {
 			std::mem::swap(raw, &mut new);
 		}
@@ -178,6 +628,8 @@ fn encode_oxipng(raw: &mut Vec<u8>) {
 /// ```bash
 /// zopflipng -m
 /// ```
+///
+/// (Or `zopflipng -m --keepchunks=…` if any chunks are to be kept.)
 fn encode_zopflipng(raw: &mut Vec<u8>) {
 	if let Some(new) = flapfli::optimize(raw) {
 		let slice: &[u8] = &new;
@@ -187,3 +639,55 @@ fn encode_zopflipng(raw: &mut Vec<u8>) {
 		}
 	}
 }
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_min_savings() {
+		for (raw, expected) in [
+			(&b"1024"[..], NonZeroU64::new(1024).map(MinSavings::Bytes)),
+			(b"5%", Some(MinSavings::Percent(5))),
+			(b"5 %", Some(MinSavings::Percent(5))),
+			(b"0", None),
+			(b"0%", None),
+			(b"100%", None),
+			(b"5.5%", None),
+			(b"", None),
+		] {
+			assert_eq!(
+				MinSavings::parse(raw),
+				expected,
+				"Wrong answer for {:?}.",
+				String::from_utf8_lossy(raw),
+			);
+		}
+	}
+
+	#[test]
+	fn t_fingerprint() {
+		let path = std::env::temp_dir().join(format!("flaca-stamp-{}.png", std::process::id()));
+		std::fs::write(&path, b"hello").expect("Write failed.");
+
+		let stamp = Fingerprint::new(&path).expect("Missing fingerprint.");
+		assert!(! stamp.changed(&path));
+
+		// Appending changes things.
+		std::fs::write(&path, b"hello world").expect("Write failed.");
+		assert!(stamp.changed(&path));
+
+		// As does replacing it with the same-sized content, inode-wise.
+		let stamp = Fingerprint::new(&path).expect("Missing fingerprint.");
+		let tmp = path.with_extension("tmp");
+		std::fs::write(&tmp, b"HELLO WORLD").expect("Write failed.");
+		std::fs::rename(&tmp, &path).expect("Rename failed.");
+		assert!(stamp.changed(&path));
+
+		// And so does deleting it.
+		let _res = std::fs::remove_file(&path);
+		assert!(stamp.changed(&path));
+	}
+}
@@ -0,0 +1,346 @@
+/*!
+# Flaca: Optimizer
+
+This is the file-free core of the encoding pipeline: bytes in, (possibly
+better) bytes out, along with a detailed accounting of how they got there.
+
+The file, pipe, daemon, and trace modes all share it, but it is internal to
+the binary. (The `flaca-capi` library is the embeddable option.)
+*/
+
+use crate::{
+	EncodingError,
+	MAX_FILESIZE,
+	Profile,
+	Stage,
+};
+use std::{
+	panic::AssertUnwindSafe,
+	sync::atomic::Ordering::Relaxed,
+	time::{
+		Duration,
+		Instant,
+	},
+};
+use super::{
+	check_resolution,
+	encoder::BUILTIN,
+	FIX_ERRORS,
+	ImageKind,
+	jpegtran,
+	MinSavings,
+};
+
+
+
+#[derive(Debug, Clone, Copy)]
+/// # Optimizer.
+///
+/// This holds the per-image settings — which kinds of images to process, and
+/// how hard to try — that aren't global. Everything else (zopfli iterations,
+/// markers to keep, etc.) is configured process-wide.
+pub(crate) struct Optimizer {
+	/// # Image Kinds.
+	kinds: ImageKind,
+
+	/// # Encoding Profile.
+	profile: Profile,
+}
+
+impl Default for Optimizer {
+	#[inline]
+	fn default() -> Self {
+		Self {
+			kinds: ImageKind::ALL,
+			profile: Profile::global(),
+		}
+	}
+}
+
+impl Optimizer {
+	#[must_use]
+	/// # With Image Kinds.
+	///
+	/// Only process these kinds of images; the rest are skipped.
+	pub(crate) const fn with_kinds(self, kinds: ImageKind) -> Self {
+		Self { kinds, ..self }
+	}
+
+	#[must_use]
+	/// # With Profile.
+	pub(crate) const fn with_profile(self, profile: Profile) -> Self {
+		Self { profile, ..self }
+	}
+
+	/// # Optimize Bytes.
+	///
+	/// Losslessly re-encode the image, returning the details. The new image
+	/// is only included if it is smaller (and the savings meet the minimum,
+	/// if any).
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the image is empty, invalid, too big, or of a
+	/// kind that isn't being processed.
+	pub(crate) fn optimize_bytes(&self, src: &[u8]) -> Result<OptimizeResult, EncodingError> {
+		self.optimize_observed(src, |_, _| {})
+	}
+
+	/// # Optimize Bytes (Observed).
+	///
+	/// Same as `Optimizer::optimize_bytes`, but `cb` is called with the stage
+	/// and (possibly unchanged) size after each encoder pass.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the image is empty, invalid, too big, or of a
+	/// kind that isn't being processed.
+	pub(crate) fn optimize_observed<F>(&self, src: &[u8], mut cb: F)
+	-> Result<OptimizeResult, EncodingError>
+	where F: FnMut(Stage, u64) {
+		let before = src.len() as u64;
+		if before == 0 { return Err(EncodingError::Empty); }
+
+		let max = MAX_FILESIZE.load(Relaxed);
+		if max != 0 && max < before { return Err(EncodingError::FileSize); }
+
+		let mut out = OptimizeResult {
+			kind: ImageKind::from_bytes(src).ok_or(EncodingError::Format)?,
+			before,
+			after: before,
+			stages: Vec::new(),
+			skipped: Vec::new(),
+			winner: None,
+			warnings: Vec::new(),
+			output: None,
+		};
+		if ! self.kinds.contains(out.kind) { return Err(EncodingError::Skipped); }
+		check_resolution(out.kind, src)?;
+
+		// Make sure the crop, if any, can be carried out.
+		if out.kind == ImageKind::JPEG { jpegtran::Crop::region(src)?; }
+
+		// Surface damaged PNGs rather than papering over them, if desired.
+		if
+			out.kind == ImageKind::PNG &&
+			! FIX_ERRORS.load(Relaxed) &&
+			ImageKind::png_is_damaged(src)
+		{
+			return Err(EncodingError::Damaged);
+		}
+
+		let mut best = src.to_vec();
+		let (kind, profile) = (out.kind, self.profile);
+		for enc in BUILTIN.iter().copied().filter(|e| e.kind() == kind) {
+			let stage = enc.stage();
+			if let Some(reason) = enc.skip(src, profile) {
+				out.skipped.push((stage, reason));
+				continue;
+			}
+
+			// Mozjpeg in particular usually panics on error, so we have to do
+			// a weird little dance to keep it from killing the whole thread.
+			let now = Instant::now();
+			let mut raw = best.clone();
+			let Ok(raw) = std::panic::catch_unwind(AssertUnwindSafe(move || {
+				enc.encode(&mut raw, profile);
+				raw
+			}))
+			// Abort without changing anything; raw might be tainted.
+			else {
+				out.warnings.push(Warning::Panicked(stage));
+				out.after = before;
+				out.winner = None;
+				return Ok(out);
+			};
+
+			// Only carry improvements forward; a pass that didn't help might
+			// still have left its mark.
+			let size = raw.len() as u64;
+			if size < out.after { best = raw; }
+			out.record(stage, size, now.elapsed(), &mut cb);
+		}
+
+		// Only keep the new version if it's worth it.
+		if out.after < before {
+			if MinSavings::worth_it(before, out.after) { out.output = Some(best); }
+			else {
+				out.warnings.push(Warning::MinSavings);
+				out.after = before;
+			}
+		}
+
+		// Note any potentially-important metadata that didn't make the cut.
+		if out.output.is_some() {
+			let (icc, orientation) = match out.kind {
+				ImageKind::JPEG => jpegtran::metadata_lost(src),
+				ImageKind::PNG => (
+					ImageKind::png_has_chunk(src, *b"iCCP") &&
+					! flapfli::keep_chunks().contains(b"iCCP"),
+					false,
+				),
+				_ => (false, false),
+			};
+			if icc { out.warnings.push(Warning::StrippedIcc(out.kind)); }
+			if orientation { out.warnings.push(Warning::StrippedOrientation); }
+		}
+
+		Ok(out)
+	}
+}
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # Optimization Result.
+pub(crate) struct OptimizeResult {
+	/// # Image Kind.
+	pub(crate) kind: ImageKind,
+
+	/// # Original Size.
+	pub(crate) before: u64,
+
+	/// # New Size.
+	///
+	/// This matches `before` if no (worthwhile) savings were achieved.
+	pub(crate) after: u64,
+
+	/// # Stages Run (In Order).
+	pub(crate) stages: Vec<StageResult>,
+
+	/// # Stages Skipped (And Why).
+	pub(crate) skipped: Vec<(Stage, &'static str)>,
+
+	/// # Winning Stage.
+	///
+	/// The last stage to improve on its predecessors, if any.
+	pub(crate) winner: Option<Stage>,
+
+	/// # Warnings.
+	pub(crate) warnings: Vec<Warning>,
+
+	/// # Output.
+	///
+	/// The new image, if it's an improvement.
+	pub(crate) output: Option<Vec<u8>>,
+}
+
+impl OptimizeResult {
+	/// # Record a Pass.
+	fn record<C>(&mut self, stage: Stage, size: u64, elapsed: Duration, cb: &mut C)
+	where C: FnMut(Stage, u64) {
+		let input = self.after;
+		if size < self.after {
+			self.after = size;
+			self.winner = Some(stage);
+		}
+		self.stages.push(StageResult { stage, input, size, elapsed });
+		cb(stage, size);
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Stage Result.
+pub(crate) struct StageResult {
+	/// # Stage.
+	pub(crate) stage: Stage,
+
+	/// # Size Before.
+	pub(crate) input: u64,
+
+	/// # Size After.
+	pub(crate) size: u64,
+
+	/// # Time Taken.
+	pub(crate) elapsed: Duration,
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Warnings.
+///
+/// These are non-fatal issues encountered along the way.
+pub(crate) enum Warning {
+	/// # Mismatched Extension.
+	///
+	/// The file extension doesn't match the image's actual kind.
+	Extension,
+
+	/// # Hard Link Broken.
+	///
+	/// The image was rewritten, leaving its other hard links pointing at the
+	/// original.
+	LinkBroken,
+
+	/// # Savings Below Minimum.
+	MinSavings,
+
+	/// # Encoder Panicked.
+	///
+	/// The original image was kept as-is.
+	Panicked(Stage),
+
+	/// # Renamed.
+	///
+	/// The file's mismatched extension was corrected.
+	Renamed,
+
+	/// # ICC Profile Stripped.
+	StrippedIcc(ImageKind),
+
+	/// # EXIF Orientation Stripped.
+	///
+	/// The image will probably display sideways (or upside down) now.
+	StrippedOrientation,
+}
+
+impl Warning {
+	#[must_use]
+	/// # As Str.
+	pub(crate) const fn as_str(self) -> &'static str {
+		match self {
+			Self::Extension => "mismatched extension",
+			Self::LinkBroken => "hard link broken",
+			Self::MinSavings => "savings below minimum",
+			Self::Panicked(Stage::Mozjpeg) => "mozjpeg failed",
+			Self::Panicked(_) => "encoder failed",
+			Self::Renamed => "extension corrected",
+			Self::StrippedIcc(_) => "ICC profile stripped",
+			Self::StrippedOrientation => "EXIF orientation stripped",
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_optimize_bytes() {
+		let raw = std::fs::read("../skel/assets/gif/01.gif").expect("Missing 01.gif.");
+		let res = Optimizer::default().optimize_bytes(&raw).expect("Optimization failed.");
+		assert_eq!(res.kind, ImageKind::GIF);
+		assert_eq!(res.before, raw.len() as u64);
+		assert!(res.after < res.before, "No savings.");
+		assert_eq!(res.output.as_ref().map(|o| o.len() as u64), Some(res.after));
+		assert_eq!(res.winner, Some(Stage::Gif));
+		assert_eq!(res.stages.len(), 1);
+
+		// Skipped.
+		let res = Optimizer::default()
+			.with_kinds(ImageKind::PNG)
+			.optimize_bytes(&raw);
+		assert!(matches!(res, Err(EncodingError::Skipped)));
+
+		// Invalid.
+		assert!(matches!(
+			Optimizer::default().optimize_bytes(b"hello"),
+			Err(EncodingError::Format),
+		));
+	}
+}
@@ -0,0 +1,71 @@
+/*!
+# Flaca: Pipe Mode
+
+This reads a single image from STDIN, crunches it in memory, and writes the
+result — smaller or not — to STDOUT, without touching the filesystem.
+*/
+
+use crate::{
+	EncodingError,
+	ExitStatus,
+	FlacaError,
+};
+use std::io::{
+	Read,
+	Write,
+};
+use super::{
+	ImageKind,
+	Optimizer,
+	verify,
+	Warning,
+};
+
+
+
+/// # Pipe.
+///
+/// Optimize the image from STDIN and write it back out to STDOUT.
+///
+/// Images that can't or won't be improved — too big, too little savings,
+/// etc. — are passed through as-are so pipelines keep flowing.
+///
+/// ## Errors
+///
+/// An error is returned if STDIN does not contain a supported image of the
+/// expected kind, or STDOUT cannot be written to.
+pub(crate) fn pipe(kinds: ImageKind) -> Result<ExitStatus, FlacaError> {
+	let mut raw = Vec::new();
+	std::io::stdin().lock().read_to_end(&mut raw).map_err(|_| FlacaError::Stdin)?;
+
+	let out = match crunch(&raw, kinds) {
+		Ok(out) => out,
+		// Too big to bother with; pass it through.
+		Err(EncodingError::FileSize | EncodingError::Memory | EncodingError::Resolution) => None,
+		Err(_) => return Err(FlacaError::Stdin),
+	};
+
+	let mut stdout = std::io::stdout().lock();
+	stdout.write_all(out.as_deref().unwrap_or(&raw))
+		.and_then(|()| stdout.flush())
+		.map_err(|_| FlacaError::Stdout)?;
+
+	if out.is_some() { Ok(ExitStatus::Success) }
+	else { Ok(ExitStatus::NoSavings) }
+}
+
+/// # Crunch.
+///
+/// Return the optimized version of `raw`, or `None` if it could not be
+/// improved.
+///
+/// ## Errors
+///
+/// An error is returned if `raw` is not a supported image of the expected
+/// kind, or is otherwise refused — too big, etc. — explaining why.
+pub(crate) fn crunch(raw: &[u8], kinds: ImageKind) -> Result<Option<Vec<u8>>, EncodingError> {
+	let res = Optimizer::default().with_kinds(kinds).optimize_bytes(raw)?;
+	if res.warnings.iter().any(|w| matches!(w, Warning::Panicked(_))) { return Ok(None); }
+
+	Ok(res.output.filter(|new| ! verify::enabled() || verify::same_image(raw, new)))
+}
@@ -0,0 +1,211 @@
+/*!
+# Flaca: Pipeline Trace
+
+This runs a single image through the encoding pipeline without saving
+anything, printing each stage attempted — its input and output sizes, timing,
+and parameters — along with the reasons any stages were skipped.
+*/
+
+use crate::{
+	EncodingError,
+	fault,
+	Profile,
+	Stage,
+};
+use dactyl::NiceU64;
+use std::{
+	fmt::Write,
+	path::Path,
+	sync::atomic::Ordering::Relaxed,
+};
+use super::{
+	ImageKind,
+	jpegtran::{
+		AUTO_ORIENT,
+		Crop,
+		GRAYSCALE_AUTO,
+		KEEP_EXIF,
+		KEEP_ICC,
+		KEEP_MARKERS,
+		KEEP_THUMBNAIL,
+		KEEP_XMP,
+	},
+	Optimizer,
+	verify,
+	Warning,
+};
+
+
+
+/// # Trace.
+///
+/// Run the image at `file` through the pipeline, and return the details as a
+/// printable string.
+pub(crate) fn trace(file: &Path, kinds: ImageKind) -> String {
+	let mut out = format!("{}\n", file.display());
+
+	let Ok(raw) = fault::read(file) else {
+		line(&mut out, "error", "read error");
+		return out;
+	};
+
+	let profile = Profile::for_path(file);
+	let res = Optimizer::default()
+		.with_kinds(kinds)
+		.with_profile(profile)
+		.optimize_bytes(&raw);
+
+	let kind = res.as_ref().map_or_else(|_| ImageKind::from_bytes(&raw), |r| Some(r.kind));
+	line(&mut out, "kind", kind_str(kind));
+	line(&mut out, "size", &format!("{} bytes", NiceU64::from(raw.len() as u64)));
+	line(&mut out, "profile", profile.as_str());
+
+	let res = match res {
+		Ok(res) => res,
+		Err(e) => {
+			let reason = match e {
+				EncodingError::Skipped => "kind filtered",
+				e => e.as_str(),
+			};
+			line(&mut out, "stages", &format!("all skipped: {reason}"));
+			return out;
+		},
+	};
+
+	// The stages that ran.
+	for s in &res.stages {
+		line(&mut out, s.stage.as_str(), &format!(
+			"{} -> {} bytes in {}ms ({})",
+			NiceU64::from(s.input),
+			NiceU64::from(s.size),
+			s.elapsed.as_millis(),
+			params(s.stage, profile),
+		));
+	}
+
+	// The stages that didn't.
+	for (stage, reason) in &res.skipped {
+		line(&mut out, stage.as_str(), &format!("skipped: {reason}"));
+	}
+
+	// Warnings.
+	for w in &res.warnings {
+		line(&mut out, "warning", w.as_str());
+	}
+	if ImageKind::from_path(file).is_some_and(|k| k != res.kind) {
+		line(&mut out, "warning", Warning::Extension.as_str());
+	}
+
+	// And the final verdict.
+	let verdict = match (res.winner, res.output.as_deref()) {
+		(Some(winner), Some(new)) => {
+			let mut v = format!(
+				"{} bytes, saved {} via {}",
+				NiceU64::from(res.after),
+				NiceU64::from(res.before - res.after),
+				winner.as_str(),
+			);
+			if verify::enabled() {
+				v.push_str(
+					if verify::same_image(&raw, new) { " (verified)" }
+					else { " (verification failed)" }
+				);
+			}
+			v
+		},
+		_ => "no savings".to_owned(),
+	};
+	line(&mut out, "result", &verdict);
+	out.push_str("  (Nothing was written.)\n");
+
+	out
+}
+
+
+
+/// # Push Line.
+fn line(out: &mut String, label: &str, value: &str) {
+	let pad = 11_usize.saturating_sub(label.len());
+	let _res = writeln!(out, "  {label}:{:pad$}{value}", "");
+}
+
+/// # Kind as Str.
+const fn kind_str(kind: Option<ImageKind>) -> &'static str {
+	match kind {
+		Some(ImageKind::GIF) => "gif",
+		Some(ImageKind::ICO) => "ico",
+		Some(ImageKind::JPEG) => "jpeg",
+		Some(ImageKind::PNG) => "png",
+		_ => "unknown",
+	}
+}
+
+/// # Stage Parameters.
+///
+/// Summarize the (relevant) settings in effect for the stage.
+fn params(stage: Stage, profile: Profile) -> String {
+	match stage {
+		Stage::Gif => "strip extensions, recompress frames".to_owned(),
+		Stage::Ico => "recompress PNG members, convert BMP members if smaller".to_owned(),
+		Stage::Mozjpeg => {
+			let keep = KEEP_MARKERS.load(Relaxed);
+			let mut out = String::from("keep: ");
+			let mut any = false;
+			for (flag, label) in [
+				(KEEP_EXIF, "exif"),
+				(KEEP_ICC, "icc"),
+				(KEEP_THUMBNAIL, "thumbnail"),
+				(KEEP_XMP, "xmp"),
+			] {
+				if 0 != keep & flag {
+					if any { out.push(','); }
+					out.push_str(label);
+					any = true;
+				}
+			}
+			if ! any { out.push_str("none"); }
+			if AUTO_ORIENT.load(Relaxed) { out.push_str("; auto-orient"); }
+			if GRAYSCALE_AUTO.load(Relaxed) { out.push_str("; grayscale-auto"); }
+			if let Some(c) = Crop::get() {
+				let _res = write!(out, "; crop {}x{}+{}+{}", c.width, c.height, c.x, c.y);
+			}
+			out
+		},
+		Stage::Oxipng => format!(
+			"level {}; keep: {}{}{}",
+			profile.oxipng_level(),
+			keep_chunks(),
+			keep_color_type(),
+			keep_alpha_data(),
+		),
+		Stage::Zopflipng => format!(
+			"{} iterations; keep: {}{}{}{}",
+			flapfli::zopfli_iterations(),
+			keep_chunks(),
+			keep_color_type(),
+			keep_alpha_data(),
+			if flapfli::exhaustive() { "; exhaustive" } else { "" },
+		),
+	}
+}
+
+/// # Kept PNG Alpha Data.
+fn keep_alpha_data() -> &'static str {
+	if flapfli::keep_alpha_data() { "; keep alpha data" } else { "" }
+}
+
+/// # Kept PNG Color Type.
+fn keep_color_type() -> &'static str {
+	if flapfli::keep_color_type() { "; keep color type" } else { "" }
+}
+
+/// # Kept PNG Chunks.
+fn keep_chunks() -> String {
+	let mut out = String::new();
+	for chunk in flapfli::keep_chunks() {
+		if ! out.is_empty() { out.push(','); }
+		out.push_str(&String::from_utf8_lossy(chunk));
+	}
+	if out.is_empty() { out.push_str("none"); }
+	out
+}
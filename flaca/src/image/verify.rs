@@ -0,0 +1,218 @@
+/*!
+# Flaca: Verification
+
+When enabled, each re-encoded image is decoded and compared against the
+original before it is allowed to replace it, and once written, read back and
+compared byte-for-byte against what was supposed to be written.
+
+Comparisons are made at the level that each format's optimizations operate:
+* PNGs are decoded to 8-bit RGBA, since color types and palettes may change;
+* JPEGs are fully decoded in their native color space (and cropped and/or
+  flattened to grayscale to match, if need be);
+* GIF frames are decoded to color indices, since the LZW data may change;
+* ICO/CUR directories must match, and each member is compared as a PNG;
+* Archive members are checked individually, as they're crunched.
+*/
+
+use super::{
+	archive,
+	gif,
+	ico,
+	jpegtran::{
+		self,
+		AUTO_ORIENT,
+		Crop,
+		DecodedJPEG,
+		GRAYSCALE_AUTO,
+	},
+	kind::ImageKind,
+};
+use std::sync::atomic::{
+	AtomicBool,
+	Ordering::Relaxed,
+};
+
+
+
+/// # Verify?
+pub(crate) static VERIFY: AtomicBool = AtomicBool::new(false);
+
+
+
+#[inline]
+/// # Enabled?
+pub(super) fn enabled() -> bool { VERIFY.load(Relaxed) }
+
+/// # Same Image?
+///
+/// Return `true` if the `old` and `new` encodings of an image decode to the
+/// same thing.
+pub(super) fn same_image(old: &[u8], new: &[u8]) -> bool {
+	if ImageKind::is_png(old) { ImageKind::is_png(new) && flapfli::same_pixels(old, new) }
+	else if ImageKind::is_jpeg(old) {
+		// Mozjpeg panics on error, so we have to catch it.
+		ImageKind::is_jpeg(new) &&
+		std::panic::catch_unwind(|| same_jpeg(old, new)).unwrap_or(false)
+	}
+	else if ImageKind::is_gif(old) {
+		gif::image_data(old).is_some_and(|o| Some(o) == gif::image_data(new))
+	}
+	else if ImageKind::is_ico(old) { ico::same_image(old, new) }
+	// Archive members are verified individually as they're crunched.
+	else if archive::is_archive(old) { archive::is_archive(new) }
+	else { false }
+}
+
+/// # Same JPEG?
+///
+/// If the image was auto-oriented, the original is rotated and/or flipped to
+/// match before comparing. Transforms change the order in which the IDCT
+/// rounds, so off-by-one differences are tolerated in that case (and only
+/// that case).
+///
+/// If the image was cropped, the original is cropped to match. (Crops start
+/// on block boundaries, so these should match exactly.)
+///
+/// If the image was converted to grayscale, the original must have been
+/// gray all along, i.e. every pixel's red, green, and blue equal.
+fn same_jpeg(old: &[u8], new: &[u8]) -> bool {
+	let crop = Crop::region(old);
+	let Some(mut old) = jpegtran::decode(old) else { return false; };
+	let Some(new) = jpegtran::decode(new) else { return false; };
+	if old.components == 3 && new.components == 1 && GRAYSCALE_AUTO.load(Relaxed) {
+		let Some(gray) = flatten(&old.pixels) else { return false; };
+		old.pixels = gray;
+		old.components = 1;
+	}
+	if old.components != new.components { return false; }
+
+	// The cropped comparison.
+	match crop {
+		Ok(Some(crop)) => return crop.width == new.width &&
+			crop.height == new.height &&
+			recrop(&old, crop).is_some_and(|p| p == new.pixels),
+		Ok(None) => {},
+		Err(_) => return false,
+	}
+
+	// The straight comparison.
+	if old.width == new.width && old.height == new.height && old.pixels == new.pixels {
+		return true;
+	}
+
+	// The oriented comparison.
+	if AUTO_ORIENT.load(Relaxed) && (2..=8).contains(&old.orientation) {
+		let (width, height, pixels) = reorient(&old);
+		return width == new.width &&
+			height == new.height &&
+			pixels.len() == new.pixels.len() &&
+			pixels.iter().zip(&new.pixels).all(|(a, b)| a.abs_diff(*b) <= 1);
+	}
+
+	false
+}
+
+/// # Flatten.
+///
+/// Convert RGB pixels to grayscale, returning `None` if any of them have
+/// color.
+fn flatten(rgb: &[u8]) -> Option<Vec<u8>> {
+	rgb.chunks_exact(3)
+		.map(|px| if px[0] == px[1] && px[1] == px[2] { Some(px[0]) } else { None })
+		.collect()
+}
+
+/// # Recrop.
+///
+/// Crop the decoded pixels to the region, returning `None` if it doesn't fit.
+fn recrop(img: &DecodedJPEG, crop: Crop) -> Option<Vec<u8>> {
+	let (w, c) = (img.width as usize, img.components);
+	let (x, y) = (crop.x as usize, crop.y as usize);
+	let (cw, ch) = (crop.width as usize, crop.height as usize);
+	if img.width < crop.x + crop.width || img.height < crop.y + crop.height { return None; }
+
+	let mut out = Vec::with_capacity(cw * ch * c);
+	for row in y..y + ch {
+		let start = (row * w + x) * c;
+		out.extend_from_slice(img.pixels.get(start..start + cw * c)?);
+	}
+	Some(out)
+}
+
+#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+/// # Reorient.
+///
+/// Apply the EXIF Orientation to the decoded pixels, returning the new
+/// width, height, and pixels.
+fn reorient(img: &DecodedJPEG) -> (u32, u32, Vec<u8>) {
+	let (w, h, c) = (img.width as usize, img.height as usize, img.components);
+	let (dw, dh) = if 5 <= img.orientation { (h, w) } else { (w, h) };
+
+	let mut out = Vec::with_capacity(img.pixels.len());
+	for y in 0..dh {
+		for x in 0..dw {
+			let (sx, sy) = match img.orientation {
+				2 => (w - 1 - x, y),
+				3 => (w - 1 - x, h - 1 - y),
+				4 => (x, h - 1 - y),
+				5 => (y, x),
+				6 => (y, h - 1 - x),
+				7 => (w - 1 - y, h - 1 - x),
+				8 => (w - 1 - y, x),
+				_ => (x, y),
+			};
+			let pos = (sy * w + sx) * c;
+			out.extend_from_slice(&img.pixels[pos..pos + c]);
+		}
+	}
+
+	(dw as u32, dh as u32, out)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_reorient() {
+		// A 3x2 single-channel image:
+		// 1 2 3
+		// 4 5 6
+		let mut img = DecodedJPEG {
+			width: 3,
+			height: 2,
+			components: 1,
+			orientation: 1,
+			pixels: vec![1, 2, 3, 4, 5, 6],
+		};
+
+		for (o, w, h, expected) in [
+			(1, 3, 2, [1, 2, 3, 4, 5, 6]),
+			(2, 3, 2, [3, 2, 1, 6, 5, 4]),
+			(3, 3, 2, [6, 5, 4, 3, 2, 1]),
+			(4, 3, 2, [4, 5, 6, 1, 2, 3]),
+			(5, 2, 3, [1, 4, 2, 5, 3, 6]),
+			(6, 2, 3, [4, 1, 5, 2, 6, 3]),
+			(7, 2, 3, [6, 3, 5, 2, 4, 1]),
+			(8, 2, 3, [3, 6, 2, 5, 1, 4]),
+		] {
+			img.orientation = o;
+			assert_eq!(reorient(&img), (w, h, expected.to_vec()), "Orientation {o}.");
+		}
+	}
+
+	#[test]
+	fn t_same_image() {
+		for file in ["../skel/assets/gif/01.gif", "../skel/assets/gif/02.gif"] {
+			let Ok(raw) = std::fs::read(file) else { panic!("Unable to open {file}."); };
+			assert!(same_image(&raw, &raw), "Self-mismatch for {file}.");
+
+			// Mess with the screen width.
+			let mut bad = raw.clone();
+			bad[6] ^= 1;
+			assert!(! same_image(&raw, &bad), "Corruption missed for {file}.");
+		}
+	}
+}
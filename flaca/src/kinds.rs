@@ -0,0 +1,155 @@
+/*!
+# Flaca: Kind Summary
+*/
+
+use crate::{
+	ImageKind,
+	Observer,
+};
+use dactyl::{
+	NiceU64,
+	traits::NiceInflection,
+};
+use fyi_msg::{
+	BeforeAfter,
+	Msg,
+};
+use std::{
+	collections::HashMap,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+/// # Slot Labels.
+///
+/// The totals are stored in this order.
+const LABELS: [&str; 5] = ["GIF", "ICO/CUR", "JPEG", "PNG", "Archive"];
+
+
+
+#[derive(Debug, Default)]
+/// # Kind Summary.
+///
+/// This observer tallies the results for each image kind — GIF, JPEG, PNG,
+/// etc. — so it's easier to see where the savings (and time) are going.
+pub(crate) struct KindSummary {
+	/// # Identified (But Unfinished) Images.
+	pending: Mutex<HashMap<PathBuf, usize>>,
+
+	/// # Totals by Kind.
+	totals: Mutex<[Totals; 5]>,
+}
+
+impl Observer for KindSummary {
+	fn on_identify(&self, src: &Path, kind: ImageKind) {
+		if let Ok(mut map) = self.pending.lock() {
+			map.insert(src.to_path_buf(), slot(kind));
+		}
+	}
+
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		// Anything that wasn't identified was an archive.
+		let idx = self.pending.lock().ok()
+			.and_then(|mut map| map.remove(src))
+			.unwrap_or(LABELS.len() - 1);
+
+		if let Ok(mut totals) = self.totals.lock() {
+			let totals = &mut totals[idx];
+			totals.images += 1;
+			totals.before += before;
+			totals.after += after;
+			if after >= before { totals.untouched += 1; }
+		}
+	}
+}
+
+impl KindSummary {
+	/// # Summarize.
+	///
+	/// Print the savings for each kind that turned up.
+	pub(crate) fn summarize(self) {
+		let Ok(totals) = self.totals.into_inner() else { return; };
+		for (label, totals) in LABELS.into_iter().zip(totals) {
+			if totals.images == 0 { continue; }
+			Msg::crunched(format!(
+				"{label} \x1b[2m({}, {} untouched)\x1b[0m",
+				totals.images.nice_inflect("image", "images"),
+				NiceU64::from(totals.untouched),
+			))
+				.with_bytes_saved(BeforeAfter::from((totals.before, totals.after)))
+				.eprint();
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Default)]
+/// # Kind Totals.
+struct Totals {
+	/// # Number of Images.
+	images: u64,
+
+	/// # Number Untouched.
+	untouched: u64,
+
+	/// # Total Size Before.
+	before: u64,
+
+	/// # Total Size After.
+	after: u64,
+}
+
+
+
+/// # Slot.
+///
+/// Return the `LABELS` index for the kind.
+const fn slot(kind: ImageKind) -> usize {
+	match kind {
+		ImageKind::GIF => 0,
+		ImageKind::ICO => 1,
+		ImageKind::JPEG => 2,
+		_ => 3,
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_totals() {
+		let summary = KindSummary::default();
+		for (path, kind, before, after) in [
+			("/tmp/a.png", Some(ImageKind::PNG), 100, 80),
+			("/tmp/b.png", Some(ImageKind::PNG), 100, 100),
+			("/tmp/c.jpg", Some(ImageKind::JPEG), 50, 40),
+			("/tmp/d.zip", None, 200, 150),
+		] {
+			let path = Path::new(path);
+			if let Some(kind) = kind { summary.on_identify(path, kind); }
+			summary.on_finish(path, before, after);
+		}
+
+		let totals = summary.totals.into_inner().expect("Poisoned.");
+		assert_eq!(totals[0].images, 0);
+		assert_eq!(
+			(totals[2].images, totals[2].untouched, totals[2].before, totals[2].after),
+			(1, 0, 50, 40),
+		);
+		assert_eq!(
+			(totals[3].images, totals[3].untouched, totals[3].before, totals[3].after),
+			(2, 1, 200, 180),
+		);
+		assert_eq!((totals[4].images, totals[4].before, totals[4].after), (1, 200, 150));
+		assert!(summary.pending.into_inner().expect("Poisoned.").is_empty());
+	}
+}
@@ -0,0 +1,161 @@
+/*!
+# Flaca: Locks
+*/
+
+use crate::FlacaError;
+use std::{
+	fs::{
+		File,
+		OpenOptions,
+	},
+	io::{
+		Error,
+		ErrorKind,
+		Write,
+	},
+	os::fd::AsRawFd,
+	path::Path,
+};
+
+
+
+#[derive(Debug)]
+/// # Image Lock.
+///
+/// This holds an exclusive (advisory) `flock` on an image while it is being
+/// read, crunched, and replaced, so cooperating processes — upload daemons
+/// appending to it, say, or another flaca — know to keep their hands off,
+/// and flaca knows to keep its hands off theirs.
+///
+/// Note: `flock` locks belong to the file, not the path. In-place saves
+/// write the new version to a temporary file and rename it over the
+/// original, so from then on the path leads to a different, unlocked file.
+/// Processes opening the path afterward won't have to wait, while any
+/// already waiting on the original will get a lock on the orphaned copy
+/// once it is released. Cooperating writers should re-open the path after
+/// locking it and make sure it is still the same file (inode) before
+/// writing. (Hard-linked images rewritten through their links, per
+/// `--preserve-links rewrite`, keep the original file, so aren't affected.)
+pub(crate) struct ImageLock {
+	/// # Image File.
+	_file: File,
+}
+
+impl ImageLock {
+	/// # New.
+	///
+	/// ## Errors
+	///
+	/// An error of kind `ErrorKind::WouldBlock` is returned if someone else
+	/// has the image locked, or any other if it cannot be opened.
+	pub(crate) fn new(path: &Path) -> Result<Self, Error> {
+		let file = File::open(path)?;
+		if try_lock(&file) { Ok(Self { _file: file }) }
+		else { Err(ErrorKind::WouldBlock.into()) }
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # Run Lock.
+///
+/// This holds an exclusive (advisory) `flock` on the `--lock` file for the
+/// lifetime of the run, so overlapping invocations — e.g. cron jobs that run
+/// long — can't process the same trees at the same time.
+///
+/// The lock is released automatically when the file is closed, even if the
+/// process is killed, so there's nothing stale to clean up afterwards.
+pub(crate) struct RunLock {
+	/// # Lock File.
+	_file: File,
+}
+
+impl RunLock {
+	/// # New.
+	///
+	/// Open (or create) the lock file and try to lock it, recording the
+	/// current process ID inside for the curious.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be opened, or is already
+	/// locked by another run.
+	pub(crate) fn new(path: &str) -> Result<Self, FlacaError> {
+		let path = path.trim();
+		if path.is_empty() { return Err(FlacaError::LockFile); }
+
+		let mut file = OpenOptions::new()
+			.create(true)
+			.truncate(false)
+			.read(true)
+			.write(true)
+			.open(path)
+			.map_err(|_| FlacaError::LockFile)?;
+
+		if ! try_lock(&file) { return Err(FlacaError::Locked); }
+
+		// The PID is just informational, so failures don't matter.
+		if file.set_len(0).is_ok() {
+			let _res = writeln!(file, "{}", std::process::id());
+		}
+
+		Ok(Self { _file: file })
+	}
+}
+
+
+
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Try Lock.
+///
+/// Try to take an exclusive `flock` on the file without waiting, returning
+/// `true` if successful. The lock is released when the file is closed.
+fn try_lock(file: &File) -> bool {
+	// Safety: the descriptor is valid for as long as the file is open.
+	0 == unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_lock() {
+		let path = std::env::temp_dir().join(format!("flaca-lock-{}", std::process::id()));
+		let path = path.to_string_lossy();
+
+		// Locks are held per open file, so a second attempt should fail even
+		// from the same process.
+		let lock = RunLock::new(&path).expect("Lock failed.");
+		assert!(matches!(RunLock::new(&path), Err(FlacaError::Locked)));
+
+		// Until the first is released.
+		drop(lock);
+		let lock = RunLock::new(&path).expect("Relock failed.");
+		drop(lock);
+
+		let _res = std::fs::remove_file(path.as_ref());
+		assert!(matches!(RunLock::new(" "), Err(FlacaError::LockFile)));
+	}
+
+	#[test]
+	fn t_image_lock() {
+		let path = std::env::temp_dir().join(format!("flaca-image-lock-{}.png", std::process::id()));
+		std::fs::write(&path, b"PNG").expect("Write failed.");
+
+		let lock = ImageLock::new(&path).expect("Lock failed.");
+		assert!(ImageLock::new(&path).is_err_and(|e| e.kind() == ErrorKind::WouldBlock));
+		drop(lock);
+		assert!(ImageLock::new(&path).is_ok());
+		let _res = std::fs::remove_file(&path);
+
+		// Missing files are a different problem.
+		assert!(
+			ImageLock::new(Path::new("../skel/assets/missing.png"))
+				.is_err_and(|e| e.kind() != ErrorKind::WouldBlock)
+		);
+	}
+}
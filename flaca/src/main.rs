@@ -32,7 +32,6 @@
 	clippy::rest_pat_in_fully_bound_structs,
 	clippy::semicolon_inside_block,
 	clippy::str_to_string,
-	clippy::string_to_string,
 	clippy::todo,
 	clippy::undocumented_unsafe_blocks,
 	clippy::unneeded_field_pattern,
@@ -54,17 +53,85 @@
 
 
 
+mod ab;
+mod advisor;
+mod budget;
+mod daemon;
+mod dirs;
+mod engine;
+mod env;
+mod errlog;
 mod error;
+mod family;
+mod fault;
+mod filter;
+mod heap;
+mod hook;
+mod ignore;
 mod image;
+mod kinds;
+mod lock;
+mod mark;
+mod memory;
+mod metrics;
+mod mmap;
+mod ndjson;
+mod output;
+mod report;
+mod resume;
+mod rules;
+mod signals;
+mod status;
+mod target;
+mod temp;
+mod top;
 
+pub(crate) use advisor::Advisor;
+pub(crate) use dirs::DirSummary;
+pub(crate) use engine::{
+	CancellationToken,
+	Observer,
+	Observers,
+	Stage,
+};
+pub(crate) use errlog::ErrorLog;
 pub(crate) use error::{
 	EncodingError,
 	FlacaError,
 };
+pub(crate) use family::Families;
+pub(crate) use filter::Filters;
+pub(crate) use hook::OnChange;
+pub(crate) use image::Warning;
 pub(crate) use image::kind::ImageKind;
+pub(crate) use kinds::KindSummary;
+pub(crate) use lock::{
+	ImageLock,
+	RunLock,
+};
+pub(crate) use memory::MemoryUsage;
+pub(crate) use metrics::Metrics;
+pub(crate) use ndjson::Ndjson;
+pub(crate) use output::{
+	Backup,
+	HardLinks,
+	NameTemplate,
+	OutDir,
+};
+pub(crate) use report::Report;
+pub(crate) use resume::Resume;
+pub(crate) use rules::{
+	Profile,
+	Thumbnails,
+};
+pub(crate) use status::{
+	ExitStatus,
+	Tally,
+};
+pub(crate) use target::TargetSize;
+pub(crate) use top::Leaderboard;
 
 use argyle::Argument;
-use crossbeam_channel::Receiver;
 use dactyl::{
 	NiceElapsed,
 	NiceU64,
@@ -77,6 +144,7 @@ use dowser::{
 	Dowser,
 	Extension,
 };
+use flapfli::ZopfliIterations;
 use fyi_msg::{
 	BeforeAfter,
 	Msg,
@@ -86,33 +154,44 @@ use fyi_msg::{
 use std::{
 	num::{
 		NonZeroU32,
+		NonZeroU64,
 		NonZeroUsize,
 	},
-	path::Path,
-	sync::{
-		Arc,
-		atomic::{
-			AtomicBool,
-			AtomicU32,
-			AtomicU64,
-			Ordering::{
-				Acquire,
-				Relaxed,
-				SeqCst,
-			},
+	path::{
+		Path,
+		PathBuf,
+	},
+	process::ExitCode,
+	sync::atomic::{
+		AtomicU32,
+		AtomicU64,
+		Ordering::{
+			Acquire,
+			Relaxed,
 		},
 	},
-	thread,
 };
 
 
 
-// The E_JPEG, E_JPG, and E_PNG constants are generated by build.rs.
+// The E_CUR, E_GIF, E_ICO, E_JPEG, E_JPG, E_PNG, E_TAR, and E_ZIP constants
+// are generated by build.rs.
 include!(concat!(env!("OUT_DIR"), "/flaca-extensions.rs"));
 
+/// # CLI Keywords.
+///
+/// These are generated by build.rs.
+pub(crate) const KEYWORDS: &[argyle::KeyWord] = &include!(concat!(env!("OUT_DIR"), "/argyle.rs"));
+
 /// # Maximum Resolution.
 pub(crate) static MAX_RESOLUTION: AtomicU32 = AtomicU32::new(0);
 
+/// # Maximum File Size.
+pub(crate) static MAX_FILESIZE: AtomicU64 = AtomicU64::new(0);
+
+/// # Maximum Decode Memory (Bytes).
+pub(crate) static MAX_MEMORY: AtomicU64 = AtomicU64::new(0);
+
 /// # Total Skipped.
 static SKIPPED: AtomicU64 = AtomicU64::new(0);
 
@@ -128,11 +207,12 @@ static AFTER: AtomicU64 = AtomicU64::new(0);
 ///
 /// This shell provides us a way to easily handle error responses. Actual
 /// processing is done by `main__()`.
-fn main() {
+fn main() -> ExitCode {
 	match main__() {
-		Ok(()) => {},
+		Ok(status) => ExitCode::from(status),
 		Err(e @ (FlacaError::PrintHelp | FlacaError::PrintVersion)) => {
 			println!("{e}");
+			ExitCode::SUCCESS
 		},
 		Err(e) => { Msg::error(e).die(1); },
 	}
@@ -142,71 +222,99 @@ fn main() {
 /// # Actual Main.
 ///
 /// This is the actual main, allowing us to easily bubble errors.
-fn main__() -> Result<(), FlacaError> {
-	// Parse CLI arguments.
-	let args = argyle::args()
-		.with_keywords(include!(concat!(env!("OUT_DIR"), "/argyle.rs")));
-
-	let mut kinds = ImageKind::All;
-	let mut threads = None;
-	let mut paths = Dowser::default();
-	let mut progress = false;
-	for arg in args {
-		match arg {
-			Argument::Key("-h" | "--help") => return Err(FlacaError::PrintHelp),
-			Argument::Key("--no-jpg" | "--no-jpeg") => { kinds = kinds.diff(ImageKind::Jpeg)?; },
-			Argument::Key("--no-png") => { kinds = kinds.diff(ImageKind::Png)?; },
-			Argument::Key("-p" | "--progress") => { progress = true; },
-			Argument::Key("-V" | "--version") => return Err(FlacaError::PrintVersion),
-
-			Argument::KeyWithValue("-j", s) => { threads.replace(s); },
-
-			Argument::KeyWithValue("-l" | "--list", s) => {
-				paths.read_paths_from_file(s).map_err(|_| FlacaError::ListFile)?;
-			},
+fn main__() -> Result<ExitStatus, FlacaError> {
+	let mut cli = Cli::parse()?;
+	cli.check()?;
 
-			Argument::KeyWithValue("--max-resolution", s) => {
-				set_pixel_limit(s.trim().as_bytes())?;
-			},
+	// Pipe mode skips the filesystem entirely.
+	if cli.stdin { return image::pipe(cli.pipe_kind.unwrap_or(cli.kinds)); }
+	if cli.pipe_kind.is_some() { return Err(FlacaError::Kind); }
 
-			Argument::KeyWithValue("-z", s) => {
-				let s = NonZeroU32::btou(s.trim().as_bytes())
-					.ok_or(FlacaError::ZopfliIterations)?;
-				if ! flapfli::set_zopfli_iterations(s) {
-					return Err(FlacaError::ZopfliIterations2);
-				}
-			},
+	// As does daemon mode, at least until jobs start coming in.
+	match (cli.daemon, cli.socket.take()) {
+		(true, Some(socket)) => {
+			let threads = max_threads(cli.threads.take(), NonZeroUsize::MAX)?;
+			if cli.nice { be_nice(); }
+			let killed = CancellationToken::default();
+			signals::install(killed.clone(), None);
+			return daemon::run(&socket, threads, cli.kinds, &killed);
+		},
+		(false, None) => {},
+		_ => return Err(FlacaError::Daemon),
+	}
 
-			// Assume these are paths.
-			Argument::Other(s) => { paths = paths.with_path(s); },
-			Argument::InvalidUtf8(s) => { paths = paths.with_path(s); },
+	// Ditto for HTTP mode.
+	if cli.serve {
+		return serve_http(cli.http_addr.as_deref(), cli.threads.take(), cli.kinds, cli.nice);
+	}
+	if cli.http_addr.is_some() { return Err(FlacaError::Listen); }
 
-			// Nothing else is relevant.
-			_ => {},
+	// A/B mode needs both settings files.
+	let ab =
+		if cli.ab {
+			let a = cli.settings_a.take().ok_or(FlacaError::AbSettings)?;
+			let b = cli.settings_b.take().ok_or(FlacaError::AbSettings)?;
+			Some((ab::Settings::load(&a)?, ab::Settings::load(&b)?))
 		}
-	}
+		else { None };
 
-	// Find and sort the images!
-	let mut paths = paths.into_vec_filtered(dowser_filter);
+	// Find the images!
+	let mut paths = cli.paths()?;
+
+	// Skip anything finished during an earlier, interrupted run.
+	if cli.resume.as_ref().is_some_and(|r| r.filter(&mut paths) != 0 && paths.is_empty()) {
+		Msg::success("Every image was already processed during the earlier run.").eprint();
+		if let Some(resume) = cli.resume { resume.finish(true); }
+		return Ok(ExitStatus::NoSavings);
+	}
 
 	// Make sure we have paths, and if we only have a few, reduce the
 	// number of threads accordingly.
 	let total = NonZeroUsize::new(paths.len()).ok_or(FlacaError::NoImages)?;
-	let threads = max_threads(threads, total);
+	let threads = max_threads(cli.threads.take(), total)?;
 
 	// Sort the paths for reproduceability.
 	paths.sort();
 
+	// Compare rather than crunch, if desired.
+	if let Some((a, b)) = ab {
+		return ab::run(&paths, threads, &a, &b).map(|()| ExitStatus::Success);
+	}
+
+	// Or just explain what would happen, if desired.
+	if cli.trace {
+		let [path] = paths.as_slice() else { return Err(FlacaError::TracePipeline); };
+		print!("{}", image::trace(path, cli.kinds));
+		return Ok(ExitStatus::Success);
+	}
+
+	// Deal with any leftovers from an earlier, interrupted run.
+	let leftovers = temp::find(&paths);
+	if ! leftovers.is_empty() { leftover_temp(&leftovers, cli.clean_temp); }
+
+	crunch(cli, paths, total, threads)
+}
+
+/// # Crunch!
+///
+/// Crunch the images — and any more added by list reloads along the way —
+/// then summarize the results.
+fn crunch(cli: Cli, mut paths: Vec<PathBuf>, total: NonZeroUsize, threads: NonZeroUsize)
+-> Result<ExitStatus, FlacaError> {
 	// Boot up a progress bar, if desired.
 	let progress =
-		if progress {
+		if cli.progress {
 			Progless::try_from(total).ok().map(|p| p.with_reticulating_splines("Flaca"))
 		}
 		else { None };
 
-	// Set up the killswitch.
-	let killed = Arc::new(AtomicBool::new(false));
-	sigint(Arc::clone(&killed), progress.clone());
+	// Step aside for more important work, if desired. This needs to happen
+	// before the worker threads are spawned so they inherit it.
+	if cli.nice { be_nice(); }
+
+	// Set up the killswitch (and other signals).
+	let killed = CancellationToken::default();
+	signals::install(killed.clone(), progress.clone());
 
 	// Hide cursor if we've got a progress bar.
 	let hide_cursor =
@@ -214,130 +322,192 @@ fn main__() -> Result<(), FlacaError> {
 		else { None };
 
 	// Now onto the thread business!
-	let mut undone: Vec<&Path> = Vec::new(); // Skipped because of CTRL+C or tx fail.
-	let (tx, rx) = crossbeam_channel::bounded::<&Path>(threads.get());
-	thread::scope(#[inline(always)] |s| {
-		// Set up the worker threads, either with or without progress.
-		let mut workers = Vec::with_capacity(threads.get());
-		if let Some(p) = progress.as_ref() {
-			for _ in 0..threads.get() {
-				workers.push(
-					s.spawn(#[inline(always)] || crunch_pretty(&rx, p, kinds))
-				);
-			}
-		}
-		else {
-			for _ in 0..threads.get() {
-				workers.push(
-					s.spawn(#[inline(always)] || crunch_quiet(&rx, kinds))
-				);
-			}
-		}
+	let pretty = progress.as_ref().map(|p| Pretty { progress: p, kinds: cli.kinds });
+	let heap = heap::profiler();
+	let by_dir = cli.by_dir.then(|| DirSummary::new(&cli.roots));
+	let by_kind = progress.is_some().then(KindSummary::default);
+	let tally = Tally::default();
+	let mut observer = Observers::default();
+	observer.push(&tally);
+	observer.push_some(by_kind.as_ref());
+	observer.push_some(pretty.as_ref());
+	observer.push_some(cli.families.as_ref());
+	observer.push_some(by_dir.as_ref());
+	observer.push_some(cli.memory.as_ref());
+	observer.push_some(cli.report.as_ref());
+	observer.push_some(cli.metrics.as_ref());
+	observer.push_some(cli.resume.as_ref());
+	observer.push_some(cli.errors.as_ref());
+	observer.push_some(heap.as_ref());
+	observer.push_some(cli.advisor.as_ref());
+	observer.push_some(cli.target.as_ref());
+	observer.push_some(cli.top.as_ref());
+	observer.push_some(cli.ndjson.as_ref());
+	observer.push_some(cli.on_change.as_ref());
+	observer.push(&signals::SNAPSHOT);
 
-		// Queue up all the image paths!
-		let mut already_dead = false;
-		for path in &paths {
-			// Early abort in progress; mark as skipped instead of giving it
-			// to a worker.
-			if killed.load(Acquire) {
-				// Skip this path for sure.
-				let mut skipped = 1_u64;
-				undone.push(path);
-
-				// But also skip anything still in the queue.
-				if ! already_dead {
-					already_dead = true;
-					let before = undone.len();
-					undone.extend(rx.try_iter());
-					skipped += (undone.len() - before) as u64;
-				}
+	signals::SNAPSHOT.add_total(paths.len() as u64);
+	let mut undone: Vec<PathBuf> = engine::run(&paths, threads, cli.kinds, &observer, &killed)
+		.into_iter()
+		.map(Path::to_path_buf)
+		.collect();
+	let mut total = paths.len() as u64;
 
-				SKIPPED.fetch_add(skipped, Relaxed);
-			}
-			// Add the path to the queue; this shouldn't fail, but if it does
-			// add it to our list so we can let the user know at the end.
-			else if tx.send(path).is_err() {
-				SKIPPED.fetch_add(1, Relaxed);
-				undone.push(path);
-			}
+	// Keep going if the lists were reloaded in the meantime.
+	while ! killed.is_cancelled() && signals::take_reload() {
+		let Some(fresh) = cli.reload(&paths) else { continue; };
+		if let Some(p) = &progress {
+			let done = u32::try_from(total).unwrap_or(u32::MAX);
+			let new_total = u32::try_from(total + fresh.len() as u64).unwrap_or(u32::MAX);
+			if p.reset(new_total).is_ok() { p.set_done(done); }
 		}
 
-		// Disconnect and wait for the threads to finish!
-		drop(tx);
-		for worker in workers { let _res = worker.join(); }
-	});
+		signals::SNAPSHOT.add_total(fresh.len() as u64);
+		undone.extend(
+			engine::run(&fresh, threads, cli.kinds, &observer, &killed)
+				.into_iter()
+				.map(Path::to_path_buf)
+		);
+		total += fresh.len() as u64;
+		paths.extend(fresh);
+		paths.sort();
+	}
+	drop(observer);
+	SKIPPED.fetch_add(undone.len() as u64, Relaxed);
 
 	// Summarize!
-	if let Some(progress) = progress { summarize(&progress, total.get() as u64); }
+	if let Some(progress) = progress { summarize(&progress, total); }
+	if let Some(by_kind) = by_kind { by_kind.summarize(); }
+	if let Some(families) = cli.families { families.summarize(); }
+	if let Some(by_dir) = by_dir { by_dir.summarize(); }
+	if let Some(top) = cli.top { top.summarize(); }
+	if let Some(on_change) = cli.on_change { on_change.summarize(); }
+	if let Some(memory) = cli.memory { memory.summarize(); }
+	if let Some(advisor) = cli.advisor { advisor.summarize(); }
+
+	// Failures trump budget misses.
+	let mut status = tally.status();
+	if let Some(target) = cli.target {
+		if status != ExitStatus::Partial && target.missed() { status = ExitStatus::OverTarget; }
+		target.summarize();
+	}
 
 	// Did anything get missed?
 	if ! undone.is_empty() { dump_undone(&undone); }
 
+	// Save or clear the checkpoint.
+	if let Some(resume) = cli.resume { resume.finish(! killed.is_cancelled()); }
+
+	// Publish the metrics.
+	if let Some(metrics) = cli.metrics {
+		if let Err(e) = metrics.save() { Msg::warning(e.as_str()).eprint(); }
+	}
+
 	// Early abort?
 	drop(hide_cursor);
-	if killed.load(Acquire) { Err(FlacaError::Killed) }
-	else { Ok(()) }
+	drop(cli.lock);
+	if killed.is_cancelled() { Err(FlacaError::Killed) }
+	else { Ok(status) }
 }
 
-#[inline(never)]
-/// # Worker Callback (Pretty).
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Be Nice.
 ///
-/// This is the worker callback for pretty crunching. It listens for "new"
-/// image paths and crunches them — and updates the progress bar, etc. —
-/// then quits when the work has dried up.
-fn crunch_pretty(rx: &Receiver::<&Path>, progress: &Progless, kinds: ImageKind) {
-	#[expect(clippy::inline_always, reason = "For performance.")]
-	#[inline(always)]
-	/// # Noteworthy Failure?
-	fn noteworthy(kinds: ImageKind, p: &Path) -> bool {
-		if matches!(kinds, ImageKind::All) { true }
-		else if Some(E_PNG) == Extension::try_from3(p) { kinds.supports_png() }
-		else { kinds.supports_jpeg() }
+/// Lower the CPU — and on Linux, IO — scheduling priority of the current
+/// thread (and any it spawns) to the minimum.
+///
+/// Failures are ignored; this is a courtesy, not a requirement.
+fn be_nice() {
+	/// # IO Priority: Who (Process).
+	#[cfg(target_os = "linux")]
+	const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+	/// # IO Priority: Idle Class.
+	#[cfg(target_os = "linux")]
+	const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+	/// # IO Priority: Class Shift.
+	#[cfg(target_os = "linux")]
+	const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+	// Safety: these only affect the scheduling of the current thread.
+	unsafe {
+		let _res = libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+
+		#[cfg(target_os = "linux")]
+		let _res = libc::syscall(
+			libc::SYS_ioprio_set,
+			IOPRIO_WHO_PROCESS,
+			0,
+			IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+		);
 	}
+}
 
-	while let Ok(p) = rx.recv() {
-		let name = p.to_string_lossy();
-		progress.add(&name);
+#[inline]
+/// # Dowser Filter.
+fn dowser_filter(p: &Path) -> bool {
+	(image::is_candidate(p) || filter::sniffable(p)) && filter::check(p)
+}
 
-		match crate::image::encode(p, kinds) {
-			// Happy.
-			Ok((b, a)) => {
-				BEFORE.fetch_add(b, Relaxed);
-				AFTER.fetch_add(a, Relaxed);
-			},
-			// Skipped.
-			Err(e) => {
-				SKIPPED.fetch_add(1, Relaxed);
-
-				if ! matches!(e, EncodingError::Skipped) && noteworthy(kinds, p) {
-					let _res = progress.push_msg(Msg::skipped(format!(
-						"{name} \x1b[2m({})\x1b[0m",
-						e.as_str(),
-					)));
-				}
-			}
-		}
+/// # Crawl.
+///
+/// Find the images in and under `roots` and `listed`, leaving behind (with a
+/// warning) any reached via symlinks leading outside them.
+fn crawl(roots: &[PathBuf], listed: &[PathBuf], max_depth: Option<NonZeroUsize>)
+-> Vec<PathBuf> {
+	// If there's a depth limit or ignore files to respect, expand the
+	// directories ourselves so the crawler doesn't go anywhere it shouldn't.
+	let requested = roots.iter().chain(listed);
+	let mut paths = Dowser::default()
+		.with_paths(
+			if ignore::RESPECT_IGNORE.load(Relaxed) { ignore::expand(requested, max_depth) }
+			else if let Some(depth) = max_depth { filter::expand_to_depth(requested, depth) }
+			else { requested.cloned().collect() }
+		)
+		.into_vec_filtered(dowser_filter);
 
-		progress.remove(&name);
+	// Never follow links out of the requested paths.
+	let escaped = filter::confine(roots.iter().chain(listed), &mut paths);
+	if escaped != 0 {
+		Msg::warning(format!(
+			"Skipped {} reached via symlinks leading outside the requested paths.",
+			escaped.nice_inflect("image", "images"),
+		)).eprint();
 	}
+
+	paths
 }
 
-#[inline(never)]
-/// # Worker Callback (Quiet).
+/// # Narrow.
 ///
-/// This is the worker callback for quiet crunching. It listens for "new" image
-/// paths and crunches them, then quits when the work has dried up.
-fn crunch_quiet(rx: &Receiver::<&Path>, kinds: ImageKind) {
-	while let Ok(p) = rx.recv() { let _res = crate::image::encode(p, kinds); }
+/// Skip or flag the thumbnails and variants, if desired. The `roots` are
+/// the paths the crawl started from.
+fn narrow<P, I>(paths: &mut Vec<PathBuf>, roots: I, thumbs: Option<Thumbnails>, variants: Option<bool>)
+where P: AsRef<Path>, I: IntoIterator<Item=P> {
+	if let Some(thumbs) = thumbs { thumbs.init(paths, roots); }
+	match variants {
+		Some(true) => { family::primary_only(paths); },
+		Some(false) => { family::skip_variants(paths); },
+		None => {},
+	}
 }
 
-#[inline]
-/// # Dowser Filter.
-fn dowser_filter(p: &Path) -> bool {
-	Extension::try_from3(p).map_or_else(
-		|| Some(E_JPEG) == Extension::try_from4(p),
-		|e| e == E_JPG || e == E_PNG
-	)
+#[cold]
+/// # Reload Lists.
+///
+/// Re-read the (file-based) lists after a `SIGHUP`, returning their entries,
+/// or `None` if a list could not be read.
+fn reload(lists: &[String], null: bool, follow_symlinks: bool) -> Option<Vec<PathBuf>> {
+	let mut listed = Vec::new();
+	for list in lists {
+		if list != "-" && read_list(list, null, &mut listed).is_err() {
+			Msg::warning(format!("Unable to reload {list}.")).eprint();
+			return None;
+		}
+	}
+	if ! follow_symlinks { listed.retain(|p| ! filter::is_symlink(p)); }
+	Some(listed)
 }
 
 #[cold]
@@ -345,7 +515,7 @@ fn dowser_filter(p: &Path) -> bool {
 ///
 /// When aborting early, the unprocessed entries get dumped to a temporary
 /// file, potentially.
-fn dump_undone(undone: &[&Path]) {
+fn dump_undone(undone: &[PathBuf]) {
 	// Merge the paths into a line-separated list, if we can.
 	let mut dump = String::new();
 	for p in undone {
@@ -355,7 +525,7 @@ fn dump_undone(undone: &[&Path]) {
 	}
 
 	// Save it if we can.
-	let path = std::env::temp_dir().join(format!("flaca-{}.txt", utc2k::unixtime()));
+	let path = temp::scratch_dir().join(format!("flaca-{}.txt", utc2k::unixtime()));
 	if write_atomic::write_file(&path, dump.as_bytes()).is_ok() {
 		Msg::notice(format!(
 			"{} missed during the run; their paths have
@@ -366,11 +536,45 @@ fn dump_undone(undone: &[&Path]) {
 	}
 }
 
+#[cold]
+/// # Leftover Temporary Files.
+///
+/// Remove or report the temporary files left behind by an earlier run.
+fn leftover_temp(found: &[PathBuf], clean: bool) {
+	if clean {
+		let removed = temp::clean(found);
+		Msg::notice(format!(
+			"Removed {} left over from an earlier run. (Files less than {} old were left alone.)",
+			removed.nice_inflect("temporary file", "temporary files"),
+			NiceElapsed::from(temp::MIN_AGE),
+		)).eprint();
+	}
+	else {
+		let mut msg = format!(
+			"Found {} at least {} old, left over from an earlier run; use --clean-temp to remove them:",
+			found.len().nice_inflect("temporary file", "temporary files"),
+			NiceElapsed::from(temp::MIN_AGE),
+		);
+		for p in found {
+			msg.push_str("\n        \x1b[2m");
+			msg.push_str(&p.to_string_lossy());
+			msg.push_str("\x1b[0m");
+		}
+		Msg::warning(msg).eprint();
+	}
+}
+
 /// # Max Threads.
 ///
 /// Given the hardware, user preference, and total number of jobs, calculate
 /// and return the maximum number of threads to spawn.
-fn max_threads(user: Option<String>, jobs: NonZeroUsize) -> NonZeroUsize {
+///
+/// ## Errors
+///
+/// An error is returned if the user preference is not a valid (non-zero)
+/// number.
+fn max_threads(user: Option<String>, jobs: NonZeroUsize)
+-> Result<NonZeroUsize, FlacaError> {
 	// The default number.
 	let mut threads = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
 
@@ -385,46 +589,163 @@ fn max_threads(user: Option<String>, jobs: NonZeroUsize) -> NonZeroUsize {
 		else if let Some(t) = NonZeroUsize::btou(t) {
 			if t < threads { threads = t; }
 		}
+		else { return Err(FlacaError::Threads); }
 	}
 
 	// Return the smaller of the user/machine and job counts.
-	NonZeroUsize::min(threads, jobs)
+	Ok(NonZeroUsize::min(threads, jobs))
 }
 
-/// # Set Pixel Limit.
-fn set_pixel_limit(raw: &[u8]) -> Result<(), FlacaError> {
-	let multiplier: u32 =
-		match raw.last() {
-			Some(b'k' | b'K') => 1_000,
-			Some(b'm' | b'M') => 1_000_000,
-			Some(b'g' | b'G') => 1_000_000_000,
-			None => return Err(FlacaError::MaxResolution),
+/// # Read Path List.
+///
+/// Read the paths from a text file — or STDIN if `-` — and add them to
+/// `paths`. Entries are separated by line breaks, or if `null`, NUL bytes.
+///
+/// ## Errors
+///
+/// An error is returned if the list cannot be read.
+fn read_list(src: &str, null: bool, paths: &mut Vec<PathBuf>) -> Result<(), FlacaError> {
+	use std::{
+		ffi::OsStr,
+		io::Read,
+		os::unix::ffi::OsStrExt,
+	};
+
+	let raw =
+		if src == "-" {
+			let mut raw = Vec::new();
+			std::io::stdin().lock().read_to_end(&mut raw).map(|_| raw)
+		}
+		else { std::fs::read(src) }
+		.map_err(|_| FlacaError::ListFile)?;
+
+	let sep = if null { b'\0' } else { b'\n' };
+	for entry in raw.split(|b| *b == sep) {
+		// Lines might have stray whitespace; NUL-delimited entries are taken
+		// literally.
+		let entry = if null { entry } else { entry.trim_ascii() };
+		if ! entry.is_empty() { paths.push(PathBuf::from(OsStr::from_bytes(entry))); }
+	}
+
+	Ok(())
+}
+
+/// # Parse Zopfli Iterations.
+///
+/// Parse a single, fixed iteration count, or a comma-separated curve of
+/// three — for small, medium, and large images.
+fn parse_zopfli_iterations(raw: &str) -> Option<ZopfliIterations> {
+	let mut parts = raw.split(',').map(|p| NonZeroU32::btou(p.trim().as_bytes()));
+	match (parts.next(), parts.next(), parts.next(), parts.next()) {
+		(Some(n), None, None, None) => n.map(ZopfliIterations::fixed),
+		(Some(small), Some(medium), Some(large), None) => Some(ZopfliIterations {
+			small: small?,
+			medium: medium?,
+			large: large?,
+		}),
+		_ => None,
+	}
+}
+
+/// # Set JPEG Markers to Keep.
+///
+/// Parse the comma-separated list of JPEG marker types to keep.
+fn set_jpeg_keep(raw: &str) -> Result<(), FlacaError> {
+	let mut keep = 0_u8;
+	for name in raw.split(',') {
+		let name = name.trim();
+		if name.is_empty() { continue; }
+		keep |= match name.to_ascii_lowercase().as_str() {
+			"exif" => image::KEEP_EXIF,
+			"icc" => image::KEEP_ICC,
+			"thumbnail" => image::KEEP_EXIF | image::KEEP_THUMBNAIL,
+			"xmp" => image::KEEP_XMP,
+			_ => return Err(FlacaError::JpegKeep),
+		};
+	}
+
+	if keep == 0 { Err(FlacaError::JpegKeep) }
+	else {
+		image::KEEP_MARKERS.store(keep, Relaxed);
+		Ok(())
+	}
+}
+
+/// # Set PNG Chunks to Keep.
+///
+/// Parse the comma-separated list of friendly chunk names and pass the
+/// corresponding PNG chunk name(s) to flapfli for safekeeping.
+fn set_keep_chunks(raw: &str) -> Result<(), FlacaError> {
+	let mut chunks = Vec::new();
+	for name in raw.split(',') {
+		let name = name.trim();
+		if name.is_empty() { continue; }
+		match name.to_ascii_lowercase().as_str() {
+			"chrm" => { chunks.push(*b"cHRM"); },
+			"cicp" => { chunks.push(*b"cICP"); },
+			"exif" => { chunks.push(*b"eXIf"); },
+			"gama" => { chunks.push(*b"gAMA"); },
+			"icc" | "iccp" => { chunks.push(*b"iCCP"); },
+			"phys" => { chunks.push(*b"pHYs"); },
+			"srgb" => { chunks.push(*b"sRGB"); },
+			"text" => { chunks.extend([*b"iTXt", *b"tEXt", *b"zTXt"]); },
+			"time" => { chunks.push(*b"tIME"); },
+			_ => return Err(FlacaError::KeepChunks),
+		}
+	}
+
+	if chunks.is_empty() { Err(FlacaError::KeepChunks) }
+	else if flapfli::set_keep_chunks(chunks) { Ok(()) }
+	else { Err(FlacaError::KeepChunks2) }
+}
+
+/// # Parse Limit.
+///
+/// Parse a non-zero number, optionally suffixed with `k`, `m`, or `g` for
+/// thousands, millions, or billions, respectively.
+pub(crate) fn parse_limit(raw: &[u8]) -> Option<u64> {
+	let multiplier: u64 =
+		match raw.last()? {
+			b'k' | b'K' => 1_000,
+			b'm' | b'M' => 1_000_000,
+			b'g' | b'G' => 1_000_000_000,
 			_ => 1,
 		};
 
 	let len = raw.len() - usize::from(multiplier != 1);
-	let limit = NonZeroU32::btou(&raw[..len])
-		.and_then(|n| n.get().checked_mul(multiplier))
-		.ok_or(FlacaError::MaxResolution)?;
+	NonZeroU64::btou(&raw[..len]).and_then(|n| n.get().checked_mul(multiplier))
+}
 
-	MAX_RESOLUTION.store(limit, Relaxed);
-	Ok(())
+#[cfg(feature = "serve")]
+/// # Serve HTTP.
+///
+/// Run the HTTP server until CTRL+C, listening on `127.0.0.1:8080` unless
+/// the user says otherwise.
+///
+/// ## Errors
+///
+/// An error is returned if the address is invalid or unavailable, or the
+/// thread count is invalid.
+fn serve_http(listen: Option<&str>, threads: Option<String>, kinds: ImageKind, nice: bool)
+-> Result<ExitStatus, FlacaError> {
+	let threads = max_threads(threads, NonZeroUsize::MAX)?;
+	if nice { be_nice(); }
+	let killed = CancellationToken::default();
+	signals::install(killed.clone(), None);
+	daemon::serve(listen.unwrap_or("127.0.0.1:8080"), threads, kinds, &killed)
 }
 
-/// # Hook Up CTRL+C.
+#[cfg(not(feature = "serve"))]
+/// # Serve HTTP (Unsupported).
 ///
-/// Once stops processing new items, twice forces immediate shutdown.
-fn sigint(killed: Arc<AtomicBool>, progress: Option<Progless>) {
-	let _res = ctrlc::set_handler(move ||
-		if killed.compare_exchange(false, true, SeqCst, Relaxed).is_ok() {
-			if let Some(p) = &progress { p.sigint(); }
-		}
-		else {
-			// Manually unhide the cursor; the drop glue probably won't run.
-			if progress.is_some() { eprint!("{}", Progless::CURSOR_UNHIDE); }
-			std::process::exit(1);
-		}
-	);
+/// The HTTP server requires the `serve` crate feature.
+///
+/// ## Errors
+///
+/// This always returns an error.
+fn serve_http(_listen: Option<&str>, _threads: Option<String>, _kinds: ImageKind, _nice: bool)
+-> Result<ExitStatus, FlacaError> {
+	Err(FlacaError::Serve)
 }
 
 /// # Summarize Results.
@@ -448,8 +769,681 @@ fn summarize(progress: &Progless, total: u64) {
 			AFTER.load(Acquire),
 		)))
 		.eprint();
+
+	// Mention any stored zopfli blocks, since they're noteworthy.
+	let (blocks, stored) = flapfli::zopfli_block_stats();
+	if stored != 0 {
+		Msg::info(format!(
+			"{} of {} zopfli {} stored uncompressed.",
+			NiceU64::from(stored),
+			NiceU64::from(blocks),
+			if blocks == 1 { "block was" } else { "blocks were" },
+		)).eprint();
+	}
 }
 
+
+
+#[expect(clippy::struct_excessive_bools, reason = "They're CLI flags.")]
+/// # CLI Options.
+///
+/// These are the parsed command-line options, minus the ones that are
+/// stored globally.
+struct Cli {
+	/// # Command: A/B.
+	ab: bool,
+
+	/// # Command: Serve.
+	serve: bool,
+
+	/// # Advisor.
+	advisor: Option<Advisor>,
+
+	/// # Backup Suffix.
+	backup: Option<String>,
+
+	/// # Summarize by Directory.
+	by_dir: bool,
+
+	/// # Keep Candidates.
+	candidates: Option<String>,
+
+	/// # Clean Leftover Temporary Files.
+	clean_temp: bool,
+
+	/// # Daemon Mode.
+	daemon: bool,
+
+	/// # Error Log.
+	errors: Option<ErrorLog>,
+
+	/// # Variant Families.
+	families: Option<Families>,
+
+	/// # Path Filters.
+	filters: Filters,
+
+	/// # Follow Symlinks.
+	follow_symlinks: bool,
+
+	/// # HTTP Address.
+	http_addr: Option<String>,
+
+	/// # Image Kinds.
+	kinds: ImageKind,
+
+	/// # Path Lists.
+	lists: Vec<String>,
+
+	/// # Run Lock.
+	lock: Option<RunLock>,
+
+	/// # Maximum Depth.
+	max_depth: Option<NonZeroUsize>,
+
+	/// # Memory Usage.
+	memory: Option<MemoryUsage>,
+
+	/// # Metrics.
+	metrics: Option<Metrics>,
+
+	/// # NDJSON Output.
+	ndjson: Option<Ndjson>,
+
+	/// # Nice.
+	nice: bool,
+
+	/// # Null-Delimited Lists.
+	null: bool,
+
+	/// # On Change Hook.
+	on_change: Option<OnChange>,
+
+	/// # Output Directory.
+	out_dir: Option<String>,
+
+	/// # Oxipng Level.
+	oxipng_level: Option<u8>,
+
+	/// # Pipe Kind.
+	pipe_kind: Option<ImageKind>,
+
+	/// # Effort Profile.
+	profile: Profile,
+
+	/// # Progress Bar.
+	progress: bool,
+
+	/// # Recurse.
+	recurse: bool,
+
+	/// # Report.
+	report: Option<Report>,
+
+	/// # Resume.
+	resume: Option<Resume>,
+
+	/// # Root Paths.
+	roots: Vec<PathBuf>,
+
+	/// # A/B Settings (A).
+	settings_a: Option<String>,
+
+	/// # A/B Settings (B).
+	settings_b: Option<String>,
+
+	/// # Daemon Socket.
+	socket: Option<PathBuf>,
+
+	/// # STDIN Mode.
+	stdin: bool,
+
+	/// # Target Size.
+	target: Option<TargetSize>,
+
+	/// # Thread Limit.
+	threads: Option<String>,
+
+	/// # Thumbnail Handling.
+	thumbs: Option<Thumbnails>,
+
+	/// # Leaderboard.
+	top: Option<Leaderboard>,
+
+	/// # Trace Pipeline.
+	trace: bool,
+
+	/// # Variant Handling.
+	variants: Option<bool>,
+}
+
+impl Cli {
+	/// # Parse.
+	///
+	/// Parse the CLI arguments, with any environment overrides mixed in.
+	///
+	/// Settings that apply globally are saved as they're encountered; the
+	/// rest are returned.
+	fn parse() -> Result<Self, FlacaError> {
+		let args = argyle::Argue::from(env::args(KEYWORDS, std::env::args_os().skip(1))?)
+			.with_keywords(KEYWORDS.iter().copied());
+
+		let mut out = Self {
+			ab: false,
+			serve: false,
+			advisor: None,
+			backup: None,
+			by_dir: false,
+			candidates: None,
+			clean_temp: false,
+			daemon: false,
+			errors: None,
+			families: None,
+			filters: Filters::default(),
+			follow_symlinks: true,
+			http_addr: None,
+			kinds: ImageKind::ALL,
+			lists: Vec::new(),
+			lock: None,
+			max_depth: None,
+			memory: None,
+			metrics: None,
+			ndjson: None,
+			nice: false,
+			null: false,
+			on_change: None,
+			out_dir: None,
+			oxipng_level: None,
+			pipe_kind: None,
+			profile: Profile::Default,
+			progress: false,
+			recurse: true,
+			report: None,
+			resume: None,
+			roots: Vec::new(),
+			settings_a: None,
+			settings_b: None,
+			socket: None,
+			stdin: false,
+			target: None,
+			threads: None,
+			thumbs: None,
+			top: None,
+			trace: false,
+			variants: None,
+		};
+
+		let mut args = args.into_iter().peekable();
+		while let Some(arg) = args.next() {
+			match arg {
+				Argument::Command("ab") => { out.ab = true; },
+				Argument::Command("serve") => { out.serve = true; },
+				Argument::Key(key) => { out.parse_key(key)?; },
+				Argument::KeyWithValue(key, s) => { out.parse_key_with_value(key, s)?; },
+
+				// Argyle can't do optional values, so this one is matched by
+				// hand. Values have to be attached with an "="; a bare
+				// --backup followed by a path is too ambiguous to guess at.
+				Argument::Other(s) if s == "--backup" || s.starts_with("--backup=") => {
+					if s.len() == 8 && args.peek().is_some_and(|a| match a {
+						Argument::Other(a) => ! a.starts_with('-'),
+						Argument::InvalidUtf8(_) => true,
+						_ => false,
+					}) {
+						return Err(FlacaError::BackupValue);
+					}
+					out.backup = Some(s[8..].trim_start_matches('=').to_owned());
+				},
+
+				// Assume these are paths.
+				Argument::Other(s) => { out.roots.push(PathBuf::from(s)); },
+				Argument::InvalidUtf8(s) => { out.roots.push(PathBuf::from(s)); },
+
+				// Nothing else is relevant.
+				_ => {},
+			}
+		}
+
+		Ok(out)
+	}
+
+	/// # Parse Flag.
+	fn parse_key(&mut self, key: &str) -> Result<(), FlacaError> {
+		match key {
+			"-0" | "--null" => { self.null = true; },
+			"--advise" => { self.advisor.get_or_insert_with(Advisor::default); },
+			"--allow-imperfect" => { image::ALLOW_IMPERFECT.store(true, Relaxed); },
+			"--archives" => { image::ARCHIVES.store(true, Relaxed); },
+			"--auto-orient" => { image::AUTO_ORIENT.store(true, Relaxed); },
+			"--clean-temp" => { self.clean_temp = true; },
+			"--daemon" => { self.daemon = true; },
+			"--default" => { self.profile = Profile::Default; },
+			"--dry-run" => { output::DRY_RUN.store(true, Relaxed); },
+			"--fast" => { self.profile = Profile::Fast; },
+			"--fast-thumbnails" => { self.thumbs.get_or_insert(Thumbnails::Fast); },
+			"--fix-extensions" => { image::FIX_EXTENSIONS.store(true, Relaxed); },
+			"--follow-symlinks" => { self.follow_symlinks = true; },
+			"--group-variants" => { self.families.get_or_insert_with(Families::default); },
+			"-h" | "--help" => return Err(FlacaError::PrintHelp),
+			"--jpeg-grayscale-auto" => { image::GRAYSCALE_AUTO.store(true, Relaxed); },
+			"--keep-alpha-data" => { flapfli::set_keep_alpha_data(true); },
+			"--keep-colortype" => { flapfli::set_keep_color_type(true); },
+			"--mark" => { mark::MARK.store(true, Relaxed); },
+			"--max" => { self.profile = Profile::Max; },
+			"--memory" => { self.memory.get_or_insert_with(MemoryUsage::default); },
+			"--ndjson" => { self.ndjson.get_or_insert(Ndjson); },
+			"--nice" => { self.nice = true; },
+			"--no-follow-symlinks" => { self.follow_symlinks = false; },
+			"--no-fix" => { image::FIX_ERRORS.store(false, Relaxed); },
+			"--no-gif" => { self.kinds = self.kinds.diff(ImageKind::GIF)?; },
+			"--no-ico" => { self.kinds = self.kinds.diff(ImageKind::ICO)?; },
+			"--no-jpg" | "--no-jpeg" => { self.kinds = self.kinds.diff(ImageKind::JPEG)?; },
+			"--no-oxipng" => { image::OXIPNG.store(false, Relaxed); },
+			"--no-png" => { self.kinds = self.kinds.diff(ImageKind::PNG)?; },
+			"--no-recurse" => { self.recurse = false; },
+			"--no-zopfli" => { image::ZOPFLI.store(false, Relaxed); },
+			"--png-exhaustive" => { flapfli::set_exhaustive(true); },
+			"-p" | "--progress" => { self.progress = true; },
+			"--preserve-perms" => { output::PRESERVE_PERMS.store(true, Relaxed); },
+			"--primary-only" => { self.variants = Some(true); },
+			"--respect-gitignore" => { ignore::RESPECT_IGNORE.store(true, Relaxed); },
+			"--skip-marked" => { mark::SKIP_MARKED.store(true, Relaxed); },
+			"--skip-thumbnails" => { self.thumbs = Some(Thumbnails::Skip); },
+			"--skip-variants" => { self.variants.get_or_insert(false); },
+			"--sniff" => { filter::SNIFF.store(true, Relaxed); },
+			"--stdin" => { self.stdin = true; },
+			"--summarize-by-dir" => { self.by_dir = true; },
+			"--trace-pipeline" => { self.trace = true; },
+			"--verify" => { image::VERIFY.store(true, Relaxed); },
+			"-V" | "--version" => return Err(FlacaError::PrintVersion),
+			_ => {},
+		}
+
+		Ok(())
+	}
+
+	#[expect(clippy::too_many_lines, reason = "There are a lot of options. Haha.")]
+	/// # Parse Option.
+	fn parse_key_with_value(&mut self, key: &str, s: String) -> Result<(), FlacaError> {
+		match key {
+			"--confirm-cmd" => { hook::set_confirm(&s)?; },
+
+			"--errors" => {
+				self.errors.replace(ErrorLog::new(&s)?);
+			},
+
+			"-j" | "--threads" => { self.threads.replace(s); },
+
+			"--socket" => { self.socket.replace(PathBuf::from(s)); },
+
+			"--jpeg-crop" => { image::Crop::init(&s)?; },
+
+			"--jpeg-keep" => { set_jpeg_keep(&s)?; },
+
+			"--keep-candidates" => { self.candidates.replace(s); },
+
+			"--keep-chunks" => { set_keep_chunks(&s)?; },
+
+			"--kind" => {
+				let kind = match s.trim().to_ascii_lowercase().as_str() {
+					"gif" => ImageKind::GIF,
+					"ico" | "cur" => ImageKind::ICO,
+					"jpeg" | "jpg" => ImageKind::JPEG,
+					"png" => ImageKind::PNG,
+					_ => return Err(FlacaError::Kind),
+				};
+				self.pipe_kind.replace(kind);
+			},
+
+			"-l" | "--list" => { self.lists.push(s); },
+
+			"--listen" => { self.http_addr.replace(s); },
+
+			"--lock" => { self.lock.replace(RunLock::new(&s)?); },
+
+			"--max-depth" => {
+				let depth = NonZeroUsize::btou(s.trim().as_bytes())
+					.ok_or(FlacaError::MaxDepth)?;
+				self.max_depth.replace(depth);
+			},
+
+			"--max-filesize" => {
+				let limit = parse_limit(s.trim().as_bytes())
+					.ok_or(FlacaError::MaxFileSize)?;
+				MAX_FILESIZE.store(limit, Relaxed);
+			},
+
+			"--max-memory" => {
+				let limit = u64::btou(s.trim().as_bytes())
+					.filter(|&n| n != 0)
+					.and_then(|n| n.checked_mul(1024 * 1024))
+					.ok_or(FlacaError::MaxMemory)?;
+				MAX_MEMORY.store(limit, Relaxed);
+			},
+
+			"--max-size" => { self.filters.set_max_size(&s)?; },
+			"--min-size" => { self.filters.set_min_size(&s)?; },
+
+			"--max-resolution" => {
+				let limit = parse_limit(s.trim().as_bytes())
+					.and_then(|n| u32::try_from(n).ok())
+					.ok_or(FlacaError::MaxResolution)?;
+				MAX_RESOLUTION.store(limit, Relaxed);
+			},
+
+			"--memory-budget" => {
+				let limit = u64::btou(s.trim().as_bytes())
+					.filter(|&n| n != 0)
+					.and_then(|n| n.checked_mul(1024 * 1024))
+					.ok_or(FlacaError::MemoryBudget)?;
+				budget::BUDGET.set_limit(limit);
+			},
+
+			"--metrics-file" => {
+				self.metrics.replace(Metrics::new(&s)?);
+			},
+
+			"--mmap-threshold" => {
+				let limit = parse_limit(s.trim().as_bytes())
+					.ok_or(FlacaError::MmapThreshold)?;
+				mmap::MMAP_THRESHOLD.store(limit, Relaxed);
+			},
+
+			"--min-savings" => {
+				image::MinSavings::set(s.trim().as_bytes())?;
+			},
+
+			"--name-template" => { NameTemplate::init(&s)?; },
+			"--newer-than" => { self.filters.set_newer_than(&s)?; },
+			"--older-than" => { self.filters.set_older_than(&s)?; },
+
+			"--on-change" => {
+				self.on_change.replace(OnChange::new(&s)?);
+			},
+
+			"--oxipng-level" => {
+				let level = u8::btou(s.trim().as_bytes())
+					.filter(|&n| n <= image::OXIPNG_LEVEL_MAX)
+					.ok_or(FlacaError::OxipngLevel)?;
+				self.oxipng_level = Some(level);
+			},
+
+			"-o" | "--out-dir" => {
+				self.out_dir.replace(s);
+			},
+
+			"--preserve-links" => { HardLinks::set(&s)?; },
+
+			"--reflink" => {
+				let reflink = match s.trim() {
+					"auto" => true,
+					"never" => false,
+					_ => return Err(FlacaError::Reflink),
+				};
+				output::REFLINK.store(reflink, Relaxed);
+			},
+
+			"--report" => {
+				self.report.replace(Report::new(&s)?);
+			},
+
+			"--resume" => {
+				self.resume.replace(Resume::new(&s)?);
+			},
+
+			"--settings-a" => { self.settings_a.replace(s); },
+			"--settings-b" => { self.settings_b.replace(s); },
+
+			"--target-size" => {
+				self.target.replace(TargetSize::new(&s)?);
+			},
+
+			"--tmpdir" => { temp::set_scratch_dir(&s)?; },
+
+			"--top" => {
+				let n = NonZeroUsize::btou(s.trim().as_bytes()).ok_or(FlacaError::Top)?;
+				self.top.replace(Leaderboard::new(n));
+			},
+
+			"--zopfli-store-threshold" => {
+				let s = s.trim();
+				let pct = u8::btou(s.strip_suffix('%').unwrap_or(s).trim_end().as_bytes())
+					.ok_or(FlacaError::ZopfliStoreThreshold)?;
+				if ! flapfli::set_zopfli_store_threshold(pct) {
+					return Err(FlacaError::ZopfliStoreThreshold);
+				}
+			},
+
+			"--zopfli-block-size" => {
+				let size = parse_limit(s.trim().as_bytes())
+					.and_then(|n| usize::try_from(n).ok())
+					.ok_or(FlacaError::ZopfliBlockSize)?;
+				if ! flapfli::set_zopfli_block_size(size) {
+					return Err(FlacaError::ZopfliBlockSize);
+				}
+			},
+
+			"--zopfli-cost-model" => {
+				let refined = match s.trim() {
+					"v1" => false,
+					"v2" => true,
+					_ => return Err(FlacaError::ZopfliCostModel),
+				};
+				if ! flapfli::set_zopfli_cost_model(refined) {
+					return Err(FlacaError::ZopfliCostModel2);
+				}
+			},
+
+			"--zopfli-max-splits" => {
+				let max = u8::btou(s.trim().as_bytes()).ok_or(FlacaError::ZopfliMaxSplits)?;
+				if ! flapfli::set_zopfli_max_splits(max) {
+					return Err(FlacaError::ZopfliMaxSplits);
+				}
+			},
+
+			"-z" => {
+				let s = parse_zopfli_iterations(&s).ok_or(FlacaError::ZopfliIterations)?;
+				if ! flapfli::set_zopfli_iterations(s) {
+					return Err(FlacaError::ZopfliIterations2);
+				}
+			},
+
+			_ => {},
+		}
+
+		Ok(())
+	}
+
+	/// # Check.
+	///
+	/// Apply the effort profile, and make sure the global settings are
+	/// compatible with one another.
+	fn check(&self) -> Result<(), FlacaError> {
+		// Apply the effort profile before anything gets encoded.
+		self.profile.init(self.oxipng_level);
+
+		// PNGs need at least one encoder.
+		if ! image::OXIPNG.load(Relaxed) && ! image::ZOPFLI.load(Relaxed) {
+			return Err(FlacaError::NoPngEncoders);
+		}
+
+		// Brute-forcing color types is at odds with keeping them.
+		if flapfli::exhaustive() && flapfli::keep_color_type() {
+			return Err(FlacaError::PngExhaustive);
+		}
+
+		// Crops are applied to the stored orientation, so can't be mixed with
+		// transformations.
+		if image::AUTO_ORIENT.load(Relaxed) && image::Crop::get().is_some() {
+			return Err(FlacaError::JpegCrop);
+		}
+
+		Ok(())
+	}
+
+	/// # Paths.
+	///
+	/// Crawl the roots and lists for images, narrow them down, and set up the
+	/// output redirection and backups, if any.
+	fn paths(&mut self) -> Result<Vec<PathBuf>, FlacaError> {
+		// Add any listed paths.
+		let mut listed = Vec::new();
+		for list in &self.lists { read_list(list, self.null, &mut listed)?; }
+
+		// Leave symlinked paths be, if desired.
+		if ! self.follow_symlinks {
+			self.roots.retain(|p| ! filter::is_symlink(p));
+			listed.retain(|p| ! filter::is_symlink(p));
+		}
+
+		// Stick to the top level, if desired.
+		if ! self.recurse { self.max_depth.replace(NonZeroUsize::MIN); }
+
+		// Find and sort the images!
+		self.filters.init();
+		let mut paths = self.find(&self.roots, &listed);
+
+		// Redirect the output, if desired.
+		if let Some(dir) = &self.out_dir { OutDir::init(dir, &mut paths)?; }
+
+		// Hold on to the would-be results of a dry run, if desired.
+		if let Some(dir) = &self.candidates {
+			if ! output::DRY_RUN.load(Relaxed) { return Err(FlacaError::KeepCandidates); }
+			OutDir::init_candidates(dir, &mut paths)?;
+		}
+
+		// Back up the originals before overwriting them, if desired.
+		if let Some(backup) = &self.backup { Backup::init(backup, &mut paths)?; }
+
+		Ok(paths)
+	}
+
+	/// # Find Images.
+	///
+	/// Crawl `roots` and `listed` for images, then skip or flag the
+	/// thumbnails and variants, if desired. The latter always considers all
+	/// of the roots and `listed` entries, whether or not they were crawled
+	/// this time around.
+	fn find(&self, roots: &[PathBuf], listed: &[PathBuf]) -> Vec<PathBuf> {
+		let mut paths = crawl(roots, listed, self.max_depth);
+		narrow(&mut paths, self.roots.iter().chain(listed), self.thumbs, self.variants);
+		paths
+	}
+
+	#[cold]
+	/// # Reload.
+	///
+	/// Re-read the lists after a `SIGHUP`, returning any new images (sorted
+	/// and narrowed), or `None` if there aren't any.
+	fn reload(&self, seen: &[PathBuf]) -> Option<Vec<PathBuf>> {
+		// The lists can only be reloaded if the output settings, which can
+		// only be initialized once, are left alone.
+		let reloadable =
+			self.lists.iter().any(|l| l != "-") &&
+			self.out_dir.is_none() &&
+			self.candidates.is_none() &&
+			self.backup.is_none();
+		if ! reloadable {
+			Msg::warning("The lists cannot be reloaded when using -o/--out-dir, --backup, or --keep-candidates.")
+				.eprint();
+			return None;
+		}
+
+		let listed = reload(&self.lists, self.null, self.follow_symlinks)?;
+		let mut fresh = self.find(&[], &listed);
+		fresh.retain(|p| seen.binary_search(p).is_err());
+		if let Some(resume) = &self.resume { resume.filter(&mut fresh); }
+		if fresh.is_empty() {
+			Msg::info("The lists were reloaded, but contained no new images.").eprint();
+			return None;
+		}
+
+		fresh.sort();
+		Msg::info(format!(
+			"The lists were reloaded, adding {}.",
+			fresh.len().nice_inflect("image", "images"),
+		)).eprint();
+		Some(fresh)
+	}
+}
+
+
+
+/// # Pretty Observer.
+///
+/// This observer keeps the progress bar and running totals up-to-date as
+/// images are crunched.
+struct Pretty<'a> {
+	/// # Progress Bar.
+	progress: &'a Progless,
+
+	/// # Image Kinds.
+	kinds: ImageKind,
+}
+
+impl Observer for Pretty<'_> {
+	fn on_start(&self, src: &Path) {
+		self.progress.add(src.to_string_lossy());
+	}
+
+	fn on_progress(&self, src: &Path, done: usize, total: usize) {
+		// Single-block images are over before anyone would notice.
+		if total < 2 { return; }
+
+		// Borrow the title to show how far along the big ones are.
+		if done < total {
+			self.progress.set_title(Some(Msg::custom("Flaca", 199, &format!(
+				"Zopfli block {}/{total} of {}\u{2026}",
+				done + 1,
+				src.to_string_lossy(),
+			))));
+		}
+		else { self.progress.set_reticulating_splines("Flaca"); }
+	}
+
+	fn on_warning(&self, src: &Path, warning: Warning) {
+		if matches!(warning, Warning::Extension | Warning::LinkBroken | Warning::Renamed) {
+			let _res = self.progress.push_msg(Msg::warning(format!(
+				"{} \x1b[2m({})\x1b[0m",
+				src.to_string_lossy(),
+				warning.as_str(),
+			)));
+		}
+	}
+
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		BEFORE.fetch_add(before, Relaxed);
+		AFTER.fetch_add(after, Relaxed);
+		self.progress.remove(src.to_string_lossy());
+	}
+
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		SKIPPED.fetch_add(1, Relaxed);
+
+		let name = src.to_string_lossy();
+		if ! matches!(err, EncodingError::Skipped) && self.noteworthy(src) {
+			let _res = self.progress.push_msg(Msg::skipped(format!(
+				"{name} \x1b[2m({})\x1b[0m",
+				err.as_str(),
+			)));
+		}
+
+		self.progress.remove(&name);
+	}
+}
+
+impl Pretty<'_> {
+	#[expect(clippy::inline_always, reason = "For performance.")]
+	#[inline(always)]
+	/// # Noteworthy Failure?
+	fn noteworthy(&self, p: &Path) -> bool {
+		self.kinds == ImageKind::ALL ||
+		ImageKind::from_path(p).is_none_or(|k| self.kinds.contains(k))
+	}
+}
+
+
+
 /// # Hide Cursor.
 ///
 /// This helps control the hiding and showing of the cursor during progress
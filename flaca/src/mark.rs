@@ -0,0 +1,109 @@
+/*!
+# Flaca: Optimization Markers
+
+With `--mark`, each image saved in place (or confirmed to have no savings) is
+tagged with a `user.flaca` extended attribute recording the flaca version and
+a hash of its final contents. With `--skip-marked`, images whose tags still
+match — same version, same contents — are skipped without any decoding.
+
+This is a filesystem-native alternative to keeping a separate database of
+what's been done. Filesystems without (user) xattr support simply won't be
+tagged.
+*/
+
+use crate::output::fnv1a;
+use std::{
+	ffi::{
+		CStr,
+		CString,
+	},
+	os::unix::ffi::OsStrExt,
+	path::Path,
+	sync::atomic::AtomicBool,
+};
+
+
+
+/// # Attribute Name.
+const NAME: &CStr = c"user.flaca";
+
+/// # Mark Images?
+pub(crate) static MARK: AtomicBool = AtomicBool::new(false);
+
+/// # Skip Marked Images?
+pub(crate) static SKIP_MARKED: AtomicBool = AtomicBool::new(false);
+
+
+
+/// # Is Marked?
+///
+/// Returns `true` if the file at `path` has a marker matching the current
+/// version and `raw` contents.
+pub(crate) fn is_marked(path: &Path, raw: &[u8]) -> bool {
+	get(path).is_some_and(|v| v == value(raw))
+}
+
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Mark.
+///
+/// Tag the file at `path` — whose contents are `raw` — returning `true` if
+/// successful.
+pub(crate) fn mark(path: &Path, raw: &[u8]) -> bool {
+	let Ok(path) = CString::new(path.as_os_str().as_bytes()) else { return false; };
+	let value = value(raw);
+
+	// Safety: the pointers and lengths are all valid.
+	0 == unsafe {
+		libc::setxattr(
+			path.as_ptr(),
+			NAME.as_ptr(),
+			value.as_ptr().cast(),
+			value.len(),
+			0,
+		)
+	}
+}
+
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Get Marker.
+fn get(path: &Path) -> Option<String> {
+	let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+	let mut buf = [0_u8; 64];
+
+	// Safety: the pointers and lengths are all valid.
+	let len = unsafe {
+		libc::getxattr(path.as_ptr(), NAME.as_ptr(), buf.as_mut_ptr().cast(), buf.len())
+	};
+	let len = usize::try_from(len).ok()?;
+	String::from_utf8(buf.get(..len)?.to_vec()).ok()
+}
+
+/// # Marker Value.
+///
+/// The version and content hash, separated by a space.
+fn value(raw: &[u8]) -> String {
+	format!("{} {:016x}", env!("CARGO_PKG_VERSION"), fnv1a(raw))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_mark() {
+		let path = std::env::temp_dir().join(format!("flaca-mark-{}.png", std::process::id()));
+		std::fs::write(&path, b"hello").expect("Write failed.");
+
+		// Some filesystems don't do xattrs.
+		assert!(! is_marked(&path, b"hello"));
+		if mark(&path, b"hello") {
+			assert!(is_marked(&path, b"hello"));
+			assert!(! is_marked(&path, b"world"), "Contents should matter.");
+			assert!(get(&path).is_some_and(|v| v.starts_with(env!("CARGO_PKG_VERSION"))));
+		}
+
+		let _res = std::fs::remove_file(&path);
+	}
+}
@@ -0,0 +1,105 @@
+/*!
+# Flaca: Memory Usage
+*/
+
+use crate::{
+	Observer,
+	Stage,
+};
+use dactyl::NiceU64;
+use fyi_msg::Msg;
+use std::{
+	path::Path,
+	sync::atomic::{
+		AtomicU64,
+		Ordering::Relaxed,
+	},
+};
+
+
+
+#[derive(Debug, Default)]
+/// # Memory Usage.
+///
+/// This observer samples the process's resident set size (in KiB) after each
+/// encoder pass, keeping track of the highest value seen for each stage.
+///
+/// Images are crunched in parallel, so the numbers reflect the whole process
+/// rather than any one image, but that's what matters for capacity planning
+/// anyway.
+///
+/// Note: sampling requires `/proc`; on other platforms nothing is recorded.
+pub(crate) struct MemoryUsage([AtomicU64; Stage::ALL.len()]);
+
+impl Observer for MemoryUsage {
+	fn on_stage_complete(&self, _src: &Path, stage: Stage, _size: u64) {
+		if let Some(rss) = sample("VmRSS:") {
+			self.0[stage as usize].fetch_max(rss, Relaxed);
+		}
+	}
+}
+
+impl MemoryUsage {
+	/// # Summarize.
+	///
+	/// Print the peak RSS observed for each stage that actually ran, followed
+	/// by the overall high-water mark for the process.
+	pub(crate) fn summarize(self) {
+		for stage in Stage::ALL {
+			let kib = self.0[stage as usize].load(Relaxed);
+			if kib != 0 {
+				Msg::info(format!(
+					"{}: {} KiB peak RSS.",
+					stage.as_str(),
+					NiceU64::from(kib),
+				)).eprint();
+			}
+		}
+
+		if let Some(kib) = sample("VmHWM:") {
+			Msg::info(format!(
+				"Overall: {} KiB peak RSS.",
+				NiceU64::from(kib),
+			)).eprint();
+		}
+	}
+}
+
+
+
+/// # Sample.
+///
+/// Return the value (in KiB) of the named field from `/proc/self/status`.
+fn sample(key: &str) -> Option<u64> {
+	let raw = std::fs::read_to_string("/proc/self/status").ok()?;
+	parse_status(&raw, key)
+}
+
+/// # Parse Status.
+///
+/// Find the named field in the `/proc/self/status`-style text and return its
+/// value, e.g. `1234` for `VmRSS:    1234 kB`.
+fn parse_status(raw: &str, key: &str) -> Option<u64> {
+	use dactyl::traits::BytesToUnsigned;
+
+	raw.lines()
+		.find_map(|line| line.strip_prefix(key))
+		.and_then(|v| v.trim().strip_suffix("kB"))
+		.and_then(|v| u64::btou(v.trim().as_bytes()))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_parse_status() {
+		let raw = "Name:\tflaca\nVmHWM:\t  204800 kB\nVmRSS:\t   10240 kB\nThreads:\t8\n";
+		assert_eq!(parse_status(raw, "VmHWM:"), Some(204_800));
+		assert_eq!(parse_status(raw, "VmRSS:"), Some(10_240));
+		assert_eq!(parse_status(raw, "VmSwap:"), None);
+		assert_eq!(parse_status(raw, "Threads:"), None);
+	}
+}
@@ -0,0 +1,236 @@
+/*!
+# Flaca: Metrics File
+*/
+
+use crate::{
+	EncodingError,
+	FlacaError,
+	Observer,
+};
+use std::{
+	cell::Cell,
+	fmt::Write as _,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::atomic::{
+		AtomicU64,
+		Ordering::Relaxed,
+	},
+	time::{
+		Duration,
+		Instant,
+		SystemTime,
+	},
+};
+
+
+
+thread_local!(
+	/// # Start Time.
+	static START: Cell<Option<Instant>> = const { Cell::new(None) };
+);
+
+
+
+#[derive(Debug)]
+/// # Metrics.
+///
+/// This observer tallies up the images processed — by outcome — along with
+/// the bytes before and after and the time spent, and writes the totals to
+/// a file in the Prometheus text exposition format at the end of the run,
+/// suitable for `node_exporter`'s textfile collector.
+pub(crate) struct Metrics {
+	/// # Destination.
+	path: PathBuf,
+
+	/// # Run Start.
+	started: Instant,
+
+	/// # Optimized.
+	optimized: AtomicU64,
+
+	/// # Unchanged.
+	unchanged: AtomicU64,
+
+	/// # Skipped.
+	skipped: AtomicU64,
+
+	/// # Failed.
+	failed: AtomicU64,
+
+	/// # Bytes Before.
+	before: AtomicU64,
+
+	/// # Bytes After.
+	after: AtomicU64,
+
+	/// # Processing Time (Microseconds).
+	busy: AtomicU64,
+}
+
+impl Observer for Metrics {
+	fn on_start(&self, _src: &Path) { START.set(Some(Instant::now())); }
+
+	fn on_finish(&self, _src: &Path, before: u64, after: u64) {
+		if after < before { self.optimized.fetch_add(1, Relaxed); }
+		else { self.unchanged.fetch_add(1, Relaxed); }
+		self.before.fetch_add(before, Relaxed);
+		self.after.fetch_add(after, Relaxed);
+		self.stop();
+	}
+
+	fn on_error(&self, _src: &Path, err: EncodingError) {
+		if err.is_failure() { self.failed.fetch_add(1, Relaxed); }
+		else { self.skipped.fetch_add(1, Relaxed); }
+		self.stop();
+	}
+}
+
+impl Metrics {
+	/// # New.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the path is empty or a directory.
+	pub(crate) fn new(path: &str) -> Result<Self, FlacaError> {
+		let path = path.trim();
+		if path.is_empty() { return Err(FlacaError::Metrics); }
+		let path = PathBuf::from(path);
+		if path.is_dir() { return Err(FlacaError::Metrics); }
+
+		Ok(Self {
+			path,
+			started: Instant::now(),
+			optimized: AtomicU64::new(0),
+			unchanged: AtomicU64::new(0),
+			skipped: AtomicU64::new(0),
+			failed: AtomicU64::new(0),
+			before: AtomicU64::new(0),
+			after: AtomicU64::new(0),
+			busy: AtomicU64::new(0),
+		})
+	}
+
+	/// # Save.
+	///
+	/// Write the totals to a temporary file alongside the destination, then
+	/// move it into place so scrapers never see a partial file.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be written.
+	pub(crate) fn save(self) -> Result<(), FlacaError> {
+		let now = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.map_or(0, |d| d.as_secs());
+		let out = self.render(self.started.elapsed().as_secs_f64(), now);
+
+		let mut tmp = self.path.as_os_str().to_owned();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		std::fs::write(&tmp, out)
+			.and_then(|()| std::fs::rename(&tmp, &self.path))
+			.map_err(|_| {
+				let _res = std::fs::remove_file(&tmp);
+				FlacaError::Metrics
+			})
+	}
+
+	/// # Stop the Clock.
+	fn stop(&self) {
+		if let Some(start) = START.take() {
+			let micros = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX);
+			self.busy.fetch_add(micros, Relaxed);
+		}
+	}
+
+	/// # Render.
+	fn render(&self, elapsed: f64, now: u64) -> String {
+		let before = self.before.load(Relaxed);
+		let after = self.after.load(Relaxed);
+		let mut out = String::with_capacity(1024);
+
+		out.push_str("# HELP flaca_images_total Images processed, by outcome.\n# TYPE flaca_images_total counter\n");
+		for (status, v) in [
+			("optimized", &self.optimized),
+			("unchanged", &self.unchanged),
+			("skipped", &self.skipped),
+			("failed", &self.failed),
+		] {
+			let _res = writeln!(out, "flaca_images_total{{status=\"{status}\"}} {}", v.load(Relaxed));
+		}
+
+		for (name, help, v) in [
+			("bytes_before", "Total size of the images before processing.", before),
+			("bytes_after", "Total size of the images after processing.", after),
+			("bytes_saved", "Total bytes saved.", before.saturating_sub(after)),
+		] {
+			let _res = writeln!(
+				out,
+				"# HELP flaca_{name}_total {help}\n# TYPE flaca_{name}_total counter\nflaca_{name}_total {v}",
+			);
+		}
+
+		let busy = Duration::from_micros(self.busy.load(Relaxed)).as_secs_f64();
+		let _res = writeln!(
+			out,
+			"# HELP flaca_image_duration_seconds_total Time spent processing images, summed across threads.
+# TYPE flaca_image_duration_seconds_total counter
+flaca_image_duration_seconds_total {busy:.3}
+# HELP flaca_run_duration_seconds Wall-clock duration of the run.
+# TYPE flaca_run_duration_seconds gauge
+flaca_run_duration_seconds {elapsed:.3}
+# HELP flaca_last_run_timestamp_seconds When the run finished.
+# TYPE flaca_last_run_timestamp_seconds gauge
+flaca_last_run_timestamp_seconds {now}",
+		);
+
+		out
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_render() {
+		let metrics = Metrics::new("/tmp/flaca.prom").expect("Metrics failed.");
+		let src = Path::new("foo.png");
+		metrics.on_finish(src, 100, 80);
+		metrics.on_finish(src, 50, 50);
+		metrics.on_error(src, EncodingError::Skipped);
+		metrics.on_error(src, EncodingError::Format);
+		metrics.on_error(src, EncodingError::Format);
+
+		let out = metrics.render(1.5, 1_700_000_000);
+		for line in [
+			"flaca_images_total{status=\"optimized\"} 1",
+			"flaca_images_total{status=\"unchanged\"} 1",
+			"flaca_images_total{status=\"skipped\"} 1",
+			"flaca_images_total{status=\"failed\"} 2",
+			"flaca_bytes_before_total 150",
+			"flaca_bytes_after_total 130",
+			"flaca_bytes_saved_total 20",
+			"flaca_run_duration_seconds 1.500",
+			"flaca_last_run_timestamp_seconds 1700000000",
+		] {
+			assert!(out.lines().any(|l| l == line), "Missing {line}:\n{out}");
+		}
+
+		// Every metric should have HELP and TYPE lines.
+		assert_eq!(
+			out.lines().filter(|l| l.starts_with("# HELP ")).count(),
+			out.lines().filter(|l| l.starts_with("# TYPE ")).count(),
+		);
+		assert!(out.ends_with('\n'));
+
+		// Empty paths and directories are no good.
+		assert!(Metrics::new(" ").is_err());
+		assert!(Metrics::new("/tmp").is_err());
+	}
+}
@@ -0,0 +1,162 @@
+/*!
+# Flaca: Memory-Mapped Input
+
+Huge images — 100 MB scans, say — are expensive to read into memory, since
+the encoders need a working copy of their own on top of the original. With
+`--mmap-threshold`, files at least that big are mapped instead, leaving the
+kernel to page the original in (and out) as needed.
+
+Note: as with any memory map, if another process truncates a file while it
+is mapped, reads past the new end will crash the program. The `flock` and
+mid-flight modification checks keep cooperative writers at bay, but can't
+stop the reckless ones.
+
+Hard-linked files that will be rewritten in place — `--preserve-links
+rewrite` — are always read instead, since flaca itself would be the one
+doing the truncating.
+*/
+
+use std::{
+	fs::File,
+	io::{
+		Error,
+		Result,
+	},
+	ops::Deref,
+	os::fd::AsRawFd,
+	ptr::NonNull,
+	sync::atomic::AtomicU64,
+};
+
+
+
+/// # Mapping Threshold.
+///
+/// Files at least this many bytes are memory-mapped rather than read. Zero
+/// disables mapping.
+pub(crate) static MMAP_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+
+
+#[derive(Debug)]
+/// # Image Input.
+///
+/// The raw file contents, either read onto the heap or mapped.
+pub(crate) enum Input {
+	/// # Read.
+	Owned(Vec<u8>),
+
+	/// # Mapped.
+	Mapped(Mmap),
+}
+
+impl Deref for Input {
+	type Target = [u8];
+
+	#[inline]
+	fn deref(&self) -> &[u8] {
+		match self {
+			Self::Owned(v) => v.as_slice(),
+			Self::Mapped(m) => m,
+		}
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # Memory Map.
+///
+/// A read-only, private mapping of an entire (non-empty) file, unmapped on
+/// drop.
+pub(crate) struct Mmap {
+	/// # Start.
+	ptr: NonNull<u8>,
+
+	/// # Length.
+	len: usize,
+}
+
+#[expect(unsafe_code, reason = "The mapping is read-only.")]
+// Safety: the mapping is never written to, so can be shared freely.
+unsafe impl Send for Mmap {}
+
+#[expect(unsafe_code, reason = "The mapping is read-only.")]
+// Safety: the mapping is never written to, so can be shared freely.
+unsafe impl Sync for Mmap {}
+
+impl Deref for Mmap {
+	type Target = [u8];
+
+	#[expect(unsafe_code, reason = "For FFI.")]
+	#[inline]
+	fn deref(&self) -> &[u8] {
+		// Safety: the pointer is valid for len bytes until drop.
+		unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+	}
+}
+
+impl Drop for Mmap {
+	#[expect(unsafe_code, reason = "For FFI.")]
+	fn drop(&mut self) {
+		// Safety: this is the mapping we made.
+		unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len); }
+	}
+}
+
+impl Mmap {
+	#[expect(unsafe_code, reason = "For FFI.")]
+	/// # New.
+	///
+	/// Map the first `len` bytes of `file`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if `len` is zero or the mapping fails.
+	pub(crate) fn new(file: &File, len: u64) -> Result<Self> {
+		let len = usize::try_from(len).ok()
+			.filter(|&n| n != 0)
+			.ok_or_else(|| Error::other("unmappable length"))?;
+
+		// Safety: a fresh read-only mapping of a valid descriptor.
+		let ptr = unsafe {
+			libc::mmap(
+				std::ptr::null_mut(),
+				len,
+				libc::PROT_READ,
+				libc::MAP_PRIVATE,
+				file.as_raw_fd(),
+				0,
+			)
+		};
+		if ptr == libc::MAP_FAILED { return Err(Error::last_os_error()); }
+
+		let ptr = NonNull::new(ptr.cast()).ok_or_else(|| Error::other("null mapping"))?;
+		Ok(Self { ptr, len })
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_mmap() {
+		let path = "../skel/assets/png/01.png";
+		let expected = std::fs::read(path).expect("Read failed.");
+
+		let file = File::open(path).expect("Open failed.");
+		let map = Mmap::new(&file, expected.len() as u64).expect("Map failed.");
+		drop(file);
+		assert_eq!(&*map, expected.as_slice());
+
+		let input = Input::Mapped(map);
+		assert_eq!(&*input, expected.as_slice());
+
+		// Empty files can't be mapped.
+		let file = File::open(path).expect("Open failed.");
+		assert!(Mmap::new(&file, 0).is_err());
+	}
+}
@@ -0,0 +1,96 @@
+/*!
+# Flaca: NDJSON Output
+*/
+
+use crate::{
+	daemon::json,
+	EncodingError,
+	Observer,
+};
+use std::{
+	io::Write,
+	path::Path,
+};
+
+
+
+#[derive(Debug, Default)]
+/// # NDJSON.
+///
+/// This observer prints a JSON object to STDOUT for each image as soon as it
+/// finishes, so orchestration tools can tail the run and react — purging a
+/// CDN, say — in real time.
+///
+/// Each line has the same shape as a `--daemon` reply: the `path`, a `status`
+/// of `ok`, `skipped`, or `error`, and either the `before`/`after` sizes or
+/// an `error` message.
+pub(crate) struct Ndjson;
+
+impl Observer for Ndjson {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		print_line(&line(src, Ok((before, after))));
+	}
+
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		print_line(&line(src, Err(err)));
+	}
+}
+
+
+
+/// # Line.
+///
+/// Build the JSON line for a finished image.
+fn line(src: &Path, res: Result<(u64, u64), EncodingError>) -> String {
+	let mut out = String::from("{\"path\":");
+	json::push_str(&mut out, &src.to_string_lossy());
+	match res {
+		Ok((before, after)) => {
+			out.push_str(",\"status\":\"ok\",\"before\":");
+			out.push_str(&before.to_string());
+			out.push_str(",\"after\":");
+			out.push_str(&after.to_string());
+		},
+		Err(e) => {
+			out.push_str(if e.is_failure() { ",\"status\":\"error\",\"error\":" } else { ",\"status\":\"skipped\",\"error\":" });
+			json::push_str(&mut out, if e.as_str().is_empty() { "skipped" } else { e.as_str() });
+		},
+	}
+	out.push_str("}\n");
+	out
+}
+
+/// # Print Line.
+///
+/// Write the line in one go — so lines from different threads never
+/// interleave — and flush it right away. Write errors (e.g. a closed pipe)
+/// are ignored.
+fn print_line(line: &str) {
+	let mut stdout = std::io::stdout().lock();
+	let _res = stdout.write_all(line.as_bytes()).and_then(|()| stdout.flush());
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_line() {
+		assert_eq!(
+			line(Path::new("/tmp/a \"b\".png"), Ok((100, 80))),
+			"{\"path\":\"/tmp/a \\\"b\\\".png\",\"status\":\"ok\",\"before\":100,\"after\":80}\n",
+		);
+		assert_eq!(
+			line(Path::new("/tmp/a.png"), Err(EncodingError::Skipped)),
+			"{\"path\":\"/tmp/a.png\",\"status\":\"skipped\",\"error\":\"skipped\"}\n",
+		);
+
+		let err = EncodingError::Format;
+		assert_eq!(
+			line(Path::new("/tmp/a.png"), Err(err)),
+			format!("{{\"path\":\"/tmp/a.png\",\"status\":\"error\",\"error\":\"{}\"}}\n", err.as_str()),
+		);
+	}
+}
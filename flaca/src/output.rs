@@ -0,0 +1,691 @@
+/*!
+# Flaca: Output Paths
+
+By default, images are re-compressed in place, but they can alternatively be
+saved to a separate (mirrored) directory tree, and/or under a different file
+name. Everything funnels through `target` so all modes resolve paths the same
+way.
+
+Each mode is a `Sink`, deciding where (and whether) an image is saved and
+how. The rest of the save step — verification, rollback, etc. — is shared.
+
+In-place rewrites keep the original owner, group, and mode automatically; the
+copies can be made to match with `--preserve-perms`.
+
+In-place rewrites replace the original file, so any other hard links to it
+keep pointing at the old version. `--preserve-links` can instead skip such
+images, or overwrite them through the existing inode so every link sees the
+change.
+
+Originals about to be overwritten can also be backed up first, either
+alongside themselves (with a suffix) or to a separate (mirrored) directory
+tree.
+
+Dry runs save nothing at all, except — with `--keep-candidates` — copies of
+the images that would have been rewritten, mirrored into a side directory for
+review.
+
+Backups and unchanged copies are byte-for-byte duplicates of files already on
+disk, so they all go through `fault::copy`, which — unless `--reflink never` —
+copies them file-to-file rather than writing them from memory, letting
+copy-on-write filesystems share the extents. (Improved images are new bytes,
+with nothing to share.)
+*/
+
+use crate::{
+	fault,
+	FlacaError,
+	ImageKind,
+};
+use std::{
+	ffi::{
+		OsStr,
+		OsString,
+	},
+	fmt::Write,
+	num::NonZeroU32,
+	os::unix::fs::MetadataExt,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::{
+		atomic::{
+			AtomicBool,
+			AtomicU8,
+			Ordering::Relaxed,
+		},
+		OnceLock,
+	},
+};
+
+
+
+/// # Output Directory.
+///
+/// When set, (re)compressed images are written to this directory tree instead
+/// of overwriting the originals.
+static OUT_DIR: OnceLock<OutDir> = OnceLock::new();
+
+/// # Backup Location.
+///
+/// When set, originals are copied here before being overwritten.
+static BACKUP: OnceLock<Backup> = OnceLock::new();
+
+/// # Candidate Directory.
+///
+/// When set during a dry run, the images that would have been rewritten are
+/// saved to this directory tree instead.
+static CANDIDATES: OnceLock<OutDir> = OnceLock::new();
+
+/// # Dry Run?
+///
+/// When set, the originals are left alone, and nothing is saved anywhere
+/// except (maybe) the `CANDIDATES`.
+pub(crate) static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// # Name Template.
+///
+/// When set, images are saved under this (rendered) file name.
+static NAME_TEMPLATE: OnceLock<NameTemplate> = OnceLock::new();
+
+/// # Preserve Permissions?
+///
+/// When set, copies are given the same owner, group, and mode as their
+/// originals.
+pub(crate) static PRESERVE_PERMS: AtomicBool = AtomicBool::new(false);
+
+/// # Reflink?
+///
+/// When set — the default — duplicates of files already on disk are copied
+/// rather than written, so copy-on-write filesystems can share the extents.
+pub(crate) static REFLINK: AtomicBool = AtomicBool::new(true);
+
+/// # Hard Link Handling.
+///
+/// See `HardLinks`.
+static HARD_LINKS: AtomicU8 = AtomicU8::new(HardLinks::Break as u8);
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+/// # Hard Link Handling.
+///
+/// This determines what happens to (in-place) images with more than one
+/// hard link.
+pub(crate) enum HardLinks {
+	/// # Break the Links.
+	///
+	/// Replace the file like any other, leaving the other links pointing at
+	/// the original. This is the default.
+	Break = 0,
+
+	/// # Skip.
+	///
+	/// Leave the image alone.
+	Skip = 1,
+
+	/// # Rewrite Through the Links.
+	///
+	/// Overwrite the existing inode so every link sees the new version. This
+	/// isn't atomic, but it does keep deduplicated stores deduplicated.
+	Rewrite = 2,
+}
+
+impl HardLinks {
+	/// # Set.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value isn't break, skip, or rewrite.
+	pub(crate) fn set(raw: &str) -> Result<(), FlacaError> {
+		let mode = match raw.trim() {
+			"break" => Self::Break,
+			"skip" => Self::Skip,
+			"rewrite" => Self::Rewrite,
+			_ => return Err(FlacaError::PreserveLinks),
+		};
+		HARD_LINKS.store(mode as u8, Relaxed);
+		Ok(())
+	}
+
+	/// # Get.
+	pub(crate) fn get() -> Self {
+		match HARD_LINKS.load(Relaxed) {
+			1 => Self::Skip,
+			2 => Self::Rewrite,
+			_ => Self::Break,
+		}
+	}
+}
+
+
+
+/// # Output Sink.
+///
+/// This is the trait implemented by each of the output modes.
+pub(crate) trait Sink {
+	/// # Destination.
+	///
+	/// Return the path the image at `src` — with the final contents `raw` —
+	/// should be saved to, or `None` if there's nothing to save. `changed`
+	/// indicates whether or not `raw` differs from the original.
+	fn destination(&self, src: &Path, raw: &[u8], changed: bool) -> Option<PathBuf>;
+
+	/// # Write.
+	///
+	/// Save `raw` to `dst`, atomically, along with any relevant metadata.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the write fails.
+	fn write(&self, src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()>;
+
+	/// # Write Unchanged.
+	///
+	/// Same as `Sink::write`, except `raw` is known to match the original
+	/// at `src`. By default, this just calls `Sink::write`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the write fails.
+	fn write_unchanged(&self, src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()> {
+		self.write(src, dst, raw)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+/// # In-Place Sink.
+///
+/// This is the default mode: originals are overwritten, but only if they've
+/// changed. (The atomic writer takes care of the owner, group, and mode.)
+///
+/// Hard-linked originals are overwritten through the existing inode instead
+/// when `--preserve-links rewrite` is set.
+pub(crate) struct InPlace;
+
+impl Sink for InPlace {
+	fn destination(&self, src: &Path, _raw: &[u8], changed: bool) -> Option<PathBuf> {
+		if changed { Some(src.to_path_buf()) }
+		else { None }
+	}
+
+	fn write(&self, _src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()> {
+		if HardLinks::get() == HardLinks::Rewrite && 1 < link_count(dst) {
+			fault::overwrite(dst, raw)
+		}
+		else { fault::write(dst, raw) }
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+/// # Copy Sink.
+///
+/// Images are saved to the output directory and/or under the templated name,
+/// whether or not they've changed. (If that works out to the original path,
+/// it's treated as in-place instead.)
+pub(crate) struct Copies;
+
+impl Sink for Copies {
+	fn destination(&self, src: &Path, raw: &[u8], changed: bool) -> Option<PathBuf> {
+		target(src, raw).or_else(|| InPlace.destination(src, raw, changed))
+	}
+
+	fn write(&self, src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()> {
+		if dst == src { return InPlace.write(src, dst, raw); }
+		fault::write(dst, raw)?;
+		copy_perms(src, dst)
+	}
+
+	fn write_unchanged(&self, src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()> {
+		if dst == src { return self.write(src, dst, raw); }
+		fault::copy(src, dst, raw)?;
+		copy_perms(src, dst)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+/// # Dry-Run Sink.
+///
+/// Nothing is overwritten, but images that would have been are saved to the
+/// candidate directory, if any, so the results can be reviewed beforehand.
+pub(crate) struct DryRun;
+
+impl Sink for DryRun {
+	fn destination(&self, src: &Path, _raw: &[u8], changed: bool) -> Option<PathBuf> {
+		if changed { CANDIDATES.get()?.target(src) }
+		else { None }
+	}
+
+	fn write(&self, src: &Path, dst: &Path, raw: &[u8]) -> std::io::Result<()> {
+		fault::write(dst, raw)?;
+		copy_perms(src, dst)
+	}
+}
+
+
+
+/// # Output Enabled?
+///
+/// Returns `true` if the originals are to be left alone, either because the
+/// images are being saved to an output directory or under a templated name
+/// (or both), or because it's a dry run.
+pub(crate) fn enabled() -> bool {
+	OUT_DIR.get().is_some() ||
+	NAME_TEMPLATE.get().is_some() ||
+	DRY_RUN.load(Relaxed)
+}
+
+/// # Link Count.
+///
+/// Return the number of hard links to the file at `path`, or one if it can't
+/// be determined.
+pub(crate) fn link_count(path: &Path) -> u64 {
+	std::fs::metadata(path).map_or(1, |m| m.nlink())
+}
+
+/// # Sink.
+///
+/// Return the sink for the current output mode.
+pub(crate) fn sink() -> &'static dyn Sink {
+	if DRY_RUN.load(Relaxed) { &DryRun }
+	else if enabled() { &Copies }
+	else { &InPlace }
+}
+
+/// # Back Up Original.
+///
+/// If enabled, save a copy of the `original` image at `src` before it gets
+/// overwritten. Existing backups are left as-are so the pristine original
+/// survives repeat runs.
+///
+/// See `fault::copy` for how `--reflink` affects this.
+///
+/// ## Errors
+///
+/// An error is returned if the backup cannot be written.
+pub(crate) fn backup(src: &Path, original: &[u8]) -> std::io::Result<()> {
+	let Some(backup) = BACKUP.get() else { return Ok(()); };
+	let dst = backup.target(src).ok_or(std::io::ErrorKind::InvalidInput)?;
+	if dst.exists() { return Ok(()); }
+
+	fault::copy(src, &dst, original)?;
+	std::fs::set_permissions(&dst, std::fs::metadata(src)?.permissions())
+}
+
+/// # Destination Path.
+///
+/// Return the output path for the image at `src` with the (final) contents
+/// `raw`, or `None` if it should be overwritten in place.
+fn target(src: &Path, raw: &[u8]) -> Option<PathBuf> {
+	let dst = match (OUT_DIR.get(), NAME_TEMPLATE.get()) {
+		(None, None) => return None,
+		(Some(out), None) => out.target(src)?,
+		(out, Some(tpl)) => {
+			let name = tpl.render(src, raw)?;
+			let dir = match out {
+				Some(out) => out.target(src.parent()?)?,
+				None => src.parent()?.to_path_buf(),
+			};
+			dir.join(name)
+		},
+	};
+
+	if dst == src { None }
+	else { Some(dst) }
+}
+
+/// # Copy Permissions.
+///
+/// If enabled, give the copy at `dst` the same mode, owner, and group as the
+/// original at `src`.
+///
+/// Changing the owner generally requires root, so failures there are
+/// ignored; the mode is still synced regardless.
+///
+/// ## Errors
+///
+/// An error is returned if the original's metadata can't be read or the
+/// mode can't be changed.
+fn copy_perms(src: &Path, dst: &Path) -> std::io::Result<()> {
+	if ! PRESERVE_PERMS.load(Relaxed) { return Ok(()); }
+
+	let meta = std::fs::metadata(src)?;
+	let _res = std::os::unix::fs::chown(dst, Some(meta.uid()), Some(meta.gid()));
+	std::fs::set_permissions(dst, meta.permissions())
+}
+
+
+
+#[derive(Debug)]
+/// # Backup Location.
+pub(crate) enum Backup {
+	/// # Sibling With Suffix.
+	Suffix(OsString),
+
+	/// # Mirrored Directory.
+	Dir(OutDir),
+}
+
+impl Backup {
+	/// # Default Suffix.
+	const SUFFIX: &str = ".bak";
+
+	/// # Initialize.
+	///
+	/// Parse the location — a suffix, or if it contains a slash, a directory
+	/// — and save it for later reference. If a directory, any of its
+	/// contents are removed from the list of `paths`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the directory cannot be created or is
+	/// otherwise invalid, or the backups have already been configured.
+	pub(crate) fn init(raw: &str, paths: &mut Vec<PathBuf>) -> Result<(), FlacaError> {
+		let raw = raw.trim();
+		let backup =
+			if raw.is_empty() { Self::Suffix(OsString::from(Self::SUFFIX)) }
+			else if raw.contains('/') { Self::Dir(OutDir::new(raw, paths, FlacaError::Backup)?) }
+			else { Self::Suffix(OsString::from(raw)) };
+
+		BACKUP.set(backup).map_err(|_| FlacaError::Backup)
+	}
+
+	/// # Backup Path.
+	///
+	/// Return the backup path corresponding to `src`.
+	fn target(&self, src: &Path) -> Option<PathBuf> {
+		match self {
+			Self::Suffix(suffix) => {
+				let mut dst = src.as_os_str().to_owned();
+				dst.push(suffix);
+				Some(PathBuf::from(dst))
+			},
+			Self::Dir(dir) => dir.target(src),
+		}
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # Output Directory.
+///
+/// This holds the (canonical) output directory along with the deepest
+/// directory common to all source images, allowing the source structure to
+/// be mirrored relative to the latter.
+pub(crate) struct OutDir {
+	/// # Source Root.
+	src: PathBuf,
+
+	/// # Destination Root.
+	dst: PathBuf,
+}
+
+impl OutDir {
+	/// # Initialize.
+	///
+	/// Create the output directory (if needed), remove any of its contents
+	/// from the list of `paths`, and save the details for later reference.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the directory cannot be created or is
+	/// otherwise invalid, or if no images remain afterward.
+	pub(crate) fn init(dir: &str, paths: &mut Vec<PathBuf>)
+	-> Result<(), FlacaError> {
+		let out = Self::new(dir, paths, FlacaError::OutDir)?;
+		OUT_DIR.set(out).map_err(|_| FlacaError::OutDir)
+	}
+
+	/// # Initialize (Candidates).
+	///
+	/// Same as `OutDir::init`, but for the `--keep-candidates` directory.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the directory cannot be created or is
+	/// otherwise invalid, or if no images remain afterward.
+	pub(crate) fn init_candidates(dir: &str, paths: &mut Vec<PathBuf>)
+	-> Result<(), FlacaError> {
+		let out = Self::new(dir, paths, FlacaError::KeepCandidates)?;
+		CANDIDATES.set(out).map_err(|_| FlacaError::KeepCandidates)
+	}
+
+	/// # New.
+	///
+	/// Create the directory (if needed), and remove any of its contents from
+	/// the list of `paths`.
+	///
+	/// ## Errors
+	///
+	/// If the directory cannot be created or is otherwise invalid, `err` is
+	/// returned. If no images remain afterward, `NoImages` is returned.
+	fn new(dir: &str, paths: &mut Vec<PathBuf>, err: FlacaError)
+	-> Result<Self, FlacaError> {
+		let dir = dir.trim();
+		if dir.is_empty() { return Err(err); }
+
+		// Make sure the directory exists, and canonicalize it.
+		std::fs::create_dir_all(dir).map_err(|_| err)?;
+		let dst = std::fs::canonicalize(dir).map_err(|_| err)?;
+		if ! dst.is_dir() { return Err(err); }
+
+		// Leave previous outputs alone.
+		paths.retain(|p| ! p.starts_with(&dst));
+		let src = common_root(paths).ok_or(FlacaError::NoImages)?;
+
+		Ok(Self { src, dst })
+	}
+
+	/// # Destination Path.
+	///
+	/// Return the mirrored output path corresponding to `src`.
+	fn target(&self, src: &Path) -> Option<PathBuf> {
+		src.strip_prefix(&self.src).ok().map(|rel| self.dst.join(rel))
+	}
+}
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # Name Template.
+///
+/// This is a parsed output file name template like `{stem}.opt.{ext}`.
+pub(crate) struct NameTemplate(Box<[Token]>);
+
+impl NameTemplate {
+	/// # Initialize.
+	///
+	/// Parse and save the template for later reference.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the template is empty, contains path
+	/// separators or unknown tokens, or has already been set.
+	pub(crate) fn init(raw: &str) -> Result<(), FlacaError> {
+		let tpl = Self::parse(raw).ok_or(FlacaError::NameTemplate)?;
+		NAME_TEMPLATE.set(tpl).map_err(|_| FlacaError::NameTemplate)
+	}
+
+	/// # Parse.
+	fn parse(raw: &str) -> Option<Self> {
+		let raw = raw.trim();
+		if raw.is_empty() || raw.contains(['/', '\\']) { return None; }
+
+		let mut out = Vec::new();
+		let mut rest = raw;
+		while let Some(start) = rest.find('{') {
+			if start != 0 { out.push(Token::Literal(rest[..start].to_owned())); }
+			let end = start + rest[start..].find('}')?;
+			out.push(match &rest[start + 1..end] {
+				"ext" => Token::Ext,
+				"hash" => Token::Hash,
+				"height" => Token::Height,
+				"stem" => Token::Stem,
+				"width" => Token::Width,
+				_ => return None,
+			});
+			rest = &rest[end + 1..];
+		}
+		if ! rest.is_empty() {
+			// Stray closing braces are probably typos.
+			if rest.contains('}') { return None; }
+			out.push(Token::Literal(rest.to_owned()));
+		}
+
+		Some(Self(out.into_boxed_slice()))
+	}
+
+	/// # Render.
+	///
+	/// Return the file name for the image at `src` with contents `raw`.
+	fn render(&self, src: &Path, raw: &[u8]) -> Option<String> {
+		let stem = src.file_stem().and_then(OsStr::to_str)?;
+		let ext = src.extension().and_then(OsStr::to_str).unwrap_or_default();
+		let (w, h) = dimensions(raw).map_or((0, 0), |(w, h)| (w.get(), h.get()));
+
+		let mut out = String::new();
+		for token in &self.0 {
+			match token {
+				Token::Ext => { out.push_str(ext); },
+				Token::Hash => { let _res = write!(out, "{:016x}", fnv1a(raw)); },
+				Token::Height => { let _res = write!(out, "{h}"); },
+				Token::Literal(s) => { out.push_str(s); },
+				Token::Stem => { out.push_str(stem); },
+				Token::Width => { let _res = write!(out, "{w}"); },
+			}
+		}
+
+		// Don't let the template produce something silly.
+		if out.is_empty() || out == "." || out == ".." { None }
+		else { Some(out) }
+	}
+}
+
+
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// # Template Token.
+enum Token {
+	/// # File Extension (Without Dot).
+	Ext,
+
+	/// # Content Hash.
+	Hash,
+
+	/// # Image Height.
+	Height,
+
+	/// # Literal Text.
+	Literal(String),
+
+	/// # File Stem.
+	Stem,
+
+	/// # Image Width.
+	Width,
+}
+
+
+
+/// # Dimensions.
+fn dimensions(raw: &[u8]) -> Option<(NonZeroU32, NonZeroU32)> {
+	if ImageKind::is_png(raw) { ImageKind::png_dimensions(raw) }
+	else if ImageKind::is_jpeg(raw) { ImageKind::jpeg_dimensions(raw) }
+	else if ImageKind::is_gif(raw) { ImageKind::gif_dimensions(raw) }
+	else if ImageKind::is_ico(raw) { ImageKind::ico_dimensions(raw) }
+	else { None }
+}
+
+/// # FNV-1a Hash.
+///
+/// This is used for the `{hash}` template token and `--mark` tags. It isn't
+/// cryptographic, but is stable and more than good enough for cache-busting.
+pub(crate) fn fnv1a(raw: &[u8]) -> u64 {
+	raw.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b|
+		(h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+	)
+}
+
+/// # Common Root.
+///
+/// Return the deepest directory shared by all of the (canonical) file paths,
+/// or `None` if the list is empty.
+pub(crate) fn common_root(paths: &[PathBuf]) -> Option<PathBuf> {
+	let mut iter = paths.iter();
+	let mut root = iter.next()?.parent()?.to_path_buf();
+	for p in iter {
+		while ! p.starts_with(&root) {
+			if ! root.pop() { return None; }
+		}
+	}
+
+	Some(root)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_name_template() {
+		let raw = std::fs::read("../skel/assets/png/small.png").expect("Missing small.png.");
+		let src = Path::new("/foo/small.png");
+		for (tpl, expected) in [
+			("{stem}.opt.{ext}", Some("small.opt.png")),
+			("{stem}-{width}x{height}.{ext}", Some("small-32x32.png")),
+			("static.png", Some("static.png")),
+			("{stem}.{nope}", None),
+			("{stem", None),
+			("stem}", None),
+			("../{stem}.{ext}", None),
+			("", None),
+		] {
+			assert_eq!(
+				NameTemplate::parse(tpl).and_then(|t| t.render(src, &raw)).as_deref(),
+				expected,
+				"Wrong answer for {tpl:?}.",
+			);
+		}
+
+		// The hash is a little harder to inline.
+		assert_eq!(
+			NameTemplate::parse("{stem}.{hash}.{ext}").and_then(|t| t.render(src, &raw)),
+			Some(format!("small.{:016x}.png", fnv1a(&raw))),
+		);
+	}
+
+	#[test]
+	fn t_common_root() {
+		assert_eq!(common_root(&[]), None);
+
+		// A single file should resolve to its parent.
+		assert_eq!(
+			common_root(&[PathBuf::from("/foo/bar/baz.png")]),
+			Some(PathBuf::from("/foo/bar")),
+		);
+
+		// Siblings and cousins.
+		assert_eq!(
+			common_root(&[
+				PathBuf::from("/foo/bar/baz.png"),
+				PathBuf::from("/foo/bar/qux/baz.jpg"),
+				PathBuf::from("/foo/barn/baz.png"),
+			]),
+			Some(PathBuf::from("/foo")),
+		);
+
+		// Nothing in common but the root.
+		assert_eq!(
+			common_root(&[
+				PathBuf::from("/foo/bar/baz.png"),
+				PathBuf::from("/bar/baz.png"),
+			]),
+			Some(PathBuf::from("/")),
+		);
+	}
+}
@@ -0,0 +1,208 @@
+/*!
+# Flaca: Per-File Report
+*/
+
+use crate::{
+	EncodingError,
+	FlacaError,
+	ImageKind,
+	Observer,
+	Stage,
+};
+use std::{
+	cell::{
+		Cell,
+		RefCell,
+	},
+	fs::{
+		File,
+		OpenOptions,
+	},
+	io::Write,
+	path::Path,
+	sync::Mutex,
+	time::Instant,
+};
+
+
+
+thread_local!(
+	/// # Start Time.
+	///
+	/// Each image is processed start to finish on a single thread, so this is
+	/// all we need to time it.
+	static START: Cell<Option<Instant>> = const { Cell::new(None) };
+
+	/// # Stage Sizes.
+	///
+	/// The output size of each encoder pass, in order.
+	static STAGES: RefCell<Vec<(Stage, u64)>> = const { RefCell::new(Vec::new()) }
+);
+
+
+
+#[derive(Debug)]
+/// # Report.
+///
+/// This observer appends a CSV row to the report file for each image
+/// processed, recording its path, kind, before and after sizes, status,
+/// processing time (in milliseconds), the encoder that produced the final
+/// bytes, and the number of bytes each encoder shaved off.
+///
+/// Rows are written as they come in, so the audit trail survives even if the
+/// run doesn't.
+pub(crate) struct Report(Mutex<File>);
+
+impl Observer for Report {
+	fn on_start(&self, _src: &Path) {
+		START.set(Some(Instant::now()));
+		STAGES.with_borrow_mut(Vec::clear);
+	}
+
+	fn on_stage_complete(&self, _src: &Path, stage: Stage, size: u64) {
+		STAGES.with_borrow_mut(|v| v.push((stage, size)));
+	}
+
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		let status = if after < before { "optimized" } else { "unchanged" };
+		self.row(src, before, after, status);
+	}
+
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		let status = match err {
+			EncodingError::Skipped => "skipped",
+			e => e.as_str(),
+		};
+		self.row(src, 0, 0, status);
+	}
+}
+
+impl Report {
+	/// # New.
+	///
+	/// Open (or create) the report file for appending, adding a header row
+	/// if it is new or empty.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be opened or written to.
+	pub(crate) fn new(path: &str) -> Result<Self, FlacaError> {
+		let path = path.trim();
+		if path.is_empty() { return Err(FlacaError::Report); }
+
+		let mut file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.map_err(|_| FlacaError::Report)?;
+
+		if file.metadata().map_err(|_| FlacaError::Report)?.len() == 0 {
+			file.write_all(b"path,kind,before,after,status,duration_ms,winner,gif_saved,ico_saved,mozjpeg_saved,oxipng_saved,zopflipng_saved\n")
+				.map_err(|_| FlacaError::Report)?;
+		}
+
+		Ok(Self(Mutex::new(file)))
+	}
+
+	/// # Write Row.
+	fn row(&self, src: &Path, before: u64, after: u64, status: &str) {
+		let elapsed = START.take().map_or(0, |s| s.elapsed().as_millis());
+		let stages = STAGES.with_borrow_mut(std::mem::take);
+		let line = format!(
+			"{},{},{before},{after},{status},{elapsed},{}\n",
+			csv_escape(&src.to_string_lossy()),
+			kind(src),
+			stage_columns(&stages, before, after),
+		);
+
+		if let Ok(mut file) = self.0.lock() {
+			let _res = file.write_all(line.as_bytes());
+		}
+	}
+}
+
+
+
+/// # CSV Escape.
+///
+/// Quote the value if it contains anything CSV-sensitive, doubling any
+/// quotes within.
+fn csv_escape(raw: &str) -> String {
+	if raw.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", raw.replace('"', "\"\""))
+	}
+	else { raw.to_owned() }
+}
+
+/// # Stage Columns.
+///
+/// Return the winning encoder — the last to improve on its predecessors,
+/// provided the savings were kept — followed by the bytes saved by each
+/// encoder, in `Stage::ALL` order. Stages that didn't run are left blank.
+fn stage_columns(stages: &[(Stage, u64)], before: u64, after: u64) -> String {
+	let mut best = before;
+	let mut winner = None;
+	let mut saved = [None; Stage::ALL.len()];
+	for &(stage, size) in stages {
+		let idx = Stage::ALL.iter().position(|s| *s == stage).unwrap_or(0);
+		saved[idx] = Some(best.saturating_sub(size));
+		if size < best {
+			best = size;
+			winner = Some(stage);
+		}
+	}
+
+	// If nothing was saved, nothing won.
+	let mut out = String::new();
+	if after < before {
+		if let Some(winner) = winner { out.push_str(&winner.as_str().to_ascii_lowercase()); }
+	}
+	for s in saved {
+		out.push(',');
+		if let Some(s) = s { out.push_str(&s.to_string()); }
+	}
+	out
+}
+
+/// # Image Kind (by Extension).
+fn kind(src: &Path) -> &'static str {
+	match ImageKind::from_path(src) {
+		Some(ImageKind::GIF) => "gif",
+		Some(ImageKind::ICO) => "ico",
+		Some(ImageKind::JPEG) => "jpeg",
+		Some(ImageKind::PNG) => "png",
+		_ => if crate::image::is_candidate(src) { "archive" } else { "unknown" },
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_csv_escape() {
+		assert_eq!(csv_escape("/foo/bar.png"), "/foo/bar.png");
+		assert_eq!(csv_escape("/foo/a,b.png"), "\"/foo/a,b.png\"");
+		assert_eq!(csv_escape("/foo/\"b\".png"), "\"/foo/\"\"b\"\".png\"");
+	}
+
+	#[test]
+	fn t_stage_columns() {
+		// Zopfli improved on oxipng.
+		let stages = [(Stage::Oxipng, 90), (Stage::Zopflipng, 85)];
+		assert_eq!(stage_columns(&stages, 100, 85), "zopflipng,,,,10,5");
+
+		// Zopfli did worse.
+		let stages = [(Stage::Oxipng, 90), (Stage::Zopflipng, 95)];
+		assert_eq!(stage_columns(&stages, 100, 90), "oxipng,,,,10,0");
+
+		// Savings too small to keep.
+		let stages = [(Stage::Mozjpeg, 99)];
+		assert_eq!(stage_columns(&stages, 100, 100), ",,,1,,");
+
+		// Nothing ran.
+		assert_eq!(stage_columns(&[], 0, 0), ",,,,,");
+	}
+}
@@ -0,0 +1,174 @@
+/*!
+# Flaca: Resume
+*/
+
+use crate::{
+	EncodingError,
+	FlacaError,
+	Observer,
+};
+use std::{
+	collections::HashSet,
+	ffi::OsStr,
+	fs::{
+		File,
+		OpenOptions,
+	},
+	io::Write,
+	os::unix::ffi::OsStrExt,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+#[derive(Debug)]
+/// # Resume State.
+///
+/// This observer records the path of each image processed — one per line —
+/// to a state file, writing each entry straight through as it completes so
+/// nothing is lost if the run is cut short, even forcibly. The same file can
+/// then be passed to the next run to pick up where it left off.
+///
+/// The state file is removed once a run completes.
+pub(crate) struct Resume {
+	/// # State File Path.
+	path: PathBuf,
+
+	/// # Already Done.
+	done: HashSet<PathBuf>,
+
+	/// # Writer.
+	file: Mutex<File>,
+}
+
+impl Observer for Resume {
+	fn on_finish(&self, src: &Path, _before: u64, _after: u64) { self.push(src); }
+
+	fn on_error(&self, src: &Path, err: EncodingError) {
+		// Leave anything that might work next time for next time.
+		if ! matches!(
+			err,
+			EncodingError::Locked | EncodingError::Modified |
+			EncodingError::Read | EncodingError::Vanished |
+			EncodingError::Verify | EncodingError::Write
+		) {
+			self.push(src);
+		}
+	}
+}
+
+impl Resume {
+	/// # New.
+	///
+	/// Load the paths already processed from the state file, if it exists,
+	/// and open it for appending.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the file cannot be read or opened.
+	pub(crate) fn new(path: &str) -> Result<Self, FlacaError> {
+		let path = path.trim();
+		if path.is_empty() { return Err(FlacaError::Resume); }
+		let path = PathBuf::from(path);
+
+		let done = match std::fs::read(&path) {
+			Ok(raw) => raw.split(|b| b'\n'.eq(b))
+				.filter(|line| ! line.is_empty())
+				.map(|line| PathBuf::from(OsStr::from_bytes(line)))
+				.collect(),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+			Err(_) => return Err(FlacaError::Resume),
+		};
+
+		let file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&path)
+			.map_err(|_| FlacaError::Resume)?;
+
+		Ok(Self {
+			path,
+			done,
+			file: Mutex::new(file),
+		})
+	}
+
+	/// # Filter.
+	///
+	/// Remove the paths already processed from `paths`, returning the
+	/// number removed.
+	pub(crate) fn filter(&self, paths: &mut Vec<PathBuf>) -> usize {
+		let before = paths.len();
+		if ! self.done.is_empty() { paths.retain(|p| ! self.done.contains(p)); }
+		before - paths.len()
+	}
+
+	/// # Finish.
+	///
+	/// Close the state file and, if the run was completed, remove it (since
+	/// there's nothing left to resume).
+	pub(crate) fn finish(self, completed: bool) {
+		drop(self.file);
+		if completed { let _res = std::fs::remove_file(&self.path); }
+	}
+
+	/// # Push.
+	fn push(&self, src: &Path) {
+		let raw = src.as_os_str().as_bytes();
+
+		// Line breaks would corrupt the file.
+		if raw.contains(&b'\n') { return; }
+
+		// Write the line in one go so a forced exit can't leave half of it
+		// behind.
+		let mut line = Vec::with_capacity(raw.len() + 1);
+		line.extend_from_slice(raw);
+		line.push(b'\n');
+		if let Ok(mut file) = self.file.lock() { let _res = file.write_all(&line); }
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_resume() {
+		let path = std::env::temp_dir().join(format!("flaca-resume-{}.txt", std::process::id()));
+		let _res = std::fs::remove_file(&path);
+		let path_str = path.to_str().expect("Temp path is not UTF-8.");
+
+		// Record a couple images and quit early.
+		let state = Resume::new(path_str).expect("Unable to open state.");
+		state.on_finish(Path::new("/tmp/a.png"), 10, 5);
+		state.on_error(Path::new("/tmp/b.png"), EncodingError::Format);
+		state.on_error(Path::new("/tmp/c.png"), EncodingError::Write);
+
+		// The entries should hit the disk straight away, in case the run is
+		// killed before it can finish.
+		let raw = std::fs::read(&path).expect("Unable to read state.");
+		assert_eq!(raw, b"/tmp/a.png\n/tmp/b.png\n");
+		state.finish(false);
+
+		// The completed ones should be skipped next time.
+		let state = Resume::new(path_str).expect("Unable to reopen state.");
+		let mut paths = vec![
+			PathBuf::from("/tmp/a.png"),
+			PathBuf::from("/tmp/b.png"),
+			PathBuf::from("/tmp/c.png"),
+			PathBuf::from("/tmp/d.png"),
+		];
+		assert_eq!(state.filter(&mut paths), 2);
+		assert_eq!(paths, [PathBuf::from("/tmp/c.png"), PathBuf::from("/tmp/d.png")]);
+
+		// Finishing should clean up.
+		state.finish(true);
+		assert!(! path.exists(), "State file not removed.");
+	}
+}
@@ -0,0 +1,385 @@
+/*!
+# Flaca: Path Rules
+*/
+
+use crate::image::{
+	OXIPNG_LEVEL,
+	OXIPNG_LEVEL_FAST,
+	OXIPNG_LEVEL_MAX,
+};
+use std::{
+	collections::BTreeSet,
+	ffi::{
+		OsStr,
+		OsString,
+	},
+	num::NonZeroU32,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::{
+		atomic::Ordering::Relaxed,
+		OnceLock,
+		RwLock,
+	},
+};
+
+
+
+/// # Global Profile.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static PROFILE: OnceLock<Profile> = OnceLock::new();
+
+/// # Thumbnail Handling.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static THUMBNAILS: OnceLock<Thumbnails> = OnceLock::new();
+
+/// # Retina Thumbnails.
+///
+/// The `@2x` halves of thumbnail pairs found by `Thumbnails::init`, if
+/// processing with the faster profile. (These can't be identified from their
+/// names alone.)
+static RETINA: RwLock<BTreeSet<PathBuf>> = RwLock::new(BTreeSet::new());
+
+/// # Thumbnail Roots.
+///
+/// The (canonicalized) crawl roots. Only the directories below these are
+/// checked for thumbnail-ish names.
+///
+/// Note: This value is only (possibly) set (once) during initialization; it
+/// won't change after that.
+static ROOTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Thumbnail Handling.
+///
+/// Thumbnails and other automatically-generated derivatives tend to get
+/// regenerated sooner or later, making heavy optimization a waste of time.
+pub(crate) enum Thumbnails {
+	/// # Process With a Faster Profile.
+	Fast,
+
+	/// # Skip Entirely.
+	Skip,
+}
+
+impl Thumbnails {
+	/// # Initialize.
+	///
+	/// Save the thumbnail handling for later reference, or if skipping,
+	/// remove any thumbnails from `paths` now.
+	///
+	/// Retina pairs are matched up from `paths` here, once, rather than
+	/// checked against the file system image by image.
+	///
+	/// The `roots` are the paths the crawl started from; directories above
+	/// them are never treated as thumbnail directories.
+	pub(crate) fn init<P, I>(self, paths: &mut Vec<PathBuf>, roots: I)
+	where P: AsRef<Path>, I: IntoIterator<Item=P> {
+		let roots = ROOTS.get_or_init(|| roots.into_iter()
+			.filter_map(|p| std::fs::canonicalize(p).ok())
+			.collect()
+		);
+		let retina = retina_thumbnails(paths, roots);
+		if matches!(self, Self::Skip) {
+			paths.retain(|p| ! is_thumbnail(p, roots) && ! retina.contains(p));
+		}
+		else {
+			let _res = THUMBNAILS.set(self);
+			if let Ok(mut ptr) = RETINA.write() { ptr.extend(retina); }
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+/// # Encoding Profile.
+///
+/// This determines how much effort is spent compressing a given image.
+pub(crate) enum Profile {
+	#[default]
+	/// # Default.
+	///
+	/// Run every pass with the standard settings.
+	Default,
+
+	/// # Fast.
+	///
+	/// Skip the (slow) zopfli pass for PNGs, and try fewer oxipng filters.
+	Fast,
+
+	/// # Maximum.
+	///
+	/// Crank the zopfli iterations way up.
+	Max,
+}
+
+impl Profile {
+	/// # Maximum Profile Zopfli Iterations.
+	const MAX_ITERATIONS: NonZeroU32 = NonZeroU32::new(500).unwrap();
+
+	/// # Initialize.
+	///
+	/// Save the profile as the global default, and apply its oxipng level
+	/// and zopfli iteration count unless they were explicitly overridden.
+	///
+	/// An explicit oxipng level applies to every image, including those given
+	/// the fast profile on a per-path basis.
+	pub(crate) fn init(self, oxipng_level: Option<u8>) {
+		let _res = PROFILE.set(self);
+		if let Some(level) = oxipng_level {
+			OXIPNG_LEVEL.store(level, Relaxed);
+			OXIPNG_LEVEL_FAST.store(level, Relaxed);
+		}
+		else if matches!(self, Self::Fast) {
+			OXIPNG_LEVEL.store(OXIPNG_LEVEL_FAST.load(Relaxed), Relaxed);
+		}
+		else { OXIPNG_LEVEL.store(OXIPNG_LEVEL_MAX, Relaxed); }
+
+		// This is a no-op if -z was set.
+		if matches!(self, Self::Max) {
+			let _res = flapfli::set_zopfli_iterations(Self::MAX_ITERATIONS);
+		}
+	}
+
+	/// # Profile for Path.
+	///
+	/// Return the encoding profile to use for the given image.
+	pub(crate) fn for_path(path: &Path) -> Self {
+		if
+			matches!(THUMBNAILS.get(), Some(Thumbnails::Fast)) &&
+			(
+				is_thumbnail(path, ROOTS.get().map_or(&[], Vec::as_slice)) ||
+				RETINA.read().is_ok_and(|r| r.contains(path))
+			)
+		{
+			Self::Fast
+		}
+		else { Self::global() }
+	}
+
+	/// # Global Profile.
+	///
+	/// Return the profile set via `--fast`/`--default`/`--max`.
+	pub(crate) fn global() -> Self { PROFILE.get().copied().unwrap_or_default() }
+
+	/// # As Str.
+	pub(crate) const fn as_str(self) -> &'static str {
+		match self {
+			Self::Default => "default",
+			Self::Fast => "fast",
+			Self::Max => "max",
+		}
+	}
+
+	/// # Oxipng Level.
+	///
+	/// Return the oxipng effort level for images with this profile.
+	pub(crate) fn oxipng_level(self) -> u8 {
+		if matches!(self, Self::Fast) { OXIPNG_LEVEL_FAST.load(Relaxed) }
+		else { OXIPNG_LEVEL.load(Relaxed) }
+	}
+
+	/// # Run Zopfli?
+	pub(crate) const fn zopfli(self) -> bool { ! matches!(self, Self::Fast) }
+}
+
+
+
+/// # Is Thumbnail?
+///
+/// Returns `true` if the image lives in a thumbnail directory or has a
+/// WordPress-style `-WxH` size suffix.
+///
+/// Only the directories below the deepest of the `roots` containing the
+/// image are considered, so crawling `/srv/thumbs/site` doesn't turn every
+/// image into a thumbnail. Images outside the roots only have their own
+/// directory checked.
+///
+/// Note: having an `@2x` sibling doesn't count; hand-made 1x assets have
+/// those too. See `retina_thumbnails` for the other half of such pairs.
+fn is_thumbnail(path: &Path, roots: &[PathBuf]) -> bool {
+	// Check the directories.
+	if let Some(dir) = path.parent() {
+		let below = roots.iter()
+			.filter_map(|r| dir.strip_prefix(r).ok())
+			.min_by_key(|d| d.as_os_str().len());
+		let found = below.map_or_else(
+			|| dir.file_name().is_some_and(is_thumbnail_dir),
+			|below| below.components().any(|c| is_thumbnail_dir(c.as_os_str())),
+		);
+		if found { return true; }
+	}
+
+	path.file_stem()
+		.and_then(OsStr::to_str)
+		.is_some_and(has_size_suffix)
+}
+
+/// # Retina Thumbnails.
+///
+/// Return the `@2x` images in `paths` whose 1x partners — also in `paths` —
+/// are thumbnails, e.g. `image-150x150@2x.png` for `image-150x150.png`.
+fn retina_thumbnails(paths: &[PathBuf], roots: &[PathBuf]) -> BTreeSet<PathBuf> {
+	let all: BTreeSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+	paths.iter()
+		.filter(|p| one_x(p).is_some_and(|p| all.contains(p.as_path()) && is_thumbnail(&p, roots)))
+		.cloned()
+		.collect()
+}
+
+/// # 1x Path.
+///
+/// If the image is the `@2x` half of a retina pair, return the path its 1x
+/// partner would have, e.g. `image.png` for `image@2x.png`.
+fn one_x(path: &Path) -> Option<PathBuf> {
+	let stem = path.file_stem()?.to_str()?.strip_suffix("@2x")?;
+	if stem.is_empty() { return None; }
+	let mut name = OsString::from(stem);
+	if let Some(ext) = path.extension() {
+		name.push(".");
+		name.push(ext);
+	}
+	Some(path.with_file_name(name))
+}
+
+/// # Is Thumbnail Directory?
+fn is_thumbnail_dir(name: &OsStr) -> bool {
+	name.to_str().is_some_and(|name| {
+		let name = name.trim_start_matches(['.', '_']);
+		["thumb", "thumbs", "thumbnail", "thumbnails"].iter()
+			.any(|t| name.eq_ignore_ascii_case(t))
+	})
+}
+
+/// # Has Size Suffix?
+///
+/// Returns `true` if the file stem ends with a WordPress-style `-WxH` suffix,
+/// e.g. `image-150x150`.
+fn has_size_suffix(stem: &str) -> bool { original_stem(stem).is_some() }
+
+/// # Original Stem.
+///
+/// If the file stem ends with a WordPress-style `-WxH` suffix, return the
+/// part before it, e.g. `image` for `image-150x150`.
+pub(crate) fn original_stem(stem: &str) -> Option<&str> {
+	let (base, size) = stem.rsplit_once('-')?;
+	let (w, h) = size.split_once('x')?;
+	if
+		! base.is_empty() && ! w.is_empty() && ! h.is_empty() &&
+		w.bytes().all(|b| b.is_ascii_digit()) &&
+		h.bytes().all(|b| b.is_ascii_digit())
+	{
+		Some(base)
+	}
+	else { None }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_has_size_suffix() {
+		for (stem, expected) in [
+			("image-150x150", true),
+			("my-image-1024x768", true),
+			("image-150x", false),
+			("image-x150", false),
+			("image-150y150", false),
+			("image150x150", false),
+			("image", false),
+		] {
+			assert_eq!(has_size_suffix(stem), expected, "Wrong answer for {stem}.");
+		}
+	}
+
+	#[test]
+	fn t_original_stem() {
+		assert_eq!(original_stem("image-150x150"), Some("image"));
+		assert_eq!(original_stem("my-image-1024x768"), Some("my-image"));
+		assert_eq!(original_stem("-150x150"), None);
+		assert_eq!(original_stem("image"), None);
+	}
+
+	#[test]
+	fn t_is_thumbnail() {
+		let roots = [PathBuf::from("/foo")];
+		for (path, expected) in [
+			("/foo/thumbs/image.png", true),
+			("/foo/.thumbnails/image.png", true),
+			("/foo/Thumbnails/bar/image.png", true),
+			("/foo/image-150x150.jpg", true),
+			("/foo/thumbsup/image.png", false),
+			("/foo/image.png", false),
+			("/foo/image@2x.png", false),
+		] {
+			assert_eq!(is_thumbnail(Path::new(path), &roots), expected, "Wrong answer for {path}.");
+		}
+	}
+
+	#[test]
+	fn t_is_thumbnail_roots() {
+		// Directories above the root don't count.
+		let roots = [PathBuf::from("/srv/thumbs/site")];
+		assert!(! is_thumbnail(Path::new("/srv/thumbs/site/image.png"), &roots));
+		assert!(! is_thumbnail(Path::new("/srv/thumbs/site/uploads/image.png"), &roots));
+		assert!(is_thumbnail(Path::new("/srv/thumbs/site/uploads/thumbs/image.png"), &roots));
+
+		// The deepest matching root wins.
+		let roots = [PathBuf::from("/srv"), PathBuf::from("/srv/thumbs/site")];
+		assert!(! is_thumbnail(Path::new("/srv/thumbs/site/image.png"), &roots));
+
+		// Images outside the roots only have their own directory checked.
+		assert!(is_thumbnail(Path::new("/var/thumbs/image.png"), &[]));
+		assert!(! is_thumbnail(Path::new("/var/thumbs/bar/image.png"), &[]));
+	}
+
+	#[test]
+	fn t_oxipng_level() {
+		// Fast images get the lighter pass; everything else gets the full one.
+		assert_eq!(Profile::Fast.oxipng_level(), 2);
+		assert_eq!(Profile::Default.oxipng_level(), OXIPNG_LEVEL_MAX);
+		assert_eq!(Profile::Max.oxipng_level(), OXIPNG_LEVEL_MAX);
+	}
+
+	#[test]
+	fn t_retina_thumbnails() {
+		let paths: Vec<PathBuf> = [
+			"/foo/image.png",
+			"/foo/image@2x.png",
+			"/foo/image-150x150.png",
+			"/foo/image-150x150@2x.png",
+			"/foo/other-150x150@2x.png",
+		].into_iter().map(PathBuf::from).collect();
+
+		// Only the retina copy of the (paired) thumbnail should match.
+		let retina = retina_thumbnails(&paths, &[]);
+		assert_eq!(
+			retina.into_iter().collect::<Vec<_>>(),
+			[PathBuf::from("/foo/image-150x150@2x.png")],
+		);
+
+		// Neither half of the plain pair is a thumbnail.
+		assert!(! is_thumbnail(Path::new("/foo/image.png"), &[]));
+		assert!(! is_thumbnail(Path::new("/foo/image@2x.png"), &[]));
+	}
+
+	#[test]
+	fn t_one_x() {
+		assert_eq!(one_x(Path::new("/foo/image@2x.png")), Some(PathBuf::from("/foo/image.png")));
+		assert_eq!(one_x(Path::new("/foo/image@2x")), Some(PathBuf::from("/foo/image")));
+		assert_eq!(one_x(Path::new("/foo/image.png")), None);
+		assert_eq!(one_x(Path::new("/foo/@2x.png")), None);
+	}
+}
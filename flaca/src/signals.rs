@@ -0,0 +1,236 @@
+/*!
+# Flaca: Signals
+
+Flaca listens for the following signals:
+
+| Signal | Effect |
+| ------ | ------ |
+| `SIGINT`/`SIGTERM` | Stop once the in-progress images are done; a second abandons them, leaving their originals untouched. |
+| `SIGHUP` | Re-read the `-l`/`--list` files once the current batch is done, and crunch anything new. |
+| `SIGUSR1` | Print a snapshot of the run's progress to STDERR. |
+
+The handlers themselves only flip atomic flags; the actual work happens on a
+watcher thread (or for `SIGHUP`, back in `main`).
+*/
+
+use crate::{
+	CancellationToken,
+	EncodingError,
+	Observer,
+};
+use dactyl::NiceU64;
+use fyi_msg::{
+	Msg,
+	Progless,
+};
+use std::{
+	ffi::c_int,
+	path::Path,
+	sync::atomic::{
+		AtomicBool,
+		AtomicU64,
+		Ordering::{
+			Relaxed,
+			SeqCst,
+		},
+	},
+	time::Duration,
+};
+
+
+
+/// # Stop Requests (SIGINT/SIGTERM).
+static STOP: AtomicU64 = AtomicU64::new(0);
+
+/// # Reload Requested (SIGHUP).
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// # Stats Requested (SIGUSR1).
+static STATS: AtomicBool = AtomicBool::new(false);
+
+/// # Poll Interval.
+///
+/// How often the watcher thread checks for new signals.
+const POLL: Duration = Duration::from_millis(50);
+
+/// # Progress Snapshot.
+///
+/// This observer keeps the running totals printed on `SIGUSR1`.
+pub(crate) static SNAPSHOT: Snapshot = Snapshot::new();
+
+
+
+#[derive(Debug)]
+/// # Progress Snapshot.
+pub(crate) struct Snapshot {
+	/// # Total Images.
+	total: AtomicU64,
+
+	/// # Images Done.
+	done: AtomicU64,
+
+	/// # Images Failed.
+	failed: AtomicU64,
+
+	/// # Bytes Before.
+	before: AtomicU64,
+
+	/// # Bytes After.
+	after: AtomicU64,
+}
+
+impl Observer for Snapshot {
+	fn on_finish(&self, _src: &Path, before: u64, after: u64) {
+		self.done.fetch_add(1, Relaxed);
+		self.before.fetch_add(before, Relaxed);
+		self.after.fetch_add(after, Relaxed);
+	}
+
+	fn on_error(&self, _src: &Path, err: EncodingError) {
+		self.done.fetch_add(1, Relaxed);
+		if err.is_failure() { self.failed.fetch_add(1, Relaxed); }
+	}
+}
+
+impl Snapshot {
+	/// # New.
+	const fn new() -> Self {
+		Self {
+			total: AtomicU64::new(0),
+			done: AtomicU64::new(0),
+			failed: AtomicU64::new(0),
+			before: AtomicU64::new(0),
+			after: AtomicU64::new(0),
+		}
+	}
+
+	/// # Add to Total.
+	pub(crate) fn add_total(&self, total: u64) { self.total.fetch_add(total, Relaxed); }
+
+	/// # Message.
+	///
+	/// Return a summary of the progress so far, or `None` if there hasn't
+	/// been any.
+	fn msg(&self) -> Option<Msg> {
+		let total = self.total.load(Relaxed);
+		if total == 0 { return None; }
+
+		let done = self.done.load(Relaxed);
+		let failed = self.failed.load(Relaxed);
+		let saved = self.before.load(Relaxed).saturating_sub(self.after.load(Relaxed));
+		Some(Msg::info(format!(
+			"{}/{} images done, {} failed; {} bytes saved so far.",
+			NiceU64::from(done),
+			NiceU64::from(total),
+			NiceU64::from(failed),
+			NiceU64::from(saved),
+		)))
+	}
+}
+
+
+
+#[expect(unsafe_code, reason = "For FFI.")]
+/// # Install Handlers.
+///
+/// Hook up the signal handlers and spawn a thread to act on them.
+///
+/// The first `SIGINT` or `SIGTERM` cancels `killed` — letting the
+/// in-progress images finish — while the second abandons them altogether,
+/// cutting any zopfli passes short and refusing to save anything else once
+/// the pending writes have landed.
+pub(crate) fn install(killed: CancellationToken, progress: Option<Progless>) {
+	for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGUSR1] {
+		// Safety: the handler only touches atomics, which is signal-safe.
+		unsafe {
+			let mut action: libc::sigaction = std::mem::zeroed();
+			action.sa_sigaction = handle as *const () as libc::sighandler_t;
+			action.sa_flags = libc::SA_RESTART;
+			libc::sigemptyset(&raw mut action.sa_mask);
+			libc::sigaction(sig, &raw const action, std::ptr::null_mut());
+		}
+	}
+
+	let _res = std::thread::Builder::new()
+		.name("flaca-signals".to_owned())
+		.spawn(move || watch(&killed, progress.as_ref()));
+}
+
+/// # Take Reload.
+///
+/// Returns `true` if a `SIGHUP` has arrived since the last call.
+pub(crate) fn take_reload() -> bool { RELOAD.swap(false, SeqCst) }
+
+/// # Signal Handler.
+///
+/// Record the signal for the watcher thread.
+extern "C" fn handle(sig: c_int) {
+	match sig {
+		libc::SIGINT | libc::SIGTERM => { STOP.fetch_add(1, SeqCst); },
+		libc::SIGHUP => { RELOAD.store(true, SeqCst); },
+		libc::SIGUSR1 => { STATS.store(true, SeqCst); },
+		_ => {},
+	}
+}
+
+/// # Watch.
+///
+/// Act on any signals that have come in, forever.
+fn watch(killed: &CancellationToken, progress: Option<&Progless>) {
+	let mut stops = 0;
+	loop {
+		std::thread::sleep(POLL);
+
+		let now = STOP.load(SeqCst);
+		if stops != now {
+			stops = now;
+
+			// Stop handing out new work.
+			if killed.cancel() {
+				if let Some(p) = progress { p.sigint(); }
+			}
+
+			// Abandon the in-progress images too if asked twice: let any writes
+			// (or rollbacks) already underway land, refuse the rest, and cut
+			// the zopfli passes short so the workers can unwind.
+			if 1 < now {
+				crate::image::abort();
+				flapfli::cancel_zopfli();
+			}
+		}
+
+		if STATS.swap(false, SeqCst) {
+			if let Some(msg) = SNAPSHOT.msg() {
+				// Print above the progress bar, if there is one.
+				match progress {
+					Some(p) => { let _res = p.push_msg(msg); },
+					None => { msg.eprint(); },
+				}
+			}
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_snapshot() {
+		let snap = Snapshot::new();
+		assert!(snap.msg().is_none(), "Empty snapshots should be silent.");
+
+		snap.add_total(3);
+		snap.on_finish(Path::new("/tmp/a.png"), 100, 60);
+		snap.on_error(Path::new("/tmp/b.png"), EncodingError::Format);
+		snap.on_error(Path::new("/tmp/c.png"), EncodingError::Skipped);
+
+		let Some(msg) = snap.msg() else { panic!("Missing snapshot."); };
+		let msg = msg.as_str();
+		for needle in ["3/3 images", "1 failed", "40 bytes"] {
+			assert!(msg.contains(needle), "Missing {needle:?}: {msg}");
+		}
+	}
+}
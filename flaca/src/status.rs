@@ -0,0 +1,114 @@
+/*!
+# Flaca: Exit Status
+
+Build scripts often want to branch on whether a run actually changed
+anything, so flaca exits with one of the following codes:
+
+| Code | Meaning |
+| ---- | ------- |
+| 0 | Success; bytes were saved. |
+| 1 | Hard error; the run could not be completed. |
+| 2 | Success, but nothing could be improved. |
+| 3 | Some images could not be processed. |
+| 4 | Some images still exceed the `--target-size`. |
+*/
+
+use crate::{
+	EncodingError,
+	Observer,
+};
+use std::{
+	path::Path,
+	process::ExitCode,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::Relaxed,
+	},
+};
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Exit Status.
+pub(crate) enum ExitStatus {
+	/// # Success.
+	Success,
+
+	/// # Nothing Improved.
+	NoSavings,
+
+	/// # Some Failures.
+	Partial,
+
+	/// # Some Images Over Target.
+	OverTarget,
+}
+
+impl From<ExitStatus> for ExitCode {
+	fn from(src: ExitStatus) -> Self {
+		match src {
+			ExitStatus::Success => Self::SUCCESS,
+			ExitStatus::NoSavings => Self::from(2),
+			ExitStatus::Partial => Self::from(3),
+			ExitStatus::OverTarget => Self::from(4),
+		}
+	}
+}
+
+
+
+#[derive(Debug, Default)]
+/// # Exit Status Tally.
+///
+/// This observer keeps track of whether any images were improved or failed
+/// so the run's exit status can be worked out at the end.
+pub(crate) struct Tally {
+	/// # Anything Saved?
+	saved: AtomicBool,
+
+	/// # Anything Failed?
+	failed: AtomicBool,
+}
+
+impl Observer for Tally {
+	fn on_finish(&self, _src: &Path, before: u64, after: u64) {
+		if after < before { self.saved.store(true, Relaxed); }
+	}
+
+	fn on_error(&self, _src: &Path, err: EncodingError) {
+		if err.is_failure() { self.failed.store(true, Relaxed); }
+	}
+}
+
+impl Tally {
+	/// # Exit Status.
+	pub(crate) fn status(&self) -> ExitStatus {
+		if self.failed.load(Relaxed) { ExitStatus::Partial }
+		else if self.saved.load(Relaxed) { ExitStatus::Success }
+		else { ExitStatus::NoSavings }
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_status() {
+		let tally = Tally::default();
+		assert_eq!(tally.status(), ExitStatus::NoSavings);
+
+		tally.on_finish(Path::new("/tmp/a.png"), 10, 10);
+		tally.on_error(Path::new("/tmp/b.png"), EncodingError::Skipped);
+		tally.on_error(Path::new("/tmp/c.png"), EncodingError::FileSize);
+		assert_eq!(tally.status(), ExitStatus::NoSavings);
+
+		tally.on_finish(Path::new("/tmp/d.png"), 10, 5);
+		assert_eq!(tally.status(), ExitStatus::Success);
+
+		tally.on_error(Path::new("/tmp/e.png"), EncodingError::Write);
+		assert_eq!(tally.status(), ExitStatus::Partial);
+	}
+}
@@ -0,0 +1,178 @@
+/*!
+# Flaca: Target Size
+*/
+
+use crate::{
+	FlacaError,
+	Observer,
+};
+use dactyl::{
+	NiceU64,
+	traits::{
+		BytesToUnsigned,
+		NiceInflection,
+	},
+};
+use fyi_msg::Msg;
+use std::{
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+#[derive(Debug)]
+/// # Target Size.
+///
+/// Performance budgets often come with hard per-image limits — e.g. a hero
+/// image no bigger than 150 KB — that lossless compression may or may not be
+/// able to meet. This observer checks each image's final size against the
+/// `--target-size`, and lists the ones still over it at the end.
+///
+/// Flaca doesn't do lossy re-encoding, so there's nothing further to try;
+/// the misses are left for a human (or a lossy tool) to sort out.
+pub(crate) struct TargetSize {
+	/// # Target.
+	target: Target,
+
+	/// # Misses.
+	///
+	/// The path, final size, and target for each image over budget.
+	missed: Mutex<Vec<(PathBuf, u64, u64)>>,
+}
+
+impl Observer for TargetSize {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		let target = self.target.bytes(before);
+		if target < after {
+			if let Ok(mut missed) = self.missed.lock() {
+				missed.push((src.to_path_buf(), after, target));
+			}
+		}
+	}
+}
+
+impl TargetSize {
+	/// # New.
+	///
+	/// Parse the target from a raw CLI value, either a number of bytes like
+	/// `150k` or a percentage of the original size like `60%`.
+	///
+	/// ## Errors
+	///
+	/// An error is returned if the value is invalid.
+	pub(crate) fn new(raw: &str) -> Result<Self, FlacaError> {
+		let target = Target::parse(raw.trim().as_bytes()).ok_or(FlacaError::TargetSize)?;
+		Ok(Self { target, missed: Mutex::new(Vec::new()) })
+	}
+
+	/// # Missed?
+	///
+	/// Returns `true` if any images ended up over the target.
+	pub(crate) fn missed(&self) -> bool {
+		self.missed.lock().is_ok_and(|m| ! m.is_empty())
+	}
+
+	/// # Summarize.
+	///
+	/// Print the images still over the target, if any.
+	pub(crate) fn summarize(self) {
+		let Ok(mut missed) = self.missed.into_inner() else { return; };
+		if missed.is_empty() { return; }
+		missed.sort_unstable();
+
+		Msg::warning(format!(
+			"{} still exceeded the --target-size:",
+			missed.len().nice_inflect("image", "images"),
+		)).eprint();
+		for (path, after, target) in missed {
+			eprintln!(
+				"  {} \x1b[2m({} > {} bytes)\x1b[0m",
+				path.display(),
+				NiceU64::from(after),
+				NiceU64::from(target),
+			);
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Target.
+enum Target {
+	/// # Absolute Bytes.
+	Bytes(u64),
+
+	/// # Percentage of the Original (1-99).
+	Percent(u8),
+}
+
+impl Target {
+	/// # Parse.
+	fn parse(raw: &[u8]) -> Option<Self> {
+		if let Some(raw) = raw.strip_suffix(b"%") {
+			let pct = u8::btou(raw.trim_ascii())?;
+			if (1..100).contains(&pct) { Some(Self::Percent(pct)) }
+			else { None }
+		}
+		else { crate::parse_limit(raw).map(Self::Bytes) }
+	}
+
+	/// # Bytes.
+	///
+	/// Return the target size for an image originally `before` bytes.
+	const fn bytes(self, before: u64) -> u64 {
+		match self {
+			Self::Bytes(n) => n,
+			Self::Percent(pct) => before.saturating_mul(pct as u64) / 100,
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_parse() {
+		for (raw, expected) in [
+			(&b"1024"[..], Some(Target::Bytes(1024))),
+			(b"150k", Some(Target::Bytes(150_000))),
+			(b"60%", Some(Target::Percent(60))),
+			(b"60 %", Some(Target::Percent(60))),
+			(b"0", None),
+			(b"0%", None),
+			(b"100%", None),
+			(b"big", None),
+			(b"", None),
+		] {
+			assert_eq!(
+				Target::parse(raw),
+				expected,
+				"Wrong answer for {:?}.",
+				String::from_utf8_lossy(raw),
+			);
+		}
+	}
+
+	#[test]
+	fn t_missed() {
+		let target = TargetSize::new("100").expect("Target failed.");
+		target.on_finish(Path::new("/tmp/a.png"), 200, 100);
+		assert!(! target.missed());
+		target.on_finish(Path::new("/tmp/b.png"), 200, 101);
+		assert!(target.missed());
+
+		let target = TargetSize::new("50%").expect("Target failed.");
+		target.on_finish(Path::new("/tmp/a.png"), 200, 100);
+		assert!(! target.missed());
+		target.on_finish(Path::new("/tmp/b.png"), 200, 150);
+		assert!(target.missed());
+	}
+}
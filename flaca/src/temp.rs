@@ -0,0 +1,173 @@
+/*!
+# Flaca: Leftover Temporary Files
+
+Images are saved atomically: the new version is written to a temporary file
+in the same directory, then swapped into place. If a run is interrupted
+mid-write — a crash, a power cut, a `kill -9` — those temporary files can be
+left behind, and since they live right next to the images, they have a way
+of getting deployed along with them.
+
+Before crunching, the directories being worked on are checked for such
+strays so they can be reported and, with `--clean-temp`, removed. Only files
+untouched for at least an hour count; anything newer might belong to another
+run that is still going.
+
+Those temporary files always live alongside their targets — never in `/tmp`
+— so the final rename stays on one filesystem and remains atomic. Flaca's
+own scratch files — A/B copies, lists of missed images, heap profiles — are
+another matter; they go to the system temporary directory unless `--tmpdir`
+says otherwise.
+*/
+
+use crate::FlacaError;
+use std::{
+	collections::BTreeSet,
+	fs::{
+		File,
+		Metadata,
+	},
+	io::Read,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::OnceLock,
+	time::Duration,
+};
+
+
+
+/// # Minimum Age.
+///
+/// Temporary files modified more recently than this are left alone.
+pub(crate) const MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// # Temporary File Prefix.
+const PREFIX: &[u8] = b".tmp";
+
+/// # Random Suffix Length.
+const RANDOM_LEN: usize = 6;
+
+/// # Scratch Directory (Override).
+static SCRATCH: OnceLock<PathBuf> = OnceLock::new();
+
+
+
+/// # Find Leftovers.
+///
+/// Search the parent directories of each image for leftover temporary files
+/// at least `MIN_AGE` old, returning their paths, sorted.
+pub(crate) fn find(paths: &[PathBuf]) -> Vec<PathBuf> {
+	let dirs: BTreeSet<&Path> = paths.iter().filter_map(|p| p.parent()).collect();
+	let mut out = Vec::new();
+	for dir in dirs {
+		let Ok(iter) = std::fs::read_dir(dir) else { continue; };
+		for e in iter.flatten() {
+			if
+				is_temp_name(e.file_name().as_encoded_bytes()) &&
+				e.metadata().is_ok_and(|m| m.is_file() && is_stale(&m)) &&
+				is_temp_data(&e.path())
+			{
+				out.push(e.path());
+			}
+		}
+	}
+
+	out.sort();
+	out
+}
+
+/// # Scratch Directory.
+///
+/// Return the directory to use for scratch files: the `--tmpdir`, if set,
+/// otherwise the system default.
+pub(crate) fn scratch_dir() -> PathBuf {
+	SCRATCH.get().cloned().unwrap_or_else(std::env::temp_dir)
+}
+
+/// # Set Scratch Directory.
+///
+/// ## Errors
+///
+/// An error is returned if the directory doesn't exist, or was already set.
+pub(crate) fn set_scratch_dir(raw: &str) -> Result<(), FlacaError> {
+	let dir = std::fs::canonicalize(raw.trim()).ok()
+		.filter(|p| p.is_dir())
+		.ok_or(FlacaError::TmpDir)?;
+	SCRATCH.set(dir).map_err(|_| FlacaError::TmpDir)
+}
+
+/// # Clean Leftovers.
+///
+/// Remove the files, returning the number successfully deleted.
+pub(crate) fn clean(found: &[PathBuf]) -> usize {
+	found.iter().filter(|p| std::fs::remove_file(p).is_ok()).count()
+}
+
+
+
+/// # Temporary Name?
+///
+/// The atomic writer names its files `.tmp` followed by six random
+/// alphanumeric characters.
+fn is_temp_name(name: &[u8]) -> bool {
+	name.len() == PREFIX.len() + RANDOM_LEN &&
+	name.starts_with(PREFIX) &&
+	name[PREFIX.len()..].iter().all(u8::is_ascii_alphanumeric)
+}
+
+/// # Stale?
+///
+/// Returns `true` if the file was last modified at least `MIN_AGE` ago.
+/// (Timestamps from the future don't count.)
+fn is_stale(meta: &Metadata) -> bool {
+	meta.modified().ok()
+		.and_then(|t| t.elapsed().ok())
+		.is_some_and(|age| MIN_AGE <= age)
+}
+
+/// # Temporary Data?
+///
+/// Other programs use the same naming scheme, so to be safe, only empty files
+/// and ones that begin like an image count. (Only the signatures are
+/// checked; the rest of the data may well be missing.)
+fn is_temp_data(path: &Path) -> bool {
+	let mut buf = [0_u8; 8];
+	let Ok(mut file) = File::open(path) else { return false; };
+	let Ok(len) = file.read(&mut buf) else { return false; };
+
+	matches!(
+		&buf[..len],
+		[] |
+		[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'] |
+		[0xFF, 0xD8, 0xFF, ..] |
+		[b'G', b'I', b'F', b'8', ..]
+	)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_is_temp_name() {
+		for (name, expected) in [
+			(&b".tmpAb3xZ9"[..], true),
+			(b".tmp000000", true),
+			(b".tmpAb3xZ", false),
+			(b".tmpAb3xZ90", false),
+			(b".tmpAb3x-9", false),
+			(b"tmpAb3xZ9.", false),
+			(b"image.png", false),
+		] {
+			assert_eq!(
+				is_temp_name(name),
+				expected,
+				"Wrong answer for {:?}.",
+				String::from_utf8_lossy(name),
+			);
+		}
+	}
+}
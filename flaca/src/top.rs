@@ -0,0 +1,132 @@
+/*!
+# Flaca: Leaderboard
+*/
+
+use crate::Observer;
+use fyi_msg::{
+	BeforeAfter,
+	Msg,
+};
+use std::{
+	cmp::Ordering,
+	num::NonZeroUsize,
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::Mutex,
+};
+
+
+
+/// # Entry.
+///
+/// The path, before, and after sizes of an image that improved.
+type Entry = (PathBuf, u64, u64);
+
+
+
+#[derive(Debug)]
+/// # Leaderboard.
+///
+/// This observer keeps track of the images that got smaller, and prints the
+/// top `N` by absolute and relative savings at the end — the quickest way to
+/// find the bloated stock photos worth flagging to designers.
+pub(crate) struct Leaderboard {
+	/// # Number to Show.
+	n: NonZeroUsize,
+
+	/// # Entries.
+	entries: Mutex<Vec<Entry>>,
+}
+
+impl Observer for Leaderboard {
+	fn on_finish(&self, src: &Path, before: u64, after: u64) {
+		if after < before {
+			if let Ok(mut entries) = self.entries.lock() {
+				entries.push((src.to_path_buf(), before, after));
+			}
+		}
+	}
+}
+
+impl Leaderboard {
+	/// # New.
+	pub(crate) const fn new(n: NonZeroUsize) -> Self {
+		Self { n, entries: Mutex::new(Vec::new()) }
+	}
+
+	/// # Summarize.
+	///
+	/// Print the biggest savers, if any.
+	pub(crate) fn summarize(self) {
+		let Ok(mut entries) = self.entries.into_inner() else { return; };
+		if entries.is_empty() { return; }
+
+		let n = self.n.get();
+		entries.sort_unstable_by(by_bytes);
+		print("Biggest savings (bytes):", &entries[..n.min(entries.len())]);
+		entries.sort_unstable_by(by_percent);
+		print("Biggest savings (percent):", &entries[..n.min(entries.len())]);
+	}
+}
+
+
+
+/// # Print Board.
+fn print(title: &str, entries: &[Entry]) {
+	Msg::info(title).eprint();
+	for (path, before, after) in entries {
+		Msg::crunched(path.to_string_lossy())
+			.with_bytes_saved(BeforeAfter::from((*before, *after)))
+			.eprint();
+	}
+}
+
+/// # Order by Bytes Saved.
+///
+/// Biggest first, with ties broken by path.
+fn by_bytes(a: &Entry, b: &Entry) -> Ordering {
+	(b.1 - b.2).cmp(&(a.1 - a.2)).then_with(|| a.0.cmp(&b.0))
+}
+
+/// # Order by Percent Saved.
+///
+/// Biggest first, with ties broken by path. (The fractions are compared by
+/// cross-multiplication to keep things exact.)
+fn by_percent(a: &Entry, b: &Entry) -> Ordering {
+	let a_saved = u128::from(a.1 - a.2) * u128::from(b.1);
+	let b_saved = u128::from(b.1 - b.2) * u128::from(a.1);
+	b_saved.cmp(&a_saved).then_with(|| a.0.cmp(&b.0))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_order() {
+		let board = Leaderboard::new(NonZeroUsize::MIN);
+		for (name, before, after) in [
+			("a.png", 1000, 900), // 100 bytes, 10%.
+			("b.png", 100, 50),   // 50 bytes, 50%.
+			("c.png", 500, 300),  // 200 bytes, 40%.
+			("d.png", 100, 100),  // Nothing.
+		] {
+			board.on_finish(Path::new(name), before, after);
+		}
+
+		let mut entries = board.entries.into_inner().expect("Poisoned.");
+		assert_eq!(entries.len(), 3, "Unchanged images shouldn't be tracked.");
+
+		entries.sort_unstable_by(by_bytes);
+		let names: Vec<_> = entries.iter().map(|e| e.0.to_string_lossy().into_owned()).collect();
+		assert_eq!(names, ["c.png", "a.png", "b.png"]);
+
+		entries.sort_unstable_by(by_percent);
+		let names: Vec<_> = entries.iter().map(|e| e.0.to_string_lossy().into_owned()).collect();
+		assert_eq!(names, ["b.png", "c.png", "a.png"]);
+	}
+}
@@ -0,0 +1,1625 @@
+/*!
+# Flaca: CLI Integration Tests
+
+These run the real binary against synthetic directory trees.
+*/
+
+mod tree;
+
+use std::{
+	ffi::{
+		OsStr,
+		OsString,
+	},
+	io::{
+		BufRead,
+		BufReader,
+		Write,
+	},
+	os::unix::{
+		ffi::OsStrExt,
+		fs::PermissionsExt,
+		net::UnixStream,
+	},
+	process::{
+		Command,
+		Output,
+		Stdio,
+	},
+};
+use tree::{
+	Kind,
+	Tree,
+};
+
+
+
+/// # Run Flaca.
+fn flaca<I, S>(args: I) -> Output
+where I: IntoIterator<Item=S>, S: AsRef<OsStr> {
+	Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.args(args)
+		.output()
+		.expect("Unable to run flaca.")
+}
+
+/// # Run Flaca (Piped).
+fn flaca_piped(args: &[&str], input: &[u8]) -> Output {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.args(args)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.expect("Unable to run flaca.");
+	child.stdin.take().expect("Missing stdin.").write_all(input)
+		.expect("Unable to write to stdin.");
+	child.wait_with_output().expect("Unable to run flaca.")
+}
+
+/// # Stderr as String.
+fn stderr(out: &Output) -> String { String::from_utf8_lossy(&out.stderr).into_owned() }
+
+/// # Summary Fragment.
+///
+/// Return the "done/total images" part of the progress summary for runs
+/// with one or more skipped images.
+fn summary(done: usize, total: usize) -> String {
+	format!("{done}\x1b[2m/\x1b[0m{total} images")
+}
+
+/// # Exit Code: Nothing Improved.
+const EXIT_NO_SAVINGS: i32 = 2;
+
+/// # Exit Code: Some Images Failed.
+///
+/// Any run over the whole tree ends this way, thanks to the broken and
+/// unreadable images.
+const EXIT_PARTIAL: i32 = 3;
+
+
+
+#[test]
+fn t_help() {
+	let out = flaca(["--help"]);
+	assert!(out.status.success());
+	assert!(String::from_utf8_lossy(&out.stdout).contains("USAGE:"));
+
+	let out = flaca(["-V"]);
+	assert!(out.status.success());
+	assert!(String::from_utf8_lossy(&out.stdout).starts_with("Flaca v"));
+}
+
+#[test]
+fn t_bad_options() {
+	let tree = Tree::new();
+	for args in [
+		&["--max-filesize", "0"][..],
+		&["--max-resolution", "5g"],
+		&["--min-savings", "0"],
+		&["--jpeg-keep", "bogus"],
+		&["--name-template", "{bogus}"],
+		&["--threads", "0"],
+		&["-j", "lots"],
+		&["ab"],
+		&["ab", "--settings-a", "/nope.toml", "--settings-b", "/nope.toml"],
+		&["--trace-pipeline"],
+		&["--kind", "png"],
+		&["--stdin", "--kind", "webp"],
+		&["--oxipng-level", "7"],
+		&["--newer-than", "yesterday"],
+		&["--min-size", "0"],
+		&["--max-depth", "0"],
+		&["--max-memory", "0"],
+		&["--target-size", "100%"],
+		&["--top", "0"],
+		&["--jpeg-crop", "32x32+8"],
+		&["--jpeg-crop", "32x32", "--auto-orient"],
+		&["--keep-candidates", "/tmp/flaca-review"],
+		&["--png-exhaustive", "--keep-colortype"],
+		&["--daemon"],
+		&["--socket", "/tmp/flaca.sock"],
+		&["--listen", "127.0.0.1:0"],
+		&["-z", "60,20"],
+		&["-z", "60,0,5"],
+		&["--zopfli-block-size", "1k"],
+		&["--zopfli-block-size", "9m"],
+		&["--zopfli-max-splits", "0"],
+		&["--zopfli-max-splits", "64"],
+		&["--no-oxipng", "--no-zopfli"],
+	] {
+		let out = flaca(args.iter().map(OsStr::new).chain([tree.root().as_os_str()]));
+		assert_eq!(out.status.code(), Some(1), "Wrong exit code for {args:?}.");
+		assert!(! stderr(&out).contains("No images"), "Wrong error for {args:?}.");
+	}
+
+	// Nothing should have been touched.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+}
+
+#[test]
+fn t_no_images() {
+	let tree = Tree::new();
+	let dir = tree.root().join("empty");
+	std::fs::create_dir_all(&dir).expect("Unable to create directory.");
+	std::fs::write(dir.join("nothing.txt"), b"Nope.").expect("Unable to write file.");
+
+	// Non-image files and image-less directories.
+	let out = flaca([dir, tree.root().join("nested/data.json")]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("No images were found."));
+}
+
+#[test]
+fn t_tree() {
+	let tree = Tree::new();
+	let locked = tree.locked().is_some_and(|e| std::fs::File::open(&e.path).is_err());
+	let out = flaca([
+		OsStr::new("-p"),
+		OsStr::new("--no-jpeg"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// Check the summary numbers. Everything with an image extension counts
+	// toward the total; JPEGs, broken images, and (if actually unreadable)
+	// the locked image are skipped.
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let skipped = tree.count(Kind::Jpeg) + tree.count(Kind::Broken) + usize::from(locked);
+	let err = stderr(&out);
+	assert!(
+		err.contains(&summary(total - skipped, total)),
+		"Expected {}/{total} images:\n{err}",
+		total - skipped,
+	);
+
+	// The per-kind breakdown should only mention the kinds processed.
+	assert!(err.contains("PNG \x1b[2m("), "Missing PNG totals:\n{err}");
+	assert!(! err.contains("JPEG \x1b[2m("), "Unexpected JPEG totals:\n{err}");
+
+	for e in tree.entries() {
+		let now = e.current();
+		match e.kind {
+			// Images should be the same or smaller, and still valid.
+			Kind::Image => if ! locked || tree.locked().is_none_or(|l| l.path != e.path) {
+				assert!(now.len() <= e.raw.len(), "Image grew: {}", e.path.display());
+				assert_eq!(now[..4], e.raw[..4], "Image corrupted: {}", e.path.display());
+			},
+			// Everything else should be untouched.
+			Kind::Jpeg | Kind::Broken | Kind::Other => {
+				assert_eq!(now, e.raw, "File changed: {}", e.path.display());
+			},
+		}
+	}
+
+	// The small PNG, at least, should have gotten smaller.
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+	assert!(small.current().len() < small.raw.len());
+
+	// Re-running should find no further savings.
+	let before: Vec<Vec<u8>> = tree.entries().iter().map(tree::Entry::current).collect();
+	let out = flaca([OsStr::new("--no-jpeg"), tree.root().as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL));
+	for (e, old) in tree.entries().iter().zip(before) {
+		assert_eq!(e.current(), old, "File changed again: {}", e.path.display());
+	}
+}
+
+#[test]
+fn t_out_dir() {
+	let tree = Tree::new();
+	let out_dir = tree.root().join("out");
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("-o"),
+		out_dir.as_os_str(),
+		tree.root().join("nested").as_os_str(),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// The originals should all be untouched.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	// And the copies should mirror the source structure.
+	for rel in ["small.png", "nested/small-bw.png", "nested/deeper/still/small-bwa.png"] {
+		let src = tree.root().join(rel);
+		let dst = out_dir.join(rel);
+		let dst_len = std::fs::metadata(&dst).map_or(0, |m| m.len());
+		assert!(dst_len != 0, "Missing output: {}", dst.display());
+		assert!(
+			dst_len <= std::fs::metadata(&src).map_or(0, |m| m.len()),
+			"Output grew: {}", dst.display(),
+		);
+	}
+
+	// Non-images shouldn't have been copied.
+	assert!(! out_dir.join("nested/data.json").exists());
+}
+
+#[test]
+fn t_dry_run() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--dry-run"),
+		OsStr::new("--no-jpeg"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// Nothing should have been touched.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	// Again, but holding on to the candidates.
+	let review = tree.root().join("review");
+	let out = flaca([
+		OsStr::new("--dry-run"),
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--keep-candidates"),
+		review.as_os_str(),
+		tree.root().join("nested").as_os_str(),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// The originals should still be untouched.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	// Only the images that would have shrunk should be mirrored.
+	let small = review.join("small.png");
+	assert!(small.is_file(), "Missing candidate: {}", small.display());
+	for rel in ["small.png", "nested/small-bw.png", "nested/deeper/still/small-bwa.png"] {
+		let dst = review.join(rel);
+		if let Ok(meta) = std::fs::metadata(&dst) {
+			assert!(
+				meta.len() < std::fs::metadata(tree.root().join(rel)).map_or(0, |m| m.len()),
+				"Candidate didn't shrink: {}", dst.display(),
+			);
+		}
+	}
+	assert!(! review.join("nested/data.json").exists());
+}
+
+#[test]
+fn t_backup() {
+	let tree = Tree::new();
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+
+	// A bare --backup can't be followed by a path; it's too easy to mistake
+	// for a backup directory.
+	let dir = tree.root().join("backups");
+	let out = flaca([OsStr::new("--backup"), dir.as_os_str(), small.path.as_os_str()]);
+	assert_eq!(out.status.code(), Some(1));
+	assert_eq!(small.current(), small.raw, "Image should be untouched.");
+	assert!(! dir.exists(), "Nothing should have been backed up.");
+
+	// Sibling backups.
+	let out = flaca([small.path.as_os_str(), OsStr::new("--backup")]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(small.current().len() < small.raw.len(), "Image not compressed.");
+	let bak = tree.root().join("small.png.bak");
+	assert_eq!(std::fs::read(&bak).ok().as_deref(), Some(small.raw.as_slice()));
+
+	// Mirrored backups.
+	let tree = Tree::new();
+	let dir = tree.root().join("backups");
+	let mut arg = OsString::from("--backup=");
+	arg.push(&dir);
+	let out = flaca([
+		arg.as_os_str(),
+		OsStr::new("--no-jpeg"),
+		tree.root().join("nested").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let e = tree.entries().iter().find(|e| e.path.ends_with("nested/small-bw.png"))
+		.expect("Missing small-bw.png.");
+	assert_eq!(std::fs::read(dir.join("small-bw.png")).ok().as_deref(), Some(e.raw.as_slice()));
+
+	// The environment flag shouldn't swallow the paths either.
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let out = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.env("FLACA_BACKUP", "1")
+		.arg(&src)
+		.output()
+		.expect("Unable to run flaca.");
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(tree.root().join("small.png.bak").is_file(), "Missing backup.");
+}
+
+#[test]
+fn t_reflink() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+
+	// Crunch it once so later copies are unchanged.
+	let out = flaca([src.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let raw = std::fs::read(&src).expect("Missing small.png.");
+
+	// Unchanged copies should match the original either way.
+	for mode in ["auto", "never"] {
+		let out_dir = tree.root().join(format!("out-{mode}"));
+		let out = flaca([
+			OsStr::new("--reflink"),
+			OsStr::new(mode),
+			OsStr::new("-o"),
+			out_dir.as_os_str(),
+			src.as_os_str(),
+		]);
+		assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed ({mode}): {}", stderr(&out));
+		assert_eq!(
+			std::fs::read(out_dir.join("small.png")).ok().as_deref(),
+			Some(raw.as_slice()),
+			"Wrong copy ({mode}).",
+		);
+	}
+
+	// Nonsense modes are rejected.
+	let out = flaca([OsStr::new("--reflink"), OsStr::new("always"), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn t_preserve_perms() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	std::fs::set_permissions(&src, std::fs::Permissions::from_mode(0o640))
+		.expect("Unable to chmod small.png.");
+
+	let out_dir = tree.root().join("out");
+	let out = flaca([
+		OsStr::new("--preserve-perms"),
+		OsStr::new("-o"),
+		out_dir.as_os_str(),
+		src.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// The copy should have the same mode as the original.
+	let mode = std::fs::metadata(out_dir.join("small.png"))
+		.map(|m| m.permissions().mode() & 0o777)
+		.expect("Missing output.");
+	assert_eq!(mode, 0o640);
+}
+
+#[test]
+fn t_clean_temp() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let stray = tree.root().join(".tmpAb3xZ9");
+	let fresh = tree.root().join(".tmpQw7rT2");
+	for p in [&stray, &fresh] {
+		std::fs::write(p, b"\x89PNG\r\n\x1a\n").expect("Unable to write temp file.");
+	}
+
+	// Backdate the stray; the fresh one might still be in use.
+	std::fs::File::options()
+		.write(true)
+		.open(&stray)
+		.and_then(|f| f.set_modified(
+			std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 60 * 60)
+		))
+		.expect("Unable to backdate temp file.");
+
+	// Without the flag, it should only be reported.
+	let out = flaca([src.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let err = stderr(&out);
+	assert!(err.contains(".tmpAb3xZ9"), "Leftover not reported:\n{err}");
+	assert!(! err.contains(".tmpQw7rT2"), "Recent file reported:\n{err}");
+	assert!(stray.exists(), "Leftover removed without permission.");
+
+	// With it, it should be removed.
+	let out = flaca([OsStr::new("--clean-temp"), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	assert!(! stray.exists(), "Leftover not removed.");
+	assert!(fresh.exists(), "Recent file removed.");
+}
+
+#[test]
+fn t_resume() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let raw = std::fs::read(&src).expect("Unable to read small.png.");
+	let state = tree.root().join("flaca.state");
+
+	// Pretend an earlier run already got to it.
+	let done = std::fs::canonicalize(&src).expect("Unable to canonicalize small.png.");
+	let mut line = done.into_os_string().into_encoded_bytes();
+	line.push(b'\n');
+	std::fs::write(&state, line).expect("Unable to write state file.");
+
+	let out = flaca([OsStr::new("--resume"), state.as_os_str(), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	let err = stderr(&out);
+	assert!(err.contains("already processed"), "Missing resume notice:\n{err}");
+	assert_eq!(std::fs::read(&src).ok(), Some(raw), "Finished image was reprocessed.");
+	assert!(! state.exists(), "State file not removed.");
+}
+
+#[test]
+fn t_max_filesize() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("-p"),
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--max-filesize"),
+		OsStr::new("1"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+
+	// Everything (but the empty file) is too big, so nothing should change.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let err = stderr(&out);
+	assert!(err.contains(&summary(0, total)), "Expected 0/{total} images:\n{err}");
+}
+
+#[test]
+fn t_memory_budget() {
+	// A tiny budget should just slow things down, not change the results.
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("-j4"),
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--memory-budget"),
+		OsStr::new("1"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+	assert!(small.current().len() < small.raw.len());
+
+	// Zero isn't a budget.
+	let out = flaca([
+		OsStr::new("--memory-budget"),
+		OsStr::new("0"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("--memory-budget"));
+}
+
+#[test]
+fn t_mmap_threshold() {
+	// Mapped input should produce exactly the same results as read input.
+	let read = Tree::new();
+	let mapped = Tree::new();
+	let out = flaca([OsStr::new("--no-jpeg"), read.root().as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--verify"),
+		OsStr::new("--mmap-threshold"),
+		OsStr::new("1"),
+		mapped.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	for (a, b) in read.entries().iter().zip(mapped.entries()) {
+		assert_eq!(a.current(), b.current(), "Mismatch: {}", b.path.display());
+	}
+	let small = mapped.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+	assert!(small.current().len() < small.raw.len());
+}
+
+#[test]
+fn t_newer_than() {
+	let tree = Tree::new();
+
+	// Everything was just written, so an old cutoff should match nothing.
+	let out = flaca([
+		OsStr::new("--older-than"),
+		OsStr::new("2001-01-01"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("No images were found."));
+
+	// But everything should be newer than a day ago.
+	let out = flaca([
+		OsStr::new("-p"),
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--newer-than"),
+		OsStr::new("1d"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let err = stderr(&out);
+	assert!(err.contains(&format!("\x1b[2m/\x1b[0m{total} images")), "Expected {total} images:\n{err}");
+}
+
+#[test]
+fn t_symlinks() {
+	let tree = Tree::new();
+	let other = Tree::new();
+	std::os::unix::fs::symlink(other.root(), tree.root().join("link"))
+		.expect("Unable to symlink.");
+
+	// The link leads outside the requested path, so should be ignored.
+	let out = flaca([OsStr::new("--no-jpeg"), tree.root().as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	assert!(stderr(&out).contains("reached via symlinks"), "Missing warning:\n{}", stderr(&out));
+	for e in other.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	// And skipped entirely when passed directly, if desired.
+	let out = flaca([
+		OsStr::new("--no-follow-symlinks"),
+		tree.root().join("link").as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("No images were found."));
+}
+
+#[test]
+fn t_advise() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--advise"),
+		OsStr::new("--max-filesize"),
+		OsStr::new("1"),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+
+	// The limit should be called out.
+	let err = stderr(&out);
+	assert!(err.contains("--max-filesize"), "Missing advice:\n{err}");
+}
+
+#[test]
+fn t_verify() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("-p"),
+		OsStr::new("--verify"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// Nothing should have failed verification.
+	let err = stderr(&out);
+	assert!(! err.contains("verification failed"), "Verification failed:\n{err}");
+
+	// And the small PNG should still have gotten smaller.
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+	assert!(small.current().len() < small.raw.len());
+}
+
+#[test]
+fn t_jpeg_crop() {
+	/// # JPEG Width and Height.
+	fn dimensions(raw: &[u8]) -> (u16, u16) {
+		let pos = raw.windows(2)
+			.position(|w| w[0] == 0xFF && matches!(w[1], 0xC0 | 0xC2))
+			.expect("Missing SOF.");
+		(
+			u16::from_be_bytes([raw[pos + 7], raw[pos + 8]]),
+			u16::from_be_bytes([raw[pos + 5], raw[pos + 6]]),
+		)
+	}
+
+	// The photos are 72x48 with 4:2:0 subsampling, so 16x16 iMCUs.
+	let tree = Tree::new();
+	let photo = tree.root().join("photos/13.jpg");
+	let out = flaca([
+		OsStr::new("--verify"),
+		OsStr::new("--jpeg-crop"),
+		OsStr::new("32x32+16+16"),
+		photo.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let raw = std::fs::read(&photo).expect("Unable to read 13.jpg.");
+	assert_eq!(dimensions(&raw), (32, 32));
+
+	// Misaligned crops should fail…
+	let photo = tree.root().join("photos/14.jpeg");
+	let before = std::fs::read(&photo).expect("Unable to read 14.jpeg.");
+	let out = flaca([
+		OsStr::new("-p"),
+		OsStr::new("--jpeg-crop"),
+		OsStr::new("32x32+8+8"),
+		photo.as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	assert!(stderr(&out).contains("can't crop losslessly"), "Wrong error: {}", stderr(&out));
+	assert_eq!(std::fs::read(&photo).ok(), Some(before), "14.jpeg was changed.");
+
+	// …unless imperfection is allowed, in which case they grow to fit.
+	let out = flaca([
+		OsStr::new("--verify"),
+		OsStr::new("--allow-imperfect"),
+		OsStr::new("--jpeg-crop"),
+		OsStr::new("32x32+8+8"),
+		photo.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let raw = std::fs::read(&photo).expect("Unable to read 14.jpeg.");
+	assert_eq!(dimensions(&raw), (40, 40));
+}
+
+#[test]
+fn t_jpeg_grayscale_auto() {
+	/// # JPEG Component Count.
+	fn components(raw: &[u8]) -> u8 {
+		let pos = raw.windows(2)
+			.position(|w| w[0] == 0xFF && matches!(w[1], 0xC0 | 0xC2))
+			.expect("Missing SOF.");
+		raw[pos + 9]
+	}
+
+	let tree = Tree::new();
+	let scan = tree.root().join("scan.jpg");
+	std::fs::copy("../skel/assets/gray-ycc.jpg", &scan).expect("Unable to copy scan.");
+	let photo = tree.root().join("photos/13.jpg");
+	let out = flaca([
+		OsStr::new("--verify"),
+		OsStr::new("--jpeg-grayscale-auto"),
+		scan.as_os_str(),
+		photo.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// The colorless scan should be grayscale now, but the photo left alone.
+	let raw = std::fs::read(&scan).expect("Unable to read scan.");
+	assert_eq!(components(&raw), 1);
+	let raw = std::fs::read(&photo).expect("Unable to read 13.jpg.");
+	assert_eq!(components(&raw), 3);
+}
+
+#[test]
+fn t_trace_pipeline() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--trace-pipeline"),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Both PNG stages should be listed.
+	let trace = String::from_utf8_lossy(&out.stdout);
+	for needle in ["kind:", "png", "Oxipng:", "Zopflipng:", "result:", "saved"] {
+		assert!(trace.contains(needle), "Missing {needle:?}:\n{trace}");
+	}
+
+	// Unless zopfli is disabled…
+	let out = flaca([
+		OsStr::new("--trace-pipeline"),
+		OsStr::new("--no-zopfli"),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let trace = String::from_utf8_lossy(&out.stdout);
+	assert!(trace.contains("disabled (--no-zopfli)"), "Zopfli wasn't skipped:\n{trace}");
+
+	// …or oxipng.
+	let out = flaca([
+		OsStr::new("--trace-pipeline"),
+		OsStr::new("--no-oxipng"),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let trace = String::from_utf8_lossy(&out.stdout);
+	assert!(trace.contains("disabled (--no-oxipng)"), "Oxipng wasn't skipped:\n{trace}");
+
+	// But nothing should have been written.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+}
+
+#[test]
+fn t_profile_fast() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--fast"),
+		OsStr::new("--trace-pipeline"),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Zopfli should be skipped, and oxipng dialed back.
+	let trace = String::from_utf8_lossy(&out.stdout);
+	for needle in ["fast profile", "level 2"] {
+		assert!(trace.contains(needle), "Missing {needle:?}:\n{trace}");
+	}
+}
+
+#[test]
+fn t_stdin() {
+	let tree = Tree::new();
+	let raw = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+
+	// The optimized image should come out the other end.
+	let out = flaca_piped(&["--stdin", "--kind", "png"], &raw);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(out.stdout.starts_with(b"\x89PNG\r\n\x1a\n"), "Output is not a PNG.");
+	assert!(out.stdout.len() < raw.len(), "Output is not smaller.");
+
+	// The wrong kind should fail without writing anything.
+	let out = flaca_piped(&["--stdin", "--kind", "jpeg"], &raw);
+	assert_eq!(out.status.code(), Some(1), "Wrong exit code for mismatched kind.");
+	assert!(out.stdout.is_empty(), "Mismatched kind produced output.");
+
+	// And nothing on disk should have changed.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+}
+
+#[test]
+fn t_daemon() {
+	let tree = Tree::new();
+	let socket = tree.root().join("flaca.sock");
+	let mut child = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.args([OsStr::new("--daemon"), OsStr::new("--socket"), socket.as_os_str(), OsStr::new("-j2")])
+		.stderr(Stdio::null())
+		.spawn()
+		.expect("Unable to run flaca.");
+
+	// Give it a moment to start listening.
+	let mut stream = None;
+	for _ in 0..100 {
+		if let Ok(s) = UnixStream::connect(&socket) {
+			stream.replace(s);
+			break;
+		}
+		std::thread::sleep(std::time::Duration::from_millis(100));
+	}
+	let mut stream = stream.expect("Unable to connect to the daemon.");
+
+	// Only we should be able to use it.
+	let mode = std::fs::metadata(&socket).expect("Missing socket.").permissions().mode();
+	assert_eq!(mode & 0o777, 0o600, "The socket is too permissive.");
+
+	// Send a good job and a bad one.
+	let path = tree.root().join("small.png");
+	let before = std::fs::metadata(&path).expect("Missing small.png.").len();
+	writeln!(stream, r#"{{"id":1,"path":"{}"}}"#, path.display()).expect("Unable to write job.");
+	writeln!(stream, r#"{{"id":"two","data":"!!"}}"#).expect("Unable to write job.");
+	stream.shutdown(std::net::Shutdown::Write).expect("Unable to hang up.");
+
+	// Both replies should come back, in whatever order.
+	let mut replies: Vec<String> = BufReader::new(stream).lines()
+		.collect::<Result<_, _>>()
+		.expect("Unable to read replies.");
+	replies.sort();
+	assert_eq!(replies.len(), 2, "Wrong number of replies: {replies:?}");
+	assert!(
+		replies[0].starts_with(r#"{"id":"two","status":"error","#),
+		"Wrong reply for the bad job: {}", replies[0],
+	);
+	let after = std::fs::metadata(&path).expect("Missing small.png.").len();
+	assert!(after < before, "The image was not crunched.");
+	assert!(
+		replies[1].starts_with(r#"{"id":1,"path":"#) &&
+		replies[1].ends_with(&format!(r#""status":"ok","before":{before},"after":{after}}}"#)),
+		"Wrong reply for the good job: {}", replies[1],
+	);
+
+	// A SIGTERM should shut it down cleanly.
+	let status = Command::new("kill")
+		.args(["-TERM", &child.id().to_string()])
+		.status()
+		.expect("Unable to signal flaca.");
+	assert!(status.success(), "Unable to signal flaca.");
+	assert!(child.wait().expect("Unable to wait for flaca.").success(), "Daemon failed.");
+	assert!(! socket.exists(), "The socket was not removed.");
+}
+
+#[test]
+fn t_sigint() {
+	/// # Big PNG.
+	///
+	/// This needs to take long enough for the signal to land mid-crunch.
+	const BIG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../skel/assets/png/05.png");
+
+	let tree = Tree::new();
+	let big = tree.root().join("big.png");
+	let reference = tree.root().join("reference.png");
+	let small = tree.root().join("small.png");
+	std::fs::copy(BIG, &big).expect("Unable to copy big.png.");
+	std::fs::copy(BIG, &reference).expect("Unable to copy reference.png.");
+	let raw = std::fs::read(&big).expect("Unable to read big.png.");
+	let raw_small = std::fs::read(&small).expect("Unable to read small.png.");
+
+	// Crunch the reference copy without interruption.
+	let out = flaca([OsStr::new("--no-oxipng"), OsStr::new("-z"), OsStr::new("5"), reference.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let expected = std::fs::read(&reference).expect("Unable to read reference.png.");
+	assert_ne!(expected, raw, "The reference image was not crunched.");
+
+	// Do it again with a single SIGINT partway through. The big image — the
+	// first in line — should be allowed to finish its zopfli pass, but the
+	// small one should never be started.
+	let mut child = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.args([
+			OsStr::new("-j1"),
+			OsStr::new("--no-oxipng"),
+			OsStr::new("-z"),
+			OsStr::new("5"),
+			big.as_os_str(),
+			small.as_os_str(),
+		])
+		.stderr(Stdio::null())
+		.spawn()
+		.expect("Unable to run flaca.");
+	std::thread::sleep(std::time::Duration::from_millis(500));
+	let status = Command::new("kill")
+		.args(["-INT", &child.id().to_string()])
+		.status()
+		.expect("Unable to signal flaca.");
+	assert!(status.success(), "Unable to signal flaca.");
+
+	let status = child.wait().expect("Unable to wait for flaca.");
+	assert_eq!(status.code(), Some(1), "Flaca was not interrupted.");
+	assert_eq!(std::fs::read(&big).ok(), Some(expected), "The in-progress image was cut short.");
+	assert_eq!(std::fs::read(&small).ok(), Some(raw_small), "The queued image was crunched anyway.");
+}
+
+#[cfg(feature = "serve")]
+#[test]
+fn t_serve() {
+	use std::{
+		io::Read,
+		net::TcpStream,
+	};
+
+	let tree = Tree::new();
+	let mut child = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.args(["serve", "--listen", "127.0.0.1:0", "-j1"])
+		.stderr(Stdio::piped())
+		.spawn()
+		.expect("Unable to run flaca.");
+
+	// The (random) port is in the startup message.
+	let mut line = String::new();
+	BufReader::new(child.stderr.take().expect("Missing stderr."))
+		.read_line(&mut line)
+		.expect("Unable to read stderr.");
+	let addr = line.split_once("http://")
+		.and_then(|(_, rest)| rest.split_once('/'))
+		.map(|(addr, _)| addr.to_owned())
+		.expect("Missing address.");
+
+	// Send an image.
+	let raw = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+	let mut stream = TcpStream::connect(&addr).expect("Unable to connect.");
+	write!(stream, "POST /optimize HTTP/1.1\r\nHost: {addr}\r\nContent-Length: {}\r\n\r\n", raw.len())
+		.and_then(|()| stream.write_all(&raw))
+		.expect("Unable to send request.");
+	let mut res = Vec::new();
+	stream.read_to_end(&mut res).expect("Unable to read response.");
+
+	let pos = res.windows(4).position(|w| w == b"\r\n\r\n").expect("Missing headers.");
+	let head = String::from_utf8_lossy(&res[..pos]);
+	let body = &res[pos + 4..];
+	assert!(head.starts_with("HTTP/1.1 200 OK"), "Request failed:\n{head}");
+	assert!(head.contains("Content-Type: image/png"), "Wrong type:\n{head}");
+	assert!(head.contains(&format!("X-Flaca-Before: {}", raw.len())), "Wrong before:\n{head}");
+	assert!(head.contains(&format!("X-Flaca-After: {}", body.len())), "Wrong after:\n{head}");
+	assert!(body.starts_with(b"\x89PNG\r\n\x1a\n"), "Output is not a PNG.");
+	assert!(body.len() < raw.len(), "Output is not smaller.");
+
+	let _res = child.kill();
+	let _res = child.wait();
+}
+
+#[test]
+fn t_ab() {
+	let tree = Tree::new();
+	let dir = tree.root();
+	let a = dir.join("a.toml");
+	let b = dir.join("b.toml");
+	std::fs::write(&a, "no-jpeg = true\nzopfli-iterations = 1\n").expect("Unable to write a.toml.");
+	std::fs::write(&b, "no-jpeg = true\nzopfli-cost-model = \"v2\"\n").expect("Unable to write b.toml.");
+
+	let out = flaca([
+		OsStr::new("ab"),
+		OsStr::new("--settings-a"),
+		a.as_os_str(),
+		OsStr::new("--settings-b"),
+		b.as_os_str(),
+		tree.root().as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// There should be a row for each image, plus the header and total.
+	let stdout = String::from_utf8_lossy(&out.stdout);
+	assert!(stdout.starts_with("IMAGE"), "Missing header:\n{stdout}");
+	assert!(stdout.contains("small.png"), "Missing small.png:\n{stdout}");
+	assert!(stdout.lines().last().is_some_and(|l| l.starts_with("TOTAL")), "Missing total:\n{stdout}");
+
+	// The originals should be untouched.
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+}
+
+#[test]
+fn t_tmpdir() {
+	let tree = Tree::new();
+	let scratch = tree.root().join("scratch");
+	std::fs::create_dir_all(&scratch).expect("Unable to create directory.");
+	let a = tree.root().join("a.toml");
+	std::fs::write(&a, "no-jpeg = true\nzopfli-iterations = 1\n").expect("Unable to write a.toml.");
+
+	// A/B copies go to the scratch directory, and are cleaned up afterward.
+	let out = flaca([
+		OsStr::new("ab"),
+		OsStr::new("--tmpdir"),
+		scratch.as_os_str(),
+		OsStr::new("--settings-a"),
+		a.as_os_str(),
+		OsStr::new("--settings-b"),
+		a.as_os_str(),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(
+		std::fs::read_dir(&scratch).is_ok_and(|mut d| d.next().is_none()),
+		"Scratch files were left behind.",
+	);
+
+	// It has to be a directory.
+	let out = flaca([
+		OsStr::new("--tmpdir"),
+		a.as_os_str(),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("--tmpdir"));
+}
+
+#[test]
+fn t_preserve_links() {
+	use std::os::unix::fs::MetadataExt;
+
+	let tree = Tree::new();
+	let small = tree.root().join("small.png");
+	let raw = std::fs::read(&small).expect("Missing small.png.");
+
+	for (mode, code, shrunk, shared) in [
+		("skip", EXIT_NO_SAVINGS, false, true),
+		("break", 0, true, false),
+		("rewrite", 0, true, true),
+	] {
+		let src = tree.root().join(format!("links-{mode}.png"));
+		let link = tree.root().join(format!("links-{mode}-copy.png"));
+		std::fs::write(&src, &raw).expect("Unable to write image.");
+		std::fs::hard_link(&src, &link).expect("Unable to link image.");
+
+		let out = flaca([
+			OsStr::new("--preserve-links"),
+			OsStr::new(mode),
+			src.as_os_str(),
+		]);
+		assert_eq!(out.status.code(), Some(code), "Flaca failed ({mode}): {}", stderr(&out));
+
+		let now = std::fs::read(&src).expect("Missing image.");
+		assert_eq!(now.len() < raw.len(), shrunk, "Wrong size ({mode}).");
+		assert_eq!(
+			std::fs::read(&link).expect("Missing link.") == now,
+			shared,
+			"Wrong link contents ({mode}).",
+		);
+		assert_eq!(
+			std::fs::metadata(&src).expect("Missing image.").nlink() == 2,
+			shared,
+			"Wrong link count ({mode}).",
+		);
+	}
+
+	// Rewriting through the links should work with mapped input too.
+	let src = tree.root().join("links-mmap.png");
+	let link = tree.root().join("links-mmap-copy.png");
+	std::fs::write(&src, &raw).expect("Unable to write image.");
+	std::fs::hard_link(&src, &link).expect("Unable to link image.");
+	let out = flaca([
+		OsStr::new("--preserve-links"),
+		OsStr::new("rewrite"),
+		OsStr::new("--mmap-threshold"),
+		OsStr::new("1"),
+		OsStr::new("--verify"),
+		src.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed (mmap): {}", stderr(&out));
+	let now = std::fs::read(&src).expect("Missing image.");
+	assert!(now.len() < raw.len(), "Image not compressed (mmap).");
+	assert_eq!(std::fs::read(&link).ok(), Some(now), "Wrong link contents (mmap).");
+	assert_eq!(std::fs::metadata(&src).expect("Missing image.").nlink(), 2, "Wrong link count (mmap).");
+
+	// Nonsense modes are rejected.
+	let out = flaca([OsStr::new("--preserve-links"), OsStr::new("maybe"), small.as_os_str()]);
+	assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn t_report() {
+	let tree = Tree::new();
+	let report = tree.root().join("report.csv");
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--report"),
+		report.as_os_str(),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// There should be a header and one row per image.
+	let report = std::fs::read_to_string(&report).expect("Missing report.");
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let mut lines = report.lines();
+	assert_eq!(
+		lines.next(),
+		Some("path,kind,before,after,status,duration_ms,winner,gif_saved,ico_saved,mozjpeg_saved,oxipng_saved,zopflipng_saved"),
+	);
+	assert_eq!(lines.clone().count(), total, "Wrong number of rows:\n{report}");
+
+	// The small PNG should have been optimized — by one of the PNG encoders —
+	// and the photos skipped.
+	let small = format!("{},png,", tree.root().join("small.png").display());
+	assert!(
+		lines.clone().any(|l|
+			l.starts_with(&small) &&
+			l.contains(",optimized,") &&
+			(l.contains(",oxipng,") || l.contains(",zopflipng,"))
+		),
+		"Missing small.png row:\n{report}",
+	);
+	let photos = tree.root().join("photos").display().to_string();
+	assert!(
+		lines.filter(|l| l.starts_with(&photos))
+			.all(|l| l.contains(",jpeg,0,0,skipped,") && l.ends_with(",,,,,,")),
+		"JPEGs weren't skipped:\n{report}",
+	);
+}
+
+#[test]
+fn t_metrics_file() {
+	let tree = Tree::new();
+	let metrics = tree.root().join("flaca.prom");
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--metrics-file"),
+		metrics.as_os_str(),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	let metrics = std::fs::read_to_string(&metrics).expect("Missing metrics.");
+	let value = |name: &str| -> u64 {
+		metrics.lines()
+			.find_map(|l| l.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+			.unwrap_or_else(|| panic!("Missing {name}:\n{metrics}"))
+	};
+
+	// Every image should be accounted for.
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let optimized = value("flaca_images_total{status=\"optimized\"}");
+	let sum = optimized +
+		value("flaca_images_total{status=\"unchanged\"}") +
+		value("flaca_images_total{status=\"skipped\"}") +
+		value("flaca_images_total{status=\"failed\"}");
+	assert_eq!(sum, total as u64, "Wrong image count:\n{metrics}");
+	assert!(0 < optimized, "Nothing was optimized:\n{metrics}");
+	assert!(0 < value("flaca_images_total{status=\"failed\"}"), "Nothing failed:\n{metrics}");
+
+	// And the bytes should add up.
+	assert_eq!(
+		value("flaca_bytes_saved_total"),
+		value("flaca_bytes_before_total") - value("flaca_bytes_after_total"),
+	);
+	assert!(0 < value("flaca_bytes_saved_total"), "Nothing was saved:\n{metrics}");
+}
+
+#[test]
+fn t_no_recurse() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--no-recurse"),
+		OsStr::new("--max-depth"),
+		OsStr::new("5"),
+		tree.root().as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Only the top-level image should have been touched.
+	let top = tree.root().join("small.png");
+	for e in tree.entries() {
+		if e.path == top { assert_ne!(e.current(), e.raw, "small.png wasn't optimized."); }
+		else { assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display()); }
+	}
+}
+
+#[test]
+fn t_respect_gitignore() {
+	let tree = Tree::new();
+	std::fs::write(tree.root().join(".gitignore"), b"nested/\n*.gif\n")
+		.expect("Unable to write .gitignore.");
+	std::fs::write(tree.root().join(".flacaignore"), b"!01.gif\n")
+		.expect("Unable to write .flacaignore.");
+
+	let out = flaca([
+		OsStr::new("--respect-gitignore"),
+		OsStr::new("--no-jpeg"),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// Ignored images should be untouched; the rest fair game.
+	for e in tree.entries() {
+		if e.path.starts_with(tree.root().join("nested")) || e.path.ends_with("animated.gif") {
+			assert_eq!(e.current(), e.raw, "Ignored file changed: {}", e.path.display());
+		}
+	}
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png")).expect("Missing small.png.");
+	assert_ne!(small.current(), small.raw, "small.png wasn't optimized.");
+}
+
+#[test]
+fn t_target_size() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+
+	// Nobody's getting under one byte.
+	let out = flaca([OsStr::new("--target-size"), OsStr::new("1"), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(4), "Flaca failed: {}", stderr(&out));
+	let err = stderr(&out);
+	assert!(err.contains("1 image still exceeded"), "Missing summary:\n{err}");
+	assert!(err.contains(&*src.to_string_lossy()), "Missing path:\n{err}");
+
+	// But a gigabyte is fine.
+	let out = flaca([OsStr::new("--target-size"), OsStr::new("1g"), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	assert!(! stderr(&out).contains("--target-size"));
+}
+
+#[test]
+fn t_top() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--top"),
+		OsStr::new("1"),
+		tree.root().join("nested").as_os_str(),
+		tree.root().join("small.png").as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Both boards should be there, one image apiece.
+	let err = stderr(&out);
+	assert!(err.contains("Biggest savings (bytes):"), "Missing board:\n{err}");
+	assert!(err.contains("Biggest savings (percent):"), "Missing board:\n{err}");
+	let root = tree.root().to_string_lossy();
+	assert_eq!(err.matches(&*root).count(), 2, "Wrong number of entries:\n{err}");
+}
+
+#[test]
+fn t_ndjson() {
+	let tree = Tree::new();
+	let out = flaca([
+		OsStr::new("--ndjson"),
+		OsStr::new("--no-jpeg"),
+		tree.root().join("small.png").as_os_str(),
+		tree.root().join("broken").as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// One line per image.
+	let stdout = String::from_utf8_lossy(&out.stdout);
+	let lines: Vec<&str> = stdout.lines().collect();
+	assert_eq!(lines.len(), 4, "Wrong number of lines:\n{stdout}");
+	assert!(lines.iter().all(|l| l.starts_with("{\"path\":\"") && l.ends_with('}')));
+
+	let small = tree.root().join("small.png");
+	let small = lines.iter()
+		.find(|l| l.contains(&*small.to_string_lossy()))
+		.expect("Missing small.png.");
+	assert!(small.contains("\"status\":\"ok\",\"before\":"), "Wrong line: {small}");
+	assert_eq!(
+		lines.iter().filter(|l| l.contains("\"status\":\"error\"")).count(),
+		2,
+		"Wrong number of errors:\n{stdout}",
+	);
+
+	// Empty files are skipped rather than failed.
+	assert!(
+		lines.iter().any(|l| l.contains("empty.jpg\",\"status\":\"skipped\"")),
+		"Missing skip:\n{stdout}",
+	);
+}
+
+#[test]
+fn t_on_change() {
+	let tree = Tree::new();
+	let log = tree.root().join("changed.log");
+	let mut cmd = OsString::from("printf '%s\\n' {} >> ");
+	cmd.push(log.as_os_str());
+	let run = || flaca([
+		OsStr::new("--on-change"),
+		cmd.as_os_str(),
+		tree.root().join("small.png").as_os_str(),
+		tree.root().join("broken").as_os_str(),
+	]);
+
+	// Only the image that actually changed should be logged.
+	let out = run();
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	let expected = format!("{}\n", tree.root().join("small.png").display());
+	assert_eq!(std::fs::read_to_string(&log).ok(), Some(expected.clone()));
+
+	// And nothing the second time around.
+	let out = run();
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	assert_eq!(std::fs::read_to_string(&log).ok(), Some(expected));
+}
+
+#[test]
+fn t_confirm_cmd() {
+	let tree = Tree::new();
+	let small = tree.entries().iter().find(|e| e.path.ends_with("small.png"))
+		.expect("Missing small.png.");
+
+	// Declined rewrites leave the original be.
+	let out = flaca([OsStr::new("--confirm-cmd"), OsStr::new("false"), small.path.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	assert_eq!(small.current(), small.raw, "The image was rewritten anyway.");
+
+	// Approved ones don't.
+	let out = flaca([
+		OsStr::new("--confirm-cmd"),
+		OsStr::new("test -f {} && test {before} -gt {after}"),
+		small.path.as_os_str(),
+	]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(small.current().len() < small.raw.len(), "The image wasn't rewritten.");
+}
+
+#[test]
+fn t_mark() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let metrics = tree.root().join("flaca.prom");
+	let skipped = |args: &[&OsStr]| -> bool {
+		let out = flaca(args.iter().copied().chain([
+			OsStr::new("--metrics-file"),
+			metrics.as_os_str(),
+			src.as_os_str(),
+		]));
+		assert!(out.status.code().is_some_and(|c| c == 0 || c == EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+		std::fs::read_to_string(&metrics).expect("Missing metrics.")
+			.lines()
+			.any(|l| l == "flaca_images_total{status=\"skipped\"} 1")
+	};
+
+	// Nothing is marked yet.
+	assert!(! skipped(&[OsStr::new("--skip-marked")]));
+
+	// Crunch and mark it.
+	assert!(! skipped(&[OsStr::new("--mark")]));
+	let raw = std::fs::read(&src).expect("Missing small.png.");
+
+	// Some filesystems don't do xattrs, but if this one does, the next run
+	// should skip it, leaving it untouched.
+	if skipped(&[OsStr::new("--skip-marked")]) {
+		assert_eq!(std::fs::read(&src).ok(), Some(raw), "The file changed.");
+
+		// But only when asked.
+		assert!(! skipped(&[]));
+	}
+}
+
+#[test]
+fn t_env() {
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let run = |var: &str, value: &str, args: &[&OsStr]| Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.env(var, value)
+		.args(args)
+		.arg(&src)
+		.output()
+		.expect("Unable to run flaca.");
+
+	// The environment limit should cause the image to be skipped.
+	let out = run("FLACA_MAX_FILESIZE", "1", &[]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+
+	// Unless overridden on the command line.
+	let out = run("FLACA_MAX_FILESIZE", "1", &[OsStr::new("--max-filesize"), OsStr::new("1m")]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Bad flag values are an error.
+	let out = run("FLACA_NO_PNG", "maybe", &[]);
+	assert_eq!(out.status.code(), Some(1), "Flaca should have failed.");
+}
+
+#[test]
+fn t_errors() {
+	let tree = Tree::new();
+	let log = tree.root().join("errors.tsv");
+	let out = flaca([
+		OsStr::new("--no-jpeg"),
+		OsStr::new("--errors"),
+		log.as_os_str(),
+		tree.root().as_os_str(),
+	]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	// The broken images should be listed, but not the kind-filtered JPEGs.
+	let log = std::fs::read_to_string(&log).expect("Missing error log.");
+	let herring = format!("{}\tinvalid format\n", tree.root().join("broken/herring.png").display());
+	assert!(log.contains(&herring), "Missing herring.png:\n{log}");
+	assert!(! log.contains("photos"), "JPEGs were logged:\n{log}");
+}
+
+#[test]
+fn t_no_fix() {
+	let tree = Tree::new();
+
+	// Break the IHDR checksum.
+	let path = tree.root().join("damaged.png");
+	let mut raw = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+	raw[29] ^= 0xFF;
+	std::fs::write(&path, &raw).expect("Unable to write damaged.png.");
+
+	// It should be rejected…
+	let out = flaca([OsStr::new("--no-fix"), path.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	assert_eq!(std::fs::read(&path).ok(), Some(raw.clone()), "The damaged image was changed.");
+
+	// …unless repairs are allowed.
+	let out = flaca([path.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert_ne!(std::fs::read(&path).ok(), Some(raw), "The damaged image wasn't repaired.");
+}
+
+#[test]
+fn t_keep_colortype() {
+	let tree = Tree::new();
+
+	// This one is normally reduced from 8-bit gray+alpha to 1-bit gray.
+	let path = tree.root().join("nested/deeper/still/small-bwa.png");
+	let raw = std::fs::read(&path).expect("Unable to read small-bwa.png.");
+
+	let out = flaca([OsStr::new("--keep-colortype"), path.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// It should be smaller, but with the same bit depth and color type.
+	let new = std::fs::read(&path).expect("Unable to read small-bwa.png.");
+	assert!(new.len() < raw.len(), "The image wasn't compressed.");
+	assert_eq!(new[24..26], raw[24..26], "The color type changed.");
+}
+
+#[test]
+fn t_png_exhaustive() {
+	let tree = Tree::new();
+
+	// Crunch two copies of the same image, one exhaustively.
+	let a = tree.root().join("small.png");
+	let b = tree.root().join("small-exhaustive.png");
+	std::fs::copy(&a, &b).expect("Unable to copy small.png.");
+
+	let out = flaca([a.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let out = flaca([OsStr::new("--png-exhaustive"), OsStr::new("--verify"), b.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// The brute-forced version should be no worse.
+	let a = std::fs::metadata(&a).expect("Missing small.png.").len();
+	let b = std::fs::metadata(&b).expect("Missing small-exhaustive.png.").len();
+	assert!(b <= a, "Exhaustive was worse: {b} > {a}.");
+}
+
+#[test]
+fn t_keep_alpha_data() {
+	let tree = Tree::new();
+
+	// A noisy RGB pattern hiding under a fully transparent alpha channel.
+	let rgba: Vec<u8> = (0..32_u32 * 32).flat_map(|i| {
+		let [a, b, c, _] = i.wrapping_mul(2_654_435_761).to_le_bytes();
+		[a, b, c, 0]
+	}).collect();
+	let raw = oxipng::RawImage::new(32, 32, oxipng::ColorType::RGBA, oxipng::BitDepth::Eight, rgba)
+		.and_then(|img| img.create_optimized_png(&oxipng::Options::from_preset(0)))
+		.expect("Unable to build hidden.png.");
+	let path = tree.root().join("hidden.png");
+	std::fs::write(&path, &raw).expect("Unable to write hidden.png.");
+
+	// Every byte of the hidden data should survive…
+	let out = flaca([OsStr::new("--keep-alpha-data"), path.as_os_str()]);
+	assert!(out.status.code().is_some_and(|c| c == 0 || c == EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	let new = std::fs::read(&path).expect("Unable to read hidden.png.");
+	flapfli::set_keep_alpha_data(true);
+	assert!(flapfli::same_pixels(&raw, &new), "The hidden data changed.");
+
+	// …unless it doesn't matter.
+	let out = flaca([path.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	let new = std::fs::read(&path).expect("Unable to read hidden.png.");
+	assert!(! flapfli::same_pixels(&raw, &new), "The hidden data wasn't optimized.");
+}
+
+#[test]
+fn t_fix_extensions() {
+	let tree = Tree::new();
+
+	// A PNG pretending to be a JPEG.
+	let raw = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+	let src = tree.root().join("fake.jpg");
+	let dst = tree.root().join("fake.png");
+	std::fs::write(&src, &raw).expect("Unable to write fake.jpg.");
+
+	// It should be flagged but left where it is…
+	let out = flaca([OsStr::new("-p"), src.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(stderr(&out).contains("mismatched extension"), "Missing warning: {}", stderr(&out));
+	assert!(src.is_file() && ! dst.exists(), "The image was renamed.");
+
+	// …unless fixes are allowed.
+	let out = flaca([OsStr::new("-p"), OsStr::new("--fix-extensions"), src.as_os_str()]);
+	assert_eq!(out.status.code(), Some(EXIT_NO_SAVINGS), "Flaca failed: {}", stderr(&out));
+	assert!(stderr(&out).contains("extension corrected"), "Missing warning: {}", stderr(&out));
+	assert!(! src.exists() && dst.is_file(), "The image wasn't renamed.");
+}
+
+#[test]
+fn t_sniff() {
+	let tree = Tree::new();
+
+	// An extension-less image and non-image, CMS-style.
+	let dir = tree.root().join("uploads");
+	std::fs::create_dir_all(&dir).expect("Unable to create directory.");
+	let raw = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+	let img = dir.join("5d41402abc4b2a76");
+	let txt = dir.join("7d793037a0760186");
+	std::fs::write(&img, &raw).expect("Unable to write image.");
+	std::fs::write(&txt, b"Hello World").expect("Unable to write file.");
+
+	// Neither is an image without sniffing…
+	let out = flaca([dir.as_os_str()]);
+	assert_eq!(out.status.code(), Some(1));
+	assert!(stderr(&out).contains("No images were found."));
+
+	// …but with it, the real image should be found and crunched.
+	let out = flaca([OsStr::new("--sniff"), dir.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	assert!(std::fs::read(&img).is_ok_and(|new| new.len() < raw.len()), "Image unchanged.");
+	assert_eq!(std::fs::read(&txt).ok().as_deref(), Some(&b"Hello World"[..]), "File changed.");
+}
+
+#[test]
+fn t_extensions() {
+	let tree = Tree::new();
+
+	// Shouty and aliased extensions should all be found.
+	let dir = tree.root().join("windows");
+	std::fs::create_dir_all(&dir).expect("Unable to create directory.");
+	let png = std::fs::read(tree.root().join("small.png")).expect("Unable to read small.png.");
+	let jpg = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../skel/assets/jpg/01.jpg"))
+		.expect("Unable to read 01.jpg.");
+	let files = [
+		(dir.join("UPPER.PNG"), &png),
+		(dir.join("Mixed.Png"), &png),
+		(dir.join("PHOTO.JPEG"), &jpg),
+		(dir.join("photo.jpe"), &jpg),
+		(dir.join("photo.JFIF"), &jpg),
+	];
+	for (path, raw) in &files {
+		std::fs::write(path, raw).expect("Unable to write image.");
+	}
+
+	let out = flaca([dir.as_os_str()]);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+	for (path, raw) in &files {
+		let new = std::fs::read(path).expect("Unable to read image.");
+		assert!(new.len() < raw.len(), "Image unchanged: {}", path.display());
+	}
+}
+
+#[test]
+fn t_list_null() {
+	let tree = Tree::new();
+
+	// Build a NUL-delimited list with a PNG and a non-image.
+	let mut list = Vec::new();
+	for rel in ["small.png", "nested/data.json"] {
+		list.extend_from_slice(tree.root().join(rel).as_os_str().as_bytes());
+		list.push(0);
+	}
+
+	// Feed it in via STDIN.
+	let out = flaca_piped(&["--no-jpeg", "-0", "-l", "-"], &list);
+	assert!(out.status.success(), "Flaca failed: {}", stderr(&out));
+
+	// Only the listed PNG should have changed.
+	let small = tree.root().join("small.png");
+	for e in tree.entries() {
+		if e.path == small {
+			assert!(e.current().len() < e.raw.len(), "Image unchanged: {}", e.path.display());
+		}
+		else {
+			assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+		}
+	}
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn t_faults() {
+	let tree = Tree::new();
+
+	// Every write fails, so nothing should change and every image should be
+	// counted as skipped.
+	let out = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.env("FLACA_FAULTS", "enospc=1")
+		.args([OsStr::new("-p"), OsStr::new("--no-jpeg"), tree.root().as_os_str()])
+		.output()
+		.expect("Unable to run flaca.");
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+
+	for e in tree.entries() {
+		assert_eq!(e.current(), e.raw, "File changed: {}", e.path.display());
+	}
+
+	// Every image either failed outright or would have needed writing.
+	let total = tree.count(Kind::Image) + tree.count(Kind::Jpeg) + tree.count(Kind::Broken);
+	let err = stderr(&out);
+	assert!(err.contains(&summary(0, total)), "Expected 0/{total} images:\n{err}");
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn t_faults_mmap_links() {
+	use std::os::unix::fs::MetadataExt;
+
+	let tree = Tree::new();
+	let src = tree.root().join("small.png");
+	let link = tree.root().join("small-copy.png");
+	let raw = std::fs::read(&src).expect("Missing small.png.");
+	std::fs::hard_link(&src, &link).expect("Unable to link image.");
+
+	// The second read — the post-write verification — fails, so the original
+	// has to be written back through the links. That only works if the
+	// original wasn't mapped from the inode being rewritten.
+	let out = Command::new(env!("CARGO_BIN_EXE_flaca"))
+		.env("FLACA_FAULTS", "fail-read=2")
+		.args([
+			OsStr::new("--preserve-links"),
+			OsStr::new("rewrite"),
+			OsStr::new("--mmap-threshold"),
+			OsStr::new("1"),
+			OsStr::new("--verify"),
+			src.as_os_str(),
+		])
+		.output()
+		.expect("Unable to run flaca.");
+	assert_eq!(out.status.code(), Some(EXIT_PARTIAL), "Flaca failed: {}", stderr(&out));
+	assert_eq!(std::fs::read(&src).ok(), Some(raw.clone()), "Original not restored.");
+	assert_eq!(std::fs::read(&link).ok(), Some(raw), "Link not restored.");
+	assert_eq!(std::fs::metadata(&src).expect("Missing image.").nlink(), 2, "Wrong link count.");
+}
@@ -0,0 +1,198 @@
+/*!
+# Flaca: Synthetic Tree Generator
+
+This builds a throwaway directory tree full of good images, bad images,
+non-images, symlinks, and other oddities for the integration tests to chew
+on.
+*/
+
+use std::{
+	path::{
+		Path,
+		PathBuf,
+	},
+	sync::atomic::{
+		AtomicUsize,
+		Ordering::Relaxed,
+	},
+};
+
+
+
+/// # Tree Counter.
+///
+/// Tests run in parallel, so each tree needs a unique root.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// # Skeleton Assets.
+const SKEL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../skel/assets");
+
+
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// # Entry Kind.
+///
+/// This describes what the binary ought to make of a given file.
+pub(crate) enum Kind {
+	/// # Valid Image.
+	Image,
+
+	/// # Valid JPEG.
+	///
+	/// (These are tracked separately so tests can opt out of them.)
+	Jpeg,
+
+	/// # Broken or Bogus Image.
+	Broken,
+
+	/// # Not an Image at All.
+	Other,
+}
+
+#[derive(Debug)]
+/// # Entry.
+pub(crate) struct Entry {
+	/// # Path.
+	pub(crate) path: PathBuf,
+
+	/// # Original Contents.
+	pub(crate) raw: Vec<u8>,
+
+	/// # Kind.
+	pub(crate) kind: Kind,
+}
+
+impl Entry {
+	/// # Current Contents.
+	pub(crate) fn current(&self) -> Vec<u8> {
+		std::fs::read(&self.path).unwrap_or_default()
+	}
+}
+
+
+
+#[derive(Debug)]
+/// # Synthetic Tree.
+///
+/// The tree is deleted on drop.
+pub(crate) struct Tree {
+	/// # Root Directory.
+	root: PathBuf,
+
+	/// # Regular Files.
+	entries: Vec<Entry>,
+}
+
+impl Drop for Tree {
+	fn drop(&mut self) {
+		// Restore permissions so everything can be deleted.
+		#[cfg(unix)]
+		for e in &self.entries { let _res = set_mode(&e.path, 0o644); }
+		let _res = std::fs::remove_dir_all(&self.root);
+	}
+}
+
+impl Tree {
+	/// # New.
+	///
+	/// Generate a fresh tree containing:
+	/// * A handful of valid PNG, GIF, and JPEG images, some nested;
+	/// * Empty, truncated, and mislabeled "images";
+	/// * Non-image files, including one with an image extension;
+	/// * A symlink to a valid image, and a dangling one;
+	/// * An unreadable image.
+	pub(crate) fn new() -> Self {
+		let root = std::env::temp_dir().join(format!(
+			"flaca-tree-{}-{}",
+			std::process::id(),
+			COUNTER.fetch_add(1, Relaxed),
+		));
+		let _res = std::fs::remove_dir_all(&root);
+		std::fs::create_dir_all(&root).expect("Unable to create tree root.");
+		let mut out = Self { root, entries: Vec::new() };
+
+		// Good images.
+		out.copy("png/small.png", "small.png", Kind::Image);
+		out.copy("png/small-bw.png", "nested/small-bw.png", Kind::Image);
+		out.copy("png/small-bwa.png", "nested/deeper/still/small-bwa.png", Kind::Image);
+		out.copy("gif/01.gif", "gif/01.gif", Kind::Image);
+		out.copy("gif/02.gif", "gif/animated.gif", Kind::Image);
+		out.copy("jpg/13.jpg", "photos/13.jpg", Kind::Jpeg);
+		out.copy("jpg/14.jpg", "photos/14.jpeg", Kind::Jpeg);
+
+		// Bad images.
+		out.copy("empty.jpg", "broken/empty.jpg", Kind::Broken);
+		out.copy("herring.png", "broken/herring.png", Kind::Broken);
+		let raw = read_skel("png/small.png");
+		out.write("broken/truncated.png", &raw[..20], Kind::Broken);
+
+		// Not images.
+		out.write("README.txt", b"Nothing to see here.\n", Kind::Other);
+		out.write("nested/data.json", b"{\"flaca\":true}\n", Kind::Other);
+		out.copy("executable.sh", "nested/executable.sh", Kind::Other);
+
+		// Symlinks.
+		#[cfg(unix)]
+		{
+			let _res = std::os::unix::fs::symlink(out.root.join("small.png"), out.root.join("link.png"));
+			let _res = std::os::unix::fs::symlink(out.root.join("missing.png"), out.root.join("dangling.png"));
+		}
+
+		// Permission oddities.
+		#[cfg(unix)]
+		{
+			out.copy("png/small.png", "locked/small.png", Kind::Image);
+			set_mode(&out.root.join("locked/small.png"), 0o000).expect("Unable to set permissions.");
+		}
+
+		out
+	}
+
+	/// # Root.
+	pub(crate) fn root(&self) -> &Path { &self.root }
+
+	/// # Entries.
+	pub(crate) fn entries(&self) -> &[Entry] { &self.entries }
+
+	/// # Locked Image.
+	///
+	/// Return the unreadable image, if any.
+	pub(crate) fn locked(&self) -> Option<&Entry> {
+		self.entries.iter().find(|e| e.path.ends_with("locked/small.png"))
+	}
+
+	/// # Count Entries by Kind.
+	pub(crate) fn count(&self, kind: Kind) -> usize {
+		self.entries.iter().filter(|e| e.kind == kind).count()
+	}
+
+	/// # Copy a Skeleton Asset.
+	fn copy(&mut self, src: &str, dst: &str, kind: Kind) {
+		let raw = read_skel(src);
+		self.write(dst, &raw, kind);
+	}
+
+	/// # Write a File.
+	fn write(&mut self, dst: &str, raw: &[u8], kind: Kind) {
+		let path = self.root.join(dst);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).expect("Unable to create directory.");
+		}
+		std::fs::write(&path, raw).expect("Unable to write file.");
+		self.entries.push(Entry { path, raw: raw.to_vec(), kind });
+	}
+}
+
+
+
+/// # Read Skeleton Asset.
+fn read_skel(src: &str) -> Vec<u8> {
+	std::fs::read(Path::new(SKEL).join(src)).expect("Missing skeleton asset.")
+}
+
+#[cfg(unix)]
+/// # Set Permissions.
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
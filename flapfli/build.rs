@@ -49,6 +49,7 @@ const ZOPFLI_WINDOW_SIZE: u16 = 32_768;
 pub fn main() {
 	println!("cargo:rerun-if-env-changed=CARGO_PKG_VERSION");
 	println!("cargo:rerun-if-env-changed=TARGET_CPU");
+	println!("cargo:rerun-if-env-changed=CARGO_CFG_TARGET_ARCH");
 	println!("cargo:rerun-if-changed=../skel/vendor/");
 
 	#[cfg(not(target_pointer_width = "64"))]
@@ -63,20 +64,26 @@ pub fn main() {
 /// The Rust port of `lodepng` is missing some functionality that is required
 /// to fully emulate `zopflipng`, so we're stuck with the C version until I
 /// decide to completely rewrite that too. Haha.
+///
+/// When targeting `wasm32`, there's no libc to lean on, so it is compiled
+/// freestanding, with a tiny shim standing in for the one header it needs.
 fn build_ffi() {
 	// Define some paths.
 	let repo = Path::new("../skel/vendor");
 	let lodepng_src = repo.join("lodepng");
+	let wasm_shim = wasm().then(|| repo.join("wasm"));
 
 	// Build Zopfli first.
 	let mut c = cc::Build::new();
+	if let Some(shim) = wasm_shim.as_deref() {
+		c.include(shim).flag("-ffreestanding");
+	}
 	c.includes([repo, &lodepng_src])
 		.cpp(false)
 		.flag_if_supported("-W")
 		.flag_if_supported("-ansi")
 		.flag_if_supported("-pedantic")
 		.pic(true)
-		.static_flag(true)
 		.files([
 			lodepng_src.join("lodepng.c"),
 		])
@@ -87,7 +94,10 @@ fn build_ffi() {
 		.define("LODEPNG_NO_COMPILE_DISK", None)
 		.compile("lodepng");
 
-	bindings(&lodepng_src);
+	// Without bindgen, the pre-generated copy of the bindings is used
+	// instead, so libclang isn't required.
+	#[cfg(feature = "bindgen")]
+	bindings(&lodepng_src, wasm_shim.as_deref());
 }
 
 /// # Build Symbols.
@@ -110,7 +120,7 @@ fn build_symbols() {
 		NumEnum::new(0..32_u16, "Distance Symbols.", "Dsym"),
 		NumEnum::new(0..259_u16, "Lit/Lengths.", "LitLen").with_eq().with_iter(),
 		NumEnum::new(0..286_u16, "Lit/Length Symbols.", "Lsym"),
-		NumEnum::new(0..15_u8, "Block Split Length.", "SplitLen").with_eq(),
+		NumEnum::new(0..64_u8, "Block Split Length.", "SplitLen").with_eq(),
 		NumEnum::new(0..30_u8, "Tree Symbol Distances.", "TreeDist").with_eq(),
 	);
 
@@ -181,11 +191,14 @@ pub(crate) const {name}_F: [f64; {N}] = {:?};
 	write(&out_path("symbols.rs"), out.as_bytes());
 }
 
+#[cfg(feature = "bindgen")]
 /// # FFI Bindings.
 ///
-/// These have been manually transcribed into the Rust sources, but this
-/// commented-out code can be re-enabled if they ever need to be updated.
-fn bindings(lodepng_src: &Path) {
+/// Generate the lodepng bindings. A copy of the output is also kept in
+/// `src/lodepng-bindgen.rs` for builds without bindgen, and will need to be
+/// updated by hand if anything here changes. (The `t_bindgen_copy` test will
+/// fail until it is.)
+fn bindings(lodepng_src: &Path, wasm_shim: Option<&Path>) {
 	bindgen::Builder::default()
 		.clang_args([
 			"-DLODEPNG_NO_COMPILE_ALLOCATORS",
@@ -194,12 +207,15 @@ fn bindings(lodepng_src: &Path) {
 			"-DLODEPNG_NO_COMPILE_CRC",
 			"-DLODEPNG_NO_COMPILE_DISK",
 		])
+		.clang_args(wasm_shim.map(|p| format!("-I{}", p.display())))
 		.header(lodepng_src.join("lodepng.h").to_string_lossy())
 		.allowlist_function("lodepng_color_mode_copy")
 		.allowlist_function("lodepng_color_stats_init")
 		.allowlist_function("lodepng_compute_color_stats")
 		.allowlist_function("lodepng_decode")
 		.allowlist_function("lodepng_encode")
+		.allowlist_function("lodepng_palette_add")
+		.allowlist_function("lodepng_palette_clear")
 		.allowlist_function("lodepng_state_cleanup")
 		.allowlist_function("lodepng_state_init")
 		.allowlist_type("LodePNGColorStats")
@@ -218,6 +234,11 @@ fn bindings(lodepng_src: &Path) {
 		.expect("Unable to save bindings");
 }
 
+/// # Targeting WASM?
+fn wasm() -> bool {
+	std::env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|a| a == "wasm32")
+}
+
 /// # Output Path.
 ///
 /// Append the sub-path to OUT_DIR and return it.
@@ -0,0 +1,214 @@
+/*!
+# Flapfli: Ancillary Chunks.
+
+Lodepng is compiled without ancillary chunk support, so any chunks worth
+keeping have to be copied over from the source manually after encoding.
+*/
+
+use std::{
+	num::NonZeroUsize,
+	sync::OnceLock,
+};
+use super::{
+	EncodedPNG,
+	ffi::flapfli_allocate,
+};
+
+
+
+/// # Chunks to Keep.
+///
+/// Note: This value is only (possibly) set (once) during `flaca`'s
+/// initialization; it won't change after that.
+static KEEP_CHUNKS: OnceLock<Box<[[u8; 4]]>> = OnceLock::new();
+
+/// # Color-Dependent Chunks.
+///
+/// These chunks describe the pixels in terms of the source's color type and
+/// bit depth, so can't survive re-encoding.
+const COLOR_CHUNKS: [[u8; 4]; 4] = [*b"bKGD", *b"hIST", *b"sBIT", *b"tRNS"];
+
+/// # Colorspace Chunks.
+///
+/// These chunks are only valid for images of the same class — greyscale or
+/// color — as the source.
+const SPACE_CHUNKS: [[u8; 4]; 3] = [*b"cHRM", *b"iCCP", *b"sRGB"];
+
+/// # PNG Signature Length.
+const SIG_LEN: usize = 8;
+
+/// # IHDR Color Type Index.
+///
+/// Signature + length + name + width + height + bit depth.
+const IHDR_COLOR: usize = SIG_LEN + 4 + 4 + 4 + 4 + 1;
+
+/// # End of IHDR.
+///
+/// The signature is followed by the IHDR chunk, which is always 25 bytes:
+/// 4 (length) + 4 (name) + 13 (data) + 4 (CRC).
+const IHDR_END: usize = SIG_LEN + 25;
+
+
+
+/// # Chunks Kept.
+///
+/// Return the ancillary chunk names (if any) that should be preserved during
+/// optimization.
+pub fn keep_chunks() -> &'static [[u8; 4]] {
+	KEEP_CHUNKS.get().map_or(&[], |k| k)
+}
+
+/// # Set Chunks to Keep.
+///
+/// Preserve the named ancillary chunks (e.g. `iCCP`) instead of stripping
+/// them along with everything else.
+///
+/// Note: chunk ordering constraints are resolved by placing all kept chunks
+/// directly after the `IHDR`, so only chunks that are valid in that position
+/// — and independent of the image's color type — can be kept. Critical
+/// chunks are ignored.
+///
+/// Colorspace chunks (`cHRM`, `iCCP`, and `sRGB`) are dropped from any output
+/// that crosses between greyscale and color.
+///
+/// Returns `false` if any of `bKGD`, `hIST`, `sBIT`, or `tRNS` are named, or
+/// if the chunks were already set.
+pub fn set_keep_chunks(mut names: Vec<[u8; 4]>) -> bool {
+	if names.iter().any(|n| COLOR_CHUNKS.contains(n)) { return false; }
+
+	// Critical chunks (uppercase first letter) are lodepng's business.
+	names.retain(|n| n[0].is_ascii_lowercase());
+	names.sort_unstable();
+	names.dedup();
+	KEEP_CHUNKS.set(names.into_boxed_slice()).is_ok()
+}
+
+
+
+impl EncodedPNG {
+	#[expect(unsafe_code, reason = "For alloc.")]
+	/// # Copy Ancillary Chunks.
+	///
+	/// Copy any to-be-kept chunks from `src` into `self`, inserting them
+	/// directly after the `IHDR`.
+	///
+	/// Colorspace chunks are skipped if the color type has changed between
+	/// greyscale and color.
+	///
+	/// If there's nothing to keep, or either image is malformed, `self` is
+	/// left as-is.
+	pub(crate) fn copy_ancillary(&mut self, src: &[u8]) {
+		let keep = keep_chunks();
+		if keep.is_empty() || self.size <= IHDR_END { return; }
+		let same_class = is_grey(src).is_some_and(|g| Some(g) == is_grey(self));
+
+		// Find the chunks worth keeping.
+		let mut extra = Vec::new();
+		for (name, chunk) in ChunkIter::new(src) {
+			if keep.contains(&name) && (same_class || ! SPACE_CHUNKS.contains(&name)) {
+				extra.extend_from_slice(chunk);
+			}
+		}
+		if extra.is_empty() { return; }
+
+		let Some(size) = NonZeroUsize::new(self.size + extra.len()) else { return; };
+
+		// Safety: the new buffer is large enough to hold everything, and the
+		// old one is non-null per the size check above.
+		let new = unsafe {
+			let buf = flapfli_allocate(std::ptr::null_mut(), size).as_ptr();
+			std::ptr::copy_nonoverlapping(self.buf, buf, IHDR_END);
+			std::ptr::copy_nonoverlapping(extra.as_ptr(), buf.add(IHDR_END), extra.len());
+			std::ptr::copy_nonoverlapping(
+				self.buf.add(IHDR_END),
+				buf.add(IHDR_END + extra.len()),
+				self.size - IHDR_END,
+			);
+			Self { buf, size: size.get() }
+		};
+
+		// The old buffer will be freed on drop.
+		drop(std::mem::replace(self, new));
+	}
+}
+
+
+
+/// # Greyscale?
+///
+/// Return `true` if the PNG's color type is greyscale (with or without
+/// alpha), `false` if it is color (truecolor or indexed), or `None` if it is
+/// too short to say.
+fn is_grey(src: &[u8]) -> Option<bool> {
+	src.get(IHDR_COLOR).map(|c| 0 == c & 2)
+}
+
+/// # Chunk Iterator.
+///
+/// This yields the name and full (length-through-CRC) slice of each chunk in
+/// a PNG, stopping at `IEND` or the first sign of trouble.
+struct ChunkIter<'a>(&'a [u8]);
+
+impl<'a> ChunkIter<'a> {
+	/// # New.
+	fn new(src: &'a [u8]) -> Self {
+		Self(src.get(SIG_LEN..).unwrap_or_default())
+	}
+}
+
+impl<'a> Iterator for ChunkIter<'a> {
+	type Item = ([u8; 4], &'a [u8]);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let [a, b, c, d, n1, n2, n3, n4, ..] = *self.0 else { return None; };
+		let name = [n1, n2, n3, n4];
+		if name == *b"IEND" { return None; }
+
+		// Length + name + data + CRC.
+		let len = usize::try_from(u32::from_be_bytes([a, b, c, d])).ok()?
+			.checked_add(12)?;
+		let chunk = self.0.get(..len)?;
+		self.0 = &self.0[len..];
+		Some((name, chunk))
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_chunk_iter() {
+		let raw = std::fs::read("../skel/assets/png/01.png").expect("Missing 01.png");
+		let mut iter = ChunkIter::new(&raw);
+
+		// The first chunk should always be the IHDR.
+		let (name, chunk) = iter.next().expect("Missing IHDR.");
+		assert_eq!(name, *b"IHDR");
+		assert_eq!(chunk.len(), IHDR_END - SIG_LEN);
+
+		// And there should be some IDAT eventually.
+		assert!(iter.any(|(name, _)| name == *b"IDAT"));
+	}
+
+	#[test]
+	fn t_is_grey() {
+		let mut raw = std::fs::read("../skel/assets/png/01.png").expect("Missing 01.png");
+		for (color, grey) in [(0, true), (2, false), (3, false), (4, true), (6, false)] {
+			raw[IHDR_COLOR] = color;
+			assert_eq!(is_grey(&raw), Some(grey), "Wrong class for color type {color}.");
+		}
+		assert_eq!(is_grey(&raw[..IHDR_COLOR]), None);
+	}
+
+	#[test]
+	fn t_set_keep_chunks() {
+		// Color-dependent chunks can't be kept.
+		for name in COLOR_CHUNKS {
+			assert!(! set_keep_chunks(vec![*b"gAMA", name]), "{} accepted.", name.escape_ascii());
+		}
+		assert!(keep_chunks().is_empty(), "Rejected chunks were saved.");
+	}
+}
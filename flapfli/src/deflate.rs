@@ -6,22 +6,37 @@ supporting components.
 */
 
 use std::{
-	cell::RefCell,
+	cell::{
+		Cell,
+		RefCell,
+	},
 	ffi::{
 		c_uchar,
 		c_uint,
 	},
+	fmt,
 	num::{
 		NonZeroUsize,
 		NonZeroU32,
 	},
 	ptr::NonNull,
-	sync::OnceLock,
+	sync::{
+		Arc,
+		atomic::{
+			AtomicBool,
+			Ordering::{
+				Acquire,
+				Release,
+			},
+		},
+		OnceLock,
+	},
 };
 use super::{
 	deflate_part,
 	ffi::flapfli_allocate,
 	lodepng::LodePNGCompressSettings,
+	LZ77Store,
 	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZopfliChunk,
 	ZopfliState,
@@ -29,26 +44,116 @@ use super::{
 
 
 
-/// # Twenty is Non-Zero.
-const NZ20: NonZeroU32 = NonZeroU32::new(20).unwrap();
-
-/// # Sixty is Non-Zero.
-const NZ60: NonZeroU32 = NonZeroU32::new(60).unwrap();
-
 /// # Max Iterations.
 const MAX_ITERATIONS: NonZeroU32 = NonZeroU32::new(i32::MAX as u32).unwrap();
 
 /// # Number of Zopfli LZ77 Iterations.
 ///
-/// `Some` values are capped to `i32::MAX`, though anything above a few
-/// thousand iterations is madness.
+/// Counts are capped to `i32::MAX`, though anything above a few thousand
+/// iterations is madness.
 ///
-/// If `None`, either twenty or sixty iterations will be performed, depending
-/// on the file size.
+/// If unset, `ZopfliIterations::DEFAULT` is used.
 ///
 /// Note: This value is only (possibly) set (once) during `flaca`'s
 /// initialization; it won't change after that.
-static NUM_ITERATIONS: OnceLock<NonZeroU32> = OnceLock::new();
+static NUM_ITERATIONS: OnceLock<ZopfliIterations> = OnceLock::new();
+
+/// # Master Block Size Range.
+///
+/// Blocks smaller than this would be splitting hairs; larger ones would need
+/// hundreds of megabytes of cache per thread.
+const BLOCK_SIZE_RANGE: std::ops::RangeInclusive<usize> = 64_000..=8_000_000;
+
+/// # Master Block Size.
+///
+/// If unset, `ZOPFLI_MASTER_BLOCK_SIZE` is used. Either way, the value is
+/// locked in the first time it is read, since the (thread-local) zopfli caches
+/// are sized to match.
+///
+/// Note: This value is only (possibly) set (once) during `flaca`'s
+/// initialization; it won't change after that.
+static BLOCK_SIZE: OnceLock<usize> = OnceLock::new();
+
+/// # Progress Callback Pointer.
+///
+/// The lifetime is erased; see `with_progress`.
+type ProgressPtr = NonNull<dyn FnMut(usize, usize)>;
+
+/// # Cancelled?
+///
+/// Once set, in-progress zopfli encodes bail at the next opportunity, and new
+/// ones won't get started.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+thread_local!(
+	/// # Current Cancellation Token.
+	///
+	/// This holds the token passed to `optimize_with_cancel`, if any, for the
+	/// duration of the call, since the zopfli bits are buried under lodepng.
+	static TOKEN: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+
+	/// # Current Progress Callback.
+	///
+	/// This holds the callback passed to `with_progress`, if any, for the
+	/// duration of the call, for the same reason.
+	static PROGRESS: Cell<Option<ProgressPtr>> = const { Cell::new(None) };
+);
+
+
+
+#[derive(Debug, Clone, Default)]
+/// # Cancellation Token.
+///
+/// This is a cheaply-cloneable flag that can be used to stop an in-progress
+/// [`optimize_with_cancel`](crate::optimize_with_cancel) call early — from
+/// another thread, say, on timeout — without affecting any others.
+///
+/// Cancellation is checked between master blocks, split points, and LZ77
+/// iterations; once noticed, the call returns `None`, the same as if no
+/// savings had been found.
+///
+/// To cancel _every_ call, past and future, use [`cancel_zopfli`] instead.
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+	/// # Cancel.
+	///
+	/// Flip the switch. There is no way to undo this.
+	pub fn cancel(&self) { self.0.store(true, Release); }
+
+	#[must_use]
+	#[inline]
+	/// # Is Cancelled?
+	pub fn is_cancelled(&self) -> bool { self.0.load(Acquire) }
+
+	/// # Scope.
+	///
+	/// Make this the current thread's token until the returned guard is
+	/// dropped.
+	pub(crate) fn scope(&self) -> TokenScope {
+		TokenScope(TOKEN.replace(Some(self.clone())))
+	}
+}
+
+/// # Token Scope.
+///
+/// This restores the current thread's previous token — usually none — when
+/// dropped, even if the call panics.
+pub(crate) struct TokenScope(Option<CancellationToken>);
+
+impl Drop for TokenScope {
+	fn drop(&mut self) { TOKEN.set(self.0.take()); }
+}
+
+/// # Progress Scope.
+///
+/// This restores the current thread's previous progress callback — usually
+/// none — when dropped, even if the call panics.
+struct ProgressScope(Option<ProgressPtr>);
+
+impl Drop for ProgressScope {
+	fn drop(&mut self) { PROGRESS.set(self.0.take()); }
+}
 
 
 
@@ -78,6 +183,10 @@ static NUM_ITERATIONS: OnceLock<NonZeroU32> = OnceLock::new();
 /// single-threaded. (All work for a given image happens on a single thread.)
 /// This is why we can leverage local statics like `STATE` without fear of
 /// access contention.
+///
+/// The same goes for the LZ77 stores, which are kept alongside the state so
+/// their (potentially huge) buffers can be recycled from chunk to chunk and
+/// image to image instead of reallocated every time.
 pub(crate) extern "C" fn flaca_png_deflate(
 	out: *mut *mut c_uchar,
 	outsize: *mut usize,
@@ -86,7 +195,10 @@ pub(crate) extern "C" fn flaca_png_deflate(
 	_settings: *const LodePNGCompressSettings,
 ) -> c_uint {
 	thread_local!(
-		static STATE: RefCell<Box<ZopfliState>> = RefCell::new(ZopfliState::new())
+		static STATE: RefCell<(Box<ZopfliState>, [LZ77Store; 3])> = RefCell::new((
+			ZopfliState::new(),
+			[LZ77Store::new(), LZ77Store::new(), LZ77Store::new()],
+		))
 	);
 
 	// Group the pointer crap to cut down on the number of args being
@@ -102,15 +214,20 @@ pub(crate) extern "C" fn flaca_png_deflate(
 	let arr = unsafe { std::slice::from_raw_parts(arr, insize) };
 
 	// Figure out how many iterations to use.
-	let numiterations = NUM_ITERATIONS.get().copied().unwrap_or(
-		if arr.len() < 200_000 { NZ60 } else { NZ20 }
-	);
+	let numiterations = zopfli_iterations().for_size(arr.len());
 
 	// Compress in chunks, à la ZopfliDeflate.
-	for chunk in DeflateIter::new(arr) {
+	let iter = DeflateIter::new(arr, STATE.with_borrow(|(state, _)| state.max_block_size()));
+	let total = iter.len();
+	report(0, total);
+	for (done, chunk) in iter.enumerate() {
+		// Stop early if we've been asked to.
+		if cancelled() { return 1; }
+
 		#[cfg(not(debug_assertions))]
-		if STATE.with_borrow_mut(|state| deflate_part(
+		if STATE.with_borrow_mut(|(state, stores)| deflate_part(
 			state,
+			stores,
 			numiterations,
 			chunk.total_len().get() == arr.len(),
 			chunk,
@@ -118,25 +235,217 @@ pub(crate) extern "C" fn flaca_png_deflate(
 		)).is_err() { return 1; };
 
 		#[cfg(debug_assertions)]
-		if let Err(e) = STATE.with_borrow_mut(|state| deflate_part(
+		if let Err(e) = STATE.with_borrow_mut(|(state, stores)| deflate_part(
 			state,
+			stores,
 			numiterations,
 			chunk.total_len().get() == arr.len(),
 			chunk,
 			&mut dst,
-		)) { panic!("{e}"); };
+		)) {
+			// Cancellation isn't a bug!
+			if cancelled() { return 1; }
+			panic!("{e}");
+		};
+
+		report(done + 1, total);
 	}
 
 	// All clear!
 	0
 }
 
+/// # Cancel Zopfli.
+///
+/// Abandon any in-progress zopfli encoding — checked between master blocks,
+/// split points, and LZ77 iterations — and refuse to start any more.
+///
+/// The affected images simply fail to optimize, the same as if zopfli had
+/// found no savings. There is no way to undo this.
+pub fn cancel_zopfli() { CANCELLED.store(true, Release); }
+
+#[inline]
+/// # Cancelled?
+///
+/// Returns `true` if zopfli has been cancelled globally, or the current
+/// thread's token — if any — has been cancelled.
+pub(crate) fn cancelled() -> bool {
+	CANCELLED.load(Acquire) ||
+	TOKEN.with_borrow(|t| t.as_ref().is_some_and(CancellationToken::is_cancelled))
+}
+
+#[expect(unsafe_code, reason = "For lifetime erasure.")]
+/// # With Progress.
+///
+/// Run `f`, passing the progress of any zopfli passes it triggers on the
+/// current thread — master blocks done, and the total — to `progress`, once
+/// at the start of each pass and again as each block finishes.
+///
+/// Zopfli works through the image data a million bytes at a time, so this
+/// is really only interesting for huge images; everything else is one and
+/// done. (Exhaustive mode adds another pass for each color mode tried.)
+pub fn with_progress<P, F, R>(mut progress: P, f: F) -> R
+where P: FnMut(usize, usize), F: FnOnce() -> R {
+	let progress: &mut dyn FnMut(usize, usize) = &mut progress;
+	let ptr = NonNull::from(progress);
+
+	// Safety: the scope guard swaps the pointer back out before `progress`
+	// goes out of scope, so it can't outlive it.
+	let ptr = unsafe {
+		std::mem::transmute::<
+			NonNull<dyn FnMut(usize, usize) + '_>,
+			ProgressPtr,
+		>(ptr)
+	};
+
+	let _scope = ProgressScope(PROGRESS.replace(Some(ptr)));
+	f()
+}
+
+#[expect(unsafe_code, reason = "For pointer deref.")]
+/// # Report Progress.
+///
+/// Pass the progress along to the current thread's callback, if any, unless
+/// the work has been cancelled.
+fn report(done: usize, total: usize) {
+	if cancelled() { return; }
+
+	// Take the callback out while it runs so a nested call can't alias it.
+	if let Some(mut ptr) = PROGRESS.take() {
+		// Safety: the pointer is valid for the duration of `with_progress`.
+		unsafe { (ptr.as_mut())(done, total); }
+		PROGRESS.set(Some(ptr));
+	}
+}
+
+#[must_use]
+/// # Iteration Count.
+///
+/// Return the number of Zopfli LZ77 iterations in effect, either as
+/// overridden or the size-based default.
+pub fn zopfli_iterations() -> ZopfliIterations {
+	NUM_ITERATIONS.get().copied().unwrap_or(ZopfliIterations::DEFAULT)
+}
+
+#[must_use]
+#[inline]
+/// # Master Block Size.
+///
+/// Return the maximum number of (filtered) image bytes zopfli works through
+/// at a time.
+pub fn zopfli_block_size() -> usize {
+	*BLOCK_SIZE.get_or_init(|| ZOPFLI_MASTER_BLOCK_SIZE)
+}
+
+/// # Set Master Block Size.
+///
+/// Zopfli splits and compresses the image data in "master blocks" of (by
+/// default) one million bytes. Larger blocks give the splitter more room to
+/// work with, which can help big images, but the caches grow to match —
+/// about 40 bytes per byte of block, per thread. Smaller blocks do the
+/// opposite.
+///
+/// The size must be between `64_000` and `8_000_000`, and can only be set
+/// once, before any zopfli work has started; `false` is returned otherwise.
+pub fn set_zopfli_block_size(size: usize) -> bool {
+	BLOCK_SIZE_RANGE.contains(&size) && BLOCK_SIZE.set(size).is_ok()
+}
+
 /// # Set Iteration Count.
 ///
 /// Override the default (size-based) number of Zopfli LZ77 iterations with a
-/// fixed value.
-pub fn set_zopfli_iterations(n: NonZeroU32) -> bool {
-	NUM_ITERATIONS.set(NonZeroU32::min(n, MAX_ITERATIONS)).is_ok()
+/// fixed value — any `NonZeroU32` — or a curve of your own.
+///
+/// This can only be set once; `false` is returned if it was already set.
+pub fn set_zopfli_iterations<I: Into<ZopfliIterations>>(n: I) -> bool {
+	let n = n.into();
+	NUM_ITERATIONS.set(ZopfliIterations {
+		small: NonZeroU32::min(n.small, MAX_ITERATIONS),
+		medium: NonZeroU32::min(n.medium, MAX_ITERATIONS),
+		large: NonZeroU32::min(n.large, MAX_ITERATIONS),
+	}).is_ok()
+}
+
+
+
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+/// # Zopfli Iterations.
+///
+/// Zopfli compression time scales more or less linearly with the number of
+/// iterations, so — à la zopflipng — smaller images get more of them, larger
+/// ones fewer.
+///
+/// The count is chosen by the size of the (uncompressed, filtered) image
+/// data: `small` under 200 KB, `medium` up to 1 MB, and `large` beyond that.
+pub struct ZopfliIterations {
+	/// # Small Images.
+	pub small: NonZeroU32,
+
+	/// # Medium Images.
+	pub medium: NonZeroU32,
+
+	/// # Large Images.
+	pub large: NonZeroU32,
+}
+
+impl Default for ZopfliIterations {
+	#[inline]
+	fn default() -> Self { Self::DEFAULT }
+}
+
+impl From<NonZeroU32> for ZopfliIterations {
+	#[inline]
+	fn from(n: NonZeroU32) -> Self { Self::fixed(n) }
+}
+
+impl fmt::Display for ZopfliIterations {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_fixed() { write!(f, "{}", self.small) }
+		else { write!(f, "{}/{}/{}", self.small, self.medium, self.large) }
+	}
+}
+
+impl ZopfliIterations {
+	/// # Default.
+	///
+	/// Sixty, twenty, and five iterations, respectively.
+	pub const DEFAULT: Self = Self {
+		small: NonZeroU32::new(60).unwrap(),
+		medium: NonZeroU32::new(20).unwrap(),
+		large: NonZeroU32::new(5).unwrap(),
+	};
+
+	/// # Small/Medium Threshold.
+	const MEDIUM: usize = 200_000;
+
+	/// # Medium/Large Threshold.
+	const LARGE: usize = 1_000_000;
+
+	#[must_use]
+	/// # Fixed.
+	///
+	/// Use the same number of iterations regardless of size.
+	pub const fn fixed(n: NonZeroU32) -> Self {
+		Self { small: n, medium: n, large: n }
+	}
+
+	#[must_use]
+	/// # Is Fixed?
+	///
+	/// Returns `true` if every size gets the same count.
+	pub const fn is_fixed(self) -> bool {
+		self.small.get() == self.medium.get() && self.medium.get() == self.large.get()
+	}
+
+	#[must_use]
+	/// # Iterations for Size.
+	///
+	/// Return the number of iterations to use for `len` bytes of image data.
+	pub const fn for_size(self, len: usize) -> NonZeroU32 {
+		if len < Self::MEDIUM { self.small }
+		else if len <= Self::LARGE { self.medium }
+		else { self.large }
+	}
 }
 
 
@@ -345,6 +654,9 @@ struct DeflateIter<'a> {
 
 	/// # Window Start.
 	pos: usize,
+
+	/// # Master Block Size.
+	max: usize,
 }
 
 impl<'a> Iterator for DeflateIter<'a> {
@@ -353,9 +665,9 @@ impl<'a> Iterator for DeflateIter<'a> {
 	fn next(&mut self) -> Option<Self::Item> {
 		if self.pos < self.arr.len() {
 			let pos = self.pos;
-			let chunk = self.arr.get(..pos + ZOPFLI_MASTER_BLOCK_SIZE).unwrap_or(self.arr);
+			let chunk = self.arr.get(..pos + self.max).unwrap_or(self.arr);
 			self.pos = chunk.len();
-			ZopfliChunk::new(chunk, pos).ok()
+			ZopfliChunk::new(chunk, pos, self.max).ok()
 		}
 		else { None }
 	}
@@ -368,13 +680,120 @@ impl<'a> Iterator for DeflateIter<'a> {
 
 impl ExactSizeIterator for DeflateIter<'_> {
 	fn len(&self) -> usize {
-		(self.arr.len() - self.pos).div_ceil(ZOPFLI_MASTER_BLOCK_SIZE)
+		(self.arr.len() - self.pos).div_ceil(self.max)
 	}
 }
 
 impl<'a> DeflateIter<'a> {
 	/// # New.
-	const fn new(arr: &'a [u8]) -> Self {
-		Self { arr, pos: 0 }
+	const fn new(arr: &'a [u8], max: usize) -> Self {
+		Self { arr, pos: 0, max }
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_iterations() {
+		let n = ZopfliIterations::DEFAULT;
+		assert_eq!(n.for_size(0).get(), 60);
+		assert_eq!(n.for_size(199_999).get(), 60);
+		assert_eq!(n.for_size(200_000).get(), 20);
+		assert_eq!(n.for_size(1_000_000).get(), 20);
+		assert_eq!(n.for_size(1_000_001).get(), 5);
+		assert!(! n.is_fixed(), "The default is a curve.");
+		assert_eq!(n.to_string(), "60/20/5");
+
+		let n = ZopfliIterations::from(NonZeroU32::new(15).unwrap());
+		assert!(n.is_fixed(), "Single values are fixed.");
+		assert_eq!(n.for_size(0), n.for_size(usize::MAX));
+		assert_eq!(n.to_string(), "15");
+	}
+
+	#[test]
+	fn t_block_size() {
+		// Out of range.
+		assert!(! set_zopfli_block_size(0));
+		assert!(! set_zopfli_block_size(63_999));
+		assert!(! set_zopfli_block_size(8_000_001));
+
+		// Once read, the size is locked in.
+		assert_eq!(zopfli_block_size(), ZOPFLI_MASTER_BLOCK_SIZE);
+		assert!(! set_zopfli_block_size(2_000_000));
+		assert_eq!(zopfli_block_size(), ZOPFLI_MASTER_BLOCK_SIZE);
+	}
+
+	#[test]
+	fn t_token_scope() {
+		let token = CancellationToken::default();
+		let other = token.clone();
+		assert!(! cancelled(), "Nothing should be cancelled yet.");
+
+		let scope = token.scope();
+		assert!(! cancelled(), "The token hasn't been cancelled yet.");
+		other.cancel();
+		assert!(token.is_cancelled(), "Clones should share state.");
+		assert!(cancelled(), "The scoped token was cancelled.");
+
+		// Once the scope ends, the token should no longer apply.
+		drop(scope);
+		assert!(! cancelled(), "The token should be out of scope.");
+	}
+
+	#[test]
+	fn t_token_scope_nested() {
+		let outer = CancellationToken::default();
+		let inner = CancellationToken::default();
+		outer.cancel();
+
+		let outer_scope = outer.scope();
+		assert!(cancelled(), "The outer token was cancelled.");
+
+		// Nested tokens take over for their duration…
+		let inner_scope = inner.scope();
+		assert!(! cancelled(), "The inner token hasn't been cancelled.");
+
+		// …and hand back to the outer one when they end.
+		drop(inner_scope);
+		assert!(cancelled(), "The outer token should be back in scope.");
+
+		drop(outer_scope);
+		assert!(! cancelled(), "Nothing should be in scope now.");
+	}
+
+	#[test]
+	fn t_optimize_with_cancel() {
+		let raw = std::fs::read("../skel/assets/png/01.png").expect("Missing 01.png");
+		let token = CancellationToken::default();
+		token.cancel();
+		assert!(
+			crate::optimize_with_cancel(&raw, &token).is_none(),
+			"Cancelled calls should give up.",
+		);
+		assert!(! cancelled(), "The token should be out of scope.");
+	}
+
+	#[test]
+	fn t_with_progress() {
+		let mut outer = Vec::new();
+		let mut inner = Vec::new();
+		with_progress(|done, total| outer.push((done, total)), || {
+			report(0, 2);
+
+			// Nested callbacks take over for their duration.
+			with_progress(|done, total| inner.push((done, total)), || report(1, 2));
+
+			report(2, 2);
+		});
+
+		// Nothing should be listening now.
+		report(3, 3);
+
+		assert_eq!(outer, [(0, 2), (2, 2)]);
+		assert_eq!(inner, [(1, 2)]);
 	}
 }
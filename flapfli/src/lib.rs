@@ -3,6 +3,10 @@
 
 This library contains a (mostly) Rust port of [`zopflipng`](https://github.com/google/zopfli/),
 heavily optimized flaca's specific use cases (hence "fla" + "pfli").
+
+The optional `jpegtran` and `oxipng` features expose the other lossless
+passes flaca and its C API have in common: `jpegtran::optimize` and
+`oxipng_options`, respectively.
 */
 
 #![deny(
@@ -35,7 +39,6 @@ heavily optimized flaca's specific use cases (hence "fla" + "pfli").
 	clippy::rest_pat_in_fully_bound_structs,
 	clippy::semicolon_inside_block,
 	clippy::str_to_string,
-	clippy::string_to_string,
 	clippy::todo,
 	clippy::undocumented_unsafe_blocks,
 	clippy::unneeded_field_pattern,
@@ -55,21 +58,58 @@ heavily optimized flaca's specific use cases (hence "fla" + "pfli").
 
 #![expect(clippy::redundant_pub_crate, reason = "Unresolvable.")]
 
+mod ancillary;
 mod deflate;
 mod ffi;
+#[cfg(feature = "jpegtran")] pub mod jpegtran;
 mod lodepng;
+#[cfg(feature = "oxipng")] mod oxi;
 mod zopflipng;
 
-pub use deflate::set_zopfli_iterations;
+pub use ancillary::{
+	keep_chunks,
+	set_keep_chunks,
+};
+pub use deflate::{
+	cancel_zopfli,
+	CancellationToken,
+	set_zopfli_block_size,
+	set_zopfli_iterations,
+	with_progress,
+	zopfli_block_size,
+	zopfli_iterations,
+	ZopfliIterations,
+};
 use ffi::EncodedPNG;
+pub use lodepng::{
+	exhaustive,
+	keep_alpha_data,
+	keep_color_type,
+	set_exhaustive,
+	set_keep_alpha_data,
+	set_keep_color_type,
+};
 use lodepng::{
+	ColorMode,
 	DecodedImage,
 	LodePNGColorType,
 	LodePNGFilterStrategy,
 	LodePNGState,
 };
+#[cfg(feature = "oxipng")]
+pub use oxi::{
+	oxipng_options,
+	OXIPNG_LEVEL_MAX,
+};
+pub use zopflipng::{
+	set_zopfli_cost_model,
+	set_zopfli_max_splits,
+	set_zopfli_store_threshold,
+	zopfli_block_stats,
+};
 use zopflipng::{
 	deflate_part,
+	LZ77Store,
 	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZopfliChunk,
 	ZopfliState,
@@ -84,6 +124,16 @@ use zopflipng::{
 /// strongest Zopfli filter strategy, and return a new PNG image if the result
 /// is smaller than the original.
 ///
+/// Ancillary chunks are stripped unless explicitly kept via
+/// [`set_keep_chunks`], and the color type may change unless kept via
+/// [`set_keep_color_type`]. (Every viable color type can be tried — slowly —
+/// via [`set_exhaustive`].)
+///
+/// To stop a call early, use [`optimize_with_cancel`] instead.
+///
+/// To follow along with the (slow) zopfli pass, wrap the call with
+/// [`with_progress`].
+///
 /// Note: 16-bit transformations are not lossless; such images will have their
 /// bit depths reduced to a more typical 8 bits.
 pub fn optimize(src: &[u8]) -> Option<EncodedPNG> {
@@ -93,21 +143,45 @@ pub fn optimize(src: &[u8]) -> Option<EncodedPNG> {
 
 	// Find the right strategy.
 	let mut enc = LodePNGState::encoder(&dec)?;
+	let keep_color_type = keep_color_type();
+	if keep_color_type { enc.keep_color_type(&dec)?; }
 	let strategy = best_strategy(&img, &mut enc);
+	if deflate::cancelled() { return None; }
 
-	// Now re-re-encode with zopfli and the best strategy.
+	// Find the most promising palette layout too, if applicable.
 	enc.set_strategy(strategy);
+	let palette = if keep_color_type { None } else { best_palette(&img, &mut enc) };
+	if deflate::cancelled() { return None; }
+
+	// And settle on strategies for every other color mode, if we're being
+	// thorough.
+	let modes =
+		if keep_color_type || ! exhaustive() { Vec::new() }
+		else { exhaustive_modes(&img, &mut enc) };
+	if deflate::cancelled() { return None; }
+	enc.set_strategy(strategy);
+
+	// Apply the palette layout, falling back to lodepng's own if it won't
+	// take.
+	let applied = match &palette {
+		Some(Palette::Reordered(palette)) => enc.set_palette(palette),
+		Some(Palette::Dropped) => enc.set_paletteless(&img),
+		None => Some(()),
+	};
+	if applied.is_none() { enc.unset_palette(); }
+
+	// Now re-re-encode with zopfli and the best strategy.
 	enc.set_zopfli();
-	let out = enc.encode(&img)?;
-
-	// For really small images, we might be able to save even more by
-	// nuking the palette.
-	if out.size < 4096 && LodePNGColorType::LCT_PALETTE.is_match(&out) {
-		if let Some(out2) = enc.try_small(&img) {
-			if out2.size < out.size && out2.size < src.len() {
-				// We improved again!
-				return Some(out2);
-			}
+	let mut out = enc.encode(&img)?;
+	out.copy_ancillary(src);
+
+	// Brute-force the rest, if desired.
+	for (mode, strategy) in modes {
+		if deflate::cancelled() { return None; }
+		enc.set_strategy(strategy);
+		if let Some(mut out2) = enc.set_color_mode(&mode).and_then(|()| enc.encode(&img)) {
+			out2.copy_ancillary(src);
+			if out2.size < out.size { out = out2; }
 		}
 	}
 
@@ -116,7 +190,99 @@ pub fn optimize(src: &[u8]) -> Option<EncodedPNG> {
 	else { None }
 }
 
+#[must_use]
+/// # Optimize (Cancellable).
+///
+/// Same as [`optimize`], except compression can be stopped early by
+/// cancelling the `cancel` token (from another thread), in which case `None`
+/// is returned. See [`CancellationToken`] for details.
+pub fn optimize_with_cancel(src: &[u8], cancel: &CancellationToken) -> Option<EncodedPNG> {
+	// Keep the token handy for the zopfli callback.
+	let _scope = cancel.scope();
+	optimize(src)
+}
+
+#[must_use]
+/// # Same Pixels?
+///
+/// Decode both PNGs to 8-bit RGBA and return `true` if their dimensions and
+/// pixels are identical (ignoring the color of fully transparent pixels,
+/// unless kept via [`set_keep_alpha_data`]).
+///
+/// Lossless optimizations are free to change the color type, bit depth,
+/// palette order, etc., so the normalized decodings are what matter.
+pub fn same_pixels(a: &[u8], b: &[u8]) -> bool {
+	let Some(a) = LodePNGState::default().decode(a) else { return false; };
+	let Some(b) = LodePNGState::default().decode(b) else { return false; };
+	a == b
+}
+
+
+
+/// # Palette Layout.
+///
+/// The alternatives to lodepng's own palette choice; see [`best_palette`].
+enum Palette {
+	/// # Same Colors, Different Order.
+	Reordered(Vec<[u8; 4]>),
+
+	/// # No Palette.
+	Dropped,
+}
+
+/// # Best Palette.
+///
+/// If lodepng went with a palette, try it in a few other orders — see
+/// [`lodepng::palettes`] — as well as without it entirely, and return
+/// whichever beat lodepng's own order, if any.
+///
+/// As with the strategies, the comparison is made with quick non-zopfli
+/// encodes, so only the winner has to sit through the (slow) zopfli pass.
+///
+/// The encoder is left with lodepng's automatic color mode either way.
+fn best_palette(img: &DecodedImage, enc: &mut LodePNGState) -> Option<Palette> {
+	let out = enc.encode(img)?;
+	if ! LodePNGColorType::LCT_PALETTE.is_match(&out) { return None; }
+
+	let mut best_size = out.size;
+	let mut best = None;
+	for palette in lodepng::palettes(img).into_iter().flatten() {
+		if let Some(out) = enc.set_palette(&palette).and_then(|()| enc.encode(img)) {
+			if out.size < best_size {
+				best_size = out.size;
+				best = Some(Palette::Reordered(palette));
+			}
+		}
+	}
+
+	if let Some(out) = enc.set_paletteless(img).and_then(|()| enc.encode(img)) {
+		if out.size < best_size { best = Some(Palette::Dropped); }
+	}
+
+	enc.unset_palette();
+	best
+}
+
+/// # Exhaustive Modes.
+///
+/// Return each viable color mode — see [`LodePNGState::color_modes`] —
+/// along with its best strategy, for brute-forcing.
+///
+/// The encoder is left with lodepng's automatic color mode afterward.
+fn exhaustive_modes(img: &DecodedImage, enc: &mut LodePNGState)
+-> Vec<(ColorMode, LodePNGFilterStrategy)> {
+	let mut out = Vec::new();
+	for mode in enc.color_modes(img).unwrap_or_default() {
+		if deflate::cancelled() { break; }
+		if enc.set_color_mode(&mode).is_some() {
+			let strategy = best_strategy(img, enc);
+			out.push((mode, strategy));
+		}
+	}
 
+	enc.unset_palette();
+	out
+}
 
 /// # Best Strategy.
 ///
@@ -0,0 +1,202 @@
+/*
+Pre-Generated Lodepng Bindings.
+
+Builds without the (default) `bindgen` feature use these instead of running
+bindgen (which needs libclang) at build time. They must match what build.rs
+would generate; if lodepng or the bindgen settings change, rebuild with the
+feature and copy OUT_DIR/lodepng-bindgen.rs over this file. The
+`t_bindgen_copy` test keeps an eye on this.
+*/
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum LodePNGColorType {
+	LCT_GREY = 0,
+	LCT_RGB = 2,
+	LCT_PALETTE = 3,
+	LCT_GREY_ALPHA = 4,
+	LCT_RGBA = 6,
+	LCT_MAX_OCTET_VALUE = 255,
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum LodePNGFilterStrategy {
+	LFS_ZERO = 0,
+	LFS_ONE = 1,
+	LFS_TWO = 2,
+	LFS_THREE = 3,
+	LFS_FOUR = 4,
+	LFS_MINSUM = 5,
+	LFS_ENTROPY = 6,
+	LFS_BRUTE_FORCE = 7,
+	LFS_PREDEFINED = 8,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGDecompressSettings {
+	pub ignore_adler32: ::std::os::raw::c_uint,
+	pub ignore_nlen: ::std::os::raw::c_uint,
+	pub max_output_size: usize,
+	pub custom_zlib: ::std::option::Option<
+		unsafe extern "C" fn(
+			arg1: *mut *mut ::std::os::raw::c_uchar,
+			arg2: *mut usize,
+			arg3: *const ::std::os::raw::c_uchar,
+			arg4: usize,
+			arg5: *const LodePNGDecompressSettings,
+		) -> ::std::os::raw::c_uint,
+	>,
+	pub custom_inflate: ::std::option::Option<
+		unsafe extern "C" fn(
+			arg1: *mut *mut ::std::os::raw::c_uchar,
+			arg2: *mut usize,
+			arg3: *const ::std::os::raw::c_uchar,
+			arg4: usize,
+			arg5: *const LodePNGDecompressSettings,
+		) -> ::std::os::raw::c_uint,
+	>,
+	pub custom_context: *const ::std::os::raw::c_void,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGCompressSettings {
+	pub btype: ::std::os::raw::c_uint,
+	pub use_lz77: ::std::os::raw::c_uint,
+	pub windowsize: ::std::os::raw::c_uint,
+	pub minmatch: ::std::os::raw::c_uint,
+	pub nicematch: ::std::os::raw::c_uint,
+	pub lazymatching: ::std::os::raw::c_uint,
+	pub custom_zlib: ::std::option::Option<
+		unsafe extern "C" fn(
+			arg1: *mut *mut ::std::os::raw::c_uchar,
+			arg2: *mut usize,
+			arg3: *const ::std::os::raw::c_uchar,
+			arg4: usize,
+			arg5: *const LodePNGCompressSettings,
+		) -> ::std::os::raw::c_uint,
+	>,
+	pub custom_deflate: ::std::option::Option<
+		unsafe extern "C" fn(
+			arg1: *mut *mut ::std::os::raw::c_uchar,
+			arg2: *mut usize,
+			arg3: *const ::std::os::raw::c_uchar,
+			arg4: usize,
+			arg5: *const LodePNGCompressSettings,
+		) -> ::std::os::raw::c_uint,
+	>,
+	pub custom_context: *const ::std::os::raw::c_void,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGColorMode {
+	pub colortype: LodePNGColorType,
+	pub bitdepth: ::std::os::raw::c_uint,
+	pub palette: *mut ::std::os::raw::c_uchar,
+	pub palettesize: usize,
+	pub key_defined: ::std::os::raw::c_uint,
+	pub key_r: ::std::os::raw::c_uint,
+	pub key_g: ::std::os::raw::c_uint,
+	pub key_b: ::std::os::raw::c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGInfo {
+	pub compression_method: ::std::os::raw::c_uint,
+	pub filter_method: ::std::os::raw::c_uint,
+	pub interlace_method: ::std::os::raw::c_uint,
+	pub color: LodePNGColorMode,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGDecoderSettings {
+	pub zlibsettings: LodePNGDecompressSettings,
+	pub ignore_crc: ::std::os::raw::c_uint,
+	pub ignore_critical: ::std::os::raw::c_uint,
+	pub ignore_end: ::std::os::raw::c_uint,
+	pub color_convert: ::std::os::raw::c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGColorStats {
+	pub colored: ::std::os::raw::c_uint,
+	pub key: ::std::os::raw::c_uint,
+	pub key_r: ::std::os::raw::c_ushort,
+	pub key_g: ::std::os::raw::c_ushort,
+	pub key_b: ::std::os::raw::c_ushort,
+	pub alpha: ::std::os::raw::c_uint,
+	pub numcolors: ::std::os::raw::c_uint,
+	pub palette: [::std::os::raw::c_uchar; 1024usize],
+	pub bits: ::std::os::raw::c_uint,
+	pub numpixels: usize,
+	pub allow_palette: ::std::os::raw::c_uint,
+	pub allow_greyscale: ::std::os::raw::c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LodePNGEncoderSettings {
+	pub zlibsettings: LodePNGCompressSettings,
+	pub auto_convert: ::std::os::raw::c_uint,
+	pub filter_palette_zero: ::std::os::raw::c_uint,
+	pub filter_strategy: LodePNGFilterStrategy,
+	pub predefined_filters: *const ::std::os::raw::c_uchar,
+	pub force_palette: ::std::os::raw::c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LodePNGState {
+	pub decoder: LodePNGDecoderSettings,
+	pub encoder: LodePNGEncoderSettings,
+	pub info_raw: LodePNGColorMode,
+	pub info_png: LodePNGInfo,
+	pub error: ::std::os::raw::c_uint,
+}
+
+unsafe extern "C" {
+	pub fn lodepng_color_mode_copy(
+		dest: *mut LodePNGColorMode,
+		source: *const LodePNGColorMode,
+	) -> ::std::os::raw::c_uint;
+	pub fn lodepng_color_stats_init(stats: *mut LodePNGColorStats);
+	pub fn lodepng_compute_color_stats(
+		stats: *mut LodePNGColorStats,
+		image: *const ::std::os::raw::c_uchar,
+		w: ::std::os::raw::c_uint,
+		h: ::std::os::raw::c_uint,
+		mode_in: *const LodePNGColorMode,
+	) -> ::std::os::raw::c_uint;
+	pub fn lodepng_decode(
+		out: *mut *mut ::std::os::raw::c_uchar,
+		w: *mut ::std::os::raw::c_uint,
+		h: *mut ::std::os::raw::c_uint,
+		state: *mut LodePNGState,
+		in_: *const ::std::os::raw::c_uchar,
+		insize: usize,
+	) -> ::std::os::raw::c_uint;
+	pub fn lodepng_encode(
+		out: *mut *mut ::std::os::raw::c_uchar,
+		outsize: *mut usize,
+		image: *const ::std::os::raw::c_uchar,
+		w: ::std::os::raw::c_uint,
+		h: ::std::os::raw::c_uint,
+		state: *mut LodePNGState,
+	) -> ::std::os::raw::c_uint;
+	pub fn lodepng_palette_add(
+		info: *mut LodePNGColorMode,
+		r: ::std::os::raw::c_uchar,
+		g: ::std::os::raw::c_uchar,
+		b: ::std::os::raw::c_uchar,
+		a: ::std::os::raw::c_uchar,
+	) -> ::std::os::raw::c_uint;
+	pub fn lodepng_palette_clear(info: *mut LodePNGColorMode);
+	pub fn lodepng_state_cleanup(state: *mut LodePNGState);
+	pub fn lodepng_state_init(state: *mut LodePNGState);
+}
@@ -12,6 +12,8 @@ use bindings::{
 	lodepng_compute_color_stats,
 	lodepng_decode,
 	lodepng_encode,
+	lodepng_palette_add,
+	lodepng_palette_clear,
 	lodepng_state_cleanup,
 	lodepng_state_init,
 	LodePNGColorStats,
@@ -30,6 +32,10 @@ use std::{
 	mem::MaybeUninit,
 	num::NonZeroU32,
 	ptr::NonNull,
+	sync::atomic::{
+		AtomicBool,
+		Ordering::Relaxed,
+	},
 };
 use super::{
 	deflate::flaca_png_deflate,
@@ -39,12 +45,13 @@ use super::{
 
 
 
-// Generated by build.rs.
+// Generated by build.rs, or pre-generated for builds without bindgen.
 #[allow(
 	clippy::allow_attributes,
 	clippy::allow_attributes_without_reason,
 	clippy::missing_docs_in_private_items,
 	clippy::unseparated_literal_suffix,
+	clippy::use_self,
 	dead_code,
 	non_snake_case,
 	trivial_casts,
@@ -53,14 +60,95 @@ use super::{
 	reason = "For bindgen.",
 )]
 mod bindings {
+	#[cfg(feature = "bindgen")]
 	include!(concat!(env!("OUT_DIR"), "/lodepng-bindgen.rs"));
+
+	#[cfg(not(feature = "bindgen"))]
+	include!("lodepng-bindgen.rs");
 }
 
 
+/// # Exhaustive?
+///
+/// Note: This value is only (possibly) set during `flaca`'s initialization;
+/// it won't change after that.
+static EXHAUSTIVE: AtomicBool = AtomicBool::new(false);
+
+/// # Keep Alpha Data?
+///
+/// Note: This value is only (possibly) set during `flaca`'s initialization;
+/// it won't change after that.
+static KEEP_ALPHA_DATA: AtomicBool = AtomicBool::new(false);
+
+/// # Keep Color Type?
+///
+/// Note: This value is only (possibly) set during `flaca`'s initialization;
+/// it won't change after that.
+static KEEP_COLOR_TYPE: AtomicBool = AtomicBool::new(false);
+
+
+
+#[must_use]
+/// # Exhaustive?
+///
+/// Returns `true` if every viable color type and bit depth is to be tried.
+/// See [`set_exhaustive`] for details.
+pub fn exhaustive() -> bool { EXHAUSTIVE.load(Relaxed) }
+
+/// # Set Exhaustive.
+///
+/// By default, PNGs are re-encoded with the color type and bit depth lodepng
+/// thinks best, plus a palette-free alternative for indexed images. Set this
+/// to `true` to also try each of the other viable combinations — palette,
+/// grey, grey-alpha, RGB, and RGBA, at 1, 2, 4, and/or 8 bits — keeping
+/// whichever compresses best.
+///
+/// This is _very_ slow, as each combination gets its own zopfli pass, but
+/// can make a difference for small images like favicons and sprites.
+///
+/// Note: this has no effect if [`keep_color_type`] is set.
+pub fn set_exhaustive(exhaustive: bool) { EXHAUSTIVE.store(exhaustive, Relaxed); }
+
+#[must_use]
+/// # Keep Alpha Data?
+///
+/// Returns `true` if the colors hiding under fully transparent pixels are to
+/// be preserved. See [`set_keep_alpha_data`] for details.
+pub fn keep_alpha_data() -> bool { KEEP_ALPHA_DATA.load(Relaxed) }
+
+/// # Set Keep Alpha Data.
+///
+/// The color of a fully transparent pixel is invisible, so by default
+/// [`same_pixels`](crate::same_pixels) doesn't care if it changes. Set this
+/// to `true` to require an exact match instead, for workflows that later
+/// un-premultiply or otherwise sample the "hidden" data.
+///
+/// Note: [`optimize`](crate::optimize) never rewrites hidden colors, so this
+/// has no effect on its output.
+pub fn set_keep_alpha_data(keep: bool) { KEEP_ALPHA_DATA.store(keep, Relaxed); }
+
+#[must_use]
+/// # Keep Color Type?
+///
+/// Returns `true` if PNGs are to be re-encoded with their original color
+/// types. See [`set_keep_color_type`] for details.
+pub fn keep_color_type() -> bool { KEEP_COLOR_TYPE.load(Relaxed) }
+
+/// # Set Keep Color Type.
+///
+/// By default, PNGs are re-encoded with whatever color type, bit depth, and
+/// palette compress best. Set this to `true` to keep the originals instead,
+/// limiting optimization to the filtering and DEFLATE stages.
+///
+/// Note: 16-bit images can't survive the (8-bit) round trip, so are left
+/// as-are when this is enabled.
+pub fn set_keep_color_type(keep: bool) { KEEP_COLOR_TYPE.store(keep, Relaxed); }
+
 
 #[no_mangle]
 #[expect(unsafe_code, reason = "For FFI.")]
 #[expect(clippy::inline_always, reason = "For performance.")]
+#[allow(unused_attributes, clippy::allow_attributes, reason = "Newer compilers ignore inlining hints on exported functions.")]
 #[inline(always)]
 /// # Lodepng CRC32.
 ///
@@ -100,6 +188,34 @@ pub(super) struct DecodedImage {
 	h: NonZeroU32,
 }
 
+impl PartialEq for DecodedImage {
+	/// # Equality.
+	///
+	/// The color of a fully transparent pixel is invisible — and fair game
+	/// for alpha optimizations — so only the alpha channel has to match for
+	/// those, unless [`keep_alpha_data`] is set.
+	fn eq(&self, other: &Self) -> bool {
+		let strict = keep_alpha_data();
+		self.w == other.w &&
+		self.h == other.h &&
+		self.pixels().chunks_exact(4)
+			.zip(other.pixels().chunks_exact(4))
+			.all(|(a, b)| a == b || (! strict && a[3] == 0 && b[3] == 0))
+	}
+}
+
+impl DecodedImage {
+	#[expect(unsafe_code, reason = "For slice from raw.")]
+	/// # Pixels.
+	///
+	/// Return the decoded (8-bit RGBA) pixel data as a slice.
+	pub(super) const fn pixels(&self) -> &[u8] {
+		let len = self.w.get() as usize * self.h.get() as usize * 4;
+		// Safety: lodepng allocated exactly this much for RGBA8 output.
+		unsafe { std::slice::from_raw_parts(self.buf.as_ptr(), len) }
+	}
+}
+
 impl Drop for DecodedImage {
 	#[expect(unsafe_code, reason = "For alloc.")]
 	fn drop(&mut self) {
@@ -108,6 +224,25 @@ impl Drop for DecodedImage {
 	}
 }
 
+#[derive(Debug, Clone)]
+/// # Color Mode.
+///
+/// A color type and bit depth (and palette or key, as applicable) to encode
+/// with. See [`LodePNGState::color_modes`].
+pub(super) enum ColorMode {
+	/// # Indexed.
+	///
+	/// The palette and bit depth.
+	Palette(Vec<[u8; 4]>, c_uint),
+
+	/// # Everything Else.
+	///
+	/// The color type, bit depth, and (16-bit) color key, if any.
+	Direct(LodePNGColorType, c_uint, Option<[u16; 3]>),
+}
+
+
+
 impl Default for LodePNGColorStats {
 	#[expect(unsafe_code, reason = "For FFI.")]
 	fn default() -> Self {
@@ -167,7 +302,7 @@ impl LodePNGState {
 
 		// Safety: a non-zero response is an error.
 		if 0 == unsafe {
-			lodepng_decode(&mut buf, &mut w, &mut h, self, src.as_ptr(), src.len())
+			lodepng_decode(&raw mut buf, &raw mut w, &raw mut h, self, src.as_ptr(), src.len())
 		} {
 			let buf = NonNull::new(buf)?;
 			if let Some(w) = NonZeroU32::new(w) {
@@ -194,13 +329,32 @@ impl LodePNGState {
 
 		// Safety: a non-zero response is an error.
 		let res = unsafe {
-			lodepng_encode(&mut out.buf, &mut out.size, img.buf.as_ptr(), img.w.get(), img.h.get(), self)
+			lodepng_encode(&raw mut out.buf, &raw mut out.size, img.buf.as_ptr(), img.w.get(), img.h.get(), self)
 		};
 
 		if 0 == res && ! out.is_null() { Some(out) }
 		else { None }
 	}
 
+	#[expect(unsafe_code, reason = "For FFI.")]
+	/// # Keep Color Type.
+	///
+	/// Copy the decoder's color mode — type, depth, palette, etc. — to the
+	/// output, and disable automatic conversion so it sticks.
+	///
+	/// This returns `None` for 16-bit images, which would lose precision.
+	pub(super) fn keep_color_type(&mut self, dec: &Self) -> Option<()> {
+		if 8 < dec.info_png.color.bitdepth { return None; }
+
+		// Safety: a non-zero response indicates an error.
+		if 0 != unsafe {
+			lodepng_color_mode_copy(&raw mut self.info_png.color, &raw const dec.info_png.color)
+		} { return None; }
+
+		self.encoder.auto_convert = 0;
+		Some(())
+	}
+
 	#[expect(unsafe_code, reason = "For FFI.")]
 	/// # Set Up Encoder.
 	///
@@ -214,7 +368,7 @@ impl LodePNGState {
 		if dec.info_png.color.colortype == LodePNGColorType::LCT_PALETTE {
 			// Safety: a non-zero response indicates an error.
 			if 0 != unsafe {
-				lodepng_color_mode_copy(&mut enc.info_raw, &dec.info_png.color)
+				lodepng_color_mode_copy(&raw mut enc.info_raw, &raw const dec.info_png.color)
 			} { return None; }
 
 			enc.info_raw.colortype = LodePNGColorType::LCT_RGBA;
@@ -228,8 +382,79 @@ impl LodePNGState {
 		Some(enc)
 	}
 
+	#[expect(unsafe_code, reason = "For FFI.")]
+	/// # Set Palette.
+	///
+	/// Encode as indexed color using exactly this palette, in this order, at
+	/// the smallest bit depth that fits it.
+	///
+	/// Note: the palette must cover every color in the image; see
+	/// [`palettes`].
+	pub(super) fn set_palette(&mut self, palette: &[[u8; 4]]) -> Option<()> {
+		self.unset_palette();
+		for &[r, g, b, a] in palette {
+			// Safety: a non-zero response is an error.
+			if 0 != unsafe {
+				lodepng_palette_add(&raw mut self.info_png.color, r, g, b, a)
+			} { return None; }
+		}
+
+		self.encoder.auto_convert = 0;
+		self.info_png.color.colortype = LodePNGColorType::LCT_PALETTE;
+		self.info_png.color.bitdepth = palette_depth(palette.len());
+		self.info_png.color.key_defined = 0;
+		Some(())
+	}
+
+	/// # Set Color Mode.
+	///
+	/// Encode with exactly this color type and bit depth.
+	///
+	/// Note: the mode must be able to represent every pixel in the image; see
+	/// [`LodePNGState::color_modes`].
+	pub(super) fn set_color_mode(&mut self, mode: &ColorMode) -> Option<()> {
+		match mode {
+			ColorMode::Palette(palette, depth) => {
+				self.set_palette(palette)?;
+				self.info_png.color.bitdepth = *depth;
+			},
+			ColorMode::Direct(colortype, depth, key) => {
+				self.unset_palette();
+				self.encoder.auto_convert = 0;
+				self.info_png.color.colortype = *colortype;
+				self.info_png.color.bitdepth = *depth;
+
+				// The stats keys are 16-bit; the mask brings them down to
+				// size.
+				if let Some([r, g, b]) = key {
+					let mask = (1 << *depth) - 1;
+					self.info_png.color.key_defined = 1;
+					self.info_png.color.key_r = c_uint::from(*r) & mask;
+					self.info_png.color.key_g = c_uint::from(*g) & mask;
+					self.info_png.color.key_b = c_uint::from(*b) & mask;
+				}
+			},
+		}
+		Some(())
+	}
+
+	#[expect(unsafe_code, reason = "For FFI.")]
+	/// # Unset Palette.
+	///
+	/// Clear any palette set by [`LodePNGState::set_palette`] and hand the
+	/// color mode back to lodepng, restoring the (default) RGBA type so it
+	/// won't go looking for the missing palette.
+	pub(super) fn unset_palette(&mut self) {
+		// Safety: this just frees the palette (if any).
+		unsafe { lodepng_palette_clear(&raw mut self.info_png.color); }
+		self.encoder.auto_convert = 1;
+		self.info_png.color.colortype = LodePNGColorType::LCT_RGBA;
+		self.info_png.color.bitdepth = 8;
+		self.info_png.color.key_defined = 0;
+	}
+
 	/// # Change Strategies.
-	pub(super) fn set_strategy(&mut self, strategy: LodePNGFilterStrategy) {
+	pub(super) const fn set_strategy(&mut self, strategy: LodePNGFilterStrategy) {
 		self.encoder.filter_strategy = strategy;
 	}
 
@@ -244,21 +469,77 @@ impl LodePNGState {
 	}
 
 	#[expect(unsafe_code, reason = "For FFI.")]
-	#[inline(never)]
-	/// # Paletteless Encode (for small images).
-	///
-	/// Patch the encoder settings to see if we can squeeze even more savings
-	/// out of the (small) image, reencode it, and return the result if there
-	/// are no errors.
+	/// # Color Stats.
 	///
-	/// Note: the caller will need to check the resulting size to see if
-	/// savings were actually achieved, and keep whichever version was better.
-	pub(super) fn try_small(&mut self, img: &DecodedImage) -> Option<EncodedPNG> {
+	/// Tally up the colors, transparency, etc., of the image.
+	fn color_stats(&self, img: &DecodedImage) -> Option<LodePNGColorStats> {
 		let mut stats = LodePNGColorStats::default();
 		// Safety: a non-zero response is an error.
-		if 0 != unsafe {
-			lodepng_compute_color_stats(&mut stats, img.buf.as_ptr(), img.w.get(), img.h.get(), &self.info_raw)
-		} { return None; }
+		if 0 == unsafe {
+			lodepng_compute_color_stats(&raw mut stats, img.buf.as_ptr(), img.w.get(), img.h.get(), &raw const self.info_raw)
+		} { Some(stats) }
+		else { None }
+	}
+
+	/// # Color Modes.
+	///
+	/// Return every color type and bit depth combination capable of
+	/// (losslessly) representing the image, for brute-forcing.
+	///
+	/// Grey and RGB are only viable if the image is opaque, or its
+	/// transparency can be expressed with a color key; grey modes are also
+	/// limited to images without color, at bit depths with enough room for
+	/// all of the shades. Palettes are likewise limited to images with 256 or
+	/// fewer colors, and bit depths with enough room for them. Grey-alpha and
+	/// RGBA, however, are always fair game (if wasteful).
+	pub(super) fn color_modes(&self, img: &DecodedImage) -> Option<Vec<ColorMode>> {
+		let stats = self.color_stats(img)?;
+		let key = (0 == stats.alpha && 0 != stats.key).then_some([stats.key_r, stats.key_g, stats.key_b]);
+		let keyable = 0 == stats.alpha;
+
+		let mut out = Vec::new();
+		if 0 == stats.colored {
+			if keyable {
+				for depth in [1, 2, 4, 8] {
+					if stats.bits <= depth {
+						out.push(ColorMode::Direct(LodePNGColorType::LCT_GREY, depth, key));
+					}
+				}
+			}
+			out.push(ColorMode::Direct(LodePNGColorType::LCT_GREY_ALPHA, 8, None));
+		}
+		if keyable {
+			out.push(ColorMode::Direct(LodePNGColorType::LCT_RGB, 8, key));
+		}
+		out.push(ColorMode::Direct(LodePNGColorType::LCT_RGBA, 8, None));
+
+		if let Some([palette, _]) = palettes(img) {
+			let min = palette_depth(palette.len());
+			for depth in [1, 2, 4, 8] {
+				if min <= depth {
+					out.push(ColorMode::Palette(palette.clone(), depth));
+				}
+			}
+		}
+
+		Some(out)
+	}
+
+	#[inline(never)]
+	/// # Set Paletteless.
+	///
+	/// Patch the encoder settings to encode the (indexed) image without its
+	/// palette, using the smallest grey or RGB(A) mode capable of holding it.
+	///
+	/// Note: the caller will need to encode the image both ways to see if
+	/// this actually helps, and keep whichever version was better.
+	///
+	/// Like the main pass, this is lossless all the way down: lodepng only
+	/// swaps the alpha channel for a color key when every transparent pixel
+	/// shares the same (hidden) color.
+	pub(super) fn set_paletteless(&mut self, img: &DecodedImage) -> Option<()> {
+		self.unset_palette();
+		let mut stats = self.color_stats(img)?;
 
 		// The image is too small for tRNS chunk overhead.
 		if img.w.checked_mul(img.h)?.get() <= 16 && 0 != stats.key { stats.alpha = 1; }
@@ -282,13 +563,94 @@ impl LodePNGState {
 		}
 		else { self.info_png.color.key_defined = 0; }
 
-		// Re-encode it and see what happens!
-		self.encode(img)
+		Some(())
 	}
 }
 
 
 
+/// # Palette Candidates.
+///
+/// Count the image's colors and return them in two alternative orders: most
+/// frequent first, and a nearest-neighbor chain — each color followed by the
+/// closest remaining one — starting from the most frequent.
+///
+/// Either way, translucent colors come first, so the tRNS chunk can stop at
+/// the first opaque entry rather than running the length of the palette.
+///
+/// This returns `None` if the image has more than 256 colors.
+pub(super) fn palettes(img: &DecodedImage) -> Option<[Vec<[u8; 4]>; 2]> {
+	// Tally the colors, keeping the list sorted for quick lookups. Runs of the
+	// same color are common enough to be worth short-circuiting.
+	let mut counts: Vec<([u8; 4], usize)> = Vec::with_capacity(256);
+	let mut idx = 0;
+	for px in img.pixels().chunks_exact(4) {
+		let px = <[u8; 4]>::try_from(px).ok()?;
+		if counts.get(idx).is_none_or(|c| c.0 != px) {
+			idx = match counts.binary_search_by(|c| c.0.cmp(&px)) {
+				Ok(i) => i,
+				Err(i) => {
+					if counts.len() == 256 { return None; }
+					counts.insert(i, (px, 0));
+					i
+				},
+			};
+		}
+		counts[idx].1 += 1;
+	}
+
+	// By frequency.
+	counts.sort_by(|a, b| (a.0[3] == 255).cmp(&(b.0[3] == 255)).then_with(|| b.1.cmp(&a.1)));
+	let freq: Vec<[u8; 4]> = counts.into_iter().map(|c| c.0).collect();
+
+	// By closeness.
+	let split = freq.iter().position(|c| c[3] == 255).unwrap_or(freq.len());
+	let mut close = chain(&freq[..split]);
+	close.extend(chain(&freq[split..]));
+
+	Some([freq, close])
+}
+
+/// # Palette Bit Depth.
+///
+/// Return the smallest bit depth with room for this many palette entries.
+const fn palette_depth(len: usize) -> c_uint {
+	match len {
+		0..=2 => 1,
+		3..=4 => 2,
+		5..=16 => 4,
+		_ => 8,
+	}
+}
+
+/// # Nearest-Neighbor Chain.
+///
+/// Starting with the first color, repeatedly append whichever remaining color
+/// is closest to the last. Ties go to the earlier (more frequent) color.
+fn chain(src: &[[u8; 4]]) -> Vec<[u8; 4]> {
+	let mut out = Vec::with_capacity(src.len());
+	let mut rest = src.to_vec();
+	while ! rest.is_empty() {
+		let next = out.last().map_or(0, |&last| {
+			rest.iter()
+				.enumerate()
+				.min_by_key(|(_, c)| distance(last, **c))
+				.map_or(0, |(i, _)| i)
+		});
+		out.push(rest.remove(next));
+	}
+	out
+}
+
+/// # Color Distance.
+///
+/// Return the (squared) Euclidean distance between two RGBA colors.
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+	a.into_iter().zip(b).map(|(a, b)| u32::from(a.abs_diff(b)).pow(2)).sum()
+}
+
+
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -309,4 +671,104 @@ mod tests {
 		let raw = std::fs::read("../skel/assets/png/01.png").unwrap();
 		assert!(! LodePNGColorType::LCT_GREY.is_match(&raw));
 	}
+
+	#[cfg(feature = "bindgen")]
+	#[test]
+	fn t_bindgen_copy() {
+		/// # Normalize.
+		///
+		/// Strip the leading comment (if any), whitespace, and trailing
+		/// commas, so formatting differences don't matter.
+		fn normalize(src: &str) -> String {
+			let src = src.trim_start();
+			let src = src.strip_prefix("/*")
+				.and_then(|s| s.split_once("*/"))
+				.map_or(src, |(_, s)| s);
+			src.chars()
+				.filter(|c| ! c.is_whitespace())
+				.collect::<String>()
+				.replace(",)", ")")
+				.replace(",}", "}")
+		}
+
+		assert_eq!(
+			normalize(include_str!(concat!(env!("OUT_DIR"), "/lodepng-bindgen.rs"))),
+			normalize(include_str!("lodepng-bindgen.rs")),
+			"The pre-generated bindings in src/lodepng-bindgen.rs are out of date.",
+		);
+	}
+
+	#[test]
+	fn t_chain() {
+		let src = [
+			[0, 0, 0, 255],
+			[250, 250, 250, 255],
+			[10, 10, 10, 255],
+			[240, 240, 240, 255],
+		];
+		assert_eq!(
+			chain(&src),
+			[src[0], src[2], src[3], src[1]],
+		);
+		assert!(chain(&[]).is_empty());
+	}
+
+	#[test]
+	fn t_color_modes() {
+		/// # Summarize Modes.
+		fn modes(path: &str) -> Vec<(LodePNGColorType, c_uint)> {
+			let raw = std::fs::read(path).expect("Missing image.");
+			let img = LodePNGState::default().decode(&raw).expect("Decode failed.");
+			LodePNGState::default().color_modes(&img)
+				.expect("Stats failed.")
+				.into_iter()
+				.map(|m| match m {
+					ColorMode::Palette(_, depth) => (LodePNGColorType::LCT_PALETTE, depth),
+					ColorMode::Direct(t, depth, _) => (t, depth),
+				})
+				.collect()
+		}
+
+		// Colored, and too many of them for a palette.
+		assert_eq!(
+			modes("../skel/assets/png/01.png"),
+			[(LodePNGColorType::LCT_RGB, 8), (LodePNGColorType::LCT_RGBA, 8)],
+		);
+
+		// Palettes should be offered at each depth with room.
+		let all = modes("../skel/assets/png/poe.png");
+		assert!(all.contains(&(LodePNGColorType::LCT_RGBA, 8)));
+		assert!(all.contains(&(LodePNGColorType::LCT_PALETTE, 8)));
+		assert!(! all.contains(&(LodePNGColorType::LCT_PALETTE, 1)));
+
+		// Grey-alpha is always viable for colorless images.
+		let all = modes("../skel/assets/png/small-bwa.png");
+		assert!(all.contains(&(LodePNGColorType::LCT_GREY_ALPHA, 8)));
+	}
+
+	#[test]
+	fn t_palettes() {
+		let raw = std::fs::read("../skel/assets/png/poe.png").expect("Missing poe.png.");
+		let img = LodePNGState::default().decode(&raw).expect("Decode failed.");
+		let [freq, close] = palettes(&img).expect("Too many colors.");
+
+		// Same colors, different orders.
+		assert!(! freq.is_empty());
+		assert_eq!(freq.len(), close.len());
+		let mut a = freq.clone();
+		let mut b = close.clone();
+		a.sort_unstable();
+		b.sort_unstable();
+		assert_eq!(a, b, "The palettes should have the same colors.");
+		a.dedup();
+		assert_eq!(a.len(), freq.len(), "The palette has duplicates.");
+
+		// Translucent colors come first.
+		for p in [&freq, &close] {
+			assert!(p.is_sorted_by_key(|c| c[3] == 255), "Opaque colors should be last.");
+		}
+
+		// Every pixel should be covered.
+		assert!(img.pixels().chunks_exact(4).all(|px| freq.iter().any(|c| c == px)));
+	}
 }
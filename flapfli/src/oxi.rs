@@ -0,0 +1,110 @@
+/*!
+# Flapfli: Oxipng.
+
+Oxipng runs ahead of zopfli for flaca and its C API alike; the settings live
+here so the two stay in sync.
+*/
+
+use oxipng::{
+	Deflaters,
+	IndexSet,
+	Interlacing,
+	Options,
+	RowFilter,
+	StripChunks,
+};
+use super::{
+	keep_alpha_data,
+	keep_chunks,
+	keep_color_type,
+};
+
+
+
+/// # Maximum Oxipng Effort Level.
+pub const OXIPNG_LEVEL_MAX: u8 = 6;
+
+
+
+#[must_use]
+/// # Oxipng Options.
+///
+/// Return the options corresponding to the effort `level`, from `0` (quick)
+/// to `6` (brute force). Lower levels try fewer row filters with a faster
+/// deflater, mirroring oxipng's own presets.
+///
+/// At the maximum level, this is comparable to calling:
+///
+/// ```bash
+/// oxipng -o 6 -s -a -i 0 --fix
+/// ```
+///
+/// Chunks, alpha, and color types are handled according to
+/// [`keep_chunks`], [`keep_alpha_data`], and [`keep_color_type`],
+/// respectively.
+pub fn oxipng_options(level: u8, fix_errors: bool) -> Options {
+	let (filter, compression) = match level {
+		0 => (IndexSet::from([RowFilter::None]), 5),
+		1 => (IndexSet::from([RowFilter::None]), 10),
+		2 => (
+			IndexSet::from([RowFilter::None, RowFilter::Sub, RowFilter::Entropy, RowFilter::Bigrams]),
+			11,
+		),
+		3 | 4 => (
+			IndexSet::from([RowFilter::None, RowFilter::Bigrams, RowFilter::BigEnt, RowFilter::Brute]),
+			if level == 3 { 11 } else { 12 },
+		),
+		5 => (
+			IndexSet::from([
+				RowFilter::None,
+				RowFilter::BigEnt,
+				RowFilter::Bigrams,
+				RowFilter::Brute,
+				RowFilter::Entropy,
+				RowFilter::MinSum,
+				RowFilter::Sub,
+				RowFilter::Up,
+			]),
+			12,
+		),
+		_ => (
+			IndexSet::from([
+				RowFilter::None,
+				RowFilter::Average,
+				RowFilter::BigEnt,
+				RowFilter::Bigrams,
+				RowFilter::Brute,
+				RowFilter::Entropy,
+				RowFilter::MinSum,
+				RowFilter::Paeth,
+				RowFilter::Sub,
+				RowFilter::Up,
+			]),
+			12,
+		),
+	};
+
+	// Color type and bit depth reductions are fine, unless they aren't.
+	let reduce = ! keep_color_type();
+
+	Options {
+		fix_errors,
+		force: false,
+		filter,
+		interlace: Some(Interlacing::None),
+		optimize_alpha: ! keep_alpha_data(),
+		bit_depth_reduction: reduce,
+		color_type_reduction: reduce,
+		palette_reduction: reduce,
+		grayscale_reduction: reduce,
+		idat_recoding: true,
+		scale_16: false,
+		strip: match keep_chunks() {
+			[] => StripChunks::All,
+			keep => StripChunks::Keep(keep.iter().copied().collect()),
+		},
+		deflate: Deflaters::Libdeflater { compression },
+		fast_evaluation: level < 3,
+		timeout: None,
+	}
+}
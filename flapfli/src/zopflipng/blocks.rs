@@ -5,13 +5,23 @@ This module contains the deflate entrypoint and all of the block-related odds
 and ends that didn't make it into other modules.
 */
 
-use std::num::{
-	NonZeroU32,
-	NonZeroUsize,
+use std::{
+	num::{
+		NonZeroU32,
+		NonZeroUsize,
+	},
+	sync::{
+		atomic::{
+			AtomicU64,
+			Ordering::Relaxed,
+		},
+		OnceLock,
+	},
 };
 use super::{
 	ArrayD,
 	ArrayLL,
+	cancelled,
 	DeflateSym,
 	DISTANCE_BITS,
 	DISTANCE_VALUES,
@@ -57,53 +67,117 @@ const NZ10: NonZeroU32 = NonZeroU32::new(10).unwrap();
 /// # Eleven is Non-Zero.
 const NZ11: NonZeroU32 = NonZeroU32::new(11).unwrap();
 
+/// # Default Maximum Split Points.
+const DEFAULT_MAX_SPLITS: u8 = 14;
+
 /// # Block Split Points.
 ///
-/// This array holds up to fourteen middle points as well as the absolute start
-/// and end indices for the chunk/store.
-type SplitPoints = [usize; 16];
+/// This array holds up to `SplitLen::MAX` (sixty-three) middle points as well
+/// as the absolute start and end indices for the chunk/store.
+type SplitPoints = [usize; SplitLen::MAX as usize + 2];
 
 /// # Zero-Filled Split Points.
-const ZEROED_SPLIT_POINTS: SplitPoints = [0; 16];
+const ZEROED_SPLIT_POINTS: SplitPoints = [0; SplitLen::MAX as usize + 2];
+
+/// # Maximum Split Points.
+///
+/// Note: This value is only (possibly) set (once) during `flaca`'s
+/// initialization; it won't change after that.
+static MAX_SPLITS: OnceLock<u8> = OnceLock::new();
+
+/// # Store Threshold (Percent).
+///
+/// Note: This value is only (possibly) set (once) during `flaca`'s
+/// initialization; it won't change after that.
+static STORE_THRESHOLD: OnceLock<u8> = OnceLock::new();
+
+/// # Total Blocks Written.
+static BLOCKS_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// # Stored (Uncompressed) Blocks Written.
+static BLOCKS_STORED: AtomicU64 = AtomicU64::new(0);
+
+
+
+/// # Block Stats.
+///
+/// Return the total number of DEFLATE blocks written by zopfli so far, along
+/// with how many of those were stored (uncompressed).
+///
+/// Note: these include blocks from encodes that wound up being discarded for
+/// one reason or another.
+pub fn zopfli_block_stats() -> (u64, u64) {
+	(BLOCKS_TOTAL.load(Relaxed), BLOCKS_STORED.load(Relaxed))
+}
+
+/// # Set Store Threshold.
+///
+/// By default, a block is only stored (uncompressed) if none of the
+/// compressed alternatives are any smaller. This raises the bar, storing
+/// blocks unless compression saves at least `pct` percent.
+///
+/// Values must be less than 100.
+pub fn set_zopfli_store_threshold(pct: u8) -> bool {
+	pct < 100 && STORE_THRESHOLD.set(pct).is_ok()
+}
+
+/// # Set Maximum Split Points.
+///
+/// By default, each master block is split into at most fifteen DEFLATE
+/// blocks — fourteen split points. Very heterogeneous images, like
+/// screenshots with photographic regions, can sometimes benefit from finer
+/// partitioning, at the cost of (a lot) more processing time.
+///
+/// Values must be between `1..=63`.
+pub fn set_zopfli_max_splits(max: u8) -> bool {
+	(1..=SplitLen::MAX as u8).contains(&max) && MAX_SPLITS.set(max).is_ok()
+}
 
 
 
 /// # Deflate a Part.
 ///
-/// Image compression is done in chunks of a million bytes. This does all the
-/// work there is to do for one such chunk.
+/// Image compression is done in chunks of (up to) `zopfli_block_size()` bytes.
+/// This does all the work there is to do for one such chunk.
 ///
 /// More specifically, this explores different possible split points for the
 /// chunk, then writes the resulting blocks to the output file.
+///
+/// The `scratch` stores are cleared before use; they're only passed in so the
+/// caller can recycle them.
 pub(crate) fn deflate_part(
 	state: &mut ZopfliState,
+	scratch: &mut [LZ77Store; 3],
 	numiterations: NonZeroU32,
 	last_block: bool,
 	chunk: ZopfliChunk<'_>,
 	out: &mut ZopfliOut,
 ) -> Result<(), ZopfliError> {
-	let mut store = LZ77Store::new();
-	let mut store2 = LZ77Store::new();
+	let [store, store2, store3] = scratch;
+	store.clear();
+	store2.clear();
+	store3.clear();
 
 	// Find the split points.
 	let (best, best_len) = split_points(
 		numiterations,
 		chunk,
-		&mut store,
-		&mut store2,
+		store,
+		store2,
+		store3,
 		state,
 	)?;
 
 	// Write the data!
 	let store_len = NonZeroUsize::new(best[best_len as usize + 1]).ok_or(zopfli_error!())?;
-	for rng in SplitPointsIter::new(&best, best_len) {
+	for rng in SplitPointsIter::new(&best, best_len, chunk.max_block_size()) {
 		let rng = rng?;
 		let store_rng = store.ranged(rng)?;
 		add_lz77_block(
 			last_block && rng.end() == store_len.get(),
 			store_rng,
 			store_len,
-			&mut store2,
+			store2,
 			state,
 			chunk,
 			out,
@@ -181,7 +255,7 @@ fn add_lz77_block(
 		chunk: ZopfliChunk<'_>,
 		out: &mut ZopfliOut,
 	) -> Result<(), ZopfliError> {
-		let rng = store.byte_range()?;
+		let rng = store.byte_range(chunk.max_block_size())?;
 		let chunk2 = chunk.reslice_rng(rng)?;
 		out.add_uncompressed_block(last_block, chunk2);
 		Ok(())
@@ -192,10 +266,18 @@ fn add_lz77_block(
 	fn dynamic_details(store: LZ77StoreRange)
 	-> Result<DynamicLengths, ZopfliError> { DynamicLengths::new(store) }
 
+	BLOCKS_TOTAL.fetch_add(1, Relaxed);
+
 	// Calculate the three costs.
-	let uncompressed_cost = store.block_size_uncompressed()?;
+	let uncompressed_cost = store.block_size_uncompressed(chunk.max_block_size())?;
 	let dynamic = dynamic_details(store)?;
 
+	// Compression has to beat the uncompressed cost by the threshold, if any,
+	// to be worth it.
+	let pct = STORE_THRESHOLD.get().copied().map_or(100, |p| 100 - u64::from(p));
+	let worth_it = |cost: NonZeroU32|
+		u64::from(cost.get()) * 100 <= u64::from(uncompressed_cost.get()) * pct;
+
 	// Most blocks won't benefit from a fixed tree layout, but if we've got a
 	// tiny one or the unoptimized-fixed size is within 10% of the dynamic size
 	// we should check it out.
@@ -203,7 +285,7 @@ fn add_lz77_block(
 		store_len.get() <= LZ77Store::SMALL_STORE ||
 		store.block_size_fixed().saturating_mul(NZ10) <= dynamic.cost().saturating_mul(NZ11)
 	{
-		let rng = store.byte_range()?;
+		let rng = store.byte_range(chunk.max_block_size())?;
 		let fixed_chunk = chunk.reslice_rng(rng)?;
 		state.init_lmc(&fixed_chunk);
 
@@ -211,19 +293,20 @@ fn add_lz77_block(
 		state.optimal_run_fixed(fixed_chunk, fixed_store)?;
 
 		// And finally, the cost!
-		let fixed_store_rng = fixed_store.ranged_full()?;
+		let fixed_store_rng = fixed_store.ranged_full(chunk.max_block_size())?;
 		let fixed_cost = fixed_store_rng.block_size_fixed();
-		if fixed_cost < dynamic.cost() && fixed_cost <= uncompressed_cost {
+		if fixed_cost < dynamic.cost() && worth_it(fixed_cost) {
 			return add_fixed(last_block, fixed_store_rng, out);
 		}
 	}
 
 	// Dynamic is best!
-	if dynamic.cost() <= uncompressed_cost {
+	if worth_it(dynamic.cost()) {
 		add_dynamic(last_block, store, out, dynamic.extra(), dynamic.ll_lengths(), dynamic.d_lengths())
 	}
 	// Nothing is everything!
 	else {
+		BLOCKS_STORED.fetch_add(1, Relaxed);
 		add_uncompressed(last_block, store, chunk, out)
 	}
 }
@@ -279,25 +362,26 @@ fn add_lz77_data(
 }
 
 /// # Calculate Best Block Size (in Bits).
-fn calculate_block_size_auto(store: &LZ77Store, rng: ZopfliRange)
+fn calculate_block_size_auto(store: &LZ77Store, rng: ZopfliRange, max_block: usize)
 -> Result<NonZeroU32, ZopfliError> {
 	let small = store.is_small();
 	let store = store.ranged(rng)?;
-	store.block_size_auto(small)
+	store.block_size_auto(small, max_block)
 }
 
 #[inline(never)]
 /// # Minimum Split Cost.
 ///
 /// Return the index of the smallest split cost between `start..end`.
-fn find_minimum_cost(store: LZ77StoreRange, small: bool)
+fn find_minimum_cost(store: LZ77StoreRange, small: bool, max_block: usize)
 -> Result<(NonZeroUsize, NonZeroU32), ZopfliError> {
 	/// # Split Block Cost.
 	///
 	/// Sum the left and right halves of the range.
-	fn split_cost(a: LZ77StoreRange, b: LZ77StoreRange, small: bool) -> Result<NonZeroU32, ZopfliError> {
-		let a = a.block_size_auto(small)?;
-		let b = b.block_size_auto(small)?;
+	fn split_cost(a: LZ77StoreRange, b: LZ77StoreRange, small: bool, max_block: usize)
+	-> Result<NonZeroU32, ZopfliError> {
+		let a = a.block_size_auto(small, max_block)?;
+		let b = b.block_size_auto(small, max_block)?;
 		Ok(a.saturating_add(b.get()))
 	}
 
@@ -308,7 +392,7 @@ fn find_minimum_cost(store: LZ77StoreRange, small: bool)
 	// Small ranges can just be iterated exhaustively.
 	if store.len().get() <= 1024 {
 		for (a, b) in store.splits()? {
-			let cost = split_cost(a, b, small)?;
+			let cost = split_cost(a, b, small, max_block)?;
 			if cost < last_best_cost {
 				last_best_cost = cost;
 				best_split = a.len(); // The split point.
@@ -324,7 +408,7 @@ fn find_minimum_cost(store: LZ77StoreRange, small: bool)
 			for (i, a, b) in splits.by_ref() {
 				let line_cost =
 					if best_split == a.len() { last_best_cost }
-					else { split_cost(a, b, small)? };
+					else { split_cost(a, b, small, max_block)? };
 
 				if i == 0 || line_cost < best_cost {
 					best_cost = line_cost;
@@ -382,6 +466,9 @@ fn lz77_optimal(
 	// stat run.
 	let mut weighted = false;
 	for i in 0..numiterations.get() {
+		// Give up if we've been asked to.
+		if cancelled() { return Err(zopfli_error!()); }
+
 		// Rebuild the symbols.
 		current_stats.crunch();
 
@@ -389,7 +476,7 @@ fn lz77_optimal(
 		state.optimal_run(chunk, &current_stats, scratch_store)?;
 
 		// At this point, we only care about the dynamic cost of the chunk.
-		let current_cost = scratch_store.ranged_full()
+		let current_cost = scratch_store.ranged_full(chunk.max_block_size())
 			.and_then(LZ77StoreRange::block_size_dynamic)?;
 
 		// We have a new best!
@@ -415,8 +502,8 @@ fn lz77_optimal(
 	}
 
 	// Find and return the current (best) cost of the store.
-	let store_rng = store.ranged_full()?;
-	store_rng.block_size_auto(store_rng.is_small())
+	let store_rng = store.ranged_full(chunk.max_block_size())?;
+	store_rng.block_size_auto(store_rng.is_small(), chunk.max_block_size())
 }
 
 #[inline(never)]
@@ -432,6 +519,7 @@ fn split_points(
 	chunk: ZopfliChunk<'_>,
 	store: &mut LZ77Store,
 	store2: &mut LZ77Store,
+	store3: &mut LZ77Store,
 	state: &mut ZopfliState,
 ) -> Result<(SplitPoints, SplitLen), ZopfliError> {
 	// We'll need two sets of split points.
@@ -445,17 +533,19 @@ fn split_points(
 	// Calculate the costs associated with that split and update the store with
 	// the symbol information encountered.
 	let mut cost1 = 0;
-	let mut store3 = LZ77Store::new();
 	for i in 0..=raw_len as usize {
 		let start = if i == 0 { chunk.pos() } else { split_a[i - 1] };
 		let end = if i < (raw_len as usize) { split_a[i] } else { chunk.total_len().get() };
 
+		// Give up if we've been asked to.
+		if cancelled() { return Err(zopfli_error!()); }
+
 		// Crunch this chunk into a clean store.
 		cost1 += lz77_optimal(
 			chunk.reslice(start, end)?,
 			numiterations,
 			store2,
-			&mut store3,
+			store3,
 			state,
 		)?.get();
 
@@ -473,7 +563,7 @@ fn split_points(
 		split_a[two_len as usize] = store.len();
 		split_a.rotate_right(1);
 
-		// SplitLen tops out at 14 so we can't actually write to 15 (now 0);
+		// SplitLen tops out at 63 so we can't actually write to 64 (now 0);
 		// it should be the default value, which was zero.
 		debug_assert!(
 			split_a[0] == 0,
@@ -481,8 +571,8 @@ fn split_points(
 		);
 
 		let mut cost2 = 0;
-		for rng in SplitPointsIter::new(&split_a, two_len) {
-			cost2 += calculate_block_size_auto(store, rng?)?.get();
+		for rng in SplitPointsIter::new(&split_a, two_len, chunk.max_block_size()) {
+			cost2 += calculate_block_size_auto(store, rng?, chunk.max_block_size())?.get();
 		}
 
 		// It's better!
@@ -560,6 +650,7 @@ fn split_points_lz77(
 		done: &SplitCache,
 		splitpoints: &[usize],
 		rng: &mut ZopfliRange,
+		max_block: usize,
 	) -> Result<bool, ZopfliError> {
 		let mut best = 0;
 		for i in 0..=splitpoints.len() {
@@ -572,7 +663,7 @@ fn split_points_lz77(
 
 			// We found a match!
 			if best < end - start && done.is_unset(start) {
-				rng.set(start, end)?;
+				rng.set(start, end, max_block)?;
 				best = end - start;
 			}
 		}
@@ -580,10 +671,12 @@ fn split_points_lz77(
 	}
 
 	// This won't work on tiny files.
-	let store = store.ranged_full()?;
+	let max_block = state.max_block_size();
+	let store = store.ranged_full(max_block)?;
 	if store.len().get() < MINIMUM_SPLIT_DISTANCE { return Ok(SplitLen::S00); }
 
 	// Get started!
+	let max = MAX_SPLITS.get().copied().unwrap_or(DEFAULT_MAX_SPLITS);
 	let mut rng = ZopfliRange::from(store);
 	let small = store.is_small(); // Smallness depends on the original store for some reason.
 	let done = state.split_cache(rng);
@@ -591,10 +684,10 @@ fn split_points_lz77(
 	let mut len = SplitLen::S00;
 	loop {
 		let store_rng = store.ranged(rng)?;
-		let (llpos, llcost) = find_minimum_cost(store_rng, small)?;
+		let (llpos, llcost) = find_minimum_cost(store_rng, small, max_block)?;
 
 		// Ignore points we've already covered.
-		if llpos.get() == 1 || store_rng.block_size_auto(small)? < llcost {
+		if llpos.get() == 1 || store_rng.block_size_auto(small, max_block)? < llcost {
 			done.set(rng.start());
 		}
 		else {
@@ -611,7 +704,7 @@ fn split_points_lz77(
 			else { last = llpos; }
 
 			// Stop if we've split the maximum number of times.
-			if len.is_max() { break; }
+			if max <= len as u8 { break; }
 		}
 
 		// Look for a split and adjust the start/end accordingly. If we don't
@@ -622,6 +715,7 @@ fn split_points_lz77(
 			done,
 			&split_b[..len as usize],
 			&mut rng,
+			max_block,
 		)? { break; }
 	}
 
@@ -633,7 +727,7 @@ fn split_points_lz77(
 /// # Split Range Iterator.
 ///
 /// This iterator converts split points into split ranges, functioning kinda
-/// like `slice.windows(2)`, returning between `1..=15` ranges spanning the
+/// like `slice.windows(2)`, returning between `1..=64` ranges spanning the
 /// length of the chunk/store.
 struct SplitPointsIter<'a> {
 	/// # Split Points.
@@ -644,12 +738,15 @@ struct SplitPointsIter<'a> {
 
 	/// # Current Position.
 	pos: usize,
+
+	/// # Master Block Size.
+	max_block: usize,
 }
 
 impl<'a> SplitPointsIter<'a> {
 	/// # New Instance.
-	const fn new(data: &'a SplitPoints, max: SplitLen) -> Self {
-		Self { data, max, pos: 0 }
+	const fn new(data: &'a SplitPoints, max: SplitLen, max_block: usize) -> Self {
+		Self { data, max, pos: 0, max_block }
 	}
 }
 
@@ -662,7 +759,7 @@ impl Iterator for SplitPointsIter<'_> {
 			let start = self.data[self.pos];
 			let end = self.data[self.pos + 1];
 			self.pos += 1;
-			Some(ZopfliRange::new(start, end))
+			Some(ZopfliRange::new(start, end, self.max_block))
 		}
 		else { None }
 	}
@@ -684,6 +781,7 @@ impl ExactSizeIterator for SplitPointsIter<'_> {
 #[cfg(test)]
 mod test {
 	use super::*;
+	use super::super::ZOPFLI_MASTER_BLOCK_SIZE;
 
 	#[test]
 	fn t_fixed_symbols() {
@@ -697,12 +795,23 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn t_max_splits() {
+		// Out of range.
+		assert!(! set_zopfli_max_splits(0));
+		assert!(! set_zopfli_max_splits(SplitLen::MAX as u8 + 1));
+
+		// The default should fit the range.
+		assert!((1..=SplitLen::MAX as u8).contains(&DEFAULT_MAX_SPLITS));
+	}
+
 	#[test]
 	fn t_split_points_iter() {
-		let data: SplitPoints = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+		let mut data: SplitPoints = ZEROED_SPLIT_POINTS;
+		for (i, v) in data.iter_mut().enumerate() { *v = i; }
 
 		// Try with no mids.
-		let mut iter = SplitPointsIter::new(&data, SplitLen::S00);
+		let mut iter = SplitPointsIter::new(&data, SplitLen::S00, ZOPFLI_MASTER_BLOCK_SIZE);
 		assert_eq!(iter.len(), 1);
 		let next = iter.next()
 			.expect("expected Some(range)")
@@ -712,7 +821,7 @@ mod test {
 		assert!(iter.next().is_none());
 
 		// Try with two mids.
-		iter = SplitPointsIter::new(&data, SplitLen::S02);
+		iter = SplitPointsIter::new(&data, SplitLen::S02, ZOPFLI_MASTER_BLOCK_SIZE);
 		let expected = [0..1_usize, 1..2, 2..3];
 		for (i, e) in expected.into_iter().enumerate() {
 			assert_eq!(iter.len(), 3 - i);
@@ -10,7 +10,6 @@ use super::{
 	LitLen,
 	SUBLEN_LEN,
 	zopfli_error,
-	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZOPFLI_MIN_MATCH,
 	ZopfliChunk,
 	ZopfliError,
@@ -41,14 +40,6 @@ const ZOPFLI_CACHE_LENGTH: usize = 8;
 /// collection is thus…
 const SUBLEN_CACHED_LEN: usize = ZOPFLI_CACHE_LENGTH * 3;
 
-/// # Length of Split Cache.
-///
-/// The split cache is mercifully boolean, so we can pack it into a bit array,
-/// reducing its size to one eighth what it otherwise would be.
-const SPLIT_CACHE_LEN: usize = ZOPFLI_MASTER_BLOCK_SIZE.div_ceil(8);
-
-
-
 #[repr(C)]
 /// # Longest Match Cache.
 ///
@@ -61,31 +52,41 @@ const SPLIT_CACHE_LEN: usize = ZOPFLI_MASTER_BLOCK_SIZE.div_ceil(8);
 /// trying to get by without without it is downright _miserable_.
 ///
 /// On the bright side, we only need one instance per thread for the duration
-/// of the program run, and thanks to some clever boxing, it winds up on the
-/// heap instead of the stack.
+/// of the program run, sized once — to match `zopfli_block_size` — at the
+/// start.
 pub(crate) struct MatchCache {
 	/// # Length and Distance.
 	///
 	/// Each pair consists of two sixteen-bit values, joined into a single
 	/// little endian `u32`.
-	ld: [u32; ZOPFLI_MASTER_BLOCK_SIZE],
+	ld: Box<[u32]>,
 
 	/// # Sublength Cache.
-	sublen: [u8; SUBLEN_CACHED_LEN * ZOPFLI_MASTER_BLOCK_SIZE],
+	sublen: Box<[u8]>,
 }
 
 impl MatchCache {
+	/// # New.
+	///
+	/// Allocate a (zeroed) cache large enough for blocks of `size` bytes.
+	pub(crate) fn new(size: usize) -> Self {
+		Self {
+			ld: vec![0; size].into_boxed_slice(),
+			sublen: vec![0; size * SUBLEN_CACHED_LEN].into_boxed_slice(),
+		}
+	}
+
 	/// # Initialize.
 	///
 	/// Reset (enough of) the cache to its initial/default state for any
 	/// subsequent processing of `chunk` we might need to do. (Most chunks will
-	/// be smaller than `ZOPFLI_MASTER_BLOCK_SIZE` so we won't normally need to
+	/// be smaller than the master block size so we won't normally need to
 	/// reset _everything_.)
 	///
 	/// The length half of `ld` defaults to one; everything else defaults to
 	/// zero.
 	pub(crate) fn init(&mut self, chunk: &ZopfliChunk<'_>) {
-		let blocksize = usize::min(chunk.block_size().get(), ZOPFLI_MASTER_BLOCK_SIZE);
+		let blocksize = usize::min(chunk.block_size().get(), self.ld.len());
 
 		// Lengths default to one, everything else to zero.
 		self.ld[..blocksize].fill(DEFAULT_LD);
@@ -109,7 +110,7 @@ impl MatchCache {
 		length: &mut LitLen,
 	) -> Result<bool, ZopfliError> {
 		// One sanity check to rule them all.
-		if pos >= ZOPFLI_MASTER_BLOCK_SIZE { return Err(zopfli_error!()); }
+		if pos >= self.ld.len() { return Err(zopfli_error!()); }
 
 		// If we have no distance, we have no cache.
 		let (cache_len, cache_dist) = ld_split(self.ld[pos]);
@@ -179,7 +180,7 @@ impl MatchCache {
 		distance: u16,
 		length: LitLen,
 	) -> Result<(), ZopfliError> {
-		if pos >= ZOPFLI_MASTER_BLOCK_SIZE { return Err(zopfli_error!()); }
+		if pos >= self.ld.len() { return Err(zopfli_error!()); }
 
 		// Cache is only worth setting if the current length/distance is the
 		// default.
@@ -250,20 +251,28 @@ impl MatchCache {
 /// for the duration of the program run.
 pub(crate) struct SplitCache {
 	/// # Set.
-	set: [u8; SPLIT_CACHE_LEN],
+	///
+	/// The split cache is mercifully boolean, so we can pack it into a bit
+	/// array, reducing its size to one eighth what it otherwise would be.
+	set: Box<[u8]>,
 }
 
 impl SplitCache {
+	/// # New.
+	///
+	/// Allocate a (zeroed) cache large enough for blocks of `size` bytes.
+	pub(crate) fn new(size: usize) -> Self {
+		Self { set: vec![0; size.div_ceil(8)].into_boxed_slice() }
+	}
+
 	/// # Initialize.
 	///
 	/// Reset the first `rng.len()` bits — these ranges always start at zero —
 	/// to false so we can track a new set of indices.
 	pub(crate) fn init(&mut self, rng: ZopfliRange) {
-		let blocksize = usize::min(rng.len().get(), ZOPFLI_MASTER_BLOCK_SIZE);
-
 		// Fill uses bytes rather than bits, so we need to round up to ensure
 		// complete coverage for our range.
-		let bitsize = blocksize.div_ceil(8);
+		let bitsize = usize::min(rng.len().get().div_ceil(8), self.set.len());
 		self.set[..bitsize].fill(0);
 	}
 
@@ -272,10 +281,10 @@ impl SplitCache {
 	///
 	/// Returns true if the value is currently _unchecked_. (The caller takes
 	/// action on the negative rather than the positive.)
-	pub(crate) const fn is_unset(&self, pos: usize) -> bool {
+	pub(crate) fn is_unset(&self, pos: usize) -> bool {
 		let idx = pos.wrapping_div(8); // The byte.
 		let mask: u8 = 1 << (pos % 8); // The bit.
-		SPLIT_CACHE_LEN <= idx || 0 == self.set[idx] & mask
+		self.set.get(idx).is_none_or(|&b| 0 == b & mask)
 	}
 
 	#[inline]
@@ -283,7 +292,7 @@ impl SplitCache {
 	pub(crate) fn set(&mut self, pos: usize) {
 		let idx = pos.wrapping_div(8); // The byte.
 		let mask: u8 = 1 << (pos % 8); // The bit.
-		if idx < SPLIT_CACHE_LEN { self.set[idx] |= mask; }
+		if let Some(b) = self.set.get_mut(idx) { *b |= mask; }
 	}
 }
 
@@ -294,24 +303,34 @@ impl SplitCache {
 /// This struct stores LZ77 length costs and paths.
 ///
 /// The actual number of costs and paths will vary from image-to-image, block-
-/// to-block, but can actually go as high as a million and one!
+/// to-block, but can actually go as high as the master block size plus one
+/// (a million and one by default)!
 ///
 /// Lest that sound like a terrible waste, this struct only exists as part of
-/// a thread-local static so will be reused as many times as needed. That
-/// static is also boxed to ensure the data winds up on the heap instead of the
-/// stack.
+/// a thread-local static so will be reused as many times as needed.
 pub(crate) struct SqueezeCache {
 	/// # Costs and Symbols.
-	costs: [(f32, LitLen); ZOPFLI_MASTER_BLOCK_SIZE + 1],
+	costs: Box<[(f32, LitLen)]>,
 
 	/// # Paths.
-	paths: [LitLen; ZOPFLI_MASTER_BLOCK_SIZE],
+	paths: Box<[LitLen]>,
 
 	/// # Block Size (+1).
-	pub(super) costs_len: Cell<usize>,
+	costs_len: Cell<usize>,
 }
 
 impl SqueezeCache {
+	/// # New.
+	///
+	/// Allocate a (zeroed) cache large enough for blocks of `size` bytes.
+	pub(crate) fn new(size: usize) -> Self {
+		Self {
+			costs: vec![(0.0, LitLen::L000); size + 1].into_boxed_slice(),
+			paths: vec![LitLen::L000; size].into_boxed_slice(),
+			costs_len: Cell::new(0),
+		}
+	}
+
 	/// # Resize Costs.
 	///
 	/// This method merely sets the internal cost-length variable to match
@@ -332,7 +351,7 @@ impl SqueezeCache {
 		// Clamping shouldn't be necessary as ZopfliChunk verifies the block
 		// size is under the limit and non-empty, and since costs is always
 		// blocks+1, there'll be at least two.
-		let len = self.costs_len.get().clamp(2, ZOPFLI_MASTER_BLOCK_SIZE + 1);
+		let len = self.costs_len.get().clamp(2, self.costs.len());
 
 		let costs = &mut self.costs[..len];
 		costs[0].0 = 0.0;
@@ -351,7 +370,7 @@ impl SqueezeCache {
 		let costs = self.costs.get(..self.costs_len.get()).unwrap_or(&[]);
 		if costs.len() < 2 { Ok(&[]) }
 		else {
-			let mut from = ZOPFLI_MASTER_BLOCK_SIZE;
+			let mut from = self.paths.len();
 			let mut idx = costs.len() - 1;
 			while 0 != from && 0 != idx {
 				let v = costs[idx].1;
@@ -426,6 +445,7 @@ fn write_sublen(src: &[u8; SUBLEN_CACHED_LEN], dst: &mut [u16; SUBLEN_LEN]) {
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use super::super::ZOPFLI_MASTER_BLOCK_SIZE;
 
 	#[test]
 	fn t_split_join() {
@@ -462,9 +482,7 @@ mod tests {
 
 	#[test]
 	fn t_split_cache() {
-		let mut cache = SplitCache {
-			set: [0_u8; SPLIT_CACHE_LEN],
-		};
+		let mut cache = SplitCache::new(ZOPFLI_MASTER_BLOCK_SIZE);
 
 		// Check that positions are false to start, true after set.
 		for i in 0..ZOPFLI_MASTER_BLOCK_SIZE {
@@ -478,7 +496,7 @@ mod tests {
 
 		// If we initialize with a small value, only those bits should be
 		// affected.
-		cache.init(ZopfliRange::new(0, 32).unwrap());
+		cache.init(ZopfliRange::new(0, 32, ZOPFLI_MASTER_BLOCK_SIZE).unwrap());
 		assert_eq!(cache.set[0], 0);
 		assert_eq!(cache.set[1], 0);
 		assert_eq!(cache.set[2], 0);
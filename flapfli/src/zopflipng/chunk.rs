@@ -5,7 +5,6 @@
 use std::num::NonZeroUsize;
 use super::{
 	zopfli_error,
-	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZOPFLI_WINDOW_SIZE,
 	ZopfliError,
 	ZopfliRange,
@@ -16,10 +15,9 @@ use super::{
 #[derive(Debug, Clone, Copy)]
 /// # Deflate Chunk.
 ///
-/// The deflate/zopfli process is weird. The data is sliced in
-/// `ZOPFLI_MASTER_BLOCK_SIZE` windows, kinda, but the previous data is
-/// included for the ride because it is sometimes relevant for hashing and
-/// caching.
+/// The deflate/zopfli process is weird. The data is sliced in master block
+/// windows, kinda, but the previous data is included for the ride because it
+/// is sometimes relevant for hashing and caching.
 ///
 /// Similar to `ZopfliRange`, this struct mainly exists to help enforce the
 /// logical constraints so we don't have to repeat sanity checks every five
@@ -28,28 +26,37 @@ use super::{
 /// The struct's `from` value may or may not be zero — on the first pass there
 /// won't be any previous data — but it will always be less than `arr.len()`,
 /// and `arr.len() - from` will always be less than or equal to
-/// `ZOPFLI_MASTER_BLOCK_SIZE`, i.e. one million.
+/// the master block size, i.e. `zopfli_block_size()` (one million by default).
+///
+/// That size is looked up once per image and carried along with the chunk —
+/// and passed on to any `ZopfliRange`s built from it — rather than fetched
+/// anew each time.
 pub(crate) struct ZopfliChunk<'a> {
 	/// # Array.
 	arr: &'a [u8],
 
 	/// # Window Start.
 	from: usize,
+
+	/// # Master Block Size.
+	max: usize,
 }
 
 impl<'a> ZopfliChunk<'a> {
 	/// # New.
 	///
-	/// Define a new chunk with the given source and starting point.
+	/// Define a new chunk with the given source, starting point, and master
+	/// block size.
 	///
 	/// ## Errors.
 	///
 	/// This will return an error if the slice is empty, `from` is out of
-	/// range, or the length from `from` is greater than
-	/// `ZOPFLI_MASTER_BLOCK_SIZE`.
-	pub(crate) const fn new(arr: &'a [u8], from: usize) -> Result<Self, ZopfliError> {
-		if from < arr.len() && arr.len() - from <= ZOPFLI_MASTER_BLOCK_SIZE {
-			Ok(Self { arr, from })
+	/// range, or the length from `from` is greater than the master block
+	/// size.
+	pub(crate) const fn new(arr: &'a [u8], from: usize, max: usize)
+	-> Result<Self, ZopfliError> {
+		if from < arr.len() && arr.len() - from <= max {
+			Ok(Self { arr, from, max })
 		}
 		else { Err(zopfli_error!()) }
 	}
@@ -62,9 +69,9 @@ impl<'a> ZopfliChunk<'a> {
 	/// The `start` serves as the new instances `from`. If `end` is less than
 	/// `arr.len()`, the new chunk's slice will be truncated accordingly.
 	pub(crate) fn reslice(&self, start: usize, end: usize) -> Result<Self, ZopfliError> {
-		if start < end && end - start <= ZOPFLI_MASTER_BLOCK_SIZE && end <= self.arr.len() {
+		if start < end && end - start <= self.max && end <= self.arr.len() {
 			let arr = &self.arr[..end];
-			Ok(Self { arr, from: start })
+			Ok(Self { arr, from: start, max: self.max })
 		}
 		else { Err(zopfli_error!()) }
 	}
@@ -79,7 +86,7 @@ impl<'a> ZopfliChunk<'a> {
 	/// we'd otherwise need to verify before giving the `Ok()`.
 	pub(crate) fn reslice_rng(&self, rng: ZopfliRange) -> Result<Self, ZopfliError> {
 		let arr = self.arr.get(..rng.end()).ok_or(zopfli_error!())?;
-		Ok(Self { arr, from: rng.start() })
+		Ok(Self { arr, from: rng.start(), max: self.max })
 	}
 }
 
@@ -124,6 +131,11 @@ impl ZopfliChunk<'_> {
 		unsafe { NonZeroUsize::new_unchecked(self.arr.len() - self.from) }
 	}
 
+	/// # Master Block Size.
+	///
+	/// Return the maximum active length, for use with `ZopfliRange`.
+	pub(crate) const fn max_block_size(&self) -> usize { self.max }
+
 	/// # Current Position.
 	///
 	/// Return the `from` index that marks the starting point of the "active"
@@ -182,8 +194,8 @@ impl<'a> ZopfliChunk<'a> {
 	///
 	/// Note: the internal slice will be truncated if needed to uphold the
 	/// maximum length constraint, but that loss doesn't actually matter since
-	/// prelude hashing never looks at more than `u16::MAX` bytes anyway. (A
-	/// million minus thirty-odd thousand is still much more than that!)
+	/// prelude hashing never looks much beyond `ZOPFLI_WINDOW_SIZE` bytes
+	/// anyway, and master blocks are always comfortably bigger than that.
 	pub(crate) fn reducing_prelude_iter(self) -> Option<std::iter::Take<ZopfliChunkIter<'a>>> {
 		// If we're at the start of the slice, there is no prelude.
 		if self.from == 0 { None }
@@ -192,10 +204,10 @@ impl<'a> ZopfliChunk<'a> {
 			if window_start >= self.arr.len() { return None; } // Impossible.
 
 			let arr =
-				if self.arr.len() - window_start <= ZOPFLI_MASTER_BLOCK_SIZE { self.arr }
-				else { &self.arr[..window_start + ZOPFLI_MASTER_BLOCK_SIZE] };
+				if self.arr.len() - window_start <= self.max { self.arr }
+				else { &self.arr[..window_start + self.max] };
 
-			let chunk = Self { arr, from: window_start };
+			let chunk = Self { arr, from: window_start, max: self.max };
 			Some(ZopfliChunkIter(chunk).take(self.from - window_start))
 		}
 	}
@@ -252,11 +264,12 @@ impl ExactSizeIterator for ZopfliChunkIter<'_> {
 #[cfg(test)]
 mod test {
 	use super::*;
+	use super::super::ZOPFLI_MASTER_BLOCK_SIZE as MAX;
 
 	#[test]
 	fn t_reducing_block_iter() {
 		let arr: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-		let chunk = ZopfliChunk { arr, from: 1 };
+		let chunk = ZopfliChunk { arr, from: 1, max: MAX };
 		let mut iter = chunk.reducing_block_iter();
 
 		let mut len = 9;
@@ -282,7 +295,7 @@ mod test {
 	#[test]
 	fn t_reducing_prelude_iter() {
 		let arr: &[u8] = &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-		let chunk = ZopfliChunk { arr, from: 1 };
+		let chunk = ZopfliChunk { arr, from: 1, max: MAX };
 		let mut iter = chunk.reducing_prelude_iter().expect("missing prelude iter");
 
 		assert_eq!(iter.len(), 1);
@@ -294,18 +307,18 @@ mod test {
 		assert!(iter.next().is_none());
 
 		// Let's try it again with a chunk that has no prelude.
-		let chunk = ZopfliChunk { arr, from: 0 };
+		let chunk = ZopfliChunk { arr, from: 0, max: MAX };
 		assert!(chunk.reducing_prelude_iter().is_none());
 
 		// And let's try one that is too big.
-		let arr = vec![0_u8; ZOPFLI_MASTER_BLOCK_SIZE + 10];
-		let chunk = ZopfliChunk::new(arr.as_slice(), 10).expect("Chunk failed.");
+		let arr = vec![0_u8; MAX + 10];
+		let chunk = ZopfliChunk::new(arr.as_slice(), 10, MAX).expect("Chunk failed.");
 		let mut iter = chunk.reducing_prelude_iter().expect("missing prelude iter");
 
 		assert_eq!(iter.len(), 10);
 		let next = iter.next().expect("reducing prelude iter terminated early");
 
 		// The slice should be truncated to fit the constraint.
-		assert_eq!(next.block_size().get(), ZOPFLI_MASTER_BLOCK_SIZE);
+		assert_eq!(next.block_size().get(), MAX);
 	}
 }
@@ -27,9 +27,11 @@ use super::{
 	MatchCache,
 	ReducingSlices,
 	SplitCache,
+	simd,
 	SqueezeCache,
 	stats::SymbolStats,
 	SUBLEN_LEN,
+	zopfli_block_size,
 	zopfli_error,
 	ZOPFLI_MAX_MATCH,
 	ZOPFLI_MIN_MATCH,
@@ -90,6 +92,12 @@ pub(crate) struct ZopfliState {
 
 	/// # LZ77 Squeeze Cache.
 	squeeze: SqueezeCache,
+
+	/// # Master Block Size.
+	///
+	/// This is fixed for the life of the program, but is kept here — next to
+	/// the caches sized by it — to spare the hot paths a `OnceLock` lookup.
+	max: usize,
 }
 
 impl ZopfliState {
@@ -107,6 +115,10 @@ impl ZopfliState {
 	/// To cut down on some of the complexity, the manual layout allocation and
 	/// boxing is done once, here, instead of separately for each individual
 	/// member.
+	///
+	/// The match, split, and squeeze caches are sized according to
+	/// `zopfli_block_size`, so can't be baked into the layout; they're
+	/// allocated separately and written in afterward.
 	pub(crate) fn new() -> Box<Self> {
 		/// # Layout.
 		const LAYOUT: Layout = Layout::new::<ZopfliState>();
@@ -118,12 +130,20 @@ impl ZopfliState {
 			.unwrap_or_else(|| handle_alloc_error(LAYOUT));
 		let ptr = out.as_ptr();
 
-		// Safety: zeroes are "valid" for all of the primitives — including
-		// LitLen, which is sized/aligned to u16 —  so alloc_zeroed has
-		// taken care of everything but the Cell in SqueezeCache, which we
-		// can sort out thusly:
+		// Allocate the runtime-sized caches.
+		let size = zopfli_block_size();
+		let lmc = MatchCache::new(size);
+		let split = SplitCache::new(size);
+		let squeeze = SqueezeCache::new(size);
+
+		// Safety: zeroes are "valid" for all of the primitives in the hash,
+		// so alloc_zeroed has taken care of everything but the caches, which
+		// we can sort out thusly:
 		unsafe {
-			addr_of_mut!((*ptr).squeeze.costs_len).write(Cell::new(0));
+			addr_of_mut!((*ptr).lmc).write(lmc);
+			addr_of_mut!((*ptr).split).write(split);
+			addr_of_mut!((*ptr).squeeze).write(squeeze);
+			addr_of_mut!((*ptr).max).write(size);
 
 			// Note: zero is not the appropriate _logical_ default in most
 			// cases, but since this struct is designed for reuse, it manually
@@ -135,6 +155,11 @@ impl ZopfliState {
 		}
 	}
 
+	/// # Master Block Size.
+	///
+	/// Return the master block size the caches were sized for.
+	pub(crate) const fn max_block_size(&self) -> usize { self.max }
+
 	/// # Initialize LMC/Squeeze Caches.
 	///
 	/// This prepares the Longest Match Cache and Squeeze caches for subsequent
@@ -467,7 +492,7 @@ impl ZopfliHash {
 		fn minimum_cost(stats: &SymbolStats) -> f64 {
 			// Find the minimum length cost.
 			let mut length_cost = f64::INFINITY;
-			for (lsym, lbits) in LENGTH_SYMBOLS.iter().copied().zip(LENGTH_SYMBOL_BITS_F.into_iter()).skip(3) {
+			for (lsym, lbits) in LENGTH_SYMBOLS.iter().copied().zip(LENGTH_SYMBOL_BITS_F).skip(3) {
 				let cost = lbits + stats.ll_symbols[lsym as usize];
 				if cost < length_cost { length_cost = cost; }
 			}
@@ -987,15 +1012,12 @@ impl ZopfliHash {
 
 					// Bump the length for each matching left/right pair, up to
 					// the limit.
-					for next in LitLen::next_iter(currentlength).take((limit as usize) - (currentlength as usize)) {
-						if
-							(currentlength as usize) < right.len() &&
-							left[currentlength as usize] == right[currentlength as usize]
-						{
-							currentlength = next;
-						}
-						else { break; }
-					}
+					currentlength = limit.min_usize(simd::match_len(
+						left,
+						right,
+						currentlength as usize,
+						limit as usize,
+					));
 
 					// We've found a better length!
 					if (bestlength as u16) < (currentlength as u16) {
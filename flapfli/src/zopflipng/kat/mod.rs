@@ -16,6 +16,7 @@ use std::{
 	},
 	num::NonZeroU32,
 	ptr::NonNull,
+	sync::OnceLock,
 };
 use super::{
 	ArrayD,
@@ -38,12 +39,22 @@ const NZ14: NonZeroU32 = NonZeroU32::new(14).unwrap();
 /// # Zero-Filled Tree Counts.
 const ZEROED_COUNTS_TREE: [u32; 19] = [0; 19];
 
+/// # Refined Cost Model.
+///
+/// Note: This value is only (possibly) set (once) during `flaca`'s
+/// initialization; it won't change after that.
+static REFINED_COST_MODEL: OnceLock<bool> = OnceLock::new();
+
 
 
 /// # Calculate the Exact Tree Size (in Bits).
 ///
 /// This returns the index (0..8) that produced the smallest size, along
 /// with that size.
+///
+/// If the refined cost model is enabled, each index is measured twice —
+/// once with the greedy RLE counts, once with the refined ones — and the
+/// smaller of the two is used.
 pub(crate) fn best_tree_size(
 	ll_lengths: &ArrayLL<DeflateSym>,
 	d_lengths: &ArrayD<DeflateSym>,
@@ -51,6 +62,7 @@ pub(crate) fn best_tree_size(
 	// Merge symbols.
 	let (raw_all, _, _) = tree_symbols(ll_lengths, d_lengths)?;
 	let all: &[DeflateSym] = &raw_all;
+	let refined = refined_cost_model();
 
 	// Our targets!
 	let mut best_extra = 0;
@@ -59,19 +71,15 @@ pub(crate) fn best_tree_size(
 	for extra in 0..8 {
 		let cl_counts = best_tree_size_counts(all, extra);
 		let cl_lengths = cl_counts.llcl()?;
-		let hclen = tree_hclen(&cl_counts);
-
-		// We can finally calculate the size!
-		let mut size = (hclen as u32 + 4) * 3;
-		size += cl_lengths.iter()
-			.copied()
-			.zip(cl_counts.iter().copied())
-			.map(|(a, b)| (a as u32) * b)
-			.sum::<u32>();
-		size += cl_counts[16] * 2; // Extra bits.
-		size += cl_counts[17] * 3;
-		size += cl_counts[18] * 7;
-		let size = NZ14.saturating_add(size);
+		let mut size = tree_size(&cl_counts, &cl_lengths);
+
+		// See if the refined counts do any better.
+		if refined {
+			if let Some(cl_counts2) = refined_tree_counts(all, extra, &cl_lengths, None) {
+				let size2 = tree_size(&cl_counts2, &cl_counts2.llcl()?);
+				if size2 < size { size = size2; }
+			}
+		}
 
 		// If better, keep it!
 		if size < best_size {
@@ -84,6 +92,18 @@ pub(crate) fn best_tree_size(
 	Ok((best_extra, best_size))
 }
 
+/// # Set Cost Model.
+///
+/// Enable (or explicitly disable) the refined tree cost model, which
+/// re-tokenizes the greedy RLE using the actual code lengths — and extra
+/// bits — of the 16/17/18 repeat symbols, keeping the result whenever it
+/// yields a smaller tree header.
+///
+/// The original (greedy) model remains the default for reproducibility.
+pub fn set_zopfli_cost_model(refined: bool) -> bool {
+	REFINED_COST_MODEL.set(refined).is_ok()
+}
+
 /// # Encode Tree.
 ///
 /// This writes the best-found tree data to `out`.
@@ -99,8 +119,23 @@ pub(crate) fn encode_tree(
 	// We'll need to store some RLE symbols and positions too.
 	let mut rle: Vec<(DeflateSym, u16)> = Vec::new();
 
-	let cl_counts = encode_tree_counts(&all, &mut rle, extra);
-	let cl_lengths = cl_counts.llcl()?;
+	let mut cl_counts = encode_tree_counts(&all, &mut rle, extra);
+	let mut cl_lengths = cl_counts.llcl()?;
+
+	// If the refined model is enabled, swap in its counts if they're better.
+	// (This mirrors the decision made by best_tree_size.)
+	if refined_cost_model() {
+		let mut rle2 = Vec::new();
+		if let Some(cl_counts2) = refined_tree_counts(&all, extra, &cl_lengths, Some(&mut rle2)) {
+			let cl_lengths2 = cl_counts2.llcl()?;
+			if tree_size(&cl_counts2, &cl_lengths2) < tree_size(&cl_counts, &cl_lengths) {
+				cl_counts = cl_counts2;
+				cl_lengths = cl_lengths2;
+				rle = rle2;
+			}
+		}
+	}
+
 	let hclen = tree_hclen(&cl_counts);
 	let cl_symbols = <[u32; 19]>::llcl_symbols(&cl_lengths);
 
@@ -257,6 +292,134 @@ fn encode_tree_counts(
 	cl_counts
 }
 
+#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
+/// # Refined Tree Counts.
+///
+/// Re-tokenize the symbols using the code lengths from a first, greedy pass,
+/// choosing the cheapest mix of literals and (allowed) 16/17/18 repeats —
+/// extra bits included — for each run, and return the resulting counts.
+///
+/// If `rle` is provided, it is populated with the symbols and positions for
+/// writing.
+///
+/// Because the counts change, so too might the code lengths and `hclen`, so
+/// the result needs to be re-measured before use. `None` is returned if a run
+/// can't be encoded with the given lengths, though that shouldn't happen.
+fn refined_tree_counts(
+	all: &[DeflateSym],
+	extra: u8,
+	cl_lengths: &[DeflateSym; 19],
+	mut rle: Option<&mut Vec<(DeflateSym, u16)>>,
+) -> Option<[u32; 19]> {
+	/// # Symbol Cost.
+	///
+	/// Return the number of bits needed to write the symbol — and `bits`
+	/// extra bits — or `None` if it has no code.
+	const fn cost(cl_lengths: &[DeflateSym; 19], symbol: DeflateSym, bits: u32)
+	-> Option<u32> {
+		let len = cl_lengths[symbol as usize] as u32;
+		if len == 0 { None }
+		else { Some(len + bits) }
+	}
+
+	let mut cl_counts = ZEROED_COUNTS_TREE;
+	let (use_16, use_17, use_18) = extra_bools(extra);
+	let c16 = if use_16 { cost(cl_lengths, DeflateSym::D16, 2) } else { None };
+	let c17 = if use_17 { cost(cl_lengths, DeflateSym::D17, 3) } else { None };
+	let c18 = if use_18 { cost(cl_lengths, DeflateSym::D18, 7) } else { None };
+
+	// For each position in a run, the cheapest total cost of reaching it, and
+	// the symbol and step that got us there.
+	let mut best: Vec<(u32, DeflateSym, u16)> = Vec::new();
+	let mut steps: Vec<(DeflateSym, u16)> = Vec::new();
+
+	for run in all.chunk_by(|a, b| a == b) {
+		let symbol = run[0];
+		let lit = cost(cl_lengths, symbol, 0);
+		let len = run.len();
+
+		best.clear();
+		best.resize(len + 1, (u32::MAX, symbol, 0));
+		best[0].0 = 0;
+
+		for i in 0..len {
+			let base = best[i].0;
+			if base == u32::MAX { continue; }
+
+			/// # Helper: Relax.
+			macro_rules! relax {
+				($sym:expr, $cost:ident, $range:expr) => (
+					for step in $range {
+						let Some(next) = best.get_mut(i + step) else { break; };
+						if base + $cost < next.0 { *next = (base + $cost, $sym, step as u16); }
+					}
+				);
+			}
+
+			if let Some(c) = lit { relax!(symbol, c, 1..=1); }
+
+			// Repeat the previous length; there has to be one!
+			if i != 0 {
+				if let Some(c) = c16 { relax!(DeflateSym::D16, c, 3..=6); }
+			}
+
+			// Repetitions of zeroes.
+			if symbol.is_zero() {
+				if let Some(c) = c17 { relax!(DeflateSym::D17, c, 3..=10); }
+				if let Some(c) = c18 { relax!(DeflateSym::D18, c, 11..=138); }
+			}
+		}
+
+		// Work backwards from the end to figure out how we got there.
+		if best[len].0 == u32::MAX { return None; }
+		steps.clear();
+		let mut j = len;
+		while j != 0 {
+			let (_, sym, step) = best[j];
+			steps.push((sym, step));
+			j -= usize::from(step);
+		}
+
+		// Record the results, forwards this time.
+		for &(sym, step) in steps.iter().rev() {
+			cl_counts[sym as usize] += 1;
+			if let Some(rle) = rle.as_deref_mut() {
+				rle.push((sym, match sym {
+					DeflateSym::D16 | DeflateSym::D17 => step - 3,
+					DeflateSym::D18 => step - 11,
+					_ => 0,
+				}));
+			}
+		}
+	}
+
+	Some(cl_counts)
+}
+
+#[inline]
+/// # Refined Cost Model?
+fn refined_cost_model() -> bool {
+	REFINED_COST_MODEL.get().is_some_and(|v| *v)
+}
+
+/// # Tree Size.
+///
+/// Calculate the size of a tree (in bits) given its counts and lengths.
+fn tree_size(cl_counts: &[u32; 19], cl_lengths: &[DeflateSym; 19]) -> NonZeroU32 {
+	let hclen = tree_hclen(cl_counts);
+
+	let mut size = (hclen as u32 + 4) * 3;
+	size += cl_lengths.iter()
+		.copied()
+		.zip(cl_counts.iter().copied())
+		.map(|(a, b)| (a as u32) * b)
+		.sum::<u32>();
+	size += cl_counts[16] * 2; // Extra bits.
+	size += cl_counts[17] * 3;
+	size += cl_counts[18] * 7;
+	NZ14.saturating_add(size)
+}
+
 /// # Extra Boolification.
 ///
 /// Extract the use-16/17/18 bools (for tree business) from a given byte. This
@@ -353,3 +516,56 @@ fn tree_symbols(ll_lengths: &ArrayLL<DeflateSym>, d_lengths: &ArrayD<DeflateSym>
 
 	Ok((symbols, hlit, hdist))
 }
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_refined_tree_counts() {
+		// A mix of short and long runs, zero and otherwise.
+		let mut all = Vec::new();
+		for (sym, n) in [
+			(DeflateSym::D08, 20),
+			(DeflateSym::D00, 2),
+			(DeflateSym::D07, 3),
+			(DeflateSym::D00, 150),
+			(DeflateSym::D09, 7),
+			(DeflateSym::D00, 9),
+			(DeflateSym::D05, 1),
+		] {
+			all.extend(std::iter::repeat_n(sym, n));
+		}
+
+		for extra in 0..8 {
+			let cl_counts = best_tree_size_counts(&all, extra);
+			let cl_lengths = cl_counts.llcl().expect("LLCL failed.");
+
+			let mut rle = Vec::new();
+			let cl_counts2 = refined_tree_counts(&all, extra, &cl_lengths, Some(&mut rle))
+				.expect("Refined counts failed.");
+
+			// The counts should match the RLE.
+			let mut expected = ZEROED_COUNTS_TREE;
+			for (sym, _) in &rle { expected[*sym as usize] += 1; }
+			assert_eq!(cl_counts2, expected, "Counts/RLE mismatch for extra {extra}.");
+
+			// And the RLE should expand back to the original.
+			let mut out: Vec<DeflateSym> = Vec::new();
+			for (sym, pos) in rle {
+				match sym {
+					DeflateSym::D16 => {
+						let last = *out.last().expect("Missing previous length.");
+						out.extend(std::iter::repeat_n(last, usize::from(pos) + 3));
+					},
+					DeflateSym::D17 => { out.extend(std::iter::repeat_n(DeflateSym::D00, usize::from(pos) + 3)); },
+					DeflateSym::D18 => { out.extend(std::iter::repeat_n(DeflateSym::D00, usize::from(pos) + 11)); },
+					_ => { out.push(sym); },
+				}
+			}
+			assert_eq!(out, all, "RLE expansion mismatch for extra {extra}.");
+		}
+	}
+}
@@ -26,7 +26,6 @@ use super::{
 	ZEROED_COUNTS_D,
 	ZEROED_COUNTS_LL,
 	zopfli_error,
-	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZopfliError,
 	ZopfliRange,
 };
@@ -67,7 +66,7 @@ impl LZ77Store {
 	///
 	/// Return an immutable ranged view of the data, or an error if the range
 	/// is invalid.
-	pub(crate) fn ranged(&self, rng: ZopfliRange) -> Result<LZ77StoreRange, ZopfliError> {
+	pub(crate) fn ranged(&self, rng: ZopfliRange) -> Result<LZ77StoreRange<'_>, ZopfliError> {
 		let entries = self.entries.get(rng.rng()).ok_or(zopfli_error!())?;
 		Ok(LZ77StoreRange { entries })
 	}
@@ -75,10 +74,11 @@ impl LZ77Store {
 	/// # Ranged (Full).
 	///
 	/// Same as `LZ77Store::range`, except the range is everything. This will
-	/// return an error if the store is empty or too large.
-	pub(crate) fn ranged_full(&self) -> Result<LZ77StoreRange, ZopfliError> {
+	/// return an error if the store is empty or larger than the master block
+	/// size `max`.
+	pub(crate) fn ranged_full(&self, max: usize) -> Result<LZ77StoreRange<'_>, ZopfliError> {
 		let entries = self.entries.as_slice();
-		if entries.is_empty() || ZOPFLI_MASTER_BLOCK_SIZE < entries.len() {
+		if entries.is_empty() || max < entries.len() {
 			Err(zopfli_error!())
 		}
 		else { Ok(LZ77StoreRange { entries }) }
@@ -152,14 +152,15 @@ impl<'a> LZ77StoreRange<'a> {
 	///
 	/// Return the original uncompressed range — from e.g. a `ZopfliChunk` —
 	/// used to build this store. If for some reason that range cannot be
-	/// recreated, an error will be returned instead.
-	pub(crate) const fn byte_range(self) -> Result<ZopfliRange, ZopfliError> {
+	/// recreated — or exceeds the master block size `max` — an error will be
+	/// returned instead.
+	pub(crate) fn byte_range(self, max: usize) -> Result<ZopfliRange, ZopfliError> {
 		let len = self.entries.len();
 		if 0 == len { return Err(zopfli_error!()); } // Ranged stores are never empty.
 
 		let first = self.entries[0];
 		let last = self.entries[len - 1];
-		ZopfliRange::new(first.pos, last.length() as usize + last.pos)
+		ZopfliRange::new(first.pos, last.length() as usize + last.pos, max)
 	}
 
 	/// # Histogram.
@@ -240,10 +241,14 @@ impl LZ77StoreRange<'_> {
 	/// Return the smallest of the uncompressed, fixed, and dynamic sizes.
 	/// (When `try_fixed` is false, only uncompressed and dynamic sizes are
 	/// calculated and compared.)
-	pub(crate) fn block_size_auto(self, try_fixed: bool) -> Result<NonZeroU32, ZopfliError> {
+	///
+	/// The master block size `max` is needed for the uncompressed size; see
+	/// `LZ77StoreRange::byte_range`.
+	pub(crate) fn block_size_auto(self, try_fixed: bool, max: usize)
+	-> Result<NonZeroU32, ZopfliError> {
 		// Take the smaller of the uncompressed and dynamic costs.
 		let cost = NonZeroU32::min(
-			self.block_size_uncompressed()?,
+			self.block_size_uncompressed(max)?,
 			self.block_size_dynamic()?,
 		);
 
@@ -276,8 +281,9 @@ impl LZ77StoreRange<'_> {
 	}
 
 	/// # Calculate Block Size (Uncompressed).
-	pub(crate) fn block_size_uncompressed(self) -> Result<NonZeroU32, ZopfliError> {
-		let blocksize = self.byte_range()?.len32();
+	pub(crate) fn block_size_uncompressed(self, max: usize)
+	-> Result<NonZeroU32, ZopfliError> {
+		let blocksize = self.byte_range(max)?.len32();
 
 		// Uncompressed blocks are split at u16::MAX.
 		let chunks = blocksize.get().div_ceil(u32::from(u16::MAX));
@@ -400,13 +406,13 @@ impl<'a> LZ77StoreRangeSplitsChunked<'a> {
 	/// anew.
 	///
 	/// Regardless, the mid point between start and end is returned.
-	pub(crate) fn reset(&mut self, n: usize) -> NonZeroUsize {
+	pub(crate) const fn reset(&mut self, n: usize) -> NonZeroUsize {
 		// Find the midpoint first; the new start and end are relative to it.
 		// Safety: chunk and (n+1) are both non-zero.
 		let mid = unsafe { NonZeroUsize::new_unchecked(self.pos_at(n + 1)) };
 
 		// Tweak the ranges.
-		if 0 != n { self.start = mid.get() - self.chunk.get(); };
+		if 0 != n { self.start = mid.get() - self.chunk.get(); }
 		if n + 1 < Self::SPLITS { self.end = mid.get() + self.chunk.get(); }
 
 		// If we're still chunkable, reset for another round!
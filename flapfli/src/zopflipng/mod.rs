@@ -24,10 +24,16 @@ mod kat;
 mod lz77;
 mod rle;
 mod rng;
+mod simd;
 mod stats;
 mod symbols;
 
 pub(crate) use blocks::deflate_part;
+pub use blocks::{
+	set_zopfli_max_splits,
+	set_zopfli_store_threshold,
+	zopfli_block_stats,
+};
 use cache::{
 	MatchCache,
 	SplitCache,
@@ -45,13 +51,16 @@ use kat::{
 	encode_tree,
 	LengthLimitedCodeLengths,
 };
-use lz77::{
-	LZ77Store,
-	LZ77StoreRange,
-};
+pub use kat::set_zopfli_cost_model;
+pub(crate) use lz77::LZ77Store;
+use lz77::LZ77StoreRange;
 use rng::ZopfliRange;
 use rle::DynamicLengths;
-use super::deflate::ZopfliOut;
+use super::deflate::{
+	cancelled,
+	zopfli_block_size,
+	ZopfliOut,
+};
 use symbols::{
 	DeflateSym,
 	DeflateSymBasic,
@@ -125,7 +134,7 @@ const FIXED_SYMBOLS_D: ArrayD<u32> = [
 /// # Step Size for Deflate Parts.
 ///
 /// The "active" portion of the `ZopfliChunk` passed from lodepng will never
-/// exceed a million bytes.
+/// exceed a million bytes, unless overridden by `set_zopfli_block_size`.
 pub(super) const ZOPFLI_MASTER_BLOCK_SIZE: usize = 1_000_000;
 
 /// # Hash/LZ77 Window Size.
@@ -12,7 +12,6 @@ use std::{
 use super::{
 	LZ77StoreRange,
 	zopfli_error,
-	ZOPFLI_MASTER_BLOCK_SIZE,
 	ZopfliError,
 };
 
@@ -22,7 +21,7 @@ use super::{
 /// # Block Range.
 ///
 /// This struct exists primarily to guarantee a range is non-empty and no
-/// larger than `ZOPFLI_MASTER_BLOCK_SIZE`.
+/// larger than the master block size.
 ///
 /// It also implements `Copy`, so there's that too! Haha.
 pub(crate) struct ZopfliRange {
@@ -36,8 +35,8 @@ pub(crate) struct ZopfliRange {
 impl From<LZ77StoreRange<'_>> for ZopfliRange {
 	#[inline]
 	fn from(src: LZ77StoreRange<'_>) -> Self {
-		// Safety: LZ77StoreRange requires slices be non-empty and within a
-		// million, same as ZopfliRange.
+		// Safety: LZ77StoreRange requires slices be non-empty and within the
+		// master block size, same as ZopfliRange.
 		Self {
 			start: 0,
 			end: src.len().get(),
@@ -49,9 +48,11 @@ impl ZopfliRange {
 	/// # New.
 	///
 	/// Return a new instance spanning `start..end` so long as the struct's
-	/// requirements are met, otherwise an error.
-	pub(crate) const fn new(start: usize, end: usize) -> Result<Self, ZopfliError> {
-		if start < end && end - start <= ZOPFLI_MASTER_BLOCK_SIZE {
+	/// requirements are met — given the master block size `max` — otherwise
+	/// an error.
+	pub(crate) const fn new(start: usize, end: usize, max: usize)
+	-> Result<Self, ZopfliError> {
+		if start < end && end - start <= max {
 			Ok(Self { start, end })
 		}
 		else { Err(zopfli_error!()) }
@@ -60,9 +61,11 @@ impl ZopfliRange {
 	/// # Update.
 	///
 	/// Adjust the start and end positions of the range so long as the new
-	/// values satisfy the struct's requirements, otherwise an error.
-	pub(crate) fn set(&mut self, start: usize, end: usize) -> Result<(), ZopfliError> {
-		if start < end && end - start <= ZOPFLI_MASTER_BLOCK_SIZE {
+	/// values satisfy the struct's requirements — given the master block size
+	/// `max` — otherwise an error.
+	pub(crate) const fn set(&mut self, start: usize, end: usize, max: usize)
+	-> Result<(), ZopfliError> {
+		if start < end && end - start <= max {
 			self.start = start;
 			self.end = end;
 			Ok(())
@@ -91,18 +94,18 @@ impl ZopfliRange {
 	#[expect(
 		clippy::cast_possible_truncation,
 		unsafe_code,
-		reason = "Length is non-empty and at most a million.",
+		reason = "Length is non-empty and at most eight million.",
 	)]
 	/// # Length (32-bit).
 	///
 	/// Same as `ZopfliRange::len`, but more convenient in cases where 32-bit
 	/// values are needed (such as cost/size calculations).
 	///
-	/// Because our ranges are capped at a million, the lengths will always fit
-	/// without truncation.
+	/// Because our ranges are capped at the master block size — eight million
+	/// at most — the lengths will always fit without truncation.
 	pub(crate) const fn len32(&self) -> NonZeroU32 {
 		// Safety: we verified start is less than end during construction, and
-		// the total is within a million.
+		// the total is within the master block size.
 		unsafe { NonZeroU32::new_unchecked((self.end - self.start) as u32) }
 	}
 }
@@ -112,34 +115,35 @@ impl ZopfliRange {
 #[cfg(test)]
 mod test {
 	use super::*;
+	use super::super::ZOPFLI_MASTER_BLOCK_SIZE as MAX;
 
 	#[test]
 	fn t_range() {
 		// Some simple bad ranges.
-		assert!(ZopfliRange::new(0, 0).is_err());
-		assert!(ZopfliRange::new(3, 2).is_err());
-		assert!(ZopfliRange::new(0, ZOPFLI_MASTER_BLOCK_SIZE + 1).is_err());
+		assert!(ZopfliRange::new(0, 0, MAX).is_err());
+		assert!(ZopfliRange::new(3, 2, MAX).is_err());
+		assert!(ZopfliRange::new(0, MAX + 1, MAX).is_err());
 
 		// This should fit!.
-		assert!(ZopfliRange::new(0, ZOPFLI_MASTER_BLOCK_SIZE).is_ok());
+		assert!(ZopfliRange::new(0, MAX, MAX).is_ok());
 
 		// Let's test the getters.
-		let mut rng = ZopfliRange::new(1, 5).expect("Range failed!");
+		let mut rng = ZopfliRange::new(1, 5, MAX).expect("Range failed!");
 		assert_eq!(rng.start(), 1);
 		assert_eq!(rng.end(), 5);
 		assert_eq!(rng.len(), NonZeroUsize::new(4).unwrap());
 		assert_eq!(rng.rng(), 1..5);
 
 		// And the setters.
-		assert!(rng.set(2, 6).is_ok());
+		assert!(rng.set(2, 6, MAX).is_ok());
 		assert_eq!(rng.start(), 2);
 		assert_eq!(rng.end(), 6);
 		assert_eq!(rng.len(), NonZeroUsize::new(4).unwrap());
 		assert_eq!(rng.rng(), 2..6);
 
 		// This should fail.
-		assert!(rng.set(0, 0).is_err());
-		assert!(rng.set(3, 2).is_err());
-		assert!(rng.set(0, ZOPFLI_MASTER_BLOCK_SIZE + 1).is_err());
+		assert!(rng.set(0, 0, MAX).is_err());
+		assert!(rng.set(3, 2, MAX).is_err());
+		assert!(rng.set(0, MAX + 1, MAX).is_err());
 	}
 }
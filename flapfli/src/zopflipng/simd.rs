@@ -0,0 +1,273 @@
+/*!
+# Flapfli: SIMD Matching.
+
+The longest-match search spends most of its time comparing runs of bytes to
+see how far they agree. The functions in this module do that a vector at a
+time — AVX2 or SSE2 on `x86_64`, NEON on `aarch64`, depending on what the CPU
+supports — falling back to plain old byte-by-byte comparison otherwise.
+*/
+
+
+
+#[inline]
+/// # Match Length.
+///
+/// Return the index of the first mismatch between `a` and `b` at or after
+/// `start`, or `limit` (or the end of the shorter slice) if there isn't one.
+///
+/// If `start` is already at or beyond the end, it is returned as-is.
+pub(super) fn match_len(a: &[u8], b: &[u8], start: usize, limit: usize) -> usize {
+	let end = limit.min(a.len()).min(b.len());
+	if end <= start { return start; }
+
+	let a = &a[start..end];
+	let b = &b[start..end];
+	start + match_len_arch(a, b)
+}
+
+
+
+#[cfg(target_arch = "x86_64")]
+#[expect(unsafe_code, reason = "For SIMD.")]
+#[inline]
+/// # Match Length (`x86_64`).
+///
+/// SSE2 is part of the `x86_64` baseline, but AVX2 has to be checked for at
+/// runtime. (The standard library caches the answer.)
+fn match_len_arch(a: &[u8], b: &[u8]) -> usize {
+	if std::arch::is_x86_feature_detected!("avx2") {
+		// Safety: the CPU supports AVX2.
+		unsafe { match_len_avx2(a, b) }
+	}
+	else { match_len_sse2(a, b) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[expect(clippy::cast_ptr_alignment, reason = "The loads are unaligned.")]
+#[expect(unsafe_code, reason = "For SIMD.")]
+#[target_feature(enable = "avx2")]
+/// # Match Length (AVX2).
+///
+/// Compare 32 bytes at a time, leaving any remainder to SSE2.
+///
+/// ## Safety
+///
+/// The CPU must support AVX2.
+unsafe fn match_len_avx2(a: &[u8], b: &[u8]) -> usize {
+	use std::arch::x86_64::{
+		__m256i,
+		_mm256_cmpeq_epi8,
+		_mm256_loadu_si256,
+		_mm256_movemask_epi8,
+	};
+
+	let len = a.len().min(b.len());
+	let mut idx = 0;
+	while idx + 32 <= len {
+		// Safety: the range is in bounds, and unaligned loads are fine. Each
+		// bit of the mask is set if the corresponding bytes match.
+		let mask = unsafe {
+			let va = _mm256_loadu_si256(a.as_ptr().add(idx).cast::<__m256i>());
+			let vb = _mm256_loadu_si256(b.as_ptr().add(idx).cast::<__m256i>());
+			_mm256_movemask_epi8(_mm256_cmpeq_epi8(va, vb))
+		};
+		if mask != -1 { return idx + (! mask).trailing_zeros() as usize; }
+		idx += 32;
+	}
+
+	idx + match_len_sse2(&a[idx..], &b[idx..])
+}
+
+#[cfg(target_arch = "x86_64")]
+#[expect(clippy::cast_ptr_alignment, reason = "The loads are unaligned.")]
+#[expect(unsafe_code, reason = "For SIMD.")]
+/// # Match Length (SSE2).
+///
+/// Compare 16 bytes at a time, leaving any remainder to the scalar loop.
+fn match_len_sse2(a: &[u8], b: &[u8]) -> usize {
+	use std::arch::x86_64::{
+		__m128i,
+		_mm_cmpeq_epi8,
+		_mm_loadu_si128,
+		_mm_movemask_epi8,
+	};
+
+	let len = a.len().min(b.len());
+	let mut idx = 0;
+	while idx + 16 <= len {
+		// Safety: the range is in bounds, and unaligned loads are fine. Each
+		// of the low 16 bits of the mask is set if the corresponding bytes
+		// match.
+		let mask = unsafe {
+			let va = _mm_loadu_si128(a.as_ptr().add(idx).cast::<__m128i>());
+			let vb = _mm_loadu_si128(b.as_ptr().add(idx).cast::<__m128i>());
+			_mm_movemask_epi8(_mm_cmpeq_epi8(va, vb))
+		};
+		if mask != 0xFFFF { return idx + (! mask).trailing_zeros() as usize; }
+		idx += 16;
+	}
+
+	idx + match_len_scalar(&a[idx..], &b[idx..])
+}
+
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+#[expect(unsafe_code, reason = "For SIMD.")]
+#[inline]
+/// # Match Length (`aarch64`).
+///
+/// NEON is all but universal on `aarch64`, but it is still checked for at
+/// runtime like AVX2. (The standard library caches the answer.)
+fn match_len_arch(a: &[u8], b: &[u8]) -> usize {
+	if std::arch::is_aarch64_feature_detected!("neon") {
+		// Safety: the CPU supports NEON.
+		unsafe { match_len_neon(a, b) }
+	}
+	else { match_len_scalar(a, b) }
+}
+
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+#[expect(unsafe_code, reason = "For SIMD.")]
+#[target_feature(enable = "neon")]
+/// # Match Length (NEON).
+///
+/// Compare 16 bytes at a time, leaving any remainder to the scalar loop.
+///
+/// NEON has no `movemask`, so each half of the comparison is checked as a
+/// `u64` instead; mismatched bytes are the ones left zeroed.
+///
+/// ## Safety
+///
+/// The CPU must support NEON.
+unsafe fn match_len_neon(a: &[u8], b: &[u8]) -> usize {
+	use std::arch::aarch64::{
+		vceqq_u8,
+		vgetq_lane_u64,
+		vld1q_u8,
+		vreinterpretq_u64_u8,
+	};
+
+	let len = a.len().min(b.len());
+	let mut idx = 0;
+	while idx + 16 <= len {
+		// Safety: the range is in bounds. Matching bytes come back as 0xFF,
+		// so the inverted lanes are non-zero wherever there's a mismatch.
+		let (lo, hi) = unsafe {
+			let va = vld1q_u8(a.as_ptr().add(idx));
+			let vb = vld1q_u8(b.as_ptr().add(idx));
+			let eq = vreinterpretq_u64_u8(vceqq_u8(va, vb));
+			(! vgetq_lane_u64::<0>(eq), ! vgetq_lane_u64::<1>(eq))
+		};
+		if lo != 0 { return idx + (lo.trailing_zeros() / 8) as usize; }
+		if hi != 0 { return idx + 8 + (hi.trailing_zeros() / 8) as usize; }
+		idx += 16;
+	}
+
+	idx + match_len_scalar(&a[idx..], &b[idx..])
+}
+
+#[cfg(not(any(
+	target_arch = "x86_64",
+	all(target_arch = "aarch64", target_endian = "little"),
+)))]
+#[inline]
+/// # Match Length (Generic).
+fn match_len_arch(a: &[u8], b: &[u8]) -> usize { match_len_scalar(a, b) }
+
+#[inline]
+/// # Match Length (Scalar).
+///
+/// Compare the slices one byte at a time, returning the index of the first
+/// mismatch, or the length of the shorter one.
+fn match_len_scalar(a: &[u8], b: &[u8]) -> usize {
+	a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn t_match_len() {
+		// A repeating pattern with a few planted mismatches.
+		let a: Vec<u8> = (0..300_u16).map(|n| (n % 7) as u8).collect();
+		for bad in [0_usize, 1, 15, 16, 17, 31, 32, 33, 100, 257, 299] {
+			let mut b = a.clone();
+			b[bad] ^= 0xFF;
+
+			for start in [0_usize, 1, 5, 16, 40] {
+				for limit in [0_usize, 3, 16, 64, 258, 300, 500] {
+					let expected = {
+						let end = limit.min(a.len());
+						if end <= start { start }
+						else { start + match_len_scalar(&a[start..end], &b[start..end]) }
+					};
+					assert_eq!(
+						match_len(&a, &b, start, limit),
+						expected,
+						"Wrong length for mismatch {bad}, start {start}, limit {limit}.",
+					);
+				}
+			}
+		}
+
+		// No mismatches at all.
+		assert_eq!(match_len(&a, &a, 0, 258), 258);
+		assert_eq!(match_len(&a, &a[..10], 0, 258), 10);
+	}
+
+	#[test]
+	/// # SIMD vs Scalar.
+	///
+	/// Every vector implementation the CPU supports should agree with the
+	/// plain byte-by-byte loop, whatever the lengths and mismatch positions.
+	fn t_match_len_scalar() {
+		// A cheap xorshift so the runs are repeatable.
+		let mut seed = 0x2545_f491_u32;
+		let mut next = move || {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			seed
+		};
+
+		for _ in 0..2_000 {
+			let len = (next() % 300) as usize;
+			let a: Vec<u8> = (0..len).map(|_| (next() % 3) as u8).collect();
+			let mut b = a.clone();
+
+			// Plant a mismatch most of the time, and trim the other side
+			// now and then too.
+			if ! b.is_empty() && next() % 4 != 0 {
+				let bad = next() as usize % b.len();
+				b[bad] ^= 1 << (next() % 8);
+			}
+			if ! b.is_empty() && next() % 5 == 0 {
+				b.truncate(next() as usize % b.len());
+			}
+
+			let expected = match_len_scalar(&a, &b);
+			assert_eq!(match_len_arch(&a, &b), expected, "Arch mismatch: {a:?} {b:?}");
+
+			#[cfg(target_arch = "x86_64")]
+			{
+				assert_eq!(match_len_sse2(&a, &b), expected, "SSE2 mismatch: {a:?} {b:?}");
+				if std::arch::is_x86_feature_detected!("avx2") {
+					// Safety: the CPU supports AVX2.
+					#[expect(unsafe_code, reason = "For SIMD.")]
+					let found = unsafe { match_len_avx2(&a, &b) };
+					assert_eq!(found, expected, "AVX2 mismatch: {a:?} {b:?}");
+				}
+			}
+
+			#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+			if std::arch::is_aarch64_feature_detected!("neon") {
+				// Safety: the CPU supports NEON.
+				#[expect(unsafe_code, reason = "For SIMD.")]
+				let found = unsafe { match_len_neon(&a, &b) };
+				assert_eq!(found, expected, "NEON mismatch: {a:?} {b:?}");
+			}
+		}
+	}
+}
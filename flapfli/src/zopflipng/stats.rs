@@ -17,14 +17,16 @@ use super::{
 
 
 
-#[expect(clippy::missing_docs_in_private_items, reason = "Unimportant.")]
 #[derive(Clone, Copy)]
 /// # Randomness.
 ///
 /// This struct is only used to cheaply (and predictably) shuffle stat
 /// frequencies.
 pub(crate) struct RanState {
+	/// # State (W).
 	m_w: u32,
+
+	/// # State (Z).
 	m_z: u32,
 }
 
@@ -41,7 +43,7 @@ impl RanState {
 	///
 	/// A simple, repeatable [MWC PRNG](https://en.wikipedia.org/wiki/Multiply-with-carry_pseudorandom_number_generator),
 	/// used to shuffle frequencies between runs.
-	fn randomize(&mut self) -> u32 {
+	const fn randomize(&mut self) -> u32 {
 		self.m_z = 36_969 * (self.m_z & 65_535) + (self.m_z >> 16);
 		self.m_w = 18_000 * (self.m_w & 65_535) + (self.m_w >> 16);
 		(self.m_z << 16).wrapping_add(self.m_w)
@@ -145,6 +147,7 @@ impl SymbolStats {
 	///
 	/// Note: this does _not_ rebuild the symbol tables.
 	pub(crate) fn randomize(&mut self, state: &mut RanState) {
+		#[allow(clippy::allow_attributes, clippy::manual_is_multiple_of, reason = "is_multiple_of is too new.")]
 		/// # Shuffle Counts.
 		fn shuffle_counts<const N: usize>(counts: &mut [u32; N], state: &mut RanState) {
 			const {
@@ -157,13 +157,6 @@ impl LitLen {
 	/// Return an iterator covering `ZOPFLI_MIN_MATCH..=ZOPFLI_MAX_MATCH`.
 	pub(crate) const fn matchable_iter() -> LitLenIter { LitLenIter(3) }
 
-	/// # Next Iter.
-	///
-	/// Return an iterator beginning with the next litlen, if any.
-	pub(crate) const fn next_iter(after: Self) -> LitLenIter {
-		LitLenIter(after as u16 + 1)
-	}
-
 	#[expect(clippy::cast_possible_truncation, reason = "False positive.")]
 	/// # To Packed U8.
 	///
@@ -196,11 +189,6 @@ impl SplitLen {
 	///
 	/// Returns `true` if `self` is zero.
 	pub(crate) const fn is_zero(self) -> bool { matches!(self, Self::S00) }
-
-	/// # Is Max?
-	///
-	/// Returns `true` if `self` is the maximum value (`SplitLen::S14`).
-	pub(crate) const fn is_max(self) -> bool { matches!(self, Self::S14) }
 }
 
 